@@ -0,0 +1,27 @@
+use crate::commands::activity::prune_activity_log;
+use crate::commands::db::{get_connection, DbPool};
+use std::thread;
+use std::time::Duration;
+
+/// How often the pruner trims the activity log.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts a background thread that keeps the activity log within its retention period
+/// and entry cap. The first check runs at startup.
+pub fn start_activity_pruner(pool: DbPool) {
+    thread::spawn(move || loop {
+        if let Err(e) = run_activity_prune(&pool) {
+            println!("Failed to prune the activity log: {}", e);
+        }
+        thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+fn run_activity_prune(pool: &DbPool) -> Result<(), String> {
+    let conn = get_connection(pool)?;
+    let deleted = prune_activity_log(&conn).map_err(|e| e.to_string())?;
+    if deleted > 0 {
+        println!("Dropped {} old entries from the activity log.", deleted);
+    }
+    Ok(())
+}
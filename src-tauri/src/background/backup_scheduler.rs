@@ -0,0 +1,62 @@
+use crate::commands::activity::{record_activity, NO_FILES};
+use crate::commands::db::{get_connection, DbPool};
+use crate::commands::file_ops::parse_timestamp_millis;
+use crate::commands::maintenance::{
+    create_backup, get_backup_folder, get_backup_settings, list_backups, prune_backups,
+};
+use crate::commands::structures::ActivityActor;
+use chrono::Utc;
+use std::thread;
+use std::time::Duration;
+
+/// How often the scheduler wakes up to see whether a backup is due.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Starts a background thread that backs up the library whenever the newest backup in
+/// the backup folder is older than the configured interval. Settings are re-read on
+/// every check, so changes apply without a restart.
+pub fn start_backup_scheduler(pool: DbPool) {
+    thread::spawn(move || loop {
+        if let Err(e) = run_scheduled_backup(&pool) {
+            println!("Scheduled backup failed: {}", e);
+        }
+        thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+fn run_scheduled_backup(pool: &DbPool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let conn = get_connection(pool)?;
+    let settings = get_backup_settings(&conn)?;
+    if settings.interval_hours == 0 {
+        return Ok(());
+    }
+
+    let folder = get_backup_folder(&conn)?;
+    let interval_ms = settings.interval_hours as i64 * 60 * 60 * 1000;
+    let due = match list_backups(&folder)?.first() {
+        Some(newest) => parse_timestamp_millis(&newest.created_at)
+            .map(|created| Utc::now().timestamp_millis() - created >= interval_ms)
+            .unwrap_or(true),
+        None => true,
+    };
+    if !due {
+        return Ok(());
+    }
+
+    let result = create_backup(&conn, &folder);
+    record_activity(
+        &conn,
+        ActivityActor::System,
+        "create_backup",
+        None,
+        NO_FILES,
+        &result,
+        "Scheduled backup.",
+    );
+    result?;
+    let removed = prune_backups(&folder, settings.keep_count)?;
+    if removed > 0 {
+        println!("Removed {} old backups from {:?}", removed, folder);
+    }
+    Ok(())
+}
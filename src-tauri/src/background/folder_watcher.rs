@@ -1,9 +1,15 @@
-use crate::commands::{actions, db, file_ops};
+use crate::commands::db::{get_connection, DbPool};
+use crate::commands::activity::{record_activity, NO_FILES};
+use crate::commands::structures::ActivityActor;
+use crate::commands::{actions, audio_ops, db, file_ops};
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use tauri::Emitter;
+use std::thread;
+use std::time::{Duration, Instant};
+use rusqlite::Connection;
+use tauri::{Emitter, State};
 use tauri::WebviewWindow;
 
 type WatcherMap = Arc<Mutex<HashMap<String, RecommendedWatcher>>>;
@@ -14,24 +20,122 @@ lazy_static! {
     static ref WATCHERS: WatcherMap = Arc::new(Mutex::new(HashMap::new()));
 }
 
+/// How long a repository's folders must be quiet before a change on disk refreshes it.
+/// Copying one large file fires a modify event for every write.
+const REFRESH_DELAY: Duration = Duration::from_secs(2);
+
+/// A refresh waiting for its repository to go quiet: when the last change was seen, and
+/// which events to emit once it has run.
+struct PendingRefresh {
+    last_change: Instant,
+    events: HashSet<&'static str>,
+}
+
+lazy_static! {
+    static ref PENDING_REFRESHES: Mutex<HashMap<String, PendingRefresh>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Starts watching a folder and stores it as one of the repository's tracked folders.
 pub fn watch_folder(
     window: WebviewWindow,
+    pool: DbPool,
     repo_id: String,
     folder_path: String,
 ) -> notify::Result<()> {
-    let path = PathBuf::from(folder_path.clone());
-    
-    // 1. Lock only for the check
-    {
-        let watchers = WATCHERS.lock().unwrap();
-        if watchers.contains_key(&folder_path) {
-            println!("Already watching folder: {}", folder_path);
-            return Ok(()); // Exit early if already watching
+    if is_watched(&folder_path) {
+        println!("Already watching folder: {}", folder_path);
+        return Ok(()); // Exit early if already watching
+    }
+    let watcher = new_watcher(window, &pool, &repo_id, &folder_path)?;
+
+    // Store the folder before keeping the watcher, so a failure leaves nothing watching
+    let conn = get_connection(&pool).map_err(|e| notify::Error::generic(&e))?;
+    db::create_tracked_folder(&conn, &repo_id, &folder_path).map_err(|e| {
+        notify::Error::generic(&format!("Failed to store tracked folder in database: {}", e))
+    })?;
+
+    WATCHERS
+        .lock()
+        .unwrap()
+        .insert(folder_path.clone(), watcher);
+
+    // println!("Started watching folder: {}", folder_path);
+
+    Ok(())
+}
+
+/// Starts watching a folder that is already tracked, at startup or when its repository
+/// comes back from the trash.
+pub fn resume_watching(
+    window: WebviewWindow,
+    pool: DbPool,
+    repo_id: String,
+    folder_path: String,
+) -> notify::Result<()> {
+    if is_watched(&folder_path) {
+        return Ok(());
+    }
+    let watcher = new_watcher(window, &pool, &repo_id, &folder_path)?;
+    WATCHERS.lock().unwrap().insert(folder_path, watcher);
+    Ok(())
+}
+
+/// Starts watching every folder a repository tracks.
+pub fn start_repository_watchers(
+    window: &WebviewWindow,
+    pool: &DbPool,
+    conn: &Connection,
+    repo_id: &str,
+) -> rusqlite::Result<()> {
+    for folder in db::get_tracked_folders(conn)? {
+        if folder.repo_id != repo_id {
+            continue;
         }
-    } // 🔓 The lock is dropped here
+        if let Err(e) = resume_watching(
+            window.clone(),
+            pool.clone(),
+            folder.repo_id,
+            folder.folder_path.clone(),
+        ) {
+            println!("Failed to watch folder '{}': {:?}", folder.folder_path, e);
+        }
+    }
+    Ok(())
+}
+
+/// Stops watching every folder a repository tracks, while keeping them tracked, so the
+/// watchers of a trashed repository don't keep refreshing it.
+pub fn stop_repository_watchers(conn: &Connection, repo_id: &str) -> rusqlite::Result<()> {
+    let mut watchers = WATCHERS.lock().unwrap();
+    for folder in db::get_tracked_folders(conn)? {
+        if folder.repo_id != repo_id {
+            continue;
+        }
+        if let Some(mut watcher) = watchers.remove(&folder.folder_path) {
+            println!("Stopping watcher for folder: {}", folder.folder_path);
+            if let Err(e) = watcher.unwatch(std::path::Path::new(&folder.folder_path)) {
+                println!("Failed to stop watching '{}': {:?}", folder.folder_path, e);
+            }
+        }
+    }
+    Ok(())
+}
 
-    let repo_id_for_watcher = repo_id.clone(); // clone here
-    let repo_id_for_db = repo_id.clone(); // clone for DB use
+fn is_watched(folder_path: &str) -> bool {
+    WATCHERS.lock().unwrap().contains_key(folder_path)
+}
+
+/// Creates a watcher that keeps a repository in step with the folder.
+fn new_watcher(
+    window: WebviewWindow,
+    pool: &DbPool,
+    repo_id: &str,
+    folder_path: &str,
+) -> notify::Result<RecommendedWatcher> {
+    let path = PathBuf::from(folder_path);
+    let repo_id_for_watcher = repo_id.to_string();
+    let pool_for_watcher = pool.clone();
 
     let mut watcher: RecommendedWatcher = RecommendedWatcher::new(
         move |res: notify::Result<Event>| {
@@ -46,6 +150,7 @@ pub fn watch_folder(
                                     println!("New file detected: {:?}", path);
                                     handle_new_file(
                                         window.clone(),
+                                        &pool_for_watcher,
                                         &repo_id_for_watcher,
                                         path.clone(),
                                     );
@@ -54,23 +159,21 @@ pub fn watch_folder(
                         }
                         EventKind::Remove(_) => {
                             println!("File removed! Refreshing repository...");
-                            let _ = actions::refresh_files_in_repository(&repo_id_for_watcher);
-
-                            // Emit folder_file_removed event!
-                            let _ = window.emit(
+                            schedule_refresh(
+                                &window,
+                                &pool_for_watcher,
+                                &repo_id_for_watcher,
                                 "folder_file_removed",
-                                format!("A file was removed from repo '{}'", repo_id_for_watcher),
                             );
                         }
 
                         EventKind::Modify(_) => {
                             println!("File modified! Refreshing repository...");
-                            let _ = actions::refresh_files_in_repository(&repo_id_for_watcher);
-
-                            // Emit folder_file_modified event! (optional, if you want to track these)
-                            let _ = window.emit(
+                            schedule_refresh(
+                                &window,
+                                &pool_for_watcher,
+                                &repo_id_for_watcher,
                                 "folder_file_modified",
-                                format!("A file was modified in repo '{}'", repo_id_for_watcher),
                             );
                         }
 
@@ -84,37 +187,116 @@ pub fn watch_folder(
     )?;
 
     watcher.watch(&path, RecursiveMode::Recursive)?;
+    Ok(watcher)
+}
 
-    WATCHERS
-        .lock()
-        .unwrap()
-        .insert(folder_path.clone(), watcher);
-
-    db::create_tracked_folder(&repo_id_for_db, &folder_path)
-        .expect("Failed to store tracked folder in database");
+/// Refreshes a repository once its folders have been quiet for `REFRESH_DELAY`, then
+/// emits `event` along with any other events that came in meanwhile. A burst of changes
+/// shares one refresh.
+fn schedule_refresh(window: &WebviewWindow, pool: &DbPool, repo_id: &str, event: &'static str) {
+    {
+        let mut pending = PENDING_REFRESHES.lock().unwrap();
+        if let Some(refresh) = pending.get_mut(repo_id) {
+            refresh.last_change = Instant::now();
+            refresh.events.insert(event);
+            return;
+        }
+        pending.insert(
+            repo_id.to_string(),
+            PendingRefresh {
+                last_change: Instant::now(),
+                events: HashSet::from([event]),
+            },
+        );
+    }
 
-    // println!("Started watching folder: {}", folder_path);
+    let window = window.clone();
+    let pool = pool.clone();
+    let repo_id = repo_id.to_string();
+    thread::spawn(move || {
+        let events = loop {
+            thread::sleep(REFRESH_DELAY);
+            let mut pending = PENDING_REFRESHES.lock().unwrap();
+            let waiting = matches!(
+                pending.get(&repo_id),
+                Some(refresh) if refresh.last_change.elapsed() < REFRESH_DELAY
+            );
+            if !waiting {
+                break pending
+                    .remove(&repo_id)
+                    .map(|refresh| refresh.events)
+                    .unwrap_or_default();
+            }
+        };
+        refresh_repository(&pool, &repo_id);
+        for event in events {
+            let message = match event {
+                "folder_file_removed" => format!("A file was removed from repo '{}'", repo_id),
+                _ => format!("A file was modified in repo '{}'", repo_id),
+            };
+            let _ = window.emit(event, message);
+        }
+    });
+}
 
-    Ok(())
+/// Re-checks a repository after a change on disk, with its own pooled connection.
+fn refresh_repository(pool: &DbPool, repo_id: &str) {
+    if let Ok(conn) = get_connection(pool) {
+        let result = actions::refresh_files_in_repository(&conn, repo_id);
+        // Only refreshes that changed rows, or failed, are worth a log entry.
+        if !matches!(result, Ok(0)) {
+            let details = match &result {
+                Ok(count) => format!("{} files refreshed after a change on disk.", count),
+                Err(e) => format!("Failed to refresh after a change on disk: {}", e),
+            };
+            record_activity(
+                &conn,
+                ActivityActor::Watcher,
+                "refresh_files_in_repository",
+                Some(repo_id),
+                NO_FILES,
+                &result,
+                &details,
+            );
+        }
+    }
 }
 
-fn handle_new_file(window: WebviewWindow, repo_id: &str, path: PathBuf) {
+fn handle_new_file(window: WebviewWindow, pool: &DbPool, repo_id: &str, path: PathBuf) {
 
     if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-        if stem.ends_with("_converted5334112025") {
-            println!("Ignoring folder with '_converted5334112025' suffix: {}", stem);
+        if stem.ends_with(audio_ops::CONVERSION_TEMP_SUFFIX) {
+            println!(
+                "Ignoring folder with '{}' suffix: {}",
+                audio_ops::CONVERSION_TEMP_SUFFIX,
+                stem
+            );
             return; // Ignore this file
         }
     }
 
     if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-        let audio_extensions = ["mp3", "wav", "flac", "ogg", "aac"];
-        if audio_extensions.contains(&ext.to_lowercase().as_str()) {
+        if file_ops::AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()) {
             match file_ops::get_audio_metadata_from_file(path.to_str().unwrap()) {
                 Ok(file_metadata) => {
                     let mut file = file_metadata.clone();
                     file.id = uuid::Uuid::new_v4().to_string();
-                    if let Err(err) = db::create_file(repo_id, &file) {
+                    let result = get_connection(pool).and_then(|conn| {
+                        let result = db::create_file(&conn, repo_id, &file);
+                        let details =
+                            format!("New file '{}' found in a watched folder.", file.path);
+                        record_activity(
+                            &conn,
+                            ActivityActor::Watcher,
+                            "add_file",
+                            Some(repo_id),
+                            &[&file.id],
+                            &result,
+                            &details,
+                        );
+                        result.map_err(|e| e.to_string())
+                    });
+                    if let Err(err) = result {
                         println!("Error adding file to DB: {:?}", err);
                     } else {
                         println!("File added successfully: {:?}", file.name);
@@ -132,6 +314,7 @@ fn handle_new_file(window: WebviewWindow, repo_id: &str, path: PathBuf) {
 }
 
 pub fn unwatch_folder(
+    conn: &Connection,
     repo_id: String,
     folder_path: String,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -146,24 +329,24 @@ pub fn unwatch_folder(
         println!("No active watcher found for folder: {}", folder_path);
     }
 
-    // 2. Remove any files in this folder (and subfolders) from the database.
-    let files = db::get_files_in_repository(&repo_id)?;
+    // 2. Remove any files in this folder (and subfolders) from the database, all at once.
+    let files = db::get_files_in_repository(conn, &repo_id)?;
 
     let folder_path_normalized = folder_path.replace("\\", "/");
 
-    for file in files {
-        let file_path_normalized = file.path.replace("\\", "/");
-
-        if file_path_normalized.starts_with(&folder_path_normalized) {
-            println!(
-                "Deleting file '{}' from repository '{}'",
-                file.name, repo_id
-            );
-            db::delete_file(&repo_id, &file.id)?;
-        }
-    }
+    let file_ids: Vec<String> = files
+        .into_iter()
+        .filter(|file| {
+            file.path
+                .replace("\\", "/")
+                .starts_with(&folder_path_normalized)
+        })
+        .map(|file| file.id)
+        .collect();
+    let deleted = db::delete_files(conn, &repo_id, &file_ids)?;
+    println!("Deleted {} files from repository '{}'", deleted, repo_id);
 
-    db::delete_tracked_folder(&repo_id, &folder_path)
+    db::delete_tracked_folder(conn, &repo_id, &folder_path)
         .expect("Failed to remove tracked folder from database");
 
     println!(
@@ -177,17 +360,47 @@ pub fn unwatch_folder(
 #[tauri::command]
 pub async fn watch_folder_command(
     window: WebviewWindow,
+    pool: State<'_, DbPool>,
     repo_id: String,
     folder_path: String,
 ) -> Result<(), String> {
-    watch_folder(window, repo_id, folder_path)
-        .map_err(|e| format!("Failed to watch folder: {:?}", e))
+    let details = format!("Watch folder '{}'.", folder_path);
+    let result = watch_folder(window, pool.inner().clone(), repo_id.clone(), folder_path);
+    if let Ok(conn) = get_connection(pool.inner()) {
+        record_activity(
+            &conn,
+            ActivityActor::User,
+            "watch_folder",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &details,
+        );
+    }
+    result.map_err(|e| format!("Failed to watch folder: {:?}", e))
 }
 
 #[tauri::command]
-pub async fn unwatch_folder_command(repo_id: String, folder_path: String) -> Result<(), String> {
+pub async fn unwatch_folder_command(
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    folder_path: String,
+) -> Result<(), String> {
+    let pool = pool.inner().clone();
     tauri::async_runtime::spawn_blocking(move || {
-        unwatch_folder(repo_id, folder_path).map_err(|e| e.to_string())
+        let conn = get_connection(&pool)?;
+        let details = format!("Unwatch folder '{}'.", folder_path);
+        let result = unwatch_folder(&conn, repo_id.clone(), folder_path);
+        record_activity(
+            &conn,
+            ActivityActor::User,
+            "unwatch_folder",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &details,
+        );
+        result.map_err(|e| e.to_string())
     })
     .await
     .map_err(|e| format!("Failed to unwatch folder: {:?}", e))??;
@@ -195,8 +408,12 @@ pub async fn unwatch_folder_command(repo_id: String, folder_path: String) -> Res
 }
 
 #[tauri::command]
-pub async fn get_tracked_folders_command(repo_id: String) -> Result<Vec<String>, String> {
-    let folders = db::get_tracked_folders().map_err(|e| e.to_string())?;
+pub async fn get_tracked_folders_command(
+    pool: State<'_, DbPool>,
+    repo_id: String,
+) -> Result<Vec<String>, String> {
+    let conn = get_connection(&pool)?;
+    let folders = db::get_tracked_folders(&conn).map_err(|e| e.to_string())?;
 
     let filtered = folders
         .into_iter()
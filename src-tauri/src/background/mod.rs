@@ -1,3 +1,7 @@
+pub mod activity_pruner;
+pub mod backup_scheduler;
 pub mod folder_watcher;
+pub mod trash_purger;
+pub mod version_baseline;
 
 pub use folder_watcher::*;
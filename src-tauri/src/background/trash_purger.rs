@@ -0,0 +1,43 @@
+use crate::commands::activity::{record_activity, NO_FILES};
+use crate::commands::db::{get_connection, DbPool};
+use crate::commands::structures::ActivityActor;
+use crate::commands::trash::purge_expired_trash;
+use std::thread;
+use std::time::Duration;
+
+/// How often the purger looks for trashed items past the retention period.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts a background thread that deletes trashed items for good once they have been in
+/// the trash longer than `AppSettings.trash_retention_days`. The first check runs at
+/// startup; the retention is re-read on every check.
+pub fn start_trash_purger(pool: DbPool) {
+    thread::spawn(move || loop {
+        if let Err(e) = run_trash_purge(&pool) {
+            println!("Failed to purge the trash: {}", e);
+        }
+        thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+fn run_trash_purge(pool: &DbPool) -> Result<(), String> {
+    let conn = get_connection(pool)?;
+    let result = purge_expired_trash(&conn);
+    // Only purges that did something, or failed, are worth a log entry.
+    if !matches!(result, Ok(0)) {
+        let details = match &result {
+            Ok(count) => format!("Deleted {} expired items from the trash.", count),
+            Err(e) => format!("Failed to purge the trash: {}", e),
+        };
+        record_activity(
+            &conn,
+            ActivityActor::System,
+            "purge_trash",
+            None,
+            NO_FILES,
+            &result,
+            &details,
+        );
+    }
+    result.map(|_| ()).map_err(|e| e.to_string())
+}
@@ -0,0 +1,58 @@
+use crate::commands::activity::{record_activity, NO_FILES};
+use crate::commands::assets::fill_asset_hashes;
+use crate::commands::db::{get_connection, DbPool};
+use crate::commands::structures::ActivityActor;
+use crate::commands::versions::record_baseline_versions;
+use std::thread;
+use std::time::Duration;
+
+/// How often the task looks for files that have never been versioned.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Starts a background thread that gives every tracked file a first version, so its
+/// state when it joined the history can be restored. Libraries that predate version
+/// history are copied into the object store here, after startup, rather than while the
+/// window waits; files the thread couldn't read are retried on the next check. The same
+/// pass hashes the assets whose audio changed, which file writes leave unhashed.
+pub fn start_version_baseline(pool: DbPool) {
+    thread::spawn(move || loop {
+        if let Err(e) = run_version_baseline(&pool) {
+            println!("Failed to take baseline snapshots: {}", e);
+        }
+        if let Err(e) = run_asset_hashing(&pool) {
+            println!("Failed to hash assets: {}", e);
+        }
+        thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+fn run_asset_hashing(pool: &DbPool) -> Result<(), String> {
+    let conn = get_connection(pool)?;
+    let hashed = fill_asset_hashes(&conn).map_err(|e| e.to_string())?;
+    if hashed > 0 {
+        println!("Hashed the audio of {} assets.", hashed);
+    }
+    Ok(())
+}
+
+fn run_version_baseline(pool: &DbPool) -> Result<(), String> {
+    let conn = get_connection(pool)?;
+    let result = record_baseline_versions(&conn);
+    // Only passes that did something, or failed, are worth a log entry.
+    if !matches!(result, Ok(0)) {
+        let details = match &result {
+            Ok(count) => format!("Took a baseline snapshot of {} files.", count),
+            Err(e) => format!("Failed to take baseline snapshots: {}", e),
+        };
+        record_activity(
+            &conn,
+            ActivityActor::System,
+            "record_baseline_versions",
+            None,
+            NO_FILES,
+            &result,
+            &details,
+        );
+    }
+    result.map(|_| ()).map_err(|e| e.to_string())
+}
@@ -1,15 +1,29 @@
+use std::collections::HashSet;
 use std::error::Error;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use crate::commands::db::{get_files_in_repository, get_repositories, update_file};
-use crate::commands::file_ops::get_audio_metadata_from_file;
-use crate::commands::structures::FileMetadata;
-use tauri::{Emitter, Window};
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::audio_ops::CONVERSION_TEMP_SUFFIX;
+use crate::commands::db::{
+    get_connection, get_files_in_repository, get_repositories, get_tracked_folders, update_file,
+    DbPool,
+};
+use crate::commands::file_ops::{
+    get_audio_metadata_from_file, is_audio_file, read_date_modified, timestamps_match,
+};
+use crate::commands::structures::{FileMetadata, FileStatus, FileStatusEntry, RepositoryStatus};
+use crate::commands::versions::record_file_version;
+use rusqlite::Connection;
+use tauri::{Emitter, State, Window};
 
-/// Refreshes all file records for a single repository.
-pub fn refresh_files_in_repository(repo_id: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let files = get_files_in_repository(repo_id)?;
+/// Refreshes all file records for a single repository, returning how many rows changed.
+pub fn refresh_files_in_repository(
+    conn: &Connection,
+    repo_id: &str,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let files = get_files_in_repository(conn, repo_id)?;
+    let mut changed = 0;
     for file in files {
         let path = Path::new(&file.path);
 
@@ -18,75 +32,199 @@ pub fn refresh_files_in_repository(repo_id: &str) -> Result<(), Box<dyn Error +
             if updated_file.accessible {
                 println!("Marking '{}' as inaccessible", updated_file.name);
                 updated_file.accessible = false;
-                update_file(repo_id, &updated_file)?;
+                update_file(conn, repo_id, &updated_file)?;
+                changed += 1;
             }
             continue;
         }
 
-        let fs_metadata = fs::metadata(&file.path)?;
-        let new_date_modified = fs_metadata
-            .modified()
-            .map(|t| format!("{:?}", t))
-            .unwrap_or_default();
+        let new_date_modified = read_date_modified(&file.path)?;
 
-        if new_date_modified != file.date_modified {
+        if !timestamps_match(&new_date_modified, &file.date_modified) {
             // File changed! Reload full metadata
             let new_file_metadata = get_audio_metadata_from_file(&file.path)?;
             let updated_file = FileMetadata {
                 id: file.id.clone(),
                 tags: file.tags.clone(),
+                notes: file.notes.clone(),
                 audio_fingerprint: file.audio_fingerprint.clone(),
                 ..new_file_metadata
             };
             println!("Updating metadata for '{}'", updated_file.name);
-            update_file(repo_id, &updated_file)?;
+            update_file(conn, repo_id, &updated_file)?;
+            snapshot_file(conn, repo_id, &updated_file);
+            changed += 1;
         } else if !file.accessible {
             // File is back, but no change in date_modified - still needs marking as accessible!
             let mut updated_file = file.clone();
             updated_file.accessible = true;
             println!("Marking '{}' as accessible again", updated_file.name);
-            update_file(repo_id, &updated_file)?;
+            update_file(conn, repo_id, &updated_file)?;
+            snapshot_file(conn, repo_id, &updated_file);
+            changed += 1;
         }
+        // Files that have never been versioned get their first snapshot from the
+        // background baseline task, so a refresh never copies a whole library.
     }
 
-    Ok(())
+    Ok(changed)
 }
 
-/// Refreshes file records for ALL repositories in the database.
-pub fn refresh_files_in_all_repositories() -> Result<(), Box<dyn Error + Send + Sync>> {
-    let repos = get_repositories()?;
+/// Records a version of the file's current audio, logging (rather than failing) on error
+/// so one unreadable file doesn't abort the whole refresh.
+fn snapshot_file(conn: &Connection, repo_id: &str, file: &FileMetadata) {
+    if let Err(e) = record_file_version(conn, repo_id, file) {
+        println!("Failed to snapshot '{}': {}", file.name, e);
+    }
+}
+
+/// Refreshes file records for ALL repositories in the database, returning how many rows
+/// changed.
+pub fn refresh_files_in_all_repositories(
+    conn: &Connection,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let repos = get_repositories(conn)?;
+    let mut changed = 0;
     for repo in repos {
-        refresh_files_in_repository(&repo.id)?;
+        changed += refresh_files_in_repository(conn, &repo.id)?;
+    }
+    Ok(changed)
+}
+
+/// Recursively collects every audio file below `dir`, skipping unreadable entries and
+/// in-progress conversions.
+fn collect_audio_files(dir: &Path, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Failed to read folder {:?}: {}", dir, e);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_audio_files(&path, found);
+        } else if is_audio_file(&path) {
+            let is_temp = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .map(|stem| stem.ends_with(CONVERSION_TEMP_SUFFIX))
+                .unwrap_or(false);
+            if !is_temp {
+                found.push(path);
+            }
+        }
+    }
+}
+
+/// Spells a path the same way however its separators were written, so a row and the
+/// file found on disk for it compare equal.
+fn comparable_path(path: &str) -> String {
+    Path::new(&path.replace('\\', "/"))
+        .components()
+        .collect::<PathBuf>()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Compares a repository's files against the disk without changing anything,
+/// classifying each file the way `refresh_files_in_repository` would treat it.
+pub fn get_repository_status(
+    conn: &Connection,
+    repo_id: &str,
+) -> Result<RepositoryStatus, Box<dyn Error + Send + Sync>> {
+    let files = get_files_in_repository(conn, repo_id)?;
+    let tracked_folders: Vec<String> = get_tracked_folders(conn)?
+        .into_iter()
+        .filter(|f| f.repo_id == repo_id)
+        .map(|f| f.folder_path)
+        .collect();
+
+    let mut entries = Vec::new();
+    let mut known_paths = HashSet::new();
+
+    for file in files {
+        known_paths.insert(comparable_path(&file.path));
+
+        let status = match read_date_modified(&file.path) {
+            Err(_) if !Path::new(&file.path).exists() => FileStatus::Missing,
+            // There, but unreadable (or replaced since): it no longer matches its row.
+            Err(_) => FileStatus::Modified,
+            Ok(on_disk) if !timestamps_match(&on_disk, &file.date_modified) || !file.accessible => {
+                FileStatus::Modified
+            }
+            Ok(_) => FileStatus::Unchanged,
+        };
+
+        entries.push(FileStatusEntry {
+            file_id: Some(file.id),
+            name: file.name,
+            path: file.path,
+            status,
+        });
     }
-    Ok(())
+
+    for folder in &tracked_folders {
+        let mut found = Vec::new();
+        collect_audio_files(Path::new(folder), &mut found);
+        for path in found {
+            let path_str = path.to_string_lossy().to_string();
+            if known_paths.insert(comparable_path(&path_str)) {
+                entries.push(FileStatusEntry {
+                    file_id: None,
+                    name: path
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("")
+                        .to_string(),
+                    path: path_str,
+                    status: FileStatus::Untracked,
+                });
+            }
+        }
+    }
+
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let count = |status: FileStatus| entries.iter().filter(|e| e.status == status).count();
+
+    Ok(RepositoryStatus {
+        repo_id: repo_id.to_string(),
+        tracked_folders,
+        unchanged: count(FileStatus::Unchanged),
+        modified: count(FileStatus::Modified),
+        missing: count(FileStatus::Missing),
+        untracked: count(FileStatus::Untracked),
+        files: entries,
+    })
 }
 
 #[tauri::command]
 pub async fn refresh_files_in_repository_command(
     window: Window,
+    pool: State<'_, DbPool>,
     repo_id: String,
 ) -> Result<(), String> {
     let emit_window = window.clone(); // clone for later use in the spawn_blocking
+    let pool = pool.inner().clone();
     tauri::async_runtime::spawn_blocking(move || {
-        let result = refresh_files_in_repository(&repo_id);
+        let conn = get_connection(&pool)?;
+        let result = refresh_files_in_repository(&conn, &repo_id).map(|_| ());
 
         // Emit an event based on result
         let event_payload = match &result {
             Ok(_) => format!("Repository {} refreshed successfully!", repo_id),
             Err(e) => format!("Failed to refresh repository {}: {}", repo_id, e),
         };
-
-        emit_window
-            .emit(
-                "refresh_files_in_repository_completed", // <-- Event name
-                event_payload,
-            )
-            .unwrap_or_else(|e| {
-                println!(
-                    "Failed to emit refresh_files_in_repository_completed event: {}",
-                    e
-                );
-            });
+        finish_command(
+            &conn,
+            &emit_window,
+            "refresh_files_in_repository",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &event_payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -95,30 +233,151 @@ pub async fn refresh_files_in_repository_command(
 }
 
 #[tauri::command]
-pub async fn refresh_files_in_all_repositories_command(window: Window) -> Result<(), String> {
+pub async fn refresh_files_in_all_repositories_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+) -> Result<(), String> {
     let emit_window = window.clone(); // clone for later use in the spawn_blocking
+    let pool = pool.inner().clone();
     tauri::async_runtime::spawn_blocking(move || {
-        let result = refresh_files_in_all_repositories();
+        let conn = get_connection(&pool)?;
+        let result = refresh_files_in_all_repositories(&conn).map(|_| ());
 
         let event_payload = match &result {
             Ok(_) => "All repositories refreshed successfully!".to_string(),
             Err(e) => format!("Failed to refresh all repositories: {}", e),
         };
+        finish_command(
+            &conn,
+            &emit_window,
+            "refresh_files_in_all_repositories",
+            None,
+            NO_FILES,
+            &result,
+            &event_payload,
+        );
 
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_repository_status_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+) -> Result<RepositoryStatus, String> {
+    let emit_window = window.clone();
+    let repo_id_for_status = repo_id.clone();
+    let pool = pool.inner().clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_repository_status(&conn, &repo_id_for_status).map_err(|e| e.to_string())
+    })
+    .await;
+
+    if let Ok(Ok(status)) = &result {
         emit_window
             .emit(
-                "refresh_files_in_all_repositories_completed", // <-- Event name
-                event_payload,
+                "get_repository_status_completed",
+                format!(
+                    "Repository '{}': {} modified, {} missing, {} untracked.",
+                    repo_id, status.modified, status.missing, status.untracked
+                ),
             )
             .unwrap_or_else(|e| {
                 println!(
-                    "Failed to emit refresh_files_in_all_repositories_completed event: {}",
+                    "Failed to emit get_repository_status_completed event: {}",
                     e
-                );
+                )
             });
+    }
 
-        result.map_err(|e| e.to_string())
-    })
-    .await
-    .map_err(|e| e.to_string())?
+    result.map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{create_file, create_tracked_folder};
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+    use std::time::{Duration, SystemTime};
+
+    fn statuses(status: &RepositoryStatus) -> Vec<(String, FileStatus)> {
+        status
+            .files
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.status))
+            .collect()
+    }
+
+    #[test]
+    fn files_are_classified_against_the_disk() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let dir = scratch_dir();
+        create_tracked_folder(&conn, "repo", &dir.to_string_lossy()).unwrap();
+        for name in ["a_unchanged.wav", "b_modified.wav", "c_missing.wav"] {
+            create_file(&conn, "repo", &sample_file(&dir, name, b"audio")).unwrap();
+        }
+        let modified = dir.join("b_modified.wav");
+        fs::write(&modified, b"louder").unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&modified)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        fs::remove_file(dir.join("c_missing.wav")).unwrap();
+        fs::write(dir.join("d_untracked.wav"), b"new").unwrap();
+        fs::write(dir.join("notes.txt"), b"not audio").unwrap();
+
+        let status = get_repository_status(&conn, "repo").unwrap();
+        assert_eq!(
+            statuses(&status),
+            vec![
+                ("a_unchanged.wav".to_string(), FileStatus::Unchanged),
+                ("b_modified.wav".to_string(), FileStatus::Modified),
+                ("c_missing.wav".to_string(), FileStatus::Missing),
+                ("d_untracked.wav".to_string(), FileStatus::Untracked),
+            ]
+        );
+        assert_eq!(
+            (
+                status.unchanged,
+                status.modified,
+                status.missing,
+                status.untracked
+            ),
+            (1, 1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn differently_written_paths_are_the_same_file() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let dir = scratch_dir();
+        create_tracked_folder(&conn, "repo", &format!("{}/", dir.to_string_lossy())).unwrap();
+        let mut file = sample_file(&dir, "take.wav", b"audio");
+        file.path = format!("{}//./take.wav", dir.to_string_lossy());
+        create_file(&conn, "repo", &file).unwrap();
+
+        let status = get_repository_status(&conn, "repo").unwrap();
+        assert_eq!(
+            statuses(&status),
+            vec![("take.wav".to_string(), FileStatus::Unchanged)]
+        );
+    }
+
+    #[test]
+    fn separators_are_unified() {
+        assert_eq!(comparable_path("C:\\Music\\take.wav"), "C:/Music/take.wav");
+        assert_eq!(comparable_path("/music//./take.wav"), "/music/take.wav");
+    }
 }
@@ -0,0 +1,396 @@
+// src/commands/activity.rs
+//! Persistent, append-only log of backend operations.
+//!
+//! Every command that changes something records one entry after it runs, successful or
+//! not, alongside the event it emits; the folder watcher records the changes it makes
+//! with the `watcher` actor. Reads aren't logged. Entries are never updated, and they
+//! keep their repository and file ids after those are deleted, so the log can still
+//! answer "who converted this file and when". They are dropped once they are older than
+//! `ACTIVITY_RETENTION_DAYS`, or beyond the newest `MAX_ACTIVITY_ENTRIES`.
+use crate::commands::db::{get_connection, DbPool};
+use crate::commands::structures::{ActivityActor, ActivityEntry, ActivityFilter, ActivityOutcome};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, params_from_iter, types::Value, Connection, Result, Row};
+use std::fmt::Display;
+use tauri::{Emitter, State, Window};
+
+/// Pass as `file_ids` for operations that don't concern particular files.
+pub const NO_FILES: &[&str] = &[];
+
+/// How many entries a query returns when no limit is given.
+const DEFAULT_ACTIVITY_LIMIT: u32 = 200;
+const MAX_ACTIVITY_LIMIT: u32 = 5000;
+
+/// How long entries are kept.
+const ACTIVITY_RETENTION_DAYS: i64 = 365;
+/// The most entries kept; older ones beyond it are dropped.
+const MAX_ACTIVITY_ENTRIES: i64 = 100_000;
+
+impl ActivityActor {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ActivityActor::User => "user",
+            ActivityActor::Watcher => "watcher",
+            ActivityActor::System => "system",
+        }
+    }
+
+    fn parse(value: &str) -> ActivityActor {
+        match value {
+            "watcher" => ActivityActor::Watcher,
+            "system" => ActivityActor::System,
+            _ => ActivityActor::User,
+        }
+    }
+}
+
+/// Appends an entry for an operation that just ran. `details` is the message shown to the
+/// user. Failing to write the log is reported but never fails the operation itself.
+pub fn record_activity<T, E: Display, S: AsRef<str>>(
+    conn: &Connection,
+    actor: ActivityActor,
+    operation: &str,
+    repo_id: Option<&str>,
+    file_ids: &[S],
+    result: &std::result::Result<T, E>,
+    details: &str,
+) {
+    if let Err(e) = insert_activity(conn, actor, operation, repo_id, file_ids, result, details) {
+        println!(
+            "Failed to record '{}' in the activity log: {}",
+            operation, e
+        );
+    }
+}
+
+/// Finishes a command the user ran: records it in the activity log, then emits
+/// `<operation>_completed` with the same message for the UI.
+pub fn finish_command<T, E: Display, S: AsRef<str>>(
+    conn: &Connection,
+    window: &Window,
+    operation: &str,
+    repo_id: Option<&str>,
+    file_ids: &[S],
+    result: &std::result::Result<T, E>,
+    payload: &str,
+) {
+    record_activity(
+        conn,
+        ActivityActor::User,
+        operation,
+        repo_id,
+        file_ids,
+        result,
+        payload,
+    );
+    let event = format!("{}_completed", operation);
+    window.emit(&event, payload).unwrap_or_else(|e| {
+        println!("Failed to emit {} event: {}", event, e);
+    });
+}
+
+fn insert_activity<T, E: Display, S: AsRef<str>>(
+    conn: &Connection,
+    actor: ActivityActor,
+    operation: &str,
+    repo_id: Option<&str>,
+    file_ids: &[S],
+    result: &std::result::Result<T, E>,
+    details: &str,
+) -> Result<()> {
+    let now = Utc::now();
+    let (outcome, error) = match result {
+        Ok(_) => ("success", None),
+        Err(e) => ("failure", Some(e.to_string())),
+    };
+
+    conn.prepare_cached(
+        "INSERT INTO ActivityLog (
+            timestamp, timestamp_ms, operation, actor, repo_id, outcome, error, details
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?
+    .execute(params![
+        now.to_rfc3339_opts(SecondsFormat::Millis, true),
+        now.timestamp_millis(),
+        operation,
+        actor.as_str(),
+        repo_id,
+        outcome,
+        error,
+        details
+    ])?;
+    let activity_id = conn.last_insert_rowid();
+
+    let mut link = conn.prepare_cached(
+        "INSERT OR IGNORE INTO ActivityLogFiles (activity_id, file_id) VALUES (?1, ?2)",
+    )?;
+    for file_id in file_ids {
+        link.execute(params![activity_id, file_id.as_ref()])?;
+    }
+    Ok(())
+}
+
+fn entry_from_row(row: &Row) -> Result<ActivityEntry> {
+    let actor: String = row.get(3)?;
+    let outcome: String = row.get(5)?;
+    let file_ids: Option<String> = row.get(8)?;
+    Ok(ActivityEntry {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        operation: row.get(2)?,
+        actor: ActivityActor::parse(&actor),
+        repo_id: row.get(4)?,
+        outcome: if outcome == "success" {
+            ActivityOutcome::Success
+        } else {
+            ActivityOutcome::Failure
+        },
+        error: row.get(6)?,
+        details: row.get(7)?,
+        file_ids: file_ids
+            .map(|ids| ids.split(',').map(str::to_string).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// Parses an RFC 3339 bound from a filter into milliseconds.
+fn parse_bound(value: &str) -> Result<i64> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.timestamp_millis())
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Returns log entries matching the filter, newest first.
+pub fn get_activity_log(conn: &Connection, filter: &ActivityFilter) -> Result<Vec<ActivityEntry>> {
+    let mut conditions: Vec<String> = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+
+    if let Some(repo_id) = &filter.repo_id {
+        conditions.push("a.repo_id = ?".to_string());
+        values.push(Value::Text(repo_id.clone()));
+    }
+    if let Some(file_id) = &filter.file_id {
+        conditions.push(
+            "a.id IN (SELECT activity_id FROM ActivityLogFiles WHERE file_id = ?)".to_string(),
+        );
+        values.push(Value::Text(file_id.clone()));
+    }
+    if let Some(operation) = &filter.operation {
+        conditions.push("a.operation = ?".to_string());
+        values.push(Value::Text(operation.clone()));
+    }
+    if let Some(actor) = &filter.actor {
+        conditions.push("a.actor = ?".to_string());
+        values.push(Value::Text(actor.as_str().to_string()));
+    }
+    if let Some(since) = &filter.since {
+        conditions.push("a.timestamp_ms >= ?".to_string());
+        values.push(Value::Integer(parse_bound(since)?));
+    }
+    if let Some(until) = &filter.until {
+        conditions.push("a.timestamp_ms <= ?".to_string());
+        values.push(Value::Integer(parse_bound(until)?));
+    }
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!("WHERE {}", conditions.join(" AND "))
+    };
+
+    let limit = filter
+        .limit
+        .unwrap_or(DEFAULT_ACTIVITY_LIMIT)
+        .clamp(1, MAX_ACTIVITY_LIMIT);
+    values.push(Value::Integer(limit as i64));
+    values.push(Value::Integer(filter.offset as i64));
+
+    let sql = format!(
+        "SELECT a.id, a.timestamp, a.operation, a.actor, a.repo_id, a.outcome, a.error, a.details,
+                (SELECT group_concat(file_id) FROM ActivityLogFiles WHERE activity_id = a.id)
+         FROM ActivityLog a
+         {}
+         ORDER BY a.timestamp_ms DESC, a.id DESC
+         LIMIT ? OFFSET ?",
+        where_clause
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let entries = stmt
+        .query_map(params_from_iter(values), entry_from_row)?
+        .collect::<Result<Vec<ActivityEntry>>>()?;
+    Ok(entries)
+}
+
+/// Drops entries past the retention period and beyond the entry cap, returning how many
+/// were removed. Their file links go with them.
+pub fn prune_activity_log(conn: &Connection) -> Result<usize> {
+    let cutoff = Utc::now() - chrono::Duration::days(ACTIVITY_RETENTION_DAYS);
+    delete_activity(conn, cutoff.timestamp_millis(), MAX_ACTIVITY_ENTRIES)
+}
+
+/// Deletes entries older than `before_ms`, and all but the newest `keep` of the rest.
+fn delete_activity(conn: &Connection, before_ms: i64, keep: i64) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM ActivityLog
+         WHERE timestamp_ms < ?1
+            OR id <= (SELECT id FROM ActivityLog ORDER BY id DESC LIMIT 1 OFFSET ?2)",
+        params![before_ms, keep],
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_activity_log_command(
+    pool: State<'_, DbPool>,
+    filter: ActivityFilter,
+) -> Result<Vec<ActivityEntry>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_activity_log(&conn, &filter).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::test_connection;
+
+    fn record(conn: &Connection, operation: &str, repo_id: Option<&str>, file_ids: &[&str]) {
+        let result: std::result::Result<(), String> = Ok(());
+        record_activity(
+            conn,
+            ActivityActor::User,
+            operation,
+            repo_id,
+            file_ids,
+            &result,
+            operation,
+        );
+    }
+
+    /// Moves an entry's timestamp back by `days`.
+    fn backdate(conn: &Connection, operation: &str, days: i64) {
+        let then = Utc::now() - chrono::Duration::days(days);
+        conn.execute(
+            "UPDATE ActivityLog SET timestamp = ?1, timestamp_ms = ?2 WHERE operation = ?3",
+            params![
+                then.to_rfc3339_opts(SecondsFormat::Millis, true),
+                then.timestamp_millis(),
+                operation
+            ],
+        )
+        .unwrap();
+    }
+
+    fn operations(conn: &Connection, filter: ActivityFilter) -> Vec<String> {
+        get_activity_log(conn, &filter)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.operation)
+            .collect()
+    }
+
+    #[test]
+    fn failures_are_recorded_with_their_error_and_files() {
+        let conn = test_connection();
+        let result: std::result::Result<(), String> = Err("disk full".to_string());
+        record_activity(
+            &conn,
+            ActivityActor::Watcher,
+            "add_file",
+            Some("repo"),
+            &["a", "b"],
+            &result,
+            "New file found.",
+        );
+
+        let entries = get_activity_log(&conn, &ActivityFilter::default()).unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.operation, "add_file");
+        assert!(matches!(entry.actor, ActivityActor::Watcher));
+        assert!(matches!(entry.outcome, ActivityOutcome::Failure));
+        assert_eq!(entry.error.as_deref(), Some("disk full"));
+        assert_eq!(entry.details.as_deref(), Some("New file found."));
+        assert_eq!(entry.repo_id.as_deref(), Some("repo"));
+        let mut file_ids = entry.file_ids.clone();
+        file_ids.sort();
+        assert_eq!(file_ids, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn entries_filter_by_repository_file_and_time() {
+        let conn = test_connection();
+        record(&conn, "old", Some("one"), &["a"]);
+        record(&conn, "middle", Some("two"), &["a", "b"]);
+        record(&conn, "new", Some("one"), &["b"]);
+        backdate(&conn, "old", 10);
+        backdate(&conn, "middle", 5);
+
+        assert_eq!(
+            operations(&conn, ActivityFilter::default()),
+            vec!["new", "middle", "old"]
+        );
+        let repo = ActivityFilter {
+            repo_id: Some("one".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(operations(&conn, repo), vec!["new", "old"]);
+        let file = ActivityFilter {
+            file_id: Some("a".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(operations(&conn, file), vec!["middle", "old"]);
+
+        let days_ago = |days| {
+            (Utc::now() - chrono::Duration::days(days)).to_rfc3339_opts(SecondsFormat::Millis, true)
+        };
+        let since = ActivityFilter {
+            since: Some(days_ago(7)),
+            ..Default::default()
+        };
+        assert_eq!(operations(&conn, since), vec!["new", "middle"]);
+        let window = ActivityFilter {
+            since: Some(days_ago(7)),
+            until: Some(days_ago(1)),
+            file_id: Some("b".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(operations(&conn, window), vec!["middle"]);
+        let invalid = ActivityFilter {
+            since: Some("last week".to_string()),
+            ..Default::default()
+        };
+        assert!(get_activity_log(&conn, &invalid).is_err());
+    }
+
+    #[test]
+    fn pruning_drops_expired_entries_and_those_past_the_cap() {
+        let conn = test_connection();
+        record(&conn, "expired", None, &["a"]);
+        record(&conn, "oldest", None, NO_FILES);
+        record(&conn, "older", None, NO_FILES);
+        record(&conn, "newest", None, NO_FILES);
+        backdate(&conn, "expired", ACTIVITY_RETENTION_DAYS + 1);
+
+        assert_eq!(prune_activity_log(&conn).unwrap(), 1);
+        let cutoff =
+            (Utc::now() - chrono::Duration::days(ACTIVITY_RETENTION_DAYS)).timestamp_millis();
+        assert_eq!(delete_activity(&conn, cutoff, 2).unwrap(), 1);
+        assert_eq!(
+            operations(&conn, ActivityFilter::default()),
+            vec!["newest", "older"]
+        );
+        let links: i64 = conn
+            .query_row("SELECT COUNT(*) FROM ActivityLogFiles", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(links, 0);
+    }
+}
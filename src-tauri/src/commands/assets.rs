@@ -0,0 +1,432 @@
+// src/commands/assets.rs
+//! Library-wide identity for the files on disk.
+//!
+//! A file can be tracked by any number of repositories. Each repository has its own row
+//! in Files, with its own id, tags, notes and relations, but every row for a path points
+//! at the same asset in the Assets table. The asset holds what belongs to the file itself:
+//! its embedded metadata, audio properties, fingerprint and content hash. Whenever a row
+//! is written, its shared columns become the asset's and are copied to the asset's other
+//! rows, so an edit or a fingerprint made in one repository shows up in all of them.
+//! Fingerprints are also reused between assets with identical audio.
+use std::error::Error;
+use std::path::Path;
+
+use crate::commands::activity::finish_command;
+use crate::commands::collections::refresh_file_collections;
+use crate::commands::db::{
+    create_file, get_connection, get_file, get_repository, repository_file_from_row, DbPool,
+    FILE_COLUMNS,
+};
+use crate::commands::file_ops::{read_date_modified, timestamps_match};
+use crate::commands::search::index_file;
+use crate::commands::structures::{Asset, FileMetadata, RepositoryFile};
+use crate::commands::versions::{hash_file, record_file_version};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{State, Window};
+use uuid::Uuid;
+
+/// The columns an asset shares with its file rows.
+const SHARED_COLUMNS: &str = "name, encoding, date_created, date_modified, audio_fingerprint,
+    accessible, meta_title, meta_comment, meta_album_artist, meta_album, meta_track_number,
+    meta_genre, meta_bit_rate, meta_channels, meta_sample_rate, meta_size_on_disk, meta_bpm,
+    meta_key, date_created_ms, date_modified_ms";
+
+/// Points a file row at the asset for its path, creating the asset if there is none,
+/// then makes the row's shared columns the asset's and copies them to its other rows,
+/// recording each other row's previous state in its history first. A row joining an
+/// existing asset first takes over the asset's fingerprint, as long as both have seen the
+/// same version of the audio. The audio itself is never read here, since this runs inside
+/// the callers' transactions: a content hash the change made stale is cleared, and
+/// `fill_asset_hashes` computes it again later.
+pub fn sync_file_asset(conn: &Connection, file_id: &str) -> Result<()> {
+    let (path, previous_asset_id, date_modified_ms): (String, Option<String>, Option<i64>) = conn
+        .prepare_cached("SELECT path, asset_id, date_modified_ms FROM Files WHERE id = ?1")?
+        .query_row(params![file_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+
+    let existing: Option<(String, Option<String>, Option<i64>)> = conn
+        .prepare_cached("SELECT id, content_hash, date_modified_ms FROM Assets WHERE path = ?1")?
+        .query_row(params![path], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .optional()?;
+    let (asset_id, content_hash) = match existing {
+        Some((asset_id, content_hash, asset_modified_ms)) => {
+            let unchanged = asset_modified_ms == date_modified_ms;
+            if unchanged && previous_asset_id.as_deref() != Some(asset_id.as_str()) {
+                conn.prepare_cached(
+                    "UPDATE Files SET audio_fingerprint = COALESCE(
+                        audio_fingerprint, (SELECT audio_fingerprint FROM Assets WHERE id = ?1)
+                    ) WHERE id = ?2",
+                )?
+                .execute(params![asset_id, file_id])?;
+            }
+            // The stored hash only holds while the audio hasn't changed.
+            (asset_id, if unchanged { content_hash } else { None })
+        }
+        None => {
+            let asset_id = Uuid::new_v4().to_string();
+            conn.execute(
+                &format!(
+                    "INSERT INTO Assets (id, path, {cols})
+                     SELECT ?1, path, {cols} FROM Files WHERE id = ?2",
+                    cols = SHARED_COLUMNS
+                ),
+                params![asset_id, file_id],
+            )?;
+            (asset_id, None)
+        }
+    };
+
+    conn.execute(
+        &format!(
+            "UPDATE Assets SET ({cols}) = (SELECT {cols} FROM Files WHERE id = ?1),
+                content_hash = ?2
+             WHERE id = ?3",
+            cols = SHARED_COLUMNS
+        ),
+        params![file_id, content_hash, asset_id],
+    )?;
+    conn.prepare_cached("UPDATE Files SET asset_id = ?1 WHERE id = ?2")?
+        .execute(params![asset_id, file_id])?;
+
+    let changed_siblings: Vec<RepositoryFile> = {
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {}, f.repo_id FROM Files f
+             WHERE f.asset_id = ?1 AND f.id != ?2 AND f.accessible
+               AND ({cols}) IS NOT (SELECT {cols} FROM Assets WHERE id = ?1)",
+            FILE_COLUMNS,
+            cols = SHARED_COLUMNS
+        ))?;
+        let rows = stmt
+            .query_map(params![asset_id, file_id], repository_file_from_row)?
+            .collect::<Result<Vec<RepositoryFile>>>()?;
+        rows
+    };
+    for sibling in changed_siblings {
+        record_file_version(conn, &sibling.repo_id, &sibling.file)
+            .map_err(rusqlite::Error::ToSqlConversionFailure)?;
+    }
+
+    let copied = conn.execute(
+        &format!(
+            "UPDATE Files SET ({cols}) = (SELECT {cols} FROM Assets WHERE id = ?1)
+             WHERE asset_id = ?1 AND id != ?2",
+            cols = SHARED_COLUMNS
+        ),
+        params![asset_id, file_id],
+    )?;
+    if copied > 0 {
+        // Keep search and smart collections in step with the rows just changed.
+        let siblings: Vec<RepositoryFile> = {
+            let mut stmt = conn.prepare_cached(&format!(
+                "SELECT {}, f.repo_id FROM Files f
+                 WHERE f.asset_id = ?1 AND f.id != ?2 AND f.deleted_at IS NULL",
+                FILE_COLUMNS
+            ))?;
+            let rows = stmt
+                .query_map(params![asset_id, file_id], repository_file_from_row)?
+                .collect::<Result<Vec<RepositoryFile>>>()?;
+            rows
+        };
+        for sibling in siblings {
+            index_file(conn, &sibling.file)?;
+            refresh_file_collections(conn, &sibling.repo_id, &sibling.file.id)?;
+        }
+    }
+
+    if let Some(previous_asset_id) = previous_asset_id {
+        if previous_asset_id != asset_id {
+            conn.prepare_cached(
+                "DELETE FROM Assets
+                 WHERE id = ?1 AND NOT EXISTS (SELECT 1 FROM Files WHERE asset_id = ?1)",
+            )?
+            .execute(params![previous_asset_id])?;
+        }
+    }
+    Ok(())
+}
+
+/// Computes the content hash of every asset that lacks one, reading the audio outside
+/// any transaction. A hash is only stored if the file wasn't changed while it was read.
+/// Returns how many assets were hashed.
+pub fn fill_asset_hashes(
+    conn: &Connection,
+) -> std::result::Result<usize, Box<dyn Error + Send + Sync>> {
+    let unhashed: Vec<(String, String, String, Option<i64>)> = {
+        let mut stmt = conn.prepare(
+            "SELECT id, path, date_modified, date_modified_ms FROM Assets
+             WHERE content_hash IS NULL AND accessible",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        rows
+    };
+    let mut hashed = 0;
+    for (asset_id, path, date_modified, date_modified_ms) in unhashed {
+        // Audio that changed since the asset was written is hashed once its row catches up.
+        let unchanged = read_date_modified(&path)
+            .map(|on_disk| timestamps_match(&on_disk, &date_modified))
+            .unwrap_or(false);
+        if !unchanged {
+            continue;
+        }
+        let content_hash = match hash_file(Path::new(&path)) {
+            Ok(content_hash) => content_hash,
+            Err(e) => {
+                println!("Failed to hash '{}': {}", path, e);
+                continue;
+            }
+        };
+        hashed += conn
+            .prepare_cached(
+                "UPDATE Assets SET content_hash = ?1
+                 WHERE id = ?2 AND content_hash IS NULL AND date_modified_ms IS ?3",
+            )?
+            .execute(params![content_hash, asset_id, date_modified_ms])?;
+    }
+    Ok(hashed)
+}
+
+/// Deletes assets that no file row refers to anymore.
+pub fn remove_unused_assets(conn: &Connection) -> Result<usize> {
+    conn.execute(
+        "DELETE FROM Assets
+         WHERE NOT EXISTS (SELECT 1 FROM Files f WHERE f.asset_id = Assets.id)",
+        [],
+    )
+}
+
+/// Returns a fingerprint already computed for audio identical to the file at `path`,
+/// if there is one.
+pub fn shared_fingerprint(
+    conn: &Connection,
+    path: &str,
+) -> std::result::Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let content_hash = hash_file(Path::new(path))?;
+    let fingerprint = conn
+        .prepare_cached(
+            "SELECT audio_fingerprint FROM Assets
+             WHERE content_hash = ?1 AND COALESCE(audio_fingerprint, '') != ''
+             LIMIT 1",
+        )?
+        .query_row(params![content_hash], |row| row.get(0))
+        .optional()?;
+    Ok(fingerprint)
+}
+
+/// Returns the asset behind a file row, with every live row that refers to it.
+pub fn get_asset(conn: &Connection, file_id: &str) -> Result<Asset> {
+    let (id, path, content_hash): (String, String, Option<String>) = conn.query_row(
+        "SELECT a.id, a.path, a.content_hash FROM Assets a
+         JOIN Files f ON f.asset_id = a.id
+         WHERE f.id = ?1",
+        params![file_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, f.repo_id FROM Files f
+         WHERE f.asset_id = ?1 AND f.deleted_at IS NULL
+         ORDER BY f.repo_id",
+        FILE_COLUMNS
+    ))?;
+    let files = stmt
+        .query_map(params![id], repository_file_from_row)?
+        .collect::<Result<Vec<RepositoryFile>>>()?;
+    Ok(Asset {
+        id,
+        path,
+        content_hash,
+        files,
+    })
+}
+
+/// Adds a file that is tracked elsewhere to another repository. The new row shares the
+/// file's asset but starts without tags, notes or relations of its own.
+pub fn add_file_to_repository(
+    conn: &Connection,
+    file_id: &str,
+    repo_id: &str,
+) -> std::result::Result<FileMetadata, Box<dyn Error + Send + Sync>> {
+    let source = conn.query_row(
+        &format!(
+            "SELECT {}, f.repo_id FROM Files f WHERE f.id = ?1 AND f.deleted_at IS NULL",
+            FILE_COLUMNS
+        ),
+        params![file_id],
+        repository_file_from_row,
+    )?;
+    get_repository(conn, repo_id)?;
+    let taken: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM Files WHERE repo_id = ?1 AND path = ?2)",
+        params![repo_id, source.file.path],
+        |row| row.get(0),
+    )?;
+    if taken {
+        return Err(format!("'{}' is already in the repository.", source.file.path).into());
+    }
+
+    let file = FileMetadata {
+        id: Uuid::new_v4().to_string(),
+        related_files: None,
+        tags: None,
+        notes: None,
+        ..source.file
+    };
+    create_file(conn, repo_id, &file)?;
+    Ok(get_file(conn, repo_id, &file.id)?)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_asset_command(pool: State<'_, DbPool>, file_id: String) -> Result<Asset, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_asset(&conn, &file_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn add_file_to_repository_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    file_id: String,
+    repo_id: String,
+) -> Result<FileMetadata, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = add_file_to_repository(&conn, &file_id, &repo_id);
+
+        let payload = match &result {
+            Ok(file) => format!("File '{}' added to repo '{}'.", file.name, repo_id),
+            Err(e) => format!(
+                "Failed to add file '{}' to repo '{}': {}",
+                file_id, repo_id, e
+            ),
+        };
+        let file_ids = match &result {
+            Ok(file) => vec![file_id.clone(), file.id.clone()],
+            Err(_) => vec![file_id.clone()],
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "add_file_to_repository",
+            Some(&repo_id),
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{purge_file, update_file};
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+    use crate::commands::versions::get_file_versions;
+
+    /// Tracks one sample file in "one" and "two", returning both rows.
+    fn shared_file(conn: &Connection) -> (FileMetadata, FileMetadata) {
+        add_repository(conn, "one");
+        add_repository(conn, "two");
+        let file = sample_file(&scratch_dir(), "take.wav", b"take");
+        create_file(conn, "one", &file).unwrap();
+        let other = add_file_to_repository(conn, &file.id, "two").unwrap();
+        (get_file(conn, "one", &file.id).unwrap(), other)
+    }
+
+    fn asset_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM Assets", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn shared_metadata_reaches_every_repository_and_keeps_their_history() {
+        let conn = test_connection();
+        let (file, other) = shared_file(&conn);
+        assert_eq!(get_asset(&conn, &file.id).unwrap().files.len(), 2);
+
+        for title in ["First", "Second"] {
+            let retitled = FileMetadata {
+                meta_title: Some(title.to_string()),
+                ..get_file(&conn, "one", &file.id).unwrap()
+            };
+            update_file(&conn, "one", &retitled).unwrap();
+        }
+
+        let other_now = get_file(&conn, "two", &other.id).unwrap();
+        assert_eq!(other_now.meta_title.as_deref(), Some("Second"));
+        let versions = get_file_versions(&conn, &other.id).unwrap();
+        assert!(versions
+            .iter()
+            .any(|v| v.metadata.meta_title.as_deref() == Some("First")));
+    }
+
+    #[test]
+    fn tags_and_notes_stay_with_their_repository() {
+        let conn = test_connection();
+        let (file, other) = shared_file(&conn);
+
+        let described = FileMetadata {
+            tags: Some("drums".to_string()),
+            notes: Some("keeper".to_string()),
+            ..file.clone()
+        };
+        update_file(&conn, "one", &described).unwrap();
+
+        let other_now = get_file(&conn, "two", &other.id).unwrap();
+        assert_eq!(other_now.tags, None);
+        assert_eq!(other_now.notes, None);
+        assert_eq!(
+            get_file(&conn, "one", &file.id).unwrap().notes.as_deref(),
+            Some("keeper")
+        );
+    }
+
+    #[test]
+    fn an_asset_goes_with_its_last_row() {
+        let conn = test_connection();
+        let (file, other) = shared_file(&conn);
+        assert_eq!(asset_count(&conn), 1);
+
+        purge_file(&conn, "one", &file.id).unwrap();
+        assert_eq!(asset_count(&conn), 1);
+        purge_file(&conn, "two", &other.id).unwrap();
+        assert_eq!(asset_count(&conn), 0);
+    }
+
+    #[test]
+    fn hashes_are_filled_in_outside_the_writes() {
+        let conn = test_connection();
+        let (file, _) = shared_file(&conn);
+        assert_eq!(get_asset(&conn, &file.id).unwrap().content_hash, None);
+
+        assert_eq!(fill_asset_hashes(&conn).unwrap(), 1);
+        assert_eq!(
+            get_asset(&conn, &file.id).unwrap().content_hash,
+            Some(hash_file(Path::new(&file.path)).unwrap())
+        );
+        assert_eq!(fill_asset_hashes(&conn).unwrap(), 0);
+    }
+}
@@ -1,7 +1,9 @@
 use std::error::Error;
 use std::fs::File;
 use std::path::Path;
-use crate::commands::structures::FileMetadata;
+use crate::commands::activity::{finish_command, record_activity};
+use crate::commands::assets::shared_fingerprint;
+use crate::commands::structures::{ActivityActor, FileMetadata, FileRelationKind};
 use rusty_chromaprint::Configuration;
 use rusty_chromaprint::Fingerprinter;
 use symphonia::core::audio::SampleBuffer;
@@ -11,22 +13,43 @@ use symphonia::core::formats::FormatOptions;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::meta::MetadataOptions;
 use symphonia::default::{get_codecs, get_probe};
-use crate::commands::db::update_file;
+use crate::commands::db::{get_connection, update_file, DbPool};
+use rusqlite::{Connection, OptionalExtension};
 
-use tauri::{Emitter, Window, AppHandle};
+use tauri::{Emitter, Window, AppHandle, State};
 use std::fs;
 use tauri_plugin_shell::ShellExt;
-use crate::commands::db::{get_file, delete_file, create_file};
+use crate::commands::db::{get_file, create_file, delete_file};
 use crate::commands::file_ops::get_audio_metadata_from_file;
+use crate::commands::journal::{journal_operation, restore_audio_step, snapshot_audio, AudioSnapshot, JournalStep};
+use crate::commands::relations::link_files;
+use crate::commands::versions::record_file_version;
 use uuid::Uuid;
 
+/// Suffix given to ffmpeg's temporary output while a conversion is in progress.
+/// Files carrying it are ignored by the folder watcher and repository status.
+pub const CONVERSION_TEMP_SUFFIX: &str = "_converted5334112025";
+
 /// Generates an audio fingerprint for a given file and updates its record in the database.
 pub fn generate_audio_fingerprint_for_file(
+    conn: &Connection,
     repo_id: &str,
     file_metadata: &FileMetadata,
 ) -> Result<String, Box<dyn Error>> {
     println!("Generating fingerprint for file: {}", file_metadata.path);
 
+    // Identical audio only needs fingerprinting once across the library.
+    match shared_fingerprint(conn, &file_metadata.path) {
+        Ok(Some(fp_string)) => {
+            let mut updated_file = file_metadata.clone();
+            updated_file.audio_fingerprint = Some(fp_string.clone());
+            update_file(conn, repo_id, &updated_file)?;
+            return Ok(fp_string);
+        }
+        Ok(None) => {}
+        Err(e) => println!("Failed to look up a shared fingerprint: {}", e),
+    }
+
     let path = Path::new(&file_metadata.path);
     let file = File::open(path)?;
     let mss = MediaSourceStream::new(Box::new(file), Default::default());
@@ -68,12 +91,7 @@ pub fn generate_audio_fingerprint_for_file(
     let mut sample_buf: Option<SampleBuffer<i16>> = None;
     let mut packet_count: u64 = 0;
 
-    loop {
-        let packet = match format.next_packet() {
-            Ok(packet) => packet,
-            Err(_) => break,
-        };
-
+    while let Ok(packet) = format.next_packet() {
         if packet.track_id() != track_id {
             continue;
         }
@@ -96,7 +114,7 @@ pub fn generate_audio_fingerprint_for_file(
         }
 
         packet_count += 1;
-        if packet_count % 10 == 0 {
+        if packet_count.is_multiple_of(10) {
             println!("Processed {} packets...", packet_count);
         }
     }
@@ -113,7 +131,7 @@ pub fn generate_audio_fingerprint_for_file(
 
     let mut updated_file = file_metadata.clone();
     updated_file.audio_fingerprint = Some(fp_string.clone());
-    update_file(repo_id, &updated_file)?;
+    update_file(conn, repo_id, &updated_file)?;
 
     Ok(fp_string)
 }
@@ -121,13 +139,16 @@ pub fn generate_audio_fingerprint_for_file(
 #[tauri::command]
 pub async fn generate_audio_fingerprint_for_file_command(
     window: Window, // <-- Added
+    pool: State<'_, DbPool>,
     repo_id: String,
     file: FileMetadata,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = generate_audio_fingerprint_for_file(&repo_id, &file);
+        let conn = get_connection(&pool)?;
+        let result = generate_audio_fingerprint_for_file(&conn, &repo_id, &file);
 
         let event_payload = match &result {
             Ok(fp) => {
@@ -143,18 +164,15 @@ pub async fn generate_audio_fingerprint_for_file_command(
                 )
             }
         };
-
-        emit_window
-            .emit(
-                "generate_audio_fingerprint_completed", // Event name
-                event_payload,
-            )
-            .unwrap_or_else(|e| {
-                println!(
-                    "Failed to emit generate_audio_fingerprint_completed event: {}",
-                    e
-                );
-            });
+        finish_command(
+            &conn,
+            &emit_window,
+            "generate_audio_fingerprint",
+            Some(&repo_id),
+            &[&file.id],
+            &result,
+            &event_payload,
+        );
 
         result.map(|_| ()).map_err(|e| e.to_string())
     })
@@ -167,20 +185,80 @@ pub async fn generate_audio_fingerprint_for_file_command(
 pub async fn convert_audio_file_command(
     app: AppHandle,
     window: Window,
+    pool: State<'_, DbPool>,
     repo_id: String,
     file_id: String,
     target_format: String,
 ) -> Result<(), String> {
-    convert_audio_file(&app, &window, &repo_id, &file_id, &target_format)
-        .await
-        .map_err(|e| e.to_string())
+    let result =
+        convert_audio_file(&app, &window, pool.inner(), &repo_id, &file_id, &target_format).await;
+
+    if let Ok(conn) = get_connection(pool.inner()) {
+        let details = format!("Convert file {} to {}.", file_id, target_format);
+        record_activity(
+            &conn,
+            ActivityActor::User,
+            "convert_audio_file",
+            Some(&repo_id),
+            &[&file_id],
+            &result,
+            &details,
+        );
+    }
+    result.map_err(|e| e.to_string())
+}
+
+
+/// The ffmpeg arguments selecting the encoder for a target format.
+pub fn codec_args(target_format: &str) -> Vec<String> {
+    let codec = match target_format.to_lowercase().as_str() {
+        "mp3" => "libmp3lame",
+        "flac" => "flac",
+        "wav" => "pcm_s16le",
+        "ogg" => "libvorbis",
+        "aac" => "aac",
+        "m4a" => "aac",
+        _ => return vec![],
+    };
+    vec!["-acodec".to_string(), codec.to_string()]
 }
 
+/// Runs the ffmpeg sidecar to encode `input_path` into `output_path`, with `args` given
+/// between the input and the output.
+pub async fn run_ffmpeg(
+    app: &AppHandle,
+    input_path: &Path,
+    args: &[String],
+    output_path: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let ffmpeg_cmd = app
+        .shell()
+        .sidecar("ffmpeg")?
+        .args([
+            "-y".to_string(),
+            "-i".to_string(), input_path.to_string_lossy().to_string(),
+        ].into_iter()
+        .chain(args.iter().cloned())
+        .chain([output_path.to_string_lossy().to_string()]));
+
+    let output = ffmpeg_cmd.output().await?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    eprintln!("ffmpeg stdout:\n{}", stdout);
+    eprintln!("ffmpeg stderr:\n{}", stderr);
+
+    if !output.status.success() {
+        return Err(format!("ffmpeg failed: {}", stderr).into());
+    }
+    Ok(())
+}
 
 /// Actual conversion logic, to be run in spawn_blocking.
 async fn convert_audio_file(
     app: &AppHandle,
     window: &Window,
+    pool: &DbPool,
     repo_id: &str,
     file_id: &str,
     target_format: &str,
@@ -188,62 +266,255 @@ async fn convert_audio_file(
     window.emit("conversion_progress", format!("Starting conversion for {}", file_id)).ok();
 
     // Step 1: Fetch file metadata from DB
-    let file = get_file(repo_id, file_id)?;
+    // Connections are checked out per step so none is held while ffmpeg runs.
+    let file = {
+        let conn = get_connection(pool)?;
+        get_file(&conn, repo_id, file_id)?
+    };
     let input_path = Path::new(&file.path);
     let original_stem = input_path.file_stem().unwrap().to_string_lossy();
     let original_parent = input_path.parent().unwrap();
 
-    let output_temp = original_parent.join(format!("{}_converted5334112025.{}", original_stem, target_format));
+    let output_temp = original_parent.join(format!("{}{}.{}", original_stem, CONVERSION_TEMP_SUFFIX, target_format));
     let final_output = original_parent.join(format!("{}.{}", original_stem, target_format));
 
     // Step 2: Select codec arguments based on desired format
-    let codec_args = match target_format.to_lowercase().as_str() {
-        "mp3" => vec!["-acodec", "libmp3lame"],
-        "flac" => vec!["-acodec", "flac"],
-        "wav" => vec!["-acodec", "pcm_s16le"],
-        "ogg" => vec!["-acodec", "libvorbis"],
-        "aac" => vec!["-acodec", "aac"],
-        "m4a" => vec!["-acodec", "aac"],
-        _ => vec![],
-    };
+    let args = codec_args(target_format);
 
     // Step 3: Run ffmpeg sidecar
-    let ffmpeg_cmd = app
-        .shell()
-        .sidecar("ffmpeg")?
-        .args([
-            "-y",
-            "-i", input_path.to_str().unwrap(),
-        ].into_iter()
-        .chain(codec_args)
-        .chain([output_temp.to_str().unwrap()]));
-
-    let output = ffmpeg_cmd.output().await?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let stderr = String::from_utf8_lossy(&output.stderr);
-    eprintln!("ffmpeg stdout:\n{}", stdout);
-    eprintln!("ffmpeg stderr:\n{}", stderr);
-
-    if !output.status.success() {
-        return Err(format!("ffmpeg failed: {}", stderr).into());
-    }
-
-    // Step 4: Replace original file with final converted version
-    fs::remove_file(&input_path)?;
+    run_ffmpeg(app, input_path, &args, &output_temp).await?;
+
+    // Step 4: Replace original file with final converted version.
+    // Its audio is kept in the version history first, so the original can still be restored.
+    // Whatever is about to be replaced is also captured for the undo journal.
+    let in_place = final_output.as_path() == input_path;
+    let original_audio = snapshot_audio(&file.path);
+    let replaced_audio = if in_place {
+        original_audio.clone()
+    } else {
+        snapshot_audio(final_output.to_str().unwrap())
+    };
+    let replaced_row = {
+        let conn = get_connection(pool)?;
+        record_file_version(&conn, repo_id, &file)?;
+        if in_place {
+            None
+        } else {
+            file_at_path(&conn, repo_id, final_output.to_str().unwrap())?
+        }
+    };
+    fs::remove_file(input_path)?;
     if final_output.exists() {
         fs::remove_file(&final_output)?; // prevent overwrite error
     }
     fs::rename(&output_temp, &final_output)?;
 
     // Step 5: Update database with new file
-    delete_file(repo_id, &file.id)?;
-
+    let conn = get_connection(pool)?;
     let mut new_metadata = get_audio_metadata_from_file(final_output.to_str().unwrap())?;
     new_metadata.id = Uuid::new_v4().to_string();
 
-    create_file(repo_id, &new_metadata)?;
+    if in_place {
+        // Re-encoded in place: the row keeps its id, so its tags, notes, relations and
+        // history stay with it, and takes on what was read from the new audio.
+        new_metadata = FileMetadata {
+            id: file.id.clone(),
+            tags: file.tags.clone(),
+            notes: file.notes.clone(),
+            ..new_metadata
+        };
+        update_file(&conn, repo_id, &new_metadata)?;
+    } else {
+        // The original goes to the trash, so it can still be brought back, and the new file
+        // records it was converted from it.
+        delete_file(&conn, repo_id, &file.id)?;
+        create_file(&conn, repo_id, &new_metadata)?;
+    }
+    // The folder watcher may have added the output first, under its own id.
+    let converted = file_at_path(&conn, repo_id, &new_metadata.path)?
+        .ok_or("The converted file wasn't added to the repository.")?;
+    if !in_place {
+        link_files(&conn, &converted.id, &file.id, FileRelationKind::ConvertedFrom)?;
+    }
+
+    if let (Some(original_audio), Some(replaced_audio), Some(converted_audio)) =
+        (original_audio, replaced_audio, snapshot_audio(&converted.path))
+    {
+        let (undo, redo) = conversion_steps(
+            repo_id,
+            &file,
+            &converted,
+            replaced_row,
+            &original_audio,
+            &replaced_audio,
+            &converted_audio,
+        );
+        let description = format!("Convert '{}' to {}", file.name, target_format);
+        journal_operation(&conn, "convert_audio_file", &description, undo, redo);
+    }
 
     window.emit("conversion_progress", format!("Finished converting {}", file.name)).ok();
     Ok(())
 }
+
+/// The live file row at `path` in a repository, if any.
+fn file_at_path(conn: &Connection, repo_id: &str, path: &str) -> rusqlite::Result<Option<FileMetadata>> {
+    let id: Option<String> = conn
+        .query_row(
+            "SELECT id FROM Files WHERE repo_id = ?1 AND path = ?2 AND deleted_at IS NULL",
+            rusqlite::params![repo_id, path],
+            |row| row.get(0),
+        )
+        .optional()?;
+    id.map(|id| get_file(conn, repo_id, &id)).transpose()
+}
+
+/// Builds the undo and redo steps of a conversion: `original` is the converted file's row,
+/// `replaced_row` the row of a file the output overwrote, and the snapshots hold the audio
+/// of the original, of what was at the output path before, and of the output.
+fn conversion_steps(
+    repo_id: &str,
+    original: &FileMetadata,
+    converted: &FileMetadata,
+    replaced_row: Option<FileMetadata>,
+    original_audio: &AudioSnapshot,
+    replaced_audio: &AudioSnapshot,
+    converted_audio: &AudioSnapshot,
+) -> (Vec<JournalStep>, Vec<JournalStep>) {
+    let put_row = |file: &FileMetadata| JournalStep::PutFileRow {
+        repo_id: repo_id.to_string(),
+        file: Box::new(file.clone()),
+    };
+    let remove_row = |file: &FileMetadata| JournalStep::RemoveFileRow {
+        repo_id: repo_id.to_string(),
+        file_id: file.id.clone(),
+    };
+
+    if original.path == converted.path {
+        // The same row before and after.
+        let undo = vec![
+            restore_audio_step(original_audio, converted_audio),
+            put_row(original),
+        ];
+        let redo = vec![
+            restore_audio_step(converted_audio, original_audio),
+            put_row(converted),
+        ];
+        return (undo, redo);
+    }
+
+    let no_original = AudioSnapshot {
+        path: original_audio.path.clone(),
+        content_hash: None,
+    };
+    let mut undo = vec![
+        restore_audio_step(original_audio, &no_original),
+        restore_audio_step(replaced_audio, converted_audio),
+    ];
+    match &replaced_row {
+        // The output overwrote a tracked file: its row stays, minus the new relation.
+        Some(replaced_row) => undo.extend([
+            JournalStep::UnlinkFiles {
+                source_file_id: converted.id.clone(),
+                target_file_id: original.id.clone(),
+                relation: FileRelationKind::ConvertedFrom,
+            },
+            put_row(replaced_row),
+        ]),
+        None => undo.push(remove_row(converted)),
+    }
+    undo.extend([
+        JournalStep::UntrashFile {
+            file_id: original.id.clone(),
+        },
+        put_row(original),
+    ]);
+
+    let redo = vec![
+        restore_audio_step(&no_original, original_audio),
+        restore_audio_step(converted_audio, replaced_audio),
+        JournalStep::TrashFile {
+            repo_id: repo_id.to_string(),
+            file_id: original.id.clone(),
+        },
+        put_row(converted),
+        JournalStep::LinkFiles {
+            source_file_id: converted.id.clone(),
+            target_file_id: original.id.clone(),
+            relation: FileRelationKind::ConvertedFrom,
+        },
+    ];
+    (undo, redo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::{sample_file, scratch_dir};
+
+    fn actions(steps: &[JournalStep]) -> Vec<String> {
+        steps
+            .iter()
+            .map(|step| serde_json::to_value(step).unwrap()["action"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn snapshot(path: &str, content_hash: Option<&str>) -> AudioSnapshot {
+        AudioSnapshot {
+            path: path.to_string(),
+            content_hash: content_hash.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn codecs_follow_the_target_format() {
+        assert_eq!(codec_args("MP3"), vec!["-acodec", "libmp3lame"]);
+        assert_eq!(codec_args("m4a"), vec!["-acodec", "aac"]);
+        assert!(codec_args("xyz").is_empty());
+    }
+
+    #[test]
+    fn converting_to_a_new_path_trashes_the_original_and_undo_brings_it_back() {
+        let dir = scratch_dir();
+        let original = sample_file(&dir, "a.wav", b"wav");
+        let converted = sample_file(&dir, "a.mp3", b"mp3");
+        let (undo, redo) = conversion_steps(
+            "repo",
+            &original,
+            &converted,
+            None,
+            &snapshot(&original.path, Some("wav")),
+            &snapshot(&converted.path, None),
+            &snapshot(&converted.path, Some("mp3")),
+        );
+        assert_eq!(
+            actions(&undo),
+            vec!["write_audio", "write_audio", "remove_file_row", "untrash_file", "put_file_row"]
+        );
+        assert_eq!(
+            actions(&redo),
+            vec!["write_audio", "write_audio", "trash_file", "put_file_row", "link_files"]
+        );
+    }
+
+    #[test]
+    fn converting_in_place_rewrites_the_row() {
+        let dir = scratch_dir();
+        let original = sample_file(&dir, "a.wav", b"before");
+        let converted = FileMetadata {
+            meta_bit_rate: Some("320".to_string()),
+            ..original.clone()
+        };
+        let (undo, redo) = conversion_steps(
+            "repo",
+            &original,
+            &converted,
+            None,
+            &snapshot(&original.path, Some("before")),
+            &snapshot(&original.path, Some("before")),
+            &snapshot(&original.path, Some("after")),
+        );
+        assert_eq!(actions(&undo), vec!["write_audio", "put_file_row"]);
+        assert_eq!(actions(&redo), vec!["write_audio", "put_file_row"]);
+    }
+}
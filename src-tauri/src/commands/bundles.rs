@@ -0,0 +1,1419 @@
+// src/commands/bundles.rs
+//! Bundles: saved selections of files to send to contacts.
+//!
+//! A bundle lists files by id, in order, and its recipients: single contacts, or contact
+//! lists that are expanded when the archive is built. The archive can be generated again
+//! at any time from the files as they are now; each generation is recorded with the files
+//! that went in and the contacts it was for, as they were at that moment.
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{BufWriter, Cursor, ErrorKind, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::commands::activity::{finish_command, record_activity, NO_FILES};
+use crate::commands::db::{
+    file_from_row, get_connection, get_contacts_for_list, DbPool, FILE_COLUMNS,
+};
+use crate::commands::delivery::{
+    delivery_preset_for, prepare_delivery, sanitize_file_name, with_stems,
+};
+use crate::commands::manifest::{
+    library_metadata_for_path, manifest_entry, write_manifest, ManifestSource,
+};
+use crate::commands::structures::{
+    ActivityActor, Bundle, BundleArchive, BundleGeneration, BundleGenerationRecipient,
+    BundleManifest, BundleRecipient, BundleRecipientKind, FileMetadata, GenerateBundleOptions,
+};
+use crate::commands::watermark::{allocate_marks, record_watermarks, watermark_delivery};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{SecondsFormat, Utc};
+use lazy_static::lazy_static;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tauri::{AppHandle, Emitter, State, Window};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+/// Largest total file size `bundle_files_command` will build in memory. Anything bigger
+/// has to be exported to a file.
+pub const PREVIEW_SIZE_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// How much of a file is copied into the archive between progress reports.
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+pub const CANCELLED: &str = "Bundling was cancelled.";
+
+type BundleJobs = HashMap<String, Arc<AtomicBool>>;
+type BundleJobMap = Mutex<BundleJobs>;
+
+lazy_static! {
+    /// Cancellation flags of the archives being written, by job id.
+    static ref BUNDLE_JOBS: BundleJobMap = Mutex::new(HashMap::new());
+}
+
+// ---------------------------------------------------------------------------
+// Archive writing
+// ---------------------------------------------------------------------------
+
+/// How far along an archive is. Bytes are counted from the source files, before
+/// compression.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveProgress {
+    pub file_index: usize,
+    pub file_count: usize,
+    pub bytes_done: u64,
+    pub total_bytes: u64,
+}
+
+/// Copies `reader` to `writer` a chunk at a time, calling `on_chunk` with each one.
+/// Fails with `ErrorKind::Interrupted` as soon as `cancel` is set.
+fn copy_in_chunks(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    cancel: &AtomicBool,
+    mut on_chunk: impl FnMut(&[u8]),
+) -> std::io::Result<()> {
+    let mut buffer = vec![0u8; COPY_CHUNK_SIZE];
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(ErrorKind::Interrupted, CANCELLED));
+        }
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => read,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        writer.write_all(&buffer[..read])?;
+        on_chunk(&buffer[..read]);
+    }
+}
+
+/// A file to put in an archive, and the name it goes in under.
+#[derive(Debug, Clone)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub archive_name: String,
+}
+
+/// Names the files at `paths` for an archive, numbered in the given order.
+pub fn archive_entries(paths: &[String]) -> Vec<ArchiveEntry> {
+    paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let base_name = Path::new(path)
+                .file_name()
+                .and_then(|s| s.to_str())
+                .unwrap_or("unknown_file");
+            ArchiveEntry {
+                path: path.clone(),
+                archive_name: format!("{:03}_{}", i, base_name),
+            }
+        })
+        .collect()
+}
+
+/// Writes the files of `entries` into a zip archive, in the given order. WAVs are
+/// compressed; formats that are compressed already are stored as they are. Paths that
+/// can't be read are skipped. Every file is written as a ZIP64 entry, so one that grows
+/// past 4 GB while it is copied still fits.
+/// `on_progress` is called as each file is reached and after every chunk copied; setting
+/// `cancel` stops the archive with an error. With a `manifest`, the files are hashed as
+/// they are copied and the manifests are added at the end. Returns the writer and the
+/// indices of the entries that went in.
+pub fn write_bundle_archive<W: Write + Seek>(
+    writer: W,
+    entries: &[ArchiveEntry],
+    manifest: Option<&ManifestSource>,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&ArchiveProgress),
+) -> std::result::Result<(W, Vec<usize>), Box<dyn Error + Send + Sync>> {
+    let sizes: Vec<u64> = entries
+        .iter()
+        .map(|entry| fs::metadata(&entry.path).map(|m| m.len()).unwrap_or(0))
+        .collect();
+    let mut progress = ArchiveProgress {
+        file_count: entries.len(),
+        total_bytes: sizes.iter().sum(),
+        ..Default::default()
+    };
+    let mut zip = zip::ZipWriter::new(writer);
+    let mut included = Vec::new();
+    let mut manifest_entries = Vec::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        let path_str = &entry.path;
+        if cancel.load(Ordering::Relaxed) {
+            return Err(CANCELLED.into());
+        }
+        let file_start = progress.bytes_done;
+        progress.file_index = i + 1;
+        on_progress(&progress);
+
+        let path_obj = Path::new(path_str);
+        if !path_obj.exists() {
+            eprintln!("Error: File does not exist, skipping: {}", path_str);
+            continue;
+        }
+        if !path_obj.is_file() {
+            eprintln!("Warning: Skipping non-file path: {}", path_str);
+            continue;
+        }
+
+        let extension = path_obj
+            .extension()
+            .and_then(|os_str| os_str.to_str())
+            .map(|s| s.to_lowercase());
+        let compression_method = match extension.as_deref() {
+            Some("wav") => CompressionMethod::Deflated,
+            Some("mp3") | Some("flac") | Some("ogg") | Some("aac") => CompressionMethod::Stored,
+            _ => CompressionMethod::Stored,
+        };
+
+        println!(
+            "Processing file: '{}', Using Compression: {:?}",
+            path_str, compression_method
+        );
+
+        let options = SimpleFileOptions::default()
+            .compression_method(compression_method)
+            .unix_permissions(0o755)
+            .large_file(true);
+
+        let mut file = match fs::File::open(path_obj) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!(
+                    "Error: Failed to open file '{}': {}. Skipping.",
+                    path_str, e
+                );
+                continue;
+            }
+        };
+
+        let name_in_archive = &entry.archive_name;
+
+        if let Err(e) = zip.start_file(name_in_archive, options) {
+            eprintln!(
+                "Error: Failed to start file '{}' in zip: {}. Skipping.",
+                name_in_archive, e
+            );
+            continue;
+        }
+
+        let mut hasher = manifest.map(|_| Sha256::new());
+        let copied = copy_in_chunks(&mut file, &mut zip, cancel, |chunk| {
+            if let Some(hasher) = hasher.as_mut() {
+                hasher.update(chunk);
+            }
+            progress.bytes_done += chunk.len() as u64;
+            on_progress(&progress);
+        });
+        let bytes_copied = progress.bytes_done - file_start;
+        // Account for the whole file, even if it changed size or was only partly read.
+        progress.bytes_done = file_start + sizes[i];
+        if let Err(e) = copied {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(CANCELLED.into());
+            }
+            eprintln!(
+                "Error: Failed to copy data for file '{}' to zip: {}. Skipping.",
+                name_in_archive, e
+            );
+            zip.abort_file()
+                .map_err(|e| format!("Failed to drop '{}': {}", name_in_archive, e))?;
+            continue;
+        }
+        if let (Some(source), Some(hasher)) = (manifest, hasher) {
+            manifest_entries.push(manifest_entry(
+                name_in_archive,
+                path_obj,
+                source.files.get(i).and_then(|metadata| metadata.as_ref()),
+                bytes_copied,
+                format!("{:x}", hasher.finalize()),
+            ));
+        }
+        included.push(i);
+    }
+
+    if let Some(source) = manifest {
+        let manifest = BundleManifest {
+            title: source.title.clone(),
+            generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            files: manifest_entries,
+        };
+        write_manifest(&mut zip, &manifest)
+            .map_err(|e| format!("Failed to write the bundle manifest: {}", e))?;
+    }
+
+    let writer = zip
+        .finish()
+        .map_err(|e| format!("Failed to finalize zip archive: {}", e))?;
+    Ok((writer, included))
+}
+
+/// Writes the archive to a temporary file next to `output_path` and renames it into
+/// place once it is complete, so the destination never holds a partial archive. Returns
+/// the archive's size and the indices of the entries that went in. `before_rename` is
+/// given those once the archive is complete; if it fails, the archive is deleted, so
+/// nothing it records can refer to an archive that was never delivered.
+pub fn write_bundle_to_path(
+    entries: &[ArchiveEntry],
+    manifest: Option<&ManifestSource>,
+    output_path: &Path,
+    cancel: &AtomicBool,
+    on_progress: impl FnMut(&ArchiveProgress),
+    before_rename: impl FnOnce(u64, &[usize]) -> std::result::Result<(), Box<dyn Error + Send + Sync>>,
+) -> std::result::Result<(u64, Vec<usize>), Box<dyn Error + Send + Sync>> {
+    let file_name = output_path
+        .file_name()
+        .ok_or_else(|| format!("'{}' is not a file path.", output_path.display()))?;
+    let mut temp_name = file_name.to_os_string();
+    temp_name.push(".partial");
+    let temp_path: PathBuf = output_path.with_file_name(temp_name);
+
+    let written = (|| -> std::result::Result<_, Box<dyn Error + Send + Sync>> {
+        let file = BufWriter::new(fs::File::create(&temp_path)?);
+        let (file, included) = write_bundle_archive(file, entries, manifest, cancel, on_progress)?;
+        let file = file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok((file.metadata()?.len(), included))
+    })();
+    let written = written.and_then(|(size_on_disk, included)| {
+        before_rename(size_on_disk, &included)?;
+        fs::rename(&temp_path, output_path)?;
+        Ok((size_on_disk, included))
+    });
+    if written.is_err() {
+        fs::remove_file(&temp_path).ok();
+    }
+    written
+}
+
+/// Bundles files by path into an archive at `output_path`. A manifest, if asked for,
+/// takes each file's metadata from the library where the path is tracked.
+pub fn export_bundle_files(
+    conn: &Connection,
+    paths: &[String],
+    output_path: &Path,
+    include_manifest: bool,
+    cancel: &AtomicBool,
+    on_progress: impl FnMut(&ArchiveProgress),
+) -> std::result::Result<(u64, Vec<usize>), Box<dyn Error + Send + Sync>> {
+    let manifest = if include_manifest {
+        Some(ManifestSource {
+            title: None,
+            files: paths
+                .iter()
+                .map(|path| library_metadata_for_path(conn, path))
+                .collect::<Result<Vec<_>>>()?,
+        })
+    } else {
+        None
+    };
+    write_bundle_to_path(
+        &archive_entries(paths),
+        manifest.as_ref(),
+        output_path,
+        cancel,
+        on_progress,
+        |_, _| Ok(()),
+    )
+}
+
+/// The cancellation flags, or an error if a thread panicked while holding them.
+fn bundle_jobs() -> std::result::Result<MutexGuard<'static, BundleJobs>, String> {
+    BUNDLE_JOBS
+        .lock()
+        .map_err(|_| "The list of running bundle jobs is unavailable.".to_string())
+}
+
+/// Registers an archive being written under `job_id` and returns its cancellation flag.
+fn start_bundle_job(job_id: &str) -> std::result::Result<Arc<AtomicBool>, String> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    bundle_jobs()?.insert(job_id.to_string(), cancel.clone());
+    Ok(cancel)
+}
+
+fn finish_bundle_job(job_id: &str) -> std::result::Result<(), String> {
+    bundle_jobs()?.remove(job_id);
+    Ok(())
+}
+
+/// Asks the archive running under `job_id` to stop. Returns false if there is none.
+pub fn cancel_bundle_job(job_id: &str) -> std::result::Result<bool, String> {
+    match bundle_jobs()?.get(job_id) {
+        Some(cancel) => {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Emits `bundle_progress` with how many of the files and bytes have been written.
+fn emit_bundle_progress(window: &Window, job_id: Option<&str>, progress: &ArchiveProgress) {
+    let percentage = if progress.total_bytes > 0 {
+        (progress.bytes_done as f64 / progress.total_bytes as f64) * 100.0
+    } else {
+        (progress.file_index as f64 / progress.file_count.max(1) as f64) * 100.0
+    };
+    window
+        .emit(
+            "bundle_progress",
+            json!({
+                "job_id": job_id,
+                "progress": percentage,
+                "file_index": progress.file_index,
+                "total": progress.file_count,
+                "bytes_written": progress.bytes_done,
+                "total_bytes": progress.total_bytes,
+            }),
+        )
+        .ok();
+}
+
+// ---------------------------------------------------------------------------
+// Saved bundles
+// ---------------------------------------------------------------------------
+
+pub fn get_bundle(conn: &Connection, bundle_id: &str) -> Result<Bundle> {
+    let (name, description, date_created, date_modified) = conn.query_row(
+        "SELECT name, description, date_created, date_modified FROM Bundles WHERE id = ?1",
+        params![bundle_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+
+    let file_ids = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT file_id FROM BundleFiles WHERE bundle_id = ?1 ORDER BY position",
+        )?;
+        let ids = stmt
+            .query_map(params![bundle_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>>>()?;
+        ids
+    };
+    let recipients = {
+        let mut stmt = conn.prepare_cached(
+            "SELECT contact_id, contact_list_id FROM BundleRecipients
+             WHERE bundle_id = ?1 ORDER BY rowid",
+        )?;
+        let recipients = stmt
+            .query_map(params![bundle_id], |row| {
+                let contact_id: Option<String> = row.get(0)?;
+                Ok(match contact_id {
+                    Some(id) => BundleRecipient {
+                        kind: BundleRecipientKind::Contact,
+                        id,
+                    },
+                    None => BundleRecipient {
+                        kind: BundleRecipientKind::ContactList,
+                        id: row.get(1)?,
+                    },
+                })
+            })?
+            .collect::<Result<Vec<BundleRecipient>>>()?;
+        recipients
+    };
+
+    Ok(Bundle {
+        id: bundle_id.to_string(),
+        name,
+        description,
+        date_created,
+        date_modified,
+        file_ids,
+        recipients,
+    })
+}
+
+/// Returns every bundle, most recently changed first.
+pub fn get_bundles(conn: &Connection) -> Result<Vec<Bundle>> {
+    let ids = {
+        let mut stmt = conn.prepare("SELECT id FROM Bundles ORDER BY date_modified DESC")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>>>()?;
+        ids
+    };
+    ids.iter().map(|id| get_bundle(conn, id)).collect()
+}
+
+/// Replaces a bundle's files and recipients. Every file, contact and contact list must
+/// exist and be out of the trash; repeated entries are kept once.
+fn set_bundle_contents(
+    conn: &Connection,
+    bundle_id: &str,
+    file_ids: &[String],
+    recipients: &[BundleRecipient],
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    conn.execute(
+        "DELETE FROM BundleFiles WHERE bundle_id = ?1",
+        params![bundle_id],
+    )?;
+    conn.execute(
+        "DELETE FROM BundleRecipients WHERE bundle_id = ?1",
+        params![bundle_id],
+    )?;
+
+    for (position, file_id) in file_ids.iter().enumerate() {
+        let exists: bool = conn
+            .prepare_cached(
+                "SELECT EXISTS(SELECT 1 FROM Files WHERE id = ?1 AND deleted_at IS NULL)",
+            )?
+            .query_row(params![file_id], |row| row.get(0))?;
+        if !exists {
+            return Err(format!("File {} doesn't exist.", file_id).into());
+        }
+        conn.prepare_cached(
+            "INSERT OR IGNORE INTO BundleFiles (bundle_id, file_id, position)
+             VALUES (?1, ?2, ?3)",
+        )?
+        .execute(params![bundle_id, file_id, position as i64])?;
+    }
+
+    for recipient in recipients {
+        let (table, column) = match recipient.kind {
+            BundleRecipientKind::Contact => ("Contacts", "contact_id"),
+            BundleRecipientKind::ContactList => ("ContactLists", "contact_list_id"),
+        };
+        let exists: bool = conn.query_row(
+            &format!(
+                "SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1 AND deleted_at IS NULL)",
+                table
+            ),
+            params![recipient.id],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Err(format!("Recipient {} doesn't exist.", recipient.id).into());
+        }
+        conn.execute(
+            &format!(
+                "INSERT OR IGNORE INTO BundleRecipients (bundle_id, {}) VALUES (?1, ?2)",
+                column
+            ),
+            params![bundle_id, recipient.id],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn create_bundle(
+    conn: &Connection,
+    name: &str,
+    description: Option<&str>,
+    file_ids: &[String],
+    recipients: &[BundleRecipient],
+) -> std::result::Result<Bundle, Box<dyn Error + Send + Sync>> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO Bundles (id, name, description, date_created, date_modified)
+         VALUES (?1, ?2, ?3, ?4, ?4)",
+        params![id, name, description, now],
+    )?;
+    set_bundle_contents(&tx, &id, file_ids, recipients)?;
+    tx.commit()?;
+    Ok(get_bundle(conn, &id)?)
+}
+
+/// Saves a bundle's name, description, files and recipients.
+pub fn update_bundle(
+    conn: &Connection,
+    bundle: &Bundle,
+) -> std::result::Result<Bundle, Box<dyn Error + Send + Sync>> {
+    let tx = conn.unchecked_transaction()?;
+    let updated = tx.execute(
+        "UPDATE Bundles SET name = ?1, description = ?2, date_modified = ?3 WHERE id = ?4",
+        params![
+            bundle.name,
+            bundle.description,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            bundle.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(format!("Bundle {} doesn't exist.", bundle.id).into());
+    }
+    set_bundle_contents(&tx, &bundle.id, &bundle.file_ids, &bundle.recipients)?;
+    tx.commit()?;
+    Ok(get_bundle(conn, &bundle.id)?)
+}
+
+/// Deletes a bundle along with its generation history.
+pub fn delete_bundle(conn: &Connection, bundle_id: &str) -> Result<()> {
+    conn.execute("DELETE FROM Bundles WHERE id = ?1", params![bundle_id])?;
+    Ok(())
+}
+
+/// Expands recipients into the contacts they stand for, each listed once. Contacts and
+/// lists that have been deleted since are left out.
+pub fn resolve_recipients(
+    conn: &Connection,
+    recipients: &[BundleRecipient],
+) -> Result<Vec<BundleGenerationRecipient>> {
+    let mut resolved: Vec<BundleGenerationRecipient> = Vec::new();
+    for recipient in recipients {
+        let contacts = match recipient.kind {
+            BundleRecipientKind::Contact => conn
+                .query_row(
+                    "SELECT id, name, email, delivery_preset_id FROM Contacts
+                     WHERE id = ?1 AND deleted_at IS NULL",
+                    params![recipient.id],
+                    |row| {
+                        Ok(BundleGenerationRecipient {
+                            contact_id: row.get(0)?,
+                            name: row.get(1)?,
+                            email: row.get(2)?,
+                            delivery_preset_id: row.get(3)?,
+                        })
+                    },
+                )
+                .optional()?
+                .into_iter()
+                .collect(),
+            BundleRecipientKind::ContactList => {
+                let list: Option<Option<String>> = conn
+                    .query_row(
+                        "SELECT delivery_preset_id FROM ContactLists
+                         WHERE id = ?1 AND deleted_at IS NULL",
+                        params![recipient.id],
+                        |row| row.get(0),
+                    )
+                    .optional()?;
+                match list {
+                    Some(list_preset_id) => get_contacts_for_list(conn, &recipient.id)?
+                        .into_iter()
+                        .map(|contact| BundleGenerationRecipient {
+                            contact_id: contact.id,
+                            name: contact.name,
+                            email: contact.email,
+                            delivery_preset_id: contact
+                                .delivery_preset_id
+                                .or_else(|| list_preset_id.clone()),
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                }
+            }
+        };
+        for contact in contacts {
+            if !resolved.iter().any(|r| r.contact_id == contact.contact_id) {
+                resolved.push(contact);
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+/// Builds a bundle's archive at `output_path` and records the generation. The files are
+/// delivered as the recipients' preset asks: transcoded, renamed and joined by their
+/// stems. Files that are in the trash or can't be read are left out. With a manifest the
+/// archive lists the files' metadata and checksums. A mark names one recipient, so with
+/// watermarking every recipient gets an archive of their own, delivered with their own
+/// preset and, when there are several, named after them next to `output_path`. Setting
+/// `cancel` stops at the archive being written and leaves its destination untouched;
+/// archives finished before it stay.
+pub fn generate_bundle(
+    conn: &Connection,
+    app: &AppHandle,
+    bundle_id: &str,
+    output_path: &Path,
+    options: &GenerateBundleOptions,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&ArchiveProgress),
+) -> std::result::Result<Vec<BundleGeneration>, Box<dyn Error + Send + Sync>> {
+    let bundle = get_bundle(conn, bundle_id)?;
+    let mut files: Vec<FileMetadata> = Vec::new();
+    for file_id in &bundle.file_ids {
+        let file = conn
+            .prepare_cached(&format!(
+                "SELECT {} FROM Files f WHERE f.id = ?1 AND f.deleted_at IS NULL",
+                FILE_COLUMNS
+            ))?
+            .query_row(params![file_id], file_from_row)
+            .optional()?;
+        files.extend(file);
+    }
+    if files.is_empty() {
+        return Err(format!("Bundle '{}' has no files to include.", bundle.name).into());
+    }
+    let recipients = resolve_recipients(
+        conn,
+        options.recipients.as_deref().unwrap_or(&bundle.recipients),
+    )?;
+
+    if !options.watermark {
+        let generation = generate_archive(
+            conn,
+            app,
+            &bundle,
+            &files,
+            recipients,
+            output_path,
+            options,
+            cancel,
+            &mut on_progress,
+        )?;
+        return Ok(vec![generation]);
+    }
+    if recipients.is_empty() {
+        return Err("A watermarked bundle needs at least one recipient.".into());
+    }
+    let output_paths = recipient_output_paths(output_path, &recipients);
+    let mut generations = Vec::with_capacity(recipients.len());
+    for (recipient, output_path) in recipients.into_iter().zip(output_paths) {
+        generations.push(generate_archive(
+            conn,
+            app,
+            &bundle,
+            &files,
+            vec![recipient],
+            &output_path,
+            options,
+            cancel,
+            &mut on_progress,
+        )?);
+    }
+    Ok(generations)
+}
+
+/// Where each recipient's archive goes: `output_path` itself for a single recipient,
+/// otherwise `output_path` with the recipient's name added, e.g. `Demos - Sam.zip`.
+fn recipient_output_paths(
+    output_path: &Path,
+    recipients: &[BundleGenerationRecipient],
+) -> Vec<PathBuf> {
+    if recipients.len() == 1 {
+        return vec![output_path.to_path_buf()];
+    }
+    let stem = output_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("bundle");
+    let extension = output_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("zip");
+    let mut taken = HashSet::new();
+    recipients
+        .iter()
+        .map(|recipient| {
+            let name = match sanitize_file_name(&recipient.name) {
+                name if name.is_empty() => sanitize_file_name(&recipient.email),
+                name => name,
+            };
+            let mut file_name = format!("{} - {}.{}", stem, name, extension);
+            let mut copy = 2;
+            while !taken.insert(file_name.to_lowercase()) {
+                file_name = format!("{} - {} ({}).{}", stem, name, copy, extension);
+                copy += 1;
+            }
+            output_path.with_file_name(file_name)
+        })
+        .collect()
+}
+
+/// Builds one archive of `files` for `recipients` and records it. With watermarking the
+/// marks are recorded along with the generation, in one transaction committed before the
+/// archive is moved into place.
+#[allow(clippy::too_many_arguments)]
+fn generate_archive(
+    conn: &Connection,
+    app: &AppHandle,
+    bundle: &Bundle,
+    files: &[FileMetadata],
+    recipients: Vec<BundleGenerationRecipient>,
+    output_path: &Path,
+    options: &GenerateBundleOptions,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&ArchiveProgress),
+) -> std::result::Result<BundleGeneration, Box<dyn Error + Send + Sync>> {
+    let preset = delivery_preset_for(conn, options.delivery_preset_id.as_deref(), &recipients)?;
+    let files = if preset.as_ref().is_some_and(|preset| preset.include_stems) {
+        with_stems(conn, files.to_vec())?
+    } else {
+        files.to_vec()
+    };
+    let marked_for = recipients.first().filter(|_| options.watermark).cloned();
+
+    // Transcoded and marked copies live in a scratch directory until the archive is
+    // written. Marked files are encoded by the watermarking itself, so only once.
+    let work_dir = std::env::temp_dir().join(format!("repostudio_delivery_{}", Uuid::new_v4()));
+    fs::create_dir_all(&work_dir)?;
+    let generated = (|| -> std::result::Result<_, Box<dyn Error + Send + Sync>> {
+        let delivery = prepare_delivery(
+            app,
+            &files,
+            preset.as_ref(),
+            &bundle.name,
+            marked_for.is_none().then_some(work_dir.as_path()),
+            cancel,
+            &mut on_progress,
+        )?;
+        let (entries, marks) = match marked_for {
+            Some(_) => {
+                let marks = allocate_marks(conn, delivery.entries.len())?;
+                let entries = watermark_delivery(
+                    app,
+                    &delivery,
+                    &marks,
+                    preset.as_ref(),
+                    &work_dir,
+                    cancel,
+                    &mut on_progress,
+                )?;
+                (entries, marks)
+            }
+            None => (delivery.entries, Vec::new()),
+        };
+        let manifest = options.include_manifest.then(|| ManifestSource {
+            title: Some(bundle.name.clone()),
+            files: delivery.files.iter().cloned().map(Some).collect(),
+        });
+
+        let mut generation = None;
+        write_bundle_to_path(
+            &entries,
+            manifest.as_ref(),
+            output_path,
+            cancel,
+            on_progress,
+            |size_on_disk, included| {
+                let recorded = BundleGeneration {
+                    id: Uuid::new_v4().to_string(),
+                    bundle_id: bundle.id.clone(),
+                    generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                    output_path: output_path.to_string_lossy().to_string(),
+                    size_on_disk: size_on_disk as i64,
+                    file_ids: included
+                        .iter()
+                        .map(|&i| delivery.files[i].id.clone())
+                        .collect(),
+                    recipients: recipients.clone(),
+                    delivery_preset: preset.clone(),
+                };
+                let marked: Vec<(u32, String, String)> = match marked_for {
+                    Some(_) => included
+                        .iter()
+                        .map(|&i| {
+                            (
+                                marks[i],
+                                delivery.files[i].id.clone(),
+                                entries[i].archive_name.clone(),
+                            )
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                record_generation(conn, &recorded, &bundle.name, marked_for.as_ref(), &marked)?;
+                generation = Some(recorded);
+                Ok(())
+            },
+        )?;
+        Ok(generation.ok_or("The generation wasn't recorded.")?)
+    })();
+    fs::remove_dir_all(&work_dir).ok();
+    generated
+}
+
+/// Records a generation and, for a watermarked one, who each mark went to, in one
+/// transaction. A mark taken by another generation since it was allocated fails the
+/// whole record.
+fn record_generation(
+    conn: &Connection,
+    generation: &BundleGeneration,
+    bundle_name: &str,
+    marked_for: Option<&BundleGenerationRecipient>,
+    marked: &[(u32, String, String)],
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO BundleGenerations (
+            id, bundle_id, generated_at, output_path, size_on_disk, file_ids, recipients,
+            delivery_preset
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            generation.id,
+            generation.bundle_id,
+            generation.generated_at,
+            generation.output_path,
+            generation.size_on_disk,
+            serde_json::to_string(&generation.file_ids)?,
+            serde_json::to_string(&generation.recipients)?,
+            generation
+                .delivery_preset
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?
+        ],
+    )?;
+    if let Some(recipient) = marked_for {
+        record_watermarks(&tx, generation, bundle_name, recipient, marked)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Parses a JSON column of a BundleGenerations row; NULL reads as JSON `null`.
+fn json_column<T: serde::de::DeserializeOwned>(row: &rusqlite::Row, index: usize) -> Result<T> {
+    let value: Option<String> = row.get(index)?;
+    serde_json::from_str(value.as_deref().unwrap_or("null")).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+/// Returns every time a bundle was generated, newest first.
+pub fn get_bundle_generations(conn: &Connection, bundle_id: &str) -> Result<Vec<BundleGeneration>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, bundle_id, generated_at, output_path, size_on_disk, file_ids, recipients,
+                delivery_preset
+         FROM BundleGenerations
+         WHERE bundle_id = ?1
+         ORDER BY generated_at DESC",
+    )?;
+    let generations = stmt
+        .query_map(params![bundle_id], |row| {
+            Ok(BundleGeneration {
+                id: row.get(0)?,
+                bundle_id: row.get(1)?,
+                generated_at: row.get(2)?,
+                output_path: row.get(3)?,
+                size_on_disk: row.get(4)?,
+                file_ids: json_column(row, 5)?,
+                recipients: json_column(row, 6)?,
+                delivery_preset: json_column(row, 7)?,
+            })
+        })?
+        .collect::<Result<Vec<BundleGeneration>>>()?;
+    Ok(generations)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn bundle_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    file_paths: Vec<String>,
+) -> Result<String, String> {
+    let pool = pool.inner().clone();
+    let total_files = file_paths.len();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        if file_paths.is_empty() {
+            return Err("No files provided to bundle.".into());
+        }
+
+        let total_size: u64 = file_paths
+            .iter()
+            .map(|path| fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+            .sum();
+        if total_size > PREVIEW_SIZE_LIMIT {
+            return Err(format!(
+                "The files come to {} MB, too much to bundle in memory; export the bundle to a file instead.",
+                total_size / (1024 * 1024)
+            ));
+        }
+
+        let (buffer, _) = write_bundle_archive(
+            Cursor::new(Vec::<u8>::new()),
+            &archive_entries(&file_paths),
+            None,
+            &AtomicBool::new(false),
+            |progress| emit_bundle_progress(&window, None, progress),
+        )
+        .map_err(|e| e.to_string())?;
+
+        // Emit completion event
+        window.emit("bundle_completed", "Bundling completed").ok();
+
+        // Encode the raw zip data as a base64 string before returning.
+        let zip_bytes = buffer.into_inner();
+        Ok(general_purpose::STANDARD.encode(&zip_bytes))
+    })
+    .await
+    .map_err(|join_error| format!("Async task failed: {}", join_error))?;
+
+    if let Ok(conn) = get_connection(&pool) {
+        let details = format!("Bundled {} files.", total_files);
+        record_activity(
+            &conn,
+            ActivityActor::User,
+            "bundle_files",
+            None,
+            NO_FILES,
+            &result,
+            &details,
+        );
+    }
+    result
+}
+
+#[tauri::command]
+pub async fn export_bundle_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    file_paths: Vec<String>,
+    output_path: String,
+    job_id: String,
+    include_manifest: Option<bool>,
+) -> Result<BundleArchive, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let cancel = start_bundle_job(&job_id)?;
+        let result = if file_paths.is_empty() {
+            Err("No files provided to bundle.".into())
+        } else {
+            export_bundle_files(
+                &conn,
+                &file_paths,
+                Path::new(&output_path),
+                include_manifest.unwrap_or(false),
+                &cancel,
+                |progress| emit_bundle_progress(&emit_window, Some(&job_id), progress),
+            )
+            .map(|(size_on_disk, included)| BundleArchive {
+                output_path: output_path.clone(),
+                size_on_disk: size_on_disk as i64,
+                included_paths: included
+                    .into_iter()
+                    .map(|i| file_paths[i].clone())
+                    .collect(),
+            })
+        };
+        finish_bundle_job(&job_id)?;
+
+        let payload = match &result {
+            Ok(archive) => format!(
+                "Bundled {} of {} files into '{}'.",
+                archive.included_paths.len(),
+                file_paths.len(),
+                output_path
+            ),
+            Err(e) => format!("Failed to bundle files into '{}': {}", output_path, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "export_bundle_files",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Stops the bundle export or generation started with `job_id`. Returns false if it has
+/// already finished.
+#[tauri::command]
+pub async fn cancel_bundle_command(job_id: String) -> Result<bool, String> {
+    cancel_bundle_job(&job_id)
+}
+
+#[tauri::command]
+pub async fn get_bundles_command(pool: State<'_, DbPool>) -> Result<Vec<Bundle>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_bundles(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_bundle_generations_command(
+    pool: State<'_, DbPool>,
+    bundle_id: String,
+) -> Result<Vec<BundleGeneration>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_bundle_generations(&conn, &bundle_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn create_bundle_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    name: String,
+    description: Option<String>,
+    file_ids: Vec<String>,
+    recipients: Vec<BundleRecipient>,
+) -> Result<Bundle, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = create_bundle(&conn, &name, description.as_deref(), &file_ids, &recipients);
+
+        let payload = match &result {
+            Ok(bundle) => format!("Bundle '{}' created.", bundle.name),
+            Err(e) => format!("Failed to create bundle '{}': {}", name, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "create_bundle",
+            None,
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn update_bundle_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    bundle: Bundle,
+) -> Result<Bundle, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = update_bundle(&conn, &bundle);
+
+        let payload = match &result {
+            Ok(bundle) => format!("Bundle '{}' updated.", bundle.name),
+            Err(e) => format!("Failed to update bundle '{}': {}", bundle.name, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "update_bundle",
+            None,
+            &bundle.file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_bundle_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    bundle_id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_bundle(&conn, &bundle_id);
+
+        let payload = match &result {
+            Ok(_) => format!("Bundle '{}' deleted.", bundle_id),
+            Err(e) => format!("Failed to delete bundle '{}': {}", bundle_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_bundle",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn generate_bundle_command(
+    app: AppHandle,
+    window: Window,
+    pool: State<'_, DbPool>,
+    bundle_id: String,
+    output_path: String,
+    job_id: String,
+    options: Option<GenerateBundleOptions>,
+) -> Result<Vec<BundleGeneration>, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let cancel = start_bundle_job(&job_id)?;
+        let result = generate_bundle(
+            &conn,
+            &app,
+            &bundle_id,
+            Path::new(&output_path),
+            &options.unwrap_or_default(),
+            &cancel,
+            |progress| emit_bundle_progress(&emit_window, Some(&job_id), progress),
+        );
+        finish_bundle_job(&job_id)?;
+
+        let payload = match &result {
+            Ok(generations) => {
+                let paths: Vec<&str> = generations
+                    .iter()
+                    .map(|generation| generation.output_path.as_str())
+                    .collect();
+                format!(
+                    "Bundle '{}' generated for {} recipients at '{}'.",
+                    bundle_id,
+                    generations
+                        .iter()
+                        .map(|generation| generation.recipients.len())
+                        .sum::<usize>(),
+                    paths.join("', '")
+                )
+            }
+            Err(e) => format!("Failed to generate bundle '{}': {}", bundle_id, e),
+        };
+        let file_ids: Vec<String> = result
+            .as_ref()
+            .map(|generations| {
+                let mut file_ids: Vec<String> = Vec::new();
+                for generation in generations {
+                    for file_id in &generation.file_ids {
+                        if !file_ids.contains(file_id) {
+                            file_ids.push(file_id.clone());
+                        }
+                    }
+                }
+                file_ids
+            })
+            .unwrap_or_default();
+        finish_command(
+            &conn,
+            &emit_window,
+            "generate_bundle",
+            None,
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::create_contact;
+    use crate::commands::test_support::{scratch_dir, test_connection};
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn archives_skip_missing_files_and_read_back() {
+        let dir = scratch_dir();
+        let take = dir.join("take.wav");
+        fs::write(&take, b"take audio").unwrap();
+        let paths = vec![
+            take.to_string_lossy().to_string(),
+            dir.join("missing.wav").to_string_lossy().to_string(),
+        ];
+
+        let (cursor, included) = write_bundle_archive(
+            Cursor::new(Vec::new()),
+            &archive_entries(&paths),
+            None,
+            &AtomicBool::new(false),
+            |_| {},
+        )
+        .unwrap();
+        assert_eq!(included, vec![0]);
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(cursor.into_inner())).unwrap();
+        assert_eq!(archive.len(), 1);
+        let mut contents = Vec::new();
+        archive
+            .by_name("000_take.wav")
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"take audio");
+    }
+
+    #[test]
+    fn a_cancelled_archive_fails() {
+        let dir = scratch_dir();
+        let take = dir.join("take.wav");
+        fs::write(&take, b"take audio").unwrap();
+
+        let result = write_bundle_archive(
+            Cursor::new(Vec::new()),
+            &archive_entries(&[take.to_string_lossy().to_string()]),
+            None,
+            &AtomicBool::new(true),
+            |_| {},
+        );
+        assert_eq!(result.err().unwrap().to_string(), CANCELLED);
+    }
+
+    #[test]
+    fn cancelling_reaches_a_running_job_only() {
+        let cancel = start_bundle_job("job").unwrap();
+        assert!(cancel_bundle_job("job").unwrap());
+        assert!(cancel.load(Ordering::Relaxed));
+        finish_bundle_job("job").unwrap();
+        assert!(!cancel_bundle_job("job").unwrap());
+    }
+
+    fn recipient(name: &str, email: &str) -> BundleGenerationRecipient {
+        BundleGenerationRecipient {
+            contact_id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            email: email.to_string(),
+            delivery_preset_id: None,
+        }
+    }
+
+    #[test]
+    fn each_recipient_gets_an_archive_path_of_their_own() {
+        let output_path = Path::new("/out/Mixes.zip");
+        assert_eq!(
+            recipient_output_paths(output_path, &[recipient("Ana", "ana@example.com")]),
+            vec![PathBuf::from("/out/Mixes.zip")]
+        );
+        assert_eq!(
+            recipient_output_paths(
+                output_path,
+                &[
+                    recipient("Ana", "ana@example.com"),
+                    recipient("ana", "ana@elsewhere.com"),
+                    recipient("", "ben@example.com"),
+                ]
+            ),
+            vec![
+                PathBuf::from("/out/Mixes - Ana.zip"),
+                PathBuf::from("/out/Mixes - ana (2).zip"),
+                PathBuf::from(format!(
+                    "/out/Mixes - {}.zip",
+                    sanitize_file_name("ben@example.com")
+                )),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_failed_record_leaves_no_archive() {
+        let dir = scratch_dir();
+        let take = dir.join("take.wav");
+        fs::write(&take, b"take audio").unwrap();
+        let output_path = dir.join("bundle.zip");
+
+        let result = write_bundle_to_path(
+            &archive_entries(&[take.to_string_lossy().to_string()]),
+            None,
+            &output_path,
+            &AtomicBool::new(false),
+            |_| {},
+            |_, _| Err("recording failed".into()),
+        );
+        assert_eq!(result.err().unwrap().to_string(), "recording failed");
+        assert!(!output_path.exists());
+        assert!(!dir.join("bundle.zip.partial").exists());
+
+        let (_, included) = write_bundle_to_path(
+            &archive_entries(&[take.to_string_lossy().to_string()]),
+            None,
+            &output_path,
+            &AtomicBool::new(false),
+            |_| {},
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        assert_eq!(included, vec![0]);
+        assert!(output_path.exists());
+    }
+
+    #[test]
+    fn a_taken_mark_records_nothing_of_the_generation() {
+        let conn = test_connection();
+        let bundle = create_bundle(&conn, "Mixes", None, &[], &[]).unwrap();
+        let contact_id =
+            create_contact(&conn, "Ana", "ana@example.com", None, None, None, None).unwrap();
+        let recipient = BundleGenerationRecipient {
+            contact_id,
+            ..recipient("Ana", "ana@example.com")
+        };
+        let generation = |id: &str| BundleGeneration {
+            id: id.to_string(),
+            bundle_id: bundle.id.clone(),
+            generated_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            output_path: "/out/Mixes.zip".to_string(),
+            size_on_disk: 1,
+            file_ids: Vec::new(),
+            recipients: vec![recipient.clone()],
+            delivery_preset: None,
+        };
+        let marked = vec![(7, "file".to_string(), "000_take.wav".to_string())];
+
+        record_generation(
+            &conn,
+            &generation("first"),
+            "Mixes",
+            Some(&recipient),
+            &marked,
+        )
+        .unwrap();
+        assert!(record_generation(
+            &conn,
+            &generation("second"),
+            "Mixes",
+            Some(&recipient),
+            &marked
+        )
+        .is_err());
+
+        let recorded: Vec<String> = get_bundle_generations(&conn, &bundle.id)
+            .unwrap()
+            .into_iter()
+            .map(|generation| generation.id)
+            .collect();
+        assert_eq!(recorded, vec!["first".to_string()]);
+        let generation_id: String = conn
+            .query_row(
+                "SELECT generation_id FROM Watermarks WHERE mark = 7",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(generation_id, "first");
+    }
+}
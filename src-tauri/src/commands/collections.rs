@@ -0,0 +1,811 @@
+// src/commands/collections.rs
+//! Smart collections: saved queries over file metadata.
+//!
+//! A collection belongs to one repository, or to the whole library when it has no
+//! repository. Its query is written in a small language of `field:value` terms, e.g.
+//!
+//! ```text
+//! genre:trap bpm:>140 encoding:wav accessible:true tag:"for artist X"
+//! ```
+//!
+//! Terms are ANDed together. `OR` between terms starts an alternative group, a leading `-`
+//! negates a term, and a word without a field matches the file name. Text fields match
+//! substrings (`*` is a wildcard, a leading `=` asks for an exact match), number fields
+//! take `>`, `>=`, `<`, `<=`, `=` or a `min..max` range, and `tag:` matches a tag and
+//! everything below it.
+//!
+//! Queries compile to SQL over the Files table. Matches are cached in
+//! SmartCollectionFiles: a collection is evaluated in full when it is saved, and
+//! `create_file` / `update_file` re-check the file they wrote against every collection
+//! that can contain it.
+use std::error::Error;
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{get_connection, repository_file_from_row, DbPool, FILE_COLUMNS};
+use crate::commands::structures::{RepositoryFile, SmartCollection};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, params_from_iter, types::Value, Connection, Result, Row};
+use tauri::{State, Window};
+use uuid::Uuid;
+
+const COLLECTION_COLUMNS: &str = "c.id, c.name, c.repo_id, c.query, c.date_created,
+    (SELECT COUNT(*) FROM SmartCollectionFiles cf WHERE cf.collection_id = c.id) AS file_count";
+
+fn collection_from_row(row: &Row) -> Result<SmartCollection> {
+    Ok(SmartCollection {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        repo_id: row.get(2)?,
+        query: row.get(3)?,
+        date_created: row.get(4)?,
+        file_count: row.get(5)?,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Query language
+// ---------------------------------------------------------------------------
+
+/// How a field's values are compared.
+#[derive(Clone, Copy)]
+enum FieldKind {
+    /// Substring match, case-insensitive.
+    Text(&'static str),
+    /// Whole-value match, case-insensitive.
+    Exact(&'static str),
+    /// Numeric comparison; the expression casts the stored text to a number (NULL if blank).
+    Number(&'static str),
+    /// `true` / `false`; the expression evaluates to 0 or 1.
+    Flag(&'static str),
+    /// Linked tag, including descendants.
+    Tag,
+}
+
+const FIELDS: &[(&str, FieldKind)] = &[
+    ("name", FieldKind::Text("f.name")),
+    ("path", FieldKind::Text("f.path")),
+    ("title", FieldKind::Text("f.meta_title")),
+    ("album", FieldKind::Text("f.meta_album")),
+    ("artist", FieldKind::Text("f.meta_album_artist")),
+    ("genre", FieldKind::Text("f.meta_genre")),
+    ("comment", FieldKind::Text("f.meta_comment")),
+    ("encoding", FieldKind::Exact("f.encoding")),
+    ("key", FieldKind::Exact("f.meta_key")),
+    (
+        "bpm",
+        FieldKind::Number("CAST(NULLIF(f.meta_bpm, '') AS REAL)"),
+    ),
+    (
+        "bitrate",
+        FieldKind::Number("CAST(NULLIF(f.meta_bit_rate, '') AS REAL)"),
+    ),
+    (
+        "samplerate",
+        FieldKind::Number("CAST(NULLIF(f.meta_sample_rate, '') AS REAL)"),
+    ),
+    (
+        "channels",
+        FieldKind::Number("CAST(NULLIF(f.meta_channels, '') AS REAL)"),
+    ),
+    (
+        "size",
+        FieldKind::Number("CAST(NULLIF(f.meta_size_on_disk, '') AS REAL)"),
+    ),
+    (
+        "track",
+        FieldKind::Number("CAST(NULLIF(f.meta_track_number, '') AS REAL)"),
+    ),
+    ("accessible", FieldKind::Flag("COALESCE(f.accessible, 0)")),
+    (
+        "fingerprint",
+        FieldKind::Flag("(COALESCE(f.audio_fingerprint, '') != '')"),
+    ),
+    ("tag", FieldKind::Tag),
+];
+
+/// A query compiled to a SQL condition over `Files f`, with its bound values.
+pub struct CompiledQuery {
+    pub condition: String,
+    pub values: Vec<Value>,
+}
+
+/// Splits a query into terms on whitespace, keeping quoted text together and dropping
+/// the quotes, so `tag:"for artist X"` is one term.
+fn tokenize(query: &str) -> std::result::Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in query.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("Unclosed quote in query.".to_string());
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+/// Escapes LIKE wildcards in user text, then turns `*` into `%`.
+fn like_pattern(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+        .replace('*', "%")
+}
+
+fn parse_number(field: &str, value: &str) -> std::result::Result<f64, String> {
+    value
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("'{}' is not a number (in {}:).", value, field))
+}
+
+fn parse_flag(field: &str, value: &str) -> std::result::Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(format!(
+            "{}: expects true or false, got '{}'.",
+            field, value
+        )),
+    }
+}
+
+fn compile_term(
+    field: &str,
+    kind: FieldKind,
+    value: &str,
+    values: &mut Vec<Value>,
+) -> std::result::Result<String, String> {
+    match kind {
+        FieldKind::Text(column) => {
+            if let Some(exact) = value.strip_prefix('=') {
+                values.push(Value::Text(exact.to_string()));
+                Ok(format!("{} = ? COLLATE NOCASE", column))
+            } else {
+                let pattern = like_pattern(value);
+                let pattern = if value.contains('*') {
+                    pattern
+                } else {
+                    format!("%{}%", pattern)
+                };
+                values.push(Value::Text(pattern));
+                Ok(format!("{} LIKE ? ESCAPE '\\'", column))
+            }
+        }
+        FieldKind::Exact(column) => {
+            if value.contains('*') {
+                values.push(Value::Text(like_pattern(value)));
+                Ok(format!("{} LIKE ? ESCAPE '\\'", column))
+            } else {
+                values.push(Value::Text(value.to_string()));
+                Ok(format!("{} = ? COLLATE NOCASE", column))
+            }
+        }
+        FieldKind::Number(expr) => {
+            if let Some((min, max)) = value.split_once("..") {
+                values.push(Value::Real(parse_number(field, min)?));
+                values.push(Value::Real(parse_number(field, max)?));
+                return Ok(format!("{} BETWEEN ? AND ?", expr));
+            }
+            let (op, number) = [">=", "<=", ">", "<", "="]
+                .iter()
+                .find_map(|op| value.strip_prefix(op).map(|rest| (*op, rest)))
+                .unwrap_or(("=", value));
+            values.push(Value::Real(parse_number(field, number)?));
+            Ok(format!("{} {} ?", expr, op))
+        }
+        FieldKind::Flag(expr) => {
+            values.push(Value::Integer(parse_flag(field, value)? as i64));
+            Ok(format!("{} = ?", expr))
+        }
+        FieldKind::Tag => {
+            let path = value.trim().trim_matches('/').to_string();
+            values.push(Value::Text(path.clone()));
+            values.push(Value::Text(format!("{}/%", like_pattern(&path))));
+            Ok("EXISTS (
+                SELECT 1 FROM FileTags ft JOIN Tags t ON t.id = ft.tag_id
+                WHERE ft.file_id = f.id AND (t.path = ? OR t.path LIKE ? ESCAPE '\\')
+            )"
+            .to_string())
+        }
+    }
+}
+
+/// Compiles a collection query. Errors are messages meant for the person who typed it.
+pub fn compile_query(query: &str) -> std::result::Result<CompiledQuery, String> {
+    let mut values = Vec::new();
+    let mut groups: Vec<Vec<String>> = vec![Vec::new()];
+
+    for token in tokenize(query)? {
+        if token == "OR" {
+            groups.push(Vec::new());
+            continue;
+        }
+        let (negated, term) = match token.strip_prefix('-') {
+            Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, token.as_str()),
+        };
+        let (field, value) = match term.split_once(':') {
+            Some((field, value)) => (field.to_lowercase(), value),
+            None => ("name".to_string(), term),
+        };
+        if value.is_empty() {
+            return Err(format!("{}: needs a value.", field));
+        }
+        let kind = FIELDS
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, kind)| *kind)
+            .ok_or_else(|| {
+                let known: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "Unknown field '{}'. Known fields: {}.",
+                    field,
+                    known.join(", ")
+                )
+            })?;
+
+        let condition = compile_term(&field, kind, value, &mut values)?;
+        let condition = if negated {
+            // Missing values make the comparison NULL; NOT NULL would drop the file too.
+            format!("NOT COALESCE(({}), 0)", condition)
+        } else {
+            format!("({})", condition)
+        };
+        groups.last_mut().unwrap().push(condition);
+    }
+
+    if groups.iter().any(|group| group.is_empty()) {
+        return Err("The query is empty, or OR is missing a term on one side.".to_string());
+    }
+    let condition = groups
+        .iter()
+        .map(|group| format!("({})", group.join(" AND ")))
+        .collect::<Vec<_>>()
+        .join(" OR ");
+    Ok(CompiledQuery { condition, values })
+}
+
+// ---------------------------------------------------------------------------
+// Evaluation
+// ---------------------------------------------------------------------------
+
+/// Runs a query against every file the collection scope covers.
+pub fn evaluate_query(
+    conn: &Connection,
+    repo_id: Option<&str>,
+    query: &str,
+) -> std::result::Result<Vec<RepositoryFile>, Box<dyn Error + Send + Sync>> {
+    let compiled = compile_query(query)?;
+    let mut values = compiled.values;
+    let mut sql = format!(
+        "SELECT {}, f.repo_id FROM Files f WHERE f.deleted_at IS NULL AND ({})",
+        FILE_COLUMNS, compiled.condition
+    );
+    if let Some(repo_id) = repo_id {
+        sql.push_str(" AND f.repo_id = ?");
+        values.push(Value::Text(repo_id.to_string()));
+    }
+    sql.push_str(" ORDER BY f.name COLLATE NOCASE, f.id");
+
+    let mut stmt = conn.prepare(&sql)?;
+    let files = stmt
+        .query_map(params_from_iter(values), repository_file_from_row)?
+        .collect::<Result<Vec<RepositoryFile>>>()?;
+    Ok(files)
+}
+
+/// Re-evaluates a collection from scratch and replaces its cached members.
+pub fn refresh_collection(
+    conn: &Connection,
+    collection_id: &str,
+) -> std::result::Result<usize, Box<dyn Error + Send + Sync>> {
+    let collection = get_collection(conn, collection_id)?;
+    let files = evaluate_query(conn, collection.repo_id.as_deref(), &collection.query)?;
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "DELETE FROM SmartCollectionFiles WHERE collection_id = ?1",
+        params![collection_id],
+    )?;
+    {
+        let mut insert = tx.prepare_cached(
+            "INSERT INTO SmartCollectionFiles (collection_id, file_id) VALUES (?1, ?2)",
+        )?;
+        for entry in &files {
+            insert.execute(params![collection_id, entry.file.id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(files.len())
+}
+
+/// Re-checks one file against every collection that can contain it and updates the
+/// cached membership. Called whenever a file row is written or moves repository.
+pub fn refresh_file_collections(conn: &Connection, repo_id: &str, file_id: &str) -> Result<()> {
+    // A file that moved repositories leaves the collections of the one it came from.
+    conn.prepare_cached(
+        "DELETE FROM SmartCollectionFiles
+         WHERE file_id = ?1 AND collection_id IN (
+             SELECT id FROM SmartCollections WHERE repo_id IS NOT NULL AND repo_id != ?2
+         )",
+    )?
+    .execute(params![file_id, repo_id])?;
+
+    let collections: Vec<(String, String)> = conn
+        .prepare_cached(
+            "SELECT id, query FROM SmartCollections WHERE repo_id IS NULL OR repo_id = ?1",
+        )?
+        .query_map(params![repo_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(String, String)>>>()?;
+
+    for (collection_id, query) in collections {
+        // Queries are validated when saved, so this only fails if the language changed.
+        let compiled = match compile_query(&query) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                println!("Skipping smart collection {}: {}", collection_id, e);
+                continue;
+            }
+        };
+        let mut values = compiled.values;
+        values.push(Value::Text(file_id.to_string()));
+        let matches: bool = conn.query_row(
+            &format!(
+                "SELECT EXISTS (
+                    SELECT 1 FROM Files f WHERE ({}) AND f.id = ? AND f.deleted_at IS NULL
+                )",
+                compiled.condition
+            ),
+            params_from_iter(values),
+            |row| row.get(0),
+        )?;
+
+        if matches {
+            conn.prepare_cached(
+                "INSERT OR IGNORE INTO SmartCollectionFiles (collection_id, file_id) VALUES (?1, ?2)",
+            )?
+            .execute(params![collection_id, file_id])?;
+        } else {
+            conn.prepare_cached(
+                "DELETE FROM SmartCollectionFiles WHERE collection_id = ?1 AND file_id = ?2",
+            )?
+            .execute(params![collection_id, file_id])?;
+        }
+    }
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Collections
+// ---------------------------------------------------------------------------
+
+pub fn get_collection(conn: &Connection, collection_id: &str) -> Result<SmartCollection> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM SmartCollections c WHERE c.id = ?1",
+            COLLECTION_COLUMNS
+        ),
+        params![collection_id],
+        collection_from_row,
+    )
+}
+
+/// Lists the collections visible from a repository: its own plus the library-wide ones.
+/// Without a repository, lists every collection.
+pub fn get_collections(conn: &Connection, repo_id: Option<&str>) -> Result<Vec<SmartCollection>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM SmartCollections c
+         WHERE ?1 IS NULL OR c.repo_id IS NULL OR c.repo_id = ?1
+         ORDER BY c.name COLLATE NOCASE",
+        COLLECTION_COLUMNS
+    ))?;
+    let collections = stmt
+        .query_map(params![repo_id], collection_from_row)?
+        .collect::<Result<Vec<SmartCollection>>>()?;
+    Ok(collections)
+}
+
+/// Saves a new collection and fills it.
+pub fn create_collection(
+    conn: &Connection,
+    name: &str,
+    repo_id: Option<&str>,
+    query: &str,
+) -> std::result::Result<SmartCollection, Box<dyn Error + Send + Sync>> {
+    compile_query(query)?;
+    let id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO SmartCollections (id, name, repo_id, query, date_created)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            id,
+            name.trim(),
+            repo_id,
+            query.trim(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+        ],
+    )?;
+    refresh_collection(conn, &id)?;
+    Ok(get_collection(conn, &id)?)
+}
+
+/// Renames a collection and/or replaces its query, then refills it.
+pub fn update_collection(
+    conn: &Connection,
+    collection_id: &str,
+    name: &str,
+    query: &str,
+) -> std::result::Result<SmartCollection, Box<dyn Error + Send + Sync>> {
+    compile_query(query)?;
+    conn.execute(
+        "UPDATE SmartCollections SET name = ?1, query = ?2 WHERE id = ?3",
+        params![name.trim(), query.trim(), collection_id],
+    )?;
+    refresh_collection(conn, collection_id)?;
+    Ok(get_collection(conn, collection_id)?)
+}
+
+pub fn delete_collection(conn: &Connection, collection_id: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM SmartCollections WHERE id = ?1",
+        params![collection_id],
+    )?;
+    Ok(())
+}
+
+/// Returns the cached members of a collection.
+pub fn get_collection_files(conn: &Connection, collection_id: &str) -> Result<Vec<RepositoryFile>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {}, f.repo_id FROM SmartCollectionFiles cf
+         JOIN Files f ON f.id = cf.file_id
+         WHERE cf.collection_id = ?1
+         ORDER BY f.name COLLATE NOCASE, f.id",
+        FILE_COLUMNS
+    ))?;
+    let files = stmt
+        .query_map(params![collection_id], repository_file_from_row)?
+        .collect::<Result<Vec<RepositoryFile>>>()?;
+    Ok(files)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_smart_collections_command(
+    pool: State<'_, DbPool>,
+    repo_id: Option<String>,
+) -> Result<Vec<SmartCollection>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_collections(&conn, repo_id.as_deref()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_smart_collection_files_command(
+    pool: State<'_, DbPool>,
+    collection_id: String,
+) -> Result<Vec<RepositoryFile>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_collection_files(&conn, &collection_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Runs a query without saving it, so the UI can preview a collection while it is edited.
+#[tauri::command]
+pub async fn preview_smart_collection_command(
+    pool: State<'_, DbPool>,
+    repo_id: Option<String>,
+    query: String,
+) -> Result<Vec<RepositoryFile>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        evaluate_query(&conn, repo_id.as_deref(), &query).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn create_smart_collection_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    name: String,
+    repo_id: Option<String>,
+    query: String,
+) -> Result<SmartCollection, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = create_collection(&conn, &name, repo_id.as_deref(), &query);
+
+        let payload = match &result {
+            Ok(collection) => format!(
+                "Smart collection '{}' created with {} files.",
+                collection.name, collection.file_count
+            ),
+            Err(e) => format!("Failed to create smart collection '{}': {}", name, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "create_smart_collection",
+            repo_id.as_deref(),
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn update_smart_collection_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    collection_id: String,
+    name: String,
+    query: String,
+) -> Result<SmartCollection, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = update_collection(&conn, &collection_id, &name, &query);
+
+        let payload = match &result {
+            Ok(collection) => format!(
+                "Smart collection '{}' updated with {} files.",
+                collection.name, collection.file_count
+            ),
+            Err(e) => format!("Failed to update smart collection '{}': {}", name, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "update_smart_collection",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Re-evaluates a collection in full, e.g. after files changed outside the app.
+#[tauri::command]
+pub async fn refresh_smart_collection_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    collection_id: String,
+) -> Result<usize, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = refresh_collection(&conn, &collection_id);
+
+        let payload = match &result {
+            Ok(count) => format!("Smart collection refreshed: {} files.", count),
+            Err(e) => format!("Failed to refresh smart collection: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "refresh_smart_collection",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_smart_collection_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    collection_id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_collection(&conn, &collection_id);
+
+        let payload = match &result {
+            Ok(_) => "Smart collection deleted.".to_string(),
+            Err(e) => format!("Failed to delete smart collection: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_smart_collection",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{create_file, get_file, update_file};
+    use crate::commands::structures::FileMetadata;
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+
+    fn add_file(
+        conn: &Connection,
+        repo_id: &str,
+        name: &str,
+        edit: impl Fn(&mut FileMetadata),
+    ) -> String {
+        let mut file = sample_file(&scratch_dir(), name, b"audio");
+        edit(&mut file);
+        create_file(conn, repo_id, &file).unwrap();
+        file.id
+    }
+
+    fn names(conn: &Connection, repo_id: Option<&str>, query: &str) -> Vec<String> {
+        evaluate_query(conn, repo_id, query)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.file.name)
+            .collect()
+    }
+
+    #[test]
+    fn quoted_text_stays_one_term() {
+        assert_eq!(
+            tokenize(r#"  genre:trap tag:"for artist X" -"big room" "#).unwrap(),
+            vec!["genre:trap", "tag:for artist X", "-big room"]
+        );
+        assert!(tokenize(r#"tag:"open"#).is_err());
+    }
+
+    #[test]
+    fn terms_compile_to_bound_conditions() {
+        let compiled = compile_query("genre:trap bpm:>=140 OR -accessible:no").unwrap();
+        assert_eq!(
+            compiled.condition,
+            "((f.meta_genre LIKE ? ESCAPE '\\') AND (CAST(NULLIF(f.meta_bpm, '') AS REAL) >= ?)) \
+             OR (NOT COALESCE((COALESCE(f.accessible, 0) = ?), 0))"
+        );
+        assert_eq!(
+            compiled.values,
+            vec![
+                Value::Text("%trap%".to_string()),
+                Value::Real(140.0),
+                Value::Integer(0)
+            ]
+        );
+        assert_eq!(like_pattern("50%_off*"), "50\\%\\_off%");
+    }
+
+    #[test]
+    fn bad_queries_explain_themselves() {
+        for (query, message) in [
+            ("", "empty"),
+            ("genre:trap OR", "OR is missing"),
+            ("mood:dark", "Unknown field 'mood'"),
+            ("genre:", "genre: needs a value"),
+            ("bpm:fast", "'fast' is not a number"),
+            ("bpm:100..", "is not a number"),
+            ("accessible:maybe", "expects true or false"),
+        ] {
+            let error = compile_query(query).err().unwrap();
+            assert!(error.contains(message), "{}: {}", query, error);
+        }
+    }
+
+    #[test]
+    fn queries_match_fields_ranges_tags_and_alternatives() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        add_repository(&conn, "other");
+        add_file(&conn, "repo", "Dark Trap.wav", |f| {
+            f.meta_genre = Some("Trap".to_string());
+            f.meta_bpm = Some("142".to_string());
+            f.tags = Some("drums/808".to_string());
+        });
+        add_file(&conn, "repo", "Slow Jam.mp3", |f| {
+            f.meta_genre = Some("R&B".to_string());
+            f.meta_bpm = Some("70".to_string());
+        });
+        add_file(&conn, "other", "Untitled.wav", |_| {});
+
+        assert_eq!(names(&conn, None, "genre:trap"), vec!["Dark Trap.wav"]);
+        assert_eq!(names(&conn, None, "bpm:60..100"), vec!["Slow Jam.mp3"]);
+        assert_eq!(names(&conn, None, "tag:drums"), vec!["Dark Trap.wav"]);
+        assert!(names(&conn, None, "tag:drum").is_empty());
+        assert_eq!(
+            names(&conn, None, "encoding:wav"),
+            vec!["Dark Trap.wav", "Untitled.wav"]
+        );
+        assert_eq!(
+            names(&conn, Some("repo"), "encoding:wav"),
+            vec!["Dark Trap.wav"]
+        );
+        // A file without a BPM still counts as "not over 100".
+        assert_eq!(
+            names(&conn, None, "-bpm:>100"),
+            vec!["Slow Jam.mp3", "Untitled.wav"]
+        );
+        assert_eq!(
+            names(&conn, None, "slow OR genre:=trap"),
+            vec!["Dark Trap.wav", "Slow Jam.mp3"]
+        );
+        assert_eq!(names(&conn, None, "name:*.mp3"), vec!["Slow Jam.mp3"]);
+    }
+
+    #[test]
+    fn writing_a_file_updates_collection_membership() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let fast = create_collection(&conn, "Fast", Some("repo"), "bpm:>=140").unwrap();
+        let everywhere = create_collection(&conn, "Wav", None, "encoding:wav").unwrap();
+        let file_id = add_file(&conn, "repo", "a.wav", |f| {
+            f.meta_bpm = Some("90".to_string())
+        });
+
+        assert!(get_collection_files(&conn, &fast.id).unwrap().is_empty());
+        assert_eq!(get_collection(&conn, &everywhere.id).unwrap().file_count, 1);
+
+        let file = FileMetadata {
+            meta_bpm: Some("150".to_string()),
+            ..get_file(&conn, "repo", &file_id).unwrap()
+        };
+        update_file(&conn, "repo", &file).unwrap();
+        assert_eq!(get_collection(&conn, &fast.id).unwrap().file_count, 1);
+
+        assert!(create_collection(&conn, "Broken", None, "bpm:fast").is_err());
+        assert_eq!(get_collections(&conn, Some("repo")).unwrap().len(), 2);
+    }
+}
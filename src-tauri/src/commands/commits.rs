@@ -0,0 +1,515 @@
+// src/commands/commits.rs
+//! Repository commits: a message, author and timestamp attached to a snapshot of every
+//! file row in a repository (path, content hash, tags and `meta_*` fields).
+//! Content hashes refer to objects in the version store (see `versions.rs`) and always
+//! describe the audio on disk at commit time; a file missing from disk has none.
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{get_connection, get_files_in_repository, get_repository, DbPool};
+use crate::commands::file_ops::{read_date_modified, timestamps_match};
+use crate::commands::structures::{
+    ChangeKind, Commit, CommitDetails, CommitFile, CommitFileChange, FileMetadata,
+};
+use crate::commands::versions::{
+    file_state_differs, get_latest_file_version, hash_file, record_file_version,
+};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use tauri::{State, Window};
+use uuid::Uuid;
+
+const COMMIT_COLUMNS: &str = "c.id, c.repo_id, c.parent_id, c.message, c.author, c.timestamp,
+    (SELECT COUNT(*) FROM CommitFiles cf WHERE cf.commit_id = c.id)";
+
+fn commit_from_row(row: &Row) -> rusqlite::Result<Commit> {
+    Ok(Commit {
+        id: row.get(0)?,
+        repo_id: row.get(1)?,
+        parent_id: row.get(2)?,
+        message: row.get(3)?,
+        author: row.get(4)?,
+        timestamp: row.get(5)?,
+        file_count: row.get(6)?,
+    })
+}
+
+fn commit_file_from_row(row: &Row) -> rusqlite::Result<CommitFile> {
+    let metadata_json: String = row.get(4)?;
+    let metadata: FileMetadata = serde_json::from_str(&metadata_json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(4, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    Ok(CommitFile {
+        commit_id: row.get(0)?,
+        file_id: row.get(1)?,
+        path: row.get(2)?,
+        content_hash: row.get(3)?,
+        metadata,
+    })
+}
+
+/// Returns the most recent commit of a repository, if any.
+pub fn get_head_commit(conn: &Connection, repo_id: &str) -> rusqlite::Result<Option<Commit>> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM Commits c WHERE c.repo_id = ?1 ORDER BY c.timestamp DESC, c.rowid DESC LIMIT 1",
+            COMMIT_COLUMNS
+        ),
+        params![repo_id],
+        commit_from_row,
+    )
+    .optional()
+}
+
+/// Resolves the content hash of a file's audio as it is on disk, snapshotting it if
+/// needed. A file missing from disk has no hash; one that can't be snapshotted, or whose
+/// audio changes while it is, fails the commit.
+fn current_content_hash(
+    conn: &Connection,
+    repo_id: &str,
+    file: &FileMetadata,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    if !Path::new(&file.path).exists() {
+        return Ok(None);
+    }
+    record_file_version(conn, repo_id, file)
+        .map_err(|e| format!("Failed to snapshot '{}': {}", file.name, e))?;
+    let on_disk = read_date_modified(&file.path)?;
+    match get_latest_file_version(conn, &file.id)? {
+        Some(version) if timestamps_match(&version.date_modified, &on_disk) => {
+            Ok(Some(version.content_hash))
+        }
+        _ => Err(format!(
+            "The audio of '{}' changed while it was being committed; refresh the repository and try again.",
+            file.name
+        )
+        .into()),
+    }
+}
+
+/// Resolves the content hash of a file's audio as it is on disk without recording
+/// anything, reusing the latest version when the audio hasn't changed since.
+fn disk_content_hash(
+    conn: &Connection,
+    file: &FileMetadata,
+) -> Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    let path = Path::new(&file.path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let on_disk = read_date_modified(&file.path)?;
+    match get_latest_file_version(conn, &file.id)? {
+        Some(version) if timestamps_match(&version.date_modified, &on_disk) => {
+            Ok(Some(version.content_hash))
+        }
+        _ => Ok(Some(hash_file(path)?)),
+    }
+}
+
+/// Captures the current state of every file in a live repository as a new commit.
+pub fn commit_repository(
+    conn: &Connection,
+    repo_id: &str,
+    message: &str,
+    author: &str,
+) -> Result<Commit, Box<dyn Error + Send + Sync>> {
+    get_repository(conn, repo_id)?;
+    let files = get_files_in_repository(conn, repo_id)?;
+    let parent_id = get_head_commit(conn, repo_id)?.map(|c| c.id);
+
+    let snapshots = files
+        .into_iter()
+        .map(|file| {
+            let hash = current_content_hash(conn, repo_id, &file)?;
+            Ok((file, hash))
+        })
+        .collect::<Result<Vec<(FileMetadata, Option<String>)>, Box<dyn Error + Send + Sync>>>()?;
+
+    let commit = Commit {
+        id: Uuid::new_v4().to_string(),
+        repo_id: repo_id.to_string(),
+        parent_id,
+        message: message.to_string(),
+        author: author.to_string(),
+        timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        file_count: snapshots.len() as i64,
+    };
+
+    // The connection is shared with the caller, so the transaction can't borrow it mutably.
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "INSERT INTO Commits (id, repo_id, parent_id, message, author, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            commit.id,
+            commit.repo_id,
+            commit.parent_id,
+            commit.message,
+            commit.author,
+            commit.timestamp,
+        ],
+    )?;
+    {
+        let mut stmt = tx.prepare(
+            "INSERT INTO CommitFiles (commit_id, file_id, path, content_hash, metadata)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (file, hash) in &snapshots {
+            stmt.execute(params![
+                commit.id,
+                file.id,
+                file.path,
+                hash,
+                serde_json::to_string(file)?,
+            ])?;
+        }
+    }
+    tx.commit()?;
+
+    println!(
+        "Committed {} files in repository '{}': {}",
+        commit.file_count, repo_id, commit.message
+    );
+    Ok(commit)
+}
+
+/// Walks a repository's history from its newest commit backwards.
+pub fn get_commit_log(
+    conn: &Connection,
+    repo_id: &str,
+    limit: Option<u32>,
+) -> rusqlite::Result<Vec<Commit>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM Commits c WHERE c.repo_id = ?1 ORDER BY c.timestamp DESC, c.rowid DESC LIMIT ?2",
+        COMMIT_COLUMNS
+    ))?;
+    let limit = limit.map(i64::from).unwrap_or(-1);
+    let commits = stmt
+        .query_map(params![repo_id, limit], commit_from_row)?
+        .collect::<rusqlite::Result<Vec<Commit>>>()?;
+    Ok(commits)
+}
+
+/// Returns a commit together with the file states it captured.
+pub fn get_commit(conn: &Connection, commit_id: &str) -> rusqlite::Result<CommitDetails> {
+    let commit = conn.query_row(
+        &format!("SELECT {} FROM Commits c WHERE c.id = ?1", COMMIT_COLUMNS),
+        params![commit_id],
+        commit_from_row,
+    )?;
+    let mut stmt = conn.prepare(
+        "SELECT commit_id, file_id, path, content_hash, metadata
+         FROM CommitFiles WHERE commit_id = ?1 ORDER BY path",
+    )?;
+    let files = stmt
+        .query_map(params![commit_id], commit_file_from_row)?
+        .collect::<rusqlite::Result<Vec<CommitFile>>>()?;
+    Ok(CommitDetails { commit, files })
+}
+
+/// Returns a commit of the given repository, failing for one that belongs to another.
+fn get_repository_commit(
+    conn: &Connection,
+    repo_id: &str,
+    commit_id: &str,
+) -> Result<CommitDetails, Box<dyn Error + Send + Sync>> {
+    let details = get_commit(conn, commit_id)?;
+    if details.commit.repo_id != repo_id {
+        return Err(format!(
+            "Commit '{}' doesn't belong to repository '{}'.",
+            commit_id, repo_id
+        )
+        .into());
+    }
+    Ok(details)
+}
+
+/// Lists what changed between a commit and either another commit or the repository's
+/// current state on disk (when `against_commit_id` is `None`). Both commits must belong
+/// to the repository.
+pub fn diff_commit(
+    conn: &Connection,
+    repo_id: &str,
+    commit_id: &str,
+    against_commit_id: Option<&str>,
+) -> Result<Vec<CommitFileChange>, Box<dyn Error + Send + Sync>> {
+    get_repository(conn, repo_id)?;
+    let base = get_repository_commit(conn, repo_id, commit_id)?;
+    let target: Vec<(FileMetadata, Option<String>)> = match against_commit_id {
+        Some(other_id) => get_repository_commit(conn, repo_id, other_id)?
+            .files
+            .into_iter()
+            .map(|f| (f.metadata, f.content_hash))
+            .collect(),
+        None => get_files_in_repository(conn, repo_id)?
+            .into_iter()
+            .map(|file| {
+                let hash = disk_content_hash(conn, &file)?;
+                Ok((file, hash))
+            })
+            .collect::<Result<_, Box<dyn Error + Send + Sync>>>()?,
+    };
+
+    let mut base_files: HashMap<String, CommitFile> = base
+        .files
+        .into_iter()
+        .map(|f| (f.file_id.clone(), f))
+        .collect();
+
+    let mut changes = Vec::new();
+    for (file, hash) in target {
+        match base_files.remove(&file.id) {
+            Some(old) => {
+                if old.content_hash != hash || file_state_differs(&old.metadata, &file) {
+                    changes.push(CommitFileChange {
+                        file_id: file.id.clone(),
+                        name: file.name.clone(),
+                        path: file.path.clone(),
+                        change: ChangeKind::Modified,
+                    });
+                }
+            }
+            None => changes.push(CommitFileChange {
+                file_id: file.id.clone(),
+                name: file.name.clone(),
+                path: file.path.clone(),
+                change: ChangeKind::Added,
+            }),
+        }
+    }
+    for (_, old) in base_files {
+        changes.push(CommitFileChange {
+            file_id: old.file_id,
+            name: old.metadata.name,
+            path: old.path,
+            change: ChangeKind::Removed,
+        });
+    }
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn commit_repository_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    message: String,
+    author: String,
+) -> Result<Commit, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = commit_repository(&conn, &repo_id, &message, &author);
+
+        let payload = match &result {
+            Ok(commit) => format!(
+                "Committed {} files to repository '{}'.",
+                commit.file_count, repo_id
+            ),
+            Err(e) => format!("Failed to commit repository '{}': {}", repo_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "commit_repository",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_commit_log_command(
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    limit: Option<u32>,
+) -> Result<Vec<Commit>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_commit_log(&conn, &repo_id, limit).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_commit_command(
+    pool: State<'_, DbPool>,
+    commit_id: String,
+) -> Result<CommitDetails, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_commit(&conn, &commit_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn diff_commit_command(
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    commit_id: String,
+    against_commit_id: Option<String>,
+) -> Result<Vec<CommitFileChange>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        diff_commit(&conn, &repo_id, &commit_id, against_commit_id.as_deref())
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{create_file, delete_file, delete_repository};
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+
+    fn add_file(conn: &Connection, repo_id: &str, name: &str, bytes: &[u8]) -> FileMetadata {
+        let file = sample_file(&scratch_dir(), name, bytes);
+        create_file(conn, repo_id, &file).unwrap();
+        file
+    }
+
+    /// Rewrites a file's audio with a modification time clearly later than before.
+    fn rewrite(file: &FileMetadata, bytes: &[u8]) {
+        fs::write(&file.path, bytes).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(&file.path)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+    }
+
+    #[test]
+    fn commits_capture_the_audio_on_disk_and_chain_to_their_parent() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = add_file(&conn, "repo", "take.wav", b"first take");
+
+        let first = commit_repository(&conn, "repo", "First", "ana").unwrap();
+        assert_eq!(first.parent_id, None);
+        assert_eq!(first.file_count, 1);
+        let details = get_commit(&conn, &first.id).unwrap();
+        assert_eq!(
+            details.files[0].content_hash,
+            Some(hash_file(Path::new(&file.path)).unwrap())
+        );
+
+        let second = commit_repository(&conn, "repo", "Second", "ana").unwrap();
+        assert_eq!(second.parent_id, Some(first.id.clone()));
+        let log: Vec<String> = get_commit_log(&conn, "repo", None)
+            .unwrap()
+            .into_iter()
+            .map(|commit| commit.id)
+            .collect();
+        assert_eq!(log, vec![second.id, first.id]);
+    }
+
+    #[test]
+    fn committing_needs_a_live_repository() {
+        let conn = test_connection();
+        assert!(commit_repository(&conn, "unknown", "Nothing", "ana").is_err());
+        add_repository(&conn, "repo");
+        delete_repository(&conn, "repo").unwrap();
+        assert!(commit_repository(&conn, "repo", "Trashed", "ana").is_err());
+    }
+
+    #[test]
+    fn missing_files_have_no_content_and_unreadable_ones_fail_the_commit() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let missing = add_file(&conn, "repo", "missing.wav", b"gone");
+        fs::remove_file(&missing.path).unwrap();
+
+        let commit = commit_repository(&conn, "repo", "Missing", "ana").unwrap();
+        assert_eq!(
+            get_commit(&conn, &commit.id).unwrap().files[0].content_hash,
+            None
+        );
+
+        let unreadable = add_file(&conn, "repo", "unreadable.wav", b"audio");
+        fs::remove_file(&unreadable.path).unwrap();
+        fs::create_dir(&unreadable.path).unwrap();
+        assert!(commit_repository(&conn, "repo", "Unreadable", "ana").is_err());
+        assert_eq!(get_commit_log(&conn, "repo", None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn diffing_against_the_repository_sees_the_disk() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = add_file(&conn, "repo", "take.wav", b"first take");
+        let commit = commit_repository(&conn, "repo", "First", "ana").unwrap();
+        assert!(diff_commit(&conn, "repo", &commit.id, None)
+            .unwrap()
+            .is_empty());
+
+        rewrite(&file, b"second take");
+        let changes = diff_commit(&conn, "repo", &commit.id, None).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].file_id, file.id);
+        assert!(matches!(changes[0].change, ChangeKind::Modified));
+    }
+
+    #[test]
+    fn diffing_two_commits_lists_added_and_removed_files() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let old = add_file(&conn, "repo", "old.wav", b"old");
+        let first = commit_repository(&conn, "repo", "First", "ana").unwrap();
+        let new = add_file(&conn, "repo", "new.wav", b"new");
+        delete_file(&conn, "repo", &old.id).unwrap();
+        let second = commit_repository(&conn, "repo", "Second", "ana").unwrap();
+
+        let changes: Vec<(String, bool)> = diff_commit(&conn, "repo", &first.id, Some(&second.id))
+            .unwrap()
+            .into_iter()
+            .map(|change| (change.file_id, matches!(change.change, ChangeKind::Added)))
+            .collect();
+        assert_eq!(changes.len(), 2);
+        assert!(changes.contains(&(new.id, true)));
+        assert!(changes.contains(&(old.id, false)));
+    }
+
+    #[test]
+    fn commits_of_another_repository_are_refused() {
+        let conn = test_connection();
+        add_repository(&conn, "one");
+        add_repository(&conn, "two");
+        let one = commit_repository(&conn, "one", "One", "ana").unwrap();
+        let two = commit_repository(&conn, "two", "Two", "ana").unwrap();
+
+        assert!(diff_commit(&conn, "two", &one.id, None).is_err());
+        assert!(diff_commit(&conn, "one", &one.id, Some(&two.id)).is_err());
+        assert!(diff_commit(&conn, "one", &one.id, Some(&one.id))
+            .unwrap()
+            .is_empty());
+    }
+}
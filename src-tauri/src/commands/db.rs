@@ -1,14 +1,29 @@
 // src/commands/db.rs
 //! This module handles all reads and writes to the SQLite database,
 //! including operations on repositories, file metadata, and settings.
-use crate::commands::structures::{AppSettings, FileMetadata, Repository, TrackedFolder, Contact, ContactList};
-use chrono::{DateTime, FixedOffset, Utc};
+use crate::background::folder_watcher::stop_repository_watchers;
+use crate::commands::activity::{finish_command, record_activity, NO_FILES};
+use crate::commands::assets::{remove_unused_assets, sync_file_asset};
+use crate::commands::structures::{
+    ActivityActor, AppSettings, Contact, ContactList, FileMetadata, FilePage, FileQuery,
+    FileSortKey, Repository, RepositoryFile, SortDirection, TrackedFolder,
+};
+use crate::commands::collections::refresh_file_collections;
+use crate::commands::file_ops::{normalize_timestamp, parse_timestamp_millis};
+use crate::commands::journal::{journal_file_rows, journal_moved_files, journal_trashed_files};
+use crate::commands::relations::{refresh_related_files, related_file_ids, related_file_list};
+use crate::commands::search::{index_file, unindex_file};
+use crate::commands::tags::{join_tag_paths, link_file_tags, resolve_tags};
+use crate::commands::versions::{file_state_differs, record_file_version};
+use chrono::{SecondsFormat, Utc};
 use once_cell::sync::OnceCell;
-use regex::Regex;
-use rusqlite::{params, Connection, Result};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, params_from_iter, types::Value, Connection, Result, Row};
 use std::collections::HashMap;
-use std::time::UNIX_EPOCH;
-use tauri::{Emitter, Window};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{Emitter, State, Window};
 use uuid::Uuid;
 
 pub static APP_DB_PATH: OnceCell<String> = OnceCell::new();
@@ -21,169 +36,102 @@ fn get_db_path() -> String {
         .unwrap_or_else(|| "db.sqlite".to_string())
 }
 
-/// Establish a connection to the database and ensure that the core tables exist.
-/// This function creates the Repositories and Settings tables, inserting default settings if needed.
-pub fn establish_connection() -> Result<Connection> {
-    let db_path = get_db_path();
-    let conn = Connection::open(db_path)?;
-    
-    // Create the Repositories table.
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS Repositories (
-            id          TEXT PRIMARY KEY,
-            name        TEXT NOT NULL,
-            description TEXT
-        )",
-        [],
-    )?;
-
-    // Create the AppSettings table.
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS AppSettings (
-             general_auto_fingerprint INTEGER NOT NULL,
-             general_theme TEXT NOT NULL,
-             audio_autoplay INTEGER NOT NULL,
-             setup_selected_repository TEXT
-         )",
-        [],
-    )?;
-
-    // Create TrackedFolders table (call in `establish_connection`)
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS TrackedFolders (
-            id          TEXT PRIMARY KEY,
-            repo_id     TEXT NOT NULL,
-            folder_path TEXT NOT NULL
-        )",
-        [],
-    )?;
+/// Returns the `RepoStudio_AppData` directory that holds the database and the object store.
+pub fn get_app_data_dir() -> PathBuf {
+    match Path::new(&get_db_path()).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
 
-    // Create the bundles table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS Bundles (
-            id          TEXT PRIMARY KEY,
-            name        TEXT,
-            description TEXT,
-            date_created TEXT NOT NULL,
-            included_files TEXT NOT NULL,
-            recipients TEXT NOT NULL
-        )",
-        [],
-    )?;
+/// The app-wide SQLite connection pool, registered as Tauri managed state.
+pub type DbPool = Pool<SqliteConnectionManager>;
 
-    // Create the contacts table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS Contacts (
-            id          TEXT PRIMARY KEY,
-            name        TEXT NOT NULL,
-            email       TEXT NOT NULL,
-            phone       TEXT,
-            handle      TEXT,
-            notes       TEXT,
-            profession  TEXT
-
-        )",
-        [],
-    )?;
+/// A connection checked out of the pool; it goes back to the pool when dropped.
+pub type DbConnection = PooledConnection<SqliteConnectionManager>;
 
-    // Create the ContactLists table
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS ContactLists (
-            id   TEXT PRIMARY KEY,
-            name TEXT NOT NULL
-        )",
-        [],
-    )?;
+/// How many connections the pool keeps open. WAL lets readers run alongside one writer.
+const POOL_SIZE: u32 = 8;
 
-    // Junction table for mapping contacts into lists (many‑to‑many)
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS ContactListContacts (
-            list_id    TEXT NOT NULL,
-            contact_id TEXT NOT NULL,
-            PRIMARY KEY (list_id, contact_id),
-            FOREIGN KEY (list_id)    REFERENCES ContactLists(id) ON DELETE CASCADE,
-            FOREIGN KEY (contact_id) REFERENCES Contacts(id)     ON DELETE CASCADE
-        )",
-        [],
-    )?;
+/// How long a connection waits on a lock held by another connection before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
 
-    // Ensure the default “All Contacts” list exists
-    let default_list_exists: bool = conn.query_row(
-        "SELECT EXISTS(SELECT 1 FROM ContactLists WHERE id = ?1)",
-        params!["all"],
-        |row| row.get(0),
-    )?;
-    if !default_list_exists {
-        conn.execute(
-            "INSERT INTO ContactLists (id, name) VALUES (?1, ?2)",
-            params!["all", "All Contacts"],
-        )?;
-    }
+/// Number of prepared statements each connection keeps cached.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
 
-    let app_settings_exists: bool =
-        conn.query_row("SELECT EXISTS(SELECT 1 FROM AppSettings)", [], |row| {
-            row.get(0)
-        })?;
-    if !app_settings_exists {
-        conn.execute(
-            "INSERT INTO AppSettings (general_auto_fingerprint, general_theme, audio_autoplay, setup_selected_repository)
-             VALUES (?1, ?2, ?3, ?4)",
-            rusqlite::params![0, "theme-light", 0, "default"],
+/// Creates the connection pool for the database file.
+/// Every connection runs in WAL mode with a busy timeout, foreign keys enabled and a
+/// prepared statement cache. The schema itself is created and upgraded once at startup
+/// by `migrations::run_migrations`.
+pub fn create_pool() -> std::result::Result<DbPool, r2d2::Error> {
+    let manager = SqliteConnectionManager::file(get_db_path()).with_init(|conn| {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA synchronous = NORMAL;
+             PRAGMA foreign_keys = ON;",
         )?;
-    }
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Ok(())
+    });
+    Pool::builder().max_size(POOL_SIZE).build(manager)
+}
 
-    Ok(conn)
+/// Checks a connection out of the pool, for use inside `spawn_blocking`.
+pub fn get_connection(pool: &DbPool) -> std::result::Result<DbConnection, String> {
+    pool.get().map_err(|e| format!("Failed to get a database connection: {}", e))
 }
 
-/// Sanitizes an identifier (for example, a repository ID) so it can be safely used as a SQL table name.
-pub fn sanitize_identifier(identifier: &str) -> Result<String> {
-    let re = Regex::new(r"^[a-zA-Z0-9_-]+$").unwrap();
-    if re.is_match(identifier) {
-        Ok(identifier.to_string())
-    } else {
-        Err(rusqlite::Error::InvalidQuery)
-    }
+/// Columns selected for a `FileMetadata`, in the order `file_from_row` expects.
+/// Queries alias the Files table as `f`.
+pub const FILE_COLUMNS: &str = "f.id, f.name, f.encoding, f.path, f.related_files, f.tags,
+    f.date_created, f.date_modified, f.audio_fingerprint, f.accessible,
+    f.meta_title, f.meta_comment, f.meta_album_artist, f.meta_album, f.meta_track_number,
+    f.meta_genre, f.meta_bit_rate, f.meta_channels, f.meta_sample_rate, f.meta_size_on_disk,
+    f.meta_bpm, f.meta_key, f.notes";
+
+/// Maps a row selected with `FILE_COLUMNS` into a `FileMetadata`.
+pub fn file_from_row(row: &Row) -> Result<FileMetadata> {
+    Ok(FileMetadata {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        encoding: row.get(2)?,
+        path: row.get(3)?,
+        related_files: row.get(4)?,
+        tags: row.get(5)?,
+        date_created: row.get(6)?,
+        date_modified: row.get(7)?,
+        audio_fingerprint: row.get(8)?,
+        accessible: row.get(9)?,
+        meta_title: row.get(10)?,
+        meta_comment: row.get(11)?,
+        meta_album_artist: row.get(12)?,
+        meta_album: row.get(13)?,
+        meta_track_number: row.get(14)?,
+        meta_genre: row.get(15)?,
+        meta_bit_rate: row.get(16)?,
+        meta_channels: row.get(17)?,
+        meta_sample_rate: row.get(18)?,
+        meta_size_on_disk: row.get(19)?,
+        meta_bpm: row.get(20)?,
+        meta_key: row.get(21)?,
+        notes: row.get(22)?,
+    })
 }
 
-/// Ensures that the file table for the given repository exists.
-pub fn ensure_files_table(conn: &Connection, repo_id: &str) -> Result<()> {
-    let safe_id = sanitize_identifier(repo_id)?;
-    conn.execute(
-        &format!(
-            "CREATE TABLE IF NOT EXISTS \"{}\" (
-                id                  TEXT PRIMARY KEY,
-                name                TEXT NOT NULL,
-                encoding            TEXT NOT NULL,
-                path                TEXT NOT NULL,
-                related_files       TEXT,
-                tags                TEXT,
-                date_created        TEXT NOT NULL,
-                date_modified       TEXT NOT NULL,
-                audio_fingerprint   TEXT,
-                accessible          BOOLEAN,
-                meta_title          TEXT,
-                meta_comment        TEXT,
-                meta_album_artist   TEXT,
-                meta_album          TEXT,
-                meta_track_number   TEXT,
-                meta_genre          TEXT,
-                meta_bit_rate       TEXT,
-                meta_channels       TEXT,
-                meta_sample_rate    TEXT,
-                meta_size_on_disk   TEXT
-            )",
-            safe_id
-        ),
-        [],
-    )?;
-    Ok(())
+/// Reads a `FILE_COLUMNS` row followed by `f.repo_id`.
+pub fn repository_file_from_row(row: &Row) -> Result<RepositoryFile> {
+    Ok(RepositoryFile {
+        file: file_from_row(row)?,
+        repo_id: row.get(23)?,
+    })
 }
 
 // Retrieves the app settings from the AppSettings table.
-pub fn get_app_settings() -> Result<AppSettings> {
-    let conn = establish_connection()?;
+pub fn get_app_settings(conn: &Connection) -> Result<AppSettings> {
     let mut stmt = conn.prepare(
-        "SELECT general_auto_fingerprint, general_theme, audio_autoplay, setup_selected_repository
+        "SELECT general_auto_fingerprint, general_theme, audio_autoplay, setup_selected_repository,
+                trash_retention_days
          FROM AppSettings LIMIT 1",
     )?;
     let settings = stmt.query_row([], |row| {
@@ -196,30 +144,35 @@ pub fn get_app_settings() -> Result<AppSettings> {
             general_theme,
             audio_autoplay: autoplay_flag != 0,
             setup_selected_repository: repo_id,
+            trash_retention_days: Some(row.get(4)?),
         })
     })?;
     Ok(settings)
 }
 
 // Updates the app settings row with the provided values.
+// A `trash_retention_days` of None keeps the stored retention.
 pub fn update_app_settings(
+    conn: &Connection,
     general_auto_fingerprint: bool,
     general_theme: String,
     audio_autoplay: bool,
     setup_selected_repository: &str,
+    trash_retention_days: Option<u32>,
 ) -> Result<()> {
-    let conn = establish_connection()?;
     conn.execute(
         "UPDATE AppSettings
          SET general_auto_fingerprint = ?1,
                 general_theme = ?2,
                 audio_autoplay = ?3,
-                setup_selected_repository = ?4",
+                setup_selected_repository = ?4,
+                trash_retention_days = COALESCE(?5, trash_retention_days)",
         params![
             if general_auto_fingerprint { 1 } else { 0 },
             general_theme,
             if audio_autoplay { 1 } else { 0 },
             setup_selected_repository,
+            trash_retention_days,
         ],
     )?;
     Ok(())
@@ -229,55 +182,119 @@ pub fn update_app_settings(
 // Repository operations
 // ---------------------------------------------------------------------------
 
-pub fn create_repository_with_id(id: &str, name: &str, description: &str) -> Result<()> {
-    let conn = establish_connection()?;
+/// Creates a repository, nested under `parent_id` if one is given.
+pub fn create_repository_with_id(
+    conn: &Connection,
+    id: &str,
+    name: &str,
+    description: &str,
+    parent_id: Option<&str>,
+) -> Result<()> {
+    if let Some(parent_id) = parent_id {
+        get_repository(conn, parent_id)?;
+    }
     conn.execute(
-        "INSERT INTO Repositories (id, name, description) VALUES (?1, ?2, ?3)",
-        params![id, name, description],
+        "INSERT INTO Repositories (id, name, description, parent_id) VALUES (?1, ?2, ?3, ?4)",
+        params![id, name, description, parent_id],
     )?;
-    // Create a corresponding file table for the new repository.
-    ensure_files_table(&conn, id)?;
     Ok(())
 }
 
-pub fn delete_repository(id: &str) -> Result<()> {
-    let conn = establish_connection()?;
+/// Moves a repository to the trash. Its live files go with it, stamped with the same
+/// time, so restoring the repository brings back exactly those files.
+pub fn delete_repository(conn: &Connection, id: &str) -> Result<()> {
+    let deleted_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "UPDATE Repositories SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+        params![deleted_at, id],
+    )?;
+    tx.execute(
+        "UPDATE Files SET deleted_at = ?1 WHERE repo_id = ?2 AND deleted_at IS NULL",
+        params![deleted_at, id],
+    )?;
+    tx.execute(
+        "DELETE FROM SmartCollectionFiles
+         WHERE file_id IN (SELECT id FROM Files WHERE repo_id = ?1)",
+        params![id],
+    )?;
+    tx.commit()
+}
+
+/// Deletes a repository for good; its files are removed with it by the foreign key cascade,
+/// and its commits, tracked folders and file versions here. Returns the content hashes the
+/// deleted versions and commits referred to, for `remove_unreferenced_objects` to clear
+/// from the object store once the caller's transaction commits.
+pub fn purge_repository(conn: &Connection, id: &str) -> Result<Vec<String>> {
+    let content_hashes = dropped_content_hashes(
+        conn,
+        "SELECT content_hash FROM FileVersions WHERE repo_id = ?1
+         UNION
+         SELECT cf.content_hash FROM CommitFiles cf
+         JOIN Commits c ON c.id = cf.commit_id
+         WHERE c.repo_id = ?1 AND cf.content_hash IS NOT NULL",
+        id,
+    )?;
+    conn.execute(
+        "DELETE FROM FilesSearch WHERE file_id IN (SELECT id FROM Files WHERE repo_id = ?1)",
+        params![id],
+    )?;
+    conn.execute("DELETE FROM FileVersions WHERE repo_id = ?1", params![id])?;
+    conn.execute("DELETE FROM Commits WHERE repo_id = ?1", params![id])?;
+    conn.execute("DELETE FROM TrackedFolders WHERE repo_id = ?1", params![id])?;
     conn.execute("DELETE FROM Repositories WHERE id = ?1", params![id])?;
-    let safe_id = sanitize_identifier(id)?;
-    conn.execute(&format!("DROP TABLE IF EXISTS \"{}\"", safe_id), [])?;
-    Ok(())
+    remove_unused_assets(conn)?;
+    Ok(content_hashes)
 }
 
-pub fn get_repositories() -> Result<Vec<Repository>> {
-    let conn = establish_connection()?;
-    let mut stmt = conn.prepare("SELECT id, name, description FROM Repositories")?;
+/// The content hashes selected by `sql`, given one id.
+fn dropped_content_hashes(conn: &Connection, sql: &str, id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(sql)?;
+    let hashes = stmt
+        .query_map(params![id], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(hashes)
+}
+
+pub fn get_repositories(conn: &Connection) -> Result<Vec<Repository>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, description, parent_id FROM Repositories WHERE deleted_at IS NULL",
+    )?;
     let repos = stmt
         .query_map([], |row| {
             Ok(Repository {
                 id: row.get(0)?,
                 name: row.get(1)?,
                 description: row.get(2)?,
+                parent_id: row.get(3)?,
             })
         })?
         .collect::<Result<Vec<Repository>>>()?;
     Ok(repos)
 }
 
-pub fn get_repository(id: &str) -> Result<Repository> {
-    let conn = establish_connection()?;
-    let mut stmt = conn.prepare("SELECT id, name, description FROM Repositories WHERE id = ?1")?;
+pub fn get_repository(conn: &Connection, id: &str) -> Result<Repository> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, description, parent_id FROM Repositories
+         WHERE id = ?1 AND deleted_at IS NULL",
+    )?;
     let repo = stmt.query_row(params![id], |row| {
         Ok(Repository {
             id: row.get(0)?,
             name: row.get(1)?,
             description: row.get(2)?,
+            parent_id: row.get(3)?,
         })
     })?;
     Ok(repo)
 }
 
-pub fn update_repository(id: &str, name: &str, description: &str) -> Result<()> {
-    let conn = establish_connection()?;
+pub fn update_repository(
+    conn: &Connection,
+    id: &str,
+    name: &str,
+    description: &str,
+) -> Result<()> {
     conn.execute(
         "UPDATE Repositories SET name = ?1, description = ?2 WHERE id = ?3",
         params![name, description, id],
@@ -289,144 +306,130 @@ pub fn update_repository(id: &str, name: &str, description: &str) -> Result<()>
 // File operations
 // ---------------------------------------------------------------------------
 
-pub fn get_files_in_repository(repo_id: &str) -> Result<Vec<FileMetadata>> {
-    let conn = establish_connection()?;
-    let safe_repo_id = sanitize_identifier(repo_id)?;
-    ensure_files_table(&conn, repo_id)?;
-    let mut stmt = conn.prepare(&format!(
-        "SELECT
-            id,
-            name,
-            encoding,
-            path,
-            related_files,
-            tags,
-            date_created,
-            date_modified,
-            audio_fingerprint,
-            accessible,
-            meta_title,
-            meta_comment,
-            meta_album_artist,
-            meta_album,
-            meta_track_number,
-            meta_genre,
-            meta_bit_rate,
-            meta_channels,
-            meta_sample_rate,
-            meta_size_on_disk
-        FROM \"{}\"",
-        safe_repo_id
+pub fn get_files_in_repository(conn: &Connection, repo_id: &str) -> Result<Vec<FileMetadata>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM Files f WHERE f.repo_id = ?1 AND f.deleted_at IS NULL",
+        FILE_COLUMNS
     ))?;
     let files = stmt
-        .query_map([], |row| {
-            Ok(FileMetadata {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                encoding: row.get(2)?,
-                path: row.get(3)?,
-                related_files: row.get(4)?,
-                tags: row.get(5)?,
-                date_created: row.get(6)?,
-                date_modified: row.get(7)?,
-                audio_fingerprint: row.get(8)?,
-                accessible: row.get(9)?,
-                meta_title: row.get(10)?,
-                meta_comment: row.get(11)?,
-                meta_album_artist: row.get(12)?,
-                meta_album: row.get(13)?,
-                meta_track_number: row.get(14)?,
-                meta_genre: row.get(15)?,
-                meta_bit_rate: row.get(16)?,
-                meta_channels: row.get(17)?,
-                meta_sample_rate: row.get(18)?,
-                meta_size_on_disk: row.get(19)?,
-            })
-        })?
+        .query_map(params![repo_id], file_from_row)?
         .collect::<Result<Vec<FileMetadata>>>()?;
     Ok(files)
 }
 
-pub fn get_file(repo_id: &str, file_id: &str) -> Result<FileMetadata> {
-    let conn = establish_connection()?;
-    let safe_repo_id = sanitize_identifier(repo_id)?;
-    ensure_files_table(&conn, repo_id)?;
-    let mut stmt = conn.prepare(&format!(
-        "SELECT
-            id,
-            name,
-            encoding,
-            path,
-            related_files,
-            tags,
-            date_created,
-            date_modified,
-            audio_fingerprint,
-            accessible,
-            meta_title,
-            meta_comment,
-            meta_album_artist,
-            meta_album,
-            meta_track_number,
-            meta_genre,
-            meta_bit_rate,
-            meta_channels,
-            meta_sample_rate,
-            meta_size_on_disk
-         FROM \"{}\" WHERE id = ?1",
-        safe_repo_id
+pub fn get_file(conn: &Connection, repo_id: &str, file_id: &str) -> Result<FileMetadata> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM Files f WHERE f.repo_id = ?1 AND f.id = ?2 AND f.deleted_at IS NULL",
+        FILE_COLUMNS
     ))?;
-    let file = stmt.query_row(params![file_id], |row| {
-        Ok(FileMetadata {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            encoding: row.get(2)?,
-            path: row.get(3)?,
-            related_files: row.get(4)?,
-            tags: row.get(5)?,
-            date_created: row.get(6)?,
-            date_modified: row.get(7)?,
-            audio_fingerprint: row.get(8)?,
-            accessible: row.get(9)?,
-            meta_title: row.get(10)?,
-            meta_comment: row.get(11)?,
-            meta_album_artist: row.get(12)?,
-            meta_album: row.get(13)?,
-            meta_track_number: row.get(14)?,
-            meta_genre: row.get(15)?,
-            meta_bit_rate: row.get(16)?,
-            meta_channels: row.get(17)?,
-            meta_sample_rate: row.get(18)?,
-            meta_size_on_disk: row.get(19)?,
-        })
-    })?;
+    let file = stmt.query_row(params![repo_id, file_id], file_from_row)?;
     Ok(file)
 }
 
+/// Default and maximum page sizes for `query_files`.
+const DEFAULT_PAGE_SIZE: u32 = 100;
+const MAX_PAGE_SIZE: u32 = 1000;
+
+/// The SQL expression a listing is ordered by. Numeric metadata is stored as text, so it
+/// is cast to sort numerically; these match the expression indexes from migration 5.
+/// Files missing the value sort first ascending and last descending.
+fn sort_expression(key: FileSortKey) -> &'static str {
+    match key {
+        FileSortKey::Name => "f.name COLLATE NOCASE",
+        FileSortKey::DateModified => "f.date_modified_ms",
+        FileSortKey::Size => "CAST(f.meta_size_on_disk AS INTEGER)",
+        FileSortKey::BitRate => "CAST(f.meta_bit_rate AS INTEGER)",
+        FileSortKey::SampleRate => "CAST(f.meta_sample_rate AS INTEGER)",
+    }
+}
+
+/// Returns one sorted, filtered page of a repository's files together with the total
+/// number of matching files. Rows with equal sort values are ordered by id, so pages
+/// never overlap or skip rows.
+pub fn query_files(conn: &Connection, query: &FileQuery) -> Result<FilePage> {
+    let mut conditions = vec![
+        "f.repo_id = ?".to_string(),
+        "f.deleted_at IS NULL".to_string(),
+    ];
+    let mut values: Vec<Value> = vec![Value::Text(query.repo_id.clone())];
+
+    let filters = &query.filters;
+    if let Some(encoding) = &filters.encoding {
+        conditions.push("f.encoding = ? COLLATE NOCASE".to_string());
+        values.push(Value::Text(encoding.clone()));
+    }
+    if let Some(accessible) = filters.accessible {
+        conditions.push("f.accessible = ?".to_string());
+        values.push(Value::Integer(accessible as i64));
+    }
+    if let Some(has_fingerprint) = filters.has_fingerprint {
+        let condition = if has_fingerprint {
+            "COALESCE(f.audio_fingerprint, '') != ''"
+        } else {
+            "COALESCE(f.audio_fingerprint, '') = ''"
+        };
+        conditions.push(condition.to_string());
+    }
+    if let Some(genre) = &filters.genre {
+        conditions.push("f.meta_genre = ? COLLATE NOCASE".to_string());
+        values.push(Value::Text(genre.clone()));
+    }
+    let where_clause = conditions.join(" AND ");
+
+    let total: i64 = conn.query_row(
+        &format!("SELECT COUNT(*) FROM Files f WHERE {}", where_clause),
+        params_from_iter(values.iter()),
+        |row| row.get(0),
+    )?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let direction = match query.sort_direction {
+        SortDirection::Asc => "ASC",
+        SortDirection::Desc => "DESC",
+    };
+    let sql = format!(
+        "SELECT {} FROM Files f WHERE {} ORDER BY {} {}, f.id {} LIMIT ? OFFSET ?",
+        FILE_COLUMNS,
+        where_clause,
+        sort_expression(query.sort_by),
+        direction,
+        direction
+    );
+    values.push(Value::Integer(limit as i64));
+    values.push(Value::Integer(query.offset as i64));
+
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let files = stmt
+        .query_map(params_from_iter(values.iter()), file_from_row)?
+        .collect::<Result<Vec<FileMetadata>>>()?;
+
+    let end = query.offset as i64 + files.len() as i64;
+    Ok(FilePage {
+        next_offset: if end < total { Some(end as u32) } else { None },
+        files,
+        total,
+        offset: query.offset,
+        limit,
+    })
+}
+
 /// Helper: Normalize a file path by replacing backslashes with forward slashes.
 fn normalize_path(path: &str) -> String {
     path.replace("\\", "/")
 }
 
 /// Helper: Given a group of FileMetadata records, return a reference to the one with the most recent modification date.
+/// Records whose date can't be read lose to any that can.
 fn best_file_in_group(group: &[FileMetadata]) -> Option<&FileMetadata> {
-    group.iter().max_by(|a, b| {
-        let date_a = DateTime::parse_from_rfc3339(&a.date_modified).unwrap_or_else(|_| {
-            DateTime::<Utc>::from(UNIX_EPOCH).with_timezone(&FixedOffset::east_opt(0).unwrap())
-        });
-        let date_b = DateTime::parse_from_rfc3339(&b.date_modified).unwrap_or_else(|_| {
-            DateTime::<Utc>::from(UNIX_EPOCH).with_timezone(&FixedOffset::east_opt(0).unwrap())
-        });
-        date_a.cmp(&date_b)
-    })
+    group
+        .iter()
+        .max_by_key(|file| parse_timestamp_millis(&file.date_modified).unwrap_or(i64::MIN))
 }
 
 /// Removes duplicate file records from a repository—keeping only the most recently modified version.
-pub fn remove_duplicate_files_in_repository(repo_id: &str) -> Result<()> {
+pub fn remove_duplicate_files_in_repository(conn: &Connection, repo_id: &str) -> Result<()> {
     println!("Checking for duplicate files in repository: {}", repo_id);
-    let conn = establish_connection()?;
-    let safe_repo_id = sanitize_identifier(repo_id)?;
-    let files = get_files_in_repository(repo_id)?;
+    let files = get_files_in_repository(conn, repo_id)?;
     let mut groups: HashMap<String, Vec<FileMetadata>> = HashMap::new();
 
     for file in files {
@@ -439,10 +442,12 @@ pub fn remove_duplicate_files_in_repository(repo_id: &str) -> Result<()> {
             if let Some(best_file) = best_file_in_group(&group) {
                 for file in &group {
                     if file.id != best_file.id {
-                        conn.execute(
-                            &format!("DELETE FROM \"{}\" WHERE id = ?1", safe_repo_id),
-                            params![file.id],
-                        )?;
+                        let related = related_file_ids(conn, &file.id)?;
+                        conn.execute("DELETE FROM Files WHERE id = ?1", params![file.id])?;
+                        unindex_file(conn, &file.id)?;
+                        for related_id in related {
+                            refresh_related_files(conn, &related_id)?;
+                        }
                         println!(
                             "Deleted duplicate file with id: {} (path: {})",
                             file.id, normalized_path
@@ -452,41 +457,59 @@ pub fn remove_duplicate_files_in_repository(repo_id: &str) -> Result<()> {
             }
         }
     }
+    remove_unused_assets(conn)?;
 
     Ok(())
 }
 
-/// Inserts a new file record into the repository's file table.
-/// If a file with the same path already exists, the insertion is skipped.
-/// After insertion, duplicate files are removed automatically.
-pub fn create_file(repo_id: &str, file: &FileMetadata) -> Result<()> {
-    let conn = establish_connection()?;
-    let safe_repo_id = sanitize_identifier(repo_id)?;
-    ensure_files_table(&conn, repo_id)?;
+/// Inserts a new file record into the repository.
+/// If a file with the same path already exists in the repository, even in the trash, or the
+/// repository itself is in the trash, the insertion is skipped.
+/// The record joins the asset for its path, sharing its metadata with the file's records
+/// in other repositories, and its first state is recorded in the version history. After
+/// insertion, duplicate files are removed automatically.
+pub fn create_file(conn: &Connection, repo_id: &str, file: &FileMetadata) -> Result<()> {
+    // Folder watchers keep running for trashed repositories; don't add to them.
+    let repo_trashed: bool = conn
+        .prepare_cached(
+            "SELECT EXISTS(SELECT 1 FROM Repositories WHERE id = ?1 AND deleted_at IS NOT NULL)",
+        )?
+        .query_row(params![repo_id], |row| row.get(0))?;
+    if repo_trashed {
+        return Ok(());
+    }
 
     // Skip insert if a file with the same path already exists.
-    let mut stmt = conn.prepare(&format!(
-        "SELECT COUNT(*) FROM \"{}\" WHERE path = ?1",
-        safe_repo_id
-    ))?;
-    let count: i32 = stmt.query_row(params![file.path], |row| row.get(0))?;
+    let count: i32 = conn
+        .prepare_cached("SELECT COUNT(*) FROM Files WHERE repo_id = ?1 AND path = ?2")?
+        .query_row(params![repo_id, file.path], |row| row.get(0))?;
     if count > 0 {
         return Ok(());
     }
 
+    // Store the tag list in normalized form, matching the FileTags links, and the dates
+    // as RFC 3339. `related_files` is owned by the FileRelations table.
+    let tags = resolve_tags(conn, file.tags.as_deref())?;
+    let file = &FileMetadata {
+        tags: join_tag_paths(&tags),
+        related_files: related_file_list(conn, &file.id)?,
+        date_created: normalize_timestamp(&file.date_created),
+        date_modified: normalize_timestamp(&file.date_modified),
+        ..file.clone()
+    };
+
     conn.execute(
-        &format!(
-            "INSERT INTO \"{}\" (
-                id, name, encoding, path, related_files, tags,
-                date_created, date_modified, audio_fingerprint, accessible,
-                meta_title, meta_comment, meta_album_artist, meta_album,
-                meta_track_number, meta_genre, meta_bit_rate, meta_channels,
-                meta_sample_rate, meta_size_on_disk
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20)",
-            safe_repo_id
-        ),
+        "INSERT INTO Files (
+            id, repo_id, name, encoding, path, related_files, tags,
+            date_created, date_modified, audio_fingerprint, accessible,
+            meta_title, meta_comment, meta_album_artist, meta_album,
+            meta_track_number, meta_genre, meta_bit_rate, meta_channels,
+            meta_sample_rate, meta_size_on_disk, meta_bpm, meta_key,
+            date_created_ms, date_modified_ms, notes
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26)",
         params![
             file.id,
+            repo_id,
             file.name,
             file.encoding,
             file.path,
@@ -506,86 +529,290 @@ pub fn create_file(repo_id: &str, file: &FileMetadata) -> Result<()> {
             file.meta_channels,
             file.meta_sample_rate,
             file.meta_size_on_disk,
+            file.meta_bpm,
+            file.meta_key,
+            parse_timestamp_millis(&file.date_created),
+            parse_timestamp_millis(&file.date_modified),
+            file.notes,
         ],
     )?;
+    sync_file_asset(conn, &file.id)?;
+    link_file_tags(conn, &file.id, &tags)?;
+    index_file(conn, file)?;
+    refresh_file_collections(conn, repo_id, &file.id)?;
+    // The file's first state goes into its history straight away. If its audio can't be
+    // read yet, the background baseline task snapshots it later.
+    if file.accessible {
+        if let Err(e) = record_file_version(conn, repo_id, file) {
+            println!("Failed to record the first version of '{}': {}", file.name, e);
+        }
+    }
     // Automatically remove duplicates after inserting a new file.
-    remove_duplicate_files_in_repository(repo_id)?;
+    remove_duplicate_files_in_repository(conn, repo_id)?;
     Ok(())
 }
 
 /// Updates an existing file record.
-pub fn update_file(repo_id: &str, file: &FileMetadata) -> Result<()> {
-    let conn = establish_connection()?;
-    let safe_repo_id = sanitize_identifier(repo_id)?;
-    ensure_files_table(&conn, repo_id)?;
-    conn.execute(
-        &format!(
-            "UPDATE \"{}\" SET
-                name = ?1,
-                encoding = ?2,
-                path = ?3,
-                related_files = ?4,
-                tags = ?5,
-                date_created = ?6,
-                date_modified = ?7,
-                audio_fingerprint = ?8,
-                accessible = ?9,
-                meta_title = ?10,
-                meta_comment = ?11,
-                meta_album_artist = ?12,
-                meta_album = ?13,
-                meta_track_number = ?14,
-                meta_genre = ?15,
-                meta_bit_rate = ?16,
-                meta_channels = ?17,
-                meta_sample_rate = ?18,
-                meta_size_on_disk = ?19
-            WHERE id = ?20",
-            safe_repo_id
-        ),
-        params![
-            file.name,
-            file.encoding,
-            file.path,
-            file.related_files,
-            file.tags,
-            file.date_created,
-            file.date_modified,
-            file.audio_fingerprint,
-            file.accessible,
-            file.meta_title,
-            file.meta_comment,
-            file.meta_album_artist,
-            file.meta_album,
-            file.meta_track_number,
-            file.meta_genre,
-            file.meta_bit_rate,
-            file.meta_channels,
-            file.meta_sample_rate,
-            file.meta_size_on_disk,
-            file.id,
-        ],
-    )?;
+/// The row's previous state is recorded in the version history first, so it can be restored;
+/// the update fails rather than leave a gap in the history.
+/// Its shared metadata is passed on to the file's records in other repositories.
+pub fn update_file(conn: &Connection, repo_id: &str, file: &FileMetadata) -> Result<()> {
+    write_file_row(conn, repo_id, file, true)
+}
+
+/// Writes a file row, recording the previous state first if the row changes. With
+/// `sync_asset` unset it leaves the shared asset alone, for callers that sync it after
+/// their transaction commits.
+fn write_file_row(
+    conn: &Connection,
+    repo_id: &str,
+    file: &FileMetadata,
+    sync_asset: bool,
+) -> Result<()> {
+    // Store the tag list in normalized form, matching the FileTags links, and the dates
+    // as RFC 3339. `related_files` is owned by the FileRelations table.
+    let tags = resolve_tags(conn, file.tags.as_deref())?;
+    let file = &FileMetadata {
+        tags: join_tag_paths(&tags),
+        related_files: related_file_list(conn, &file.id)?,
+        date_created: normalize_timestamp(&file.date_created),
+        date_modified: normalize_timestamp(&file.date_modified),
+        ..file.clone()
+    };
+
+    if let Ok(previous) = get_file(conn, repo_id, &file.id) {
+        if previous.accessible && file_state_differs(&previous, file) {
+            record_file_version(conn, repo_id, &previous)
+                .map_err(rusqlite::Error::ToSqlConversionFailure)?;
+        }
+    }
+
+    let updated = conn.prepare_cached(
+        "UPDATE Files SET
+            name = ?1,
+            encoding = ?2,
+            path = ?3,
+            related_files = ?4,
+            tags = ?5,
+            date_created = ?6,
+            date_modified = ?7,
+            audio_fingerprint = ?8,
+            accessible = ?9,
+            meta_title = ?10,
+            meta_comment = ?11,
+            meta_album_artist = ?12,
+            meta_album = ?13,
+            meta_track_number = ?14,
+            meta_genre = ?15,
+            meta_bit_rate = ?16,
+            meta_channels = ?17,
+            meta_sample_rate = ?18,
+            meta_size_on_disk = ?19,
+            meta_bpm = ?20,
+            meta_key = ?21,
+            date_created_ms = ?22,
+            date_modified_ms = ?23,
+            notes = ?24
+            WHERE id = ?25 AND repo_id = ?26",
+    )?
+    .execute(params![
+        file.name,
+        file.encoding,
+        file.path,
+        file.related_files,
+        file.tags,
+        file.date_created,
+        file.date_modified,
+        file.audio_fingerprint,
+        file.accessible,
+        file.meta_title,
+        file.meta_comment,
+        file.meta_album_artist,
+        file.meta_album,
+        file.meta_track_number,
+        file.meta_genre,
+        file.meta_bit_rate,
+        file.meta_channels,
+        file.meta_sample_rate,
+        file.meta_size_on_disk,
+        file.meta_bpm,
+        file.meta_key,
+        parse_timestamp_millis(&file.date_created),
+        parse_timestamp_millis(&file.date_modified),
+        file.notes,
+        file.id,
+        repo_id,
+    ])?;
+    if updated > 0 {
+        if sync_asset {
+            sync_file_asset(conn, &file.id)?;
+        }
+        link_file_tags(conn, &file.id, &tags)?;
+        index_file(conn, file)?;
+        refresh_file_collections(conn, repo_id, &file.id)?;
+    }
     Ok(())
 }
 
-/// Deletes a file record.
-pub fn delete_file(repo_id: &str, file_id: &str) -> Result<()> {
-    let conn = establish_connection()?;
-    let safe_repo_id = sanitize_identifier(repo_id)?;
-    ensure_files_table(&conn, repo_id)?;
-    conn.execute(
-        &format!("DELETE FROM \"{}\" WHERE id = ?1", safe_repo_id),
-        params![file_id],
+/// Moves a file record to the trash. It keeps its tags, relations and history, but drops
+/// out of listings, search and smart collections until it is restored.
+pub fn delete_file(conn: &Connection, repo_id: &str, file_id: &str) -> Result<()> {
+    let trashed = conn
+        .prepare_cached(
+            "UPDATE Files SET deleted_at = ?1
+             WHERE id = ?2 AND repo_id = ?3 AND deleted_at IS NULL",
+        )?
+        .execute(params![
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            file_id,
+            repo_id
+        ])?;
+    if trashed > 0 {
+        refresh_file_collections(conn, repo_id, file_id)?;
+    }
+    Ok(())
+}
+
+/// Deletes a file record for good, with its version history. Returns the content hashes
+/// the deleted versions referred to, for `remove_unreferenced_objects` to clear from the
+/// object store once the caller's transaction commits.
+pub fn purge_file(conn: &Connection, repo_id: &str, file_id: &str) -> Result<Vec<String>> {
+    let related = related_file_ids(conn, file_id)?;
+    let removed = conn.execute(
+        "DELETE FROM Files WHERE id = ?1 AND repo_id = ?2",
+        params![file_id, repo_id],
+    )?;
+    if removed == 0 {
+        return Ok(Vec::new());
+    }
+    let content_hashes = dropped_content_hashes(
+        conn,
+        "SELECT DISTINCT content_hash FROM FileVersions WHERE file_id = ?1",
+        file_id,
     )?;
+    conn.execute("DELETE FROM FileVersions WHERE file_id = ?1", params![file_id])?;
+    unindex_file(conn, file_id)?;
+    // The relations went with the row; drop it from the other files' caches too.
+    for related_id in related {
+        refresh_related_files(conn, &related_id)?;
+    }
+    remove_unused_assets(conn)?;
+    Ok(content_hashes)
+}
+
+// ---------------------------------------------------------------------------
+// Bulk file operations
+// ---------------------------------------------------------------------------
+// Each runs in one transaction: either every file is changed or, on the first error,
+// none is.
+
+/// Returns a "not found" error unless the file belongs to the repository.
+fn ensure_file_in_repository(conn: &Connection, repo_id: &str, file_id: &str) -> Result<()> {
+    let exists: bool = conn
+        .prepare_cached(
+            "SELECT EXISTS(
+                SELECT 1 FROM Files WHERE id = ?1 AND repo_id = ?2 AND deleted_at IS NULL
+            )",
+        )?
+        .query_row(params![file_id, repo_id], |row| row.get(0))?;
+    if exists {
+        Ok(())
+    } else {
+        Err(rusqlite::Error::QueryReturnedNoRows)
+    }
+}
+
+/// Updates many file records at once. Each changed file's previous state is kept in its
+/// history within the transaction, and the shared assets are synced after it commits.
+pub fn update_files(conn: &Connection, repo_id: &str, files: &[FileMetadata]) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    for file in files {
+        ensure_file_in_repository(&tx, repo_id, &file.id)?;
+        write_file_row(&tx, repo_id, file, false)?;
+    }
+    tx.commit()?;
+
+    for file in files {
+        // A fingerprint shared through the asset can change collection membership.
+        if let Err(e) = sync_file_asset(conn, &file.id)
+            .and_then(|_| refresh_file_collections(conn, repo_id, &file.id))
+        {
+            println!("Failed to sync the asset of '{}': {}", file.name, e);
+        }
+    }
+    Ok(files.len())
+}
+
+/// Deletes many file records at once.
+pub fn delete_files(
+    conn: &Connection,
+    repo_id: &str,
+    file_ids: &[String],
+) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    for file_id in file_ids {
+        ensure_file_in_repository(&tx, repo_id, file_id)?;
+        delete_file(&tx, repo_id, file_id)?;
+    }
+    tx.commit()?;
+    Ok(file_ids.len())
+}
+
+/// Moves file records (with their tags, relations and version history) to another
+/// repository. Fails without moving anything if the target is trashed or already tracks
+/// one of the paths.
+pub fn move_files_to_repository(
+    conn: &Connection,
+    from_repo_id: &str,
+    to_repo_id: &str,
+    file_ids: &[String],
+) -> std::result::Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+    if from_repo_id == to_repo_id {
+        return Ok(0);
+    }
+    let tx = conn.unchecked_transaction()?;
+    move_file_rows(&tx, from_repo_id, to_repo_id, file_ids)?;
+    tx.commit()?;
+    Ok(file_ids.len())
+}
+
+/// Moves the file rows without a transaction of its own; the journal replays moves with it.
+pub fn move_file_rows(
+    conn: &Connection,
+    from_repo_id: &str,
+    to_repo_id: &str,
+    file_ids: &[String],
+) -> std::result::Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    get_repository(conn, to_repo_id)?;
+    for file_id in file_ids {
+        let file = get_file(conn, from_repo_id, file_id)?;
+        let taken: bool = conn
+            .prepare_cached("SELECT EXISTS(SELECT 1 FROM Files WHERE repo_id = ?1 AND path = ?2)")?
+            .query_row(params![to_repo_id, file.path], |row| row.get(0))?;
+        if taken {
+            return Err(format!(
+                "'{}' is already in the target repository; no files were moved.",
+                file.path
+            )
+            .into());
+        }
+        conn.execute(
+            "UPDATE Files SET repo_id = ?1 WHERE id = ?2",
+            params![to_repo_id, file_id],
+        )?;
+        conn.execute(
+            "UPDATE FileVersions SET repo_id = ?1 WHERE file_id = ?2",
+            params![to_repo_id, file_id],
+        )?;
+        refresh_file_collections(conn, to_repo_id, file_id)?;
+    }
     Ok(())
 }
 
 // ---------------------------------------------------------------------------
 // Folder tracking operations
 // ---------------------------------------------------------------------------
-pub fn create_tracked_folder(repo_id: &str, folder_path: &str) -> Result<()> {
-    let conn = establish_connection()?;
+pub fn create_tracked_folder(conn: &Connection, repo_id: &str, folder_path: &str) -> Result<()> {
     let id = Uuid::new_v4().to_string();
     conn.execute(
         "INSERT INTO TrackedFolders (id, repo_id, folder_path) VALUES (?1, ?2, ?3)",
@@ -595,8 +822,7 @@ pub fn create_tracked_folder(repo_id: &str, folder_path: &str) -> Result<()> {
 }
 
 // Delete
-pub fn delete_tracked_folder(repo_id: &str, folder_path: &str) -> Result<()> {
-    let conn = establish_connection()?;
+pub fn delete_tracked_folder(conn: &Connection, repo_id: &str, folder_path: &str) -> Result<()> {
     conn.execute(
         "DELETE FROM TrackedFolders WHERE repo_id = ?1 AND folder_path = ?2",
         params![repo_id, folder_path],
@@ -605,8 +831,7 @@ pub fn delete_tracked_folder(repo_id: &str, folder_path: &str) -> Result<()> {
 }
 
 // Get all
-pub fn get_tracked_folders() -> Result<Vec<TrackedFolder>> {
-    let conn = establish_connection()?;
+pub fn get_tracked_folders(conn: &Connection) -> Result<Vec<TrackedFolder>> {
     let mut stmt = conn.prepare("SELECT id, repo_id, folder_path FROM TrackedFolders")?;
     let folders = stmt
         .query_map([], |row| {
@@ -623,15 +848,18 @@ pub fn get_tracked_folders() -> Result<Vec<TrackedFolder>> {
 const DEFAULT_LIST_ID: &str = "all";
 
 /// Fetch all contact‑lists.
-pub fn get_contact_lists() -> Result<Vec<ContactList>> {
-    let conn = establish_connection()?;
-    let mut stmt = conn.prepare("SELECT id, name FROM ContactLists ORDER BY name")?;
+pub fn get_contact_lists(conn: &Connection) -> Result<Vec<ContactList>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, delivery_preset_id FROM ContactLists
+         WHERE deleted_at IS NULL ORDER BY name",
+    )?;
     let lists = stmt
         .query_map([], |row| {
             Ok(ContactList {
                 id: row.get(0)?,
                 name: row.get(1)?,
                 contacts: Vec::new(),
+                delivery_preset_id: row.get(2)?,
             })
         })?
         .collect::<Result<_, _>>()?;
@@ -639,8 +867,7 @@ pub fn get_contact_lists() -> Result<Vec<ContactList>> {
 }
 
 /// Create a new custom contact‑list.
-pub fn create_contact_list(name: &str) -> Result<String> {
-    let conn = establish_connection()?;
+pub fn create_contact_list(conn: &Connection, name: &str) -> Result<String> {
     let id = Uuid::new_v4().to_string();
     conn.execute(
         "INSERT INTO ContactLists (id, name) VALUES (?1, ?2)",
@@ -650,12 +877,11 @@ pub fn create_contact_list(name: &str) -> Result<String> {
 }
 
 /// Rename (update) a contact‑list.
-pub fn update_contact_list(id: &str, name: &str) -> Result<()> {
+pub fn update_contact_list(conn: &Connection, id: &str, name: &str) -> Result<()> {
     if id == DEFAULT_LIST_ID {
         // do nothing — “All Contacts” is immutable
         return Ok(());
     }
-    let conn = establish_connection()?;
     conn.execute(
         "UPDATE ContactLists SET name = ?1 WHERE id = ?2",
         params![name, id],
@@ -663,29 +889,27 @@ pub fn update_contact_list(id: &str, name: &str) -> Result<()> {
     Ok(())
 }
 
-/// Delete a custom contact‑list.
-pub fn delete_contact_list(id: &str) -> Result<()> {
+/// Move a custom contact‑list to the trash. Its contacts stay where they are.
+pub fn delete_contact_list(conn: &Connection, id: &str) -> Result<()> {
     if id == DEFAULT_LIST_ID {
         // prevent deletion of “All Contacts”
         return Ok(());
     }
-    let conn = establish_connection()?;
     conn.execute(
-        "DELETE FROM ContactLists WHERE id = ?1",
-        params![id],
+        "UPDATE ContactLists SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+        params![Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true), id],
     )?;
     Ok(())
 }
 
 /// Fetch contacts for a given list (or all, if id == "all").
-pub fn get_contacts_for_list(list_id: &str) -> Result<Vec<Contact>> {
-    let conn = establish_connection()?;
+pub fn get_contacts_for_list(conn: &Connection, list_id: &str) -> Result<Vec<Contact>> {
     let mut contacts = Vec::new();
     if list_id == DEFAULT_LIST_ID {
         // return every contact
         let mut stmt = conn.prepare(
-            "SELECT id, name, email, phone, handle, notes, profession
-             FROM Contacts ORDER BY name",
+            "SELECT id, name, email, phone, handle, notes, profession, delivery_preset_id
+             FROM Contacts WHERE deleted_at IS NULL ORDER BY name",
         )?;
         for row in stmt.query_map([], |r| {
             Ok(Contact {
@@ -696,6 +920,7 @@ pub fn get_contacts_for_list(list_id: &str) -> Result<Vec<Contact>> {
                 handle:   r.get::<_, Option<String>>(4)?,
                 notes:    r.get::<_, Option<String>>(5)?,
                 profession: r.get::<_, Option<String>>(6)?,
+                delivery_preset_id: r.get::<_, Option<String>>(7)?,
             })
         })? {
             contacts.push(row?);
@@ -703,10 +928,11 @@ pub fn get_contacts_for_list(list_id: &str) -> Result<Vec<Contact>> {
     } else {
         // only those mapped into this list
         let mut stmt = conn.prepare(
-            "SELECT c.id, c.name, c.email, c.phone, c.handle, c.notes, c.profession
+            "SELECT c.id, c.name, c.email, c.phone, c.handle, c.notes, c.profession,
+                    c.delivery_preset_id
              FROM Contacts c
              JOIN ContactListContacts m ON c.id = m.contact_id
-             WHERE m.list_id = ?1
+             WHERE m.list_id = ?1 AND c.deleted_at IS NULL
              ORDER BY c.name",
         )?;
         for row in stmt.query_map(params![list_id], |r| {
@@ -718,6 +944,7 @@ pub fn get_contacts_for_list(list_id: &str) -> Result<Vec<Contact>> {
                 handle:     r.get(4)?,
                 notes:      r.get(5)?,
                 profession: r.get(6)?,
+                delivery_preset_id: r.get(7)?,
             })
         })? {
             contacts.push(row?);
@@ -728,6 +955,7 @@ pub fn get_contacts_for_list(list_id: &str) -> Result<Vec<Contact>> {
 
 /// Insert a new contact (returns its new ID).
 pub fn create_contact(
+    conn: &Connection,
     name: &str,
     email: &str,
     phone: Option<&str>,
@@ -735,7 +963,6 @@ pub fn create_contact(
     notes: Option<&str>,
     profession: Option<&str>,
 ) -> Result<String> {
-    let conn = establish_connection()?;
     let id = Uuid::new_v4().to_string();
     conn.execute(
         "INSERT INTO Contacts (id, name, email, phone, handle, notes, profession)
@@ -746,12 +973,11 @@ pub fn create_contact(
 }
 
 /// Map an existing contact into a custom list.
-pub fn add_contact_to_list(list_id: &str, contact_id: &str) -> Result<()> {
+pub fn add_contact_to_list(conn: &Connection, list_id: &str, contact_id: &str) -> Result<()> {
     if list_id == DEFAULT_LIST_ID {
         // no-op for “all”
         return Ok(());
     }
-    let conn = establish_connection()?;
     conn.execute(
         "INSERT OR IGNORE INTO ContactListContacts (list_id, contact_id)
          VALUES (?1, ?2)",
@@ -761,8 +987,7 @@ pub fn add_contact_to_list(list_id: &str, contact_id: &str) -> Result<()> {
 }
 
 /// Update an existing contact’s fields.
-pub fn update_contact(contact: &Contact) -> Result<()> {
-    let conn = establish_connection()?;
+pub fn update_contact(conn: &Connection, contact: &Contact) -> Result<()> {
     conn.execute(
         "UPDATE Contacts SET
             name = ?1, email = ?2, phone = ?3,
@@ -781,12 +1006,11 @@ pub fn update_contact(contact: &Contact) -> Result<()> {
     Ok(())
 }
 
-/// Delete a contact (and its list‑mappings via ON DELETE CASCADE).
-pub fn delete_contact(contact_id: &str) -> Result<()> {
-    let conn = establish_connection()?;
+/// Move a contact to the trash; its list‑mappings are kept for a restore.
+pub fn delete_contact(conn: &Connection, contact_id: &str) -> Result<()> {
     conn.execute(
-        "DELETE FROM Contacts WHERE id = ?1",
-        params![contact_id],
+        "UPDATE Contacts SET deleted_at = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+        params![Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true), contact_id],
     )?;
     Ok(())
 }
@@ -799,25 +1023,33 @@ pub fn delete_contact(contact_id: &str) -> Result<()> {
 #[tauri::command]
 pub async fn create_repository_command(
     window: Window,
+    pool: State<'_, DbPool>,
     id: String,
     name: String,
     description: String,
+    parent_id: Option<String>,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = create_repository_with_id(&id, &name, &description);
+        let conn = get_connection(&pool)?;
+        let result =
+            create_repository_with_id(&conn, &id, &name, &description, parent_id.as_deref());
 
         let payload = match &result {
             Ok(_) => format!("Repository '{}' created successfully.", name),
             Err(e) => format!("Failed to create repository '{}': {}", name, e),
         };
-
-        emit_window
-            .emit("create_repository_completed", payload)
-            .unwrap_or_else(|e| {
-                println!("Failed to emit create_repository_completed event: {}", e);
-            });
+        finish_command(
+            &conn,
+            &emit_window,
+            "create_repository",
+            Some(&id),
+            NO_FILES,
+            &result,
+            &payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -826,22 +1058,36 @@ pub async fn create_repository_command(
 }
 
 #[tauri::command]
-pub async fn delete_repository_command(window: Window, id: String) -> Result<(), String> {
+pub async fn delete_repository_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    id: String,
+) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = delete_repository(&id);
+        let conn = get_connection(&pool)?;
+        let result = delete_repository(&conn, &id);
+        if result.is_ok() {
+            if let Err(e) = stop_repository_watchers(&conn, &id) {
+                println!("Failed to stop watching repository '{}': {}", id, e);
+            }
+        }
 
         let payload = match &result {
-            Ok(_) => format!("Repository '{}' deleted successfully.", id),
+            Ok(_) => format!("Repository '{}' moved to the trash.", id),
             Err(e) => format!("Failed to delete repository '{}': {}", id, e),
         };
-
-        emit_window
-            .emit("delete_repository_completed", payload)
-            .unwrap_or_else(|e| {
-                println!("Failed to emit delete_repository_completed event: {}", e);
-            });
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_repository",
+            Some(&id),
+            NO_FILES,
+            &result,
+            &payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -852,25 +1098,31 @@ pub async fn delete_repository_command(window: Window, id: String) -> Result<(),
 #[tauri::command]
 pub async fn update_repository_command(
     window: Window,
+    pool: State<'_, DbPool>,
     id: String,
     name: String,
     description: String,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = update_repository(&id, &name, &description);
+        let conn = get_connection(&pool)?;
+        let result = update_repository(&conn, &id, &name, &description);
 
         let payload = match &result {
             Ok(_) => format!("Repository '{}' updated successfully.", id),
             Err(e) => format!("Failed to update repository '{}': {}", id, e),
         };
-
-        emit_window
-            .emit("update_repository_completed", payload)
-            .unwrap_or_else(|e| {
-                println!("Failed to emit update_repository_completed event: {}", e);
-            });
+        finish_command(
+            &conn,
+            &emit_window,
+            "update_repository",
+            Some(&id),
+            NO_FILES,
+            &result,
+            &payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -881,13 +1133,16 @@ pub async fn update_repository_command(
 #[tauri::command]
 pub async fn create_file_command(
     window: Window,
+    pool: State<'_, DbPool>,
     repo_id: String,
     file: FileMetadata,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = create_file(&repo_id, &file);
+        let conn = get_connection(&pool)?;
+        let result = create_file(&conn, &repo_id, &file);
 
         let payload = match &result {
             Ok(_) => format!(
@@ -899,12 +1154,15 @@ pub async fn create_file_command(
                 file.name, repo_id, e
             ),
         };
-
-        emit_window
-            .emit("create_file_completed", payload)
-            .unwrap_or_else(|e| {
-                println!("Failed to emit create_file_completed event: {}", e);
-            });
+        finish_command(
+            &conn,
+            &emit_window,
+            "create_file",
+            Some(&repo_id),
+            &[&file.id],
+            &result,
+            &payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -915,13 +1173,17 @@ pub async fn create_file_command(
 #[tauri::command]
 pub async fn update_file_command(
     window: Window,
+    pool: State<'_, DbPool>,
     repo_id: String,
     file: FileMetadata,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = update_file(&repo_id, &file);
+        let conn = get_connection(&pool)?;
+        let previous = get_file(&conn, &repo_id, &file.id);
+        let result = update_file(&conn, &repo_id, &file);
 
         let payload = match &result {
             Ok(_) => format!(
@@ -933,12 +1195,18 @@ pub async fn update_file_command(
                 file.name, repo_id, e
             ),
         };
-
-        emit_window
-            .emit("update_file_completed", payload)
-            .unwrap_or_else(|e| {
-                println!("Failed to emit update_file_completed event: {}", e);
-            });
+        if let (Ok(_), Ok(previous)) = (&result, previous) {
+            journal_file_rows(&conn, "update_file", &payload, &repo_id, vec![previous]);
+        }
+        finish_command(
+            &conn,
+            &emit_window,
+            "update_file",
+            Some(&repo_id),
+            &[&file.id],
+            &result,
+            &payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -949,17 +1217,22 @@ pub async fn update_file_command(
 #[tauri::command]
 pub async fn delete_file_command(
     window: Window,
+    pool: State<'_, DbPool>,
     repo_id: String,
     file_id: String,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = delete_file(&repo_id, &file_id);
+        let conn = get_connection(&pool)?;
+        // Only a file that was live can be brought back by undoing.
+        let was_live = get_file(&conn, &repo_id, &file_id).is_ok();
+        let result = delete_file(&conn, &repo_id, &file_id);
 
         let payload = match &result {
             Ok(_) => format!(
-                "File '{}' deleted successfully from repo '{}'.",
+                "File '{}' moved to the trash from repo '{}'.",
                 file_id, repo_id
             ),
             Err(e) => format!(
@@ -967,12 +1240,69 @@ pub async fn delete_file_command(
                 file_id, repo_id, e
             ),
         };
+        if result.is_ok() && was_live {
+            journal_trashed_files(
+                &conn,
+                "delete_file",
+                &payload,
+                &repo_id,
+                std::slice::from_ref(&file_id),
+            );
+        }
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_file",
+            Some(&repo_id),
+            &[&file_id],
+            &result,
+            &payload,
+        );
 
-        emit_window
-            .emit("delete_file_completed", payload)
-            .unwrap_or_else(|e| {
-                println!("Failed to emit delete_file_completed event: {}", e);
-            });
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn update_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    files: Vec<FileMetadata>,
+) -> Result<usize, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let previous: Vec<FileMetadata> = files
+            .iter()
+            .filter_map(|file| get_file(&conn, &repo_id, &file.id).ok())
+            .collect();
+        let result = update_files(&conn, &repo_id, &files);
+
+        let payload = match &result {
+            Ok(count) => format!("{} files updated in repo '{}'.", count, repo_id),
+            Err(e) => format!(
+                "Failed to update files in repo '{}', no changes were made: {}",
+                repo_id, e
+            ),
+        };
+        if result.is_ok() {
+            journal_file_rows(&conn, "update_files", &payload, &repo_id, previous);
+        }
+        let file_ids: Vec<&str> = files.iter().map(|file| file.id.as_str()).collect();
+        finish_command(
+            &conn,
+            &emit_window,
+            "update_files",
+            Some(&repo_id),
+            &file_ids,
+            &result,
+            &payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -981,11 +1311,108 @@ pub async fn delete_file_command(
 }
 
 #[tauri::command]
-pub async fn remove_duplicate_files_command(window: Window, repo_id: String) -> Result<(), String> {
+pub async fn delete_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    file_ids: Vec<String>,
+) -> Result<usize, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_files(&conn, &repo_id, &file_ids);
+
+        let payload = match &result {
+            Ok(count) => format!("{} files moved to the trash from repo '{}'.", count, repo_id),
+            Err(e) => format!(
+                "Failed to delete files from repo '{}', no changes were made: {}",
+                repo_id, e
+            ),
+        };
+        if result.is_ok() {
+            journal_trashed_files(&conn, "delete_files", &payload, &repo_id, &file_ids);
+        }
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_files",
+            Some(&repo_id),
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn move_files_to_repository_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    from_repo_id: String,
+    to_repo_id: String,
+    file_ids: Vec<String>,
+) -> Result<usize, String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     tauri::async_runtime::spawn_blocking(move || {
-        let result = remove_duplicate_files_in_repository(&repo_id);
+        let conn = get_connection(&pool)?;
+        let result = move_files_to_repository(&conn, &from_repo_id, &to_repo_id, &file_ids);
+
+        let payload = match &result {
+            Ok(count) => format!(
+                "{} files moved from repo '{}' to repo '{}'.",
+                count, from_repo_id, to_repo_id
+            ),
+            Err(e) => format!(
+                "Failed to move files to repo '{}', no changes were made: {}",
+                to_repo_id, e
+            ),
+        };
+        if matches!(result, Ok(count) if count > 0) {
+            journal_moved_files(
+                &conn,
+                "move_files_to_repository",
+                &payload,
+                &from_repo_id,
+                &to_repo_id,
+                &file_ids,
+            );
+        }
+        finish_command(
+            &conn,
+            &emit_window,
+            "move_files_to_repository",
+            Some(&to_repo_id),
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn remove_duplicate_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = remove_duplicate_files_in_repository(&conn, &repo_id);
 
         let payload = match &result {
             Ok(_) => format!("Duplicate files removed from repository '{}'.", repo_id),
@@ -994,15 +1421,15 @@ pub async fn remove_duplicate_files_command(window: Window, repo_id: String) ->
                 repo_id, e
             ),
         };
-
-        emit_window
-            .emit("remove_duplicate_files_completed", payload)
-            .unwrap_or_else(|e| {
-                println!(
-                    "Failed to emit remove_duplicate_files_completed event: {}",
-                    e
-                );
-            });
+        finish_command(
+            &conn,
+            &emit_window,
+            "remove_duplicate_files",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &payload,
+        );
 
         result.map_err(|e| e.to_string())
     })
@@ -1011,12 +1438,18 @@ pub async fn remove_duplicate_files_command(window: Window, repo_id: String) ->
 }
 
 #[tauri::command]
-pub async fn get_repositories_command(window: Window) -> Result<Vec<Repository>, String> {
+pub async fn get_repositories_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+) -> Result<Vec<Repository>, String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
-    let result =
-        tauri::async_runtime::spawn_blocking(move || get_repositories().map_err(|e| e.to_string()))
-            .await;
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_repositories(&conn).map_err(|e| e.to_string())
+    })
+    .await;
 
     if let Ok(Ok(_)) = &result {
         emit_window
@@ -1031,11 +1464,17 @@ pub async fn get_repositories_command(window: Window) -> Result<Vec<Repository>,
 }
 
 #[tauri::command]
-pub async fn get_repository_command(window: Window, id: String) -> Result<Repository, String> {
+pub async fn get_repository_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    id: String,
+) -> Result<Repository, String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     let result = tauri::async_runtime::spawn_blocking(move || {
-        get_repository(&id).map_err(|e| e.to_string())
+        let conn = get_connection(&pool)?;
+        get_repository(&conn, &id).map_err(|e| e.to_string())
     })
     .await;
 
@@ -1054,13 +1493,16 @@ pub async fn get_repository_command(window: Window, id: String) -> Result<Reposi
 #[tauri::command]
 pub async fn get_files_in_repository_command(
     window: Window,
+    pool: State<'_, DbPool>,
     repo_id: String,
 ) -> Result<Vec<FileMetadata>, String> {
     let emit_window = window.clone();
     let repo_id_for_db = repo_id.clone();
+    let pool = pool.inner().clone();
 
     let result = tauri::async_runtime::spawn_blocking(move || {
-        get_files_in_repository(&repo_id_for_db).map_err(|e| e.to_string())
+        let conn = get_connection(&pool)?;
+        get_files_in_repository(&conn, &repo_id_for_db).map_err(|e| e.to_string())
     })
     .await;
 
@@ -1081,17 +1523,53 @@ pub async fn get_files_in_repository_command(
     result.map_err(|e| e.to_string())?
 }
 
+#[tauri::command]
+pub async fn query_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    query: FileQuery,
+) -> Result<FilePage, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+    let repo_id = query.repo_id.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        query_files(&conn, &query).map_err(|e| e.to_string())
+    })
+    .await;
+
+    if let Ok(Ok(page)) = &result {
+        emit_window
+            .emit(
+                "query_files_completed",
+                format!(
+                    "Loaded {} of {} files for repository '{}'.",
+                    page.files.len(),
+                    page.total,
+                    repo_id
+                ),
+            )
+            .unwrap_or_else(|e| println!("Failed to emit query_files_completed event: {}", e));
+    }
+
+    result.map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 pub async fn get_file_command(
     window: Window,
+    pool: State<'_, DbPool>,
     repo_id: String,
     file_id: String,
 ) -> Result<FileMetadata, String> {
     let emit_window = window.clone();
     let repo_id_for_db = repo_id.clone();
+    let pool = pool.inner().clone();
 
     let result = tauri::async_runtime::spawn_blocking(move || {
-        get_file(&repo_id_for_db, &file_id).map_err(|e| e.to_string())
+        let conn = get_connection(&pool)?;
+        get_file(&conn, &repo_id_for_db, &file_id).map_err(|e| e.to_string())
     })
     .await;
 
@@ -1111,22 +1589,42 @@ pub async fn get_file_command(
 }
 
 #[tauri::command]
-pub async fn get_app_settings_command() -> Result<AppSettings, String> {
-    tauri::async_runtime::spawn_blocking(move || get_app_settings().map_err(|e| e.to_string()))
-        .await
-        .map_err(|e| e.to_string())?
+pub async fn get_app_settings_command(pool: State<'_, DbPool>) -> Result<AppSettings, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_app_settings(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
-pub async fn update_app_settings_command(args: AppSettings) -> Result<(), String> {
+pub async fn update_app_settings_command(
+    pool: State<'_, DbPool>,
+    args: AppSettings,
+) -> Result<(), String> {
+    let pool = pool.inner().clone();
     tauri::async_runtime::spawn_blocking(move || {
-        update_app_settings(
+        let conn = get_connection(&pool)?;
+        let result = update_app_settings(
+            &conn,
             args.general_auto_fingerprint,
             args.general_theme,
             args.audio_autoplay,
             &args.setup_selected_repository,
-        )
-        .map_err(|e| e.to_string())
+            args.trash_retention_days,
+        );
+        record_activity(
+            &conn,
+            ActivityActor::User,
+            "update_app_settings",
+            None,
+            NO_FILES,
+            &result,
+            "App settings updated.",
+        );
+        result.map_err(|e| e.to_string())
     })
     .await
     .map_err(|e| e.to_string())?
@@ -1134,11 +1632,14 @@ pub async fn update_app_settings_command(args: AppSettings) -> Result<(), String
 
 #[tauri::command]
 pub async fn get_contact_lists_command(
-    window: Window
+    window: Window,
+    pool: State<'_, DbPool>,
 ) -> Result<Vec<ContactList>, String> {
     let emit = window.clone();
+    let pool = pool.inner().clone();
     let result = tauri::async_runtime::spawn_blocking(move || {
-        get_contact_lists().map_err(|e| e.to_string())
+        let conn = get_connection(&pool)?;
+        get_contact_lists(&conn).map_err(|e| e.to_string())
     }).await.map_err(|e| e.to_string())?;
     // optional: emit an event
     emit.emit("get_contact_lists_completed", "Loaded contact lists").ok();
@@ -1148,15 +1649,20 @@ pub async fn get_contact_lists_command(
 #[tauri::command]
 pub async fn create_contact_list_command(
     window: Window,
+    pool: State<'_, DbPool>,
     name: String
 ) -> Result<String, String> {
-    let emit = window.clone();
-    let result = tauri::async_runtime::spawn_blocking(move || {
-        create_contact_list(&name).map_err(|e| e.to_string())
-    }).await.map_err(|e| e.to_string())?;
-    let id = result?;
-    emit.emit("create_contact_list_completed", &id).ok();
-    Ok(id)
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = create_contact_list(&conn, &name);
+        let payload = match &result {
+            Ok(_) => format!("Contact list '{}' created.", name),
+            Err(e) => format!("Failed to create contact list '{}': {}", name, e),
+        };
+        finish_command(&conn, &window, "create_contact_list", None, NO_FILES, &result, &payload);
+        result.map_err(|e| e.to_string())
+    }).await.map_err(|e| e.to_string())?
 }
 
 // …and similarly for update_contact_list_command, delete_contact_list_command
@@ -1164,11 +1670,14 @@ pub async fn create_contact_list_command(
 #[tauri::command]
 pub async fn get_contacts_command(
     window: Window,
+    pool: State<'_, DbPool>,
     list_id: String
 ) -> Result<Vec<Contact>, String> {
     let emit = window.clone();
+    let pool = pool.inner().clone();
     let result = tauri::async_runtime::spawn_blocking(move || {
-        get_contacts_for_list(&list_id).map_err(|e| e.to_string())
+        let conn = get_connection(&pool)?;
+        get_contacts_for_list(&conn, &list_id).map_err(|e| e.to_string())
     }).await.map_err(|e| e.to_string())?;
     let contacts = result?;
     emit.emit("get_contacts_completed", format!("Loaded {} contacts", contacts.len())).ok();
@@ -1176,8 +1685,10 @@ pub async fn get_contacts_command(
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_contact_command(
     window: Window,
+    pool: State<'_, DbPool>,
     name: String,
     email: String,
     phone: Option<String>,
@@ -1186,74 +1697,346 @@ pub async fn create_contact_command(
     profession: Option<String>,
     list_id: String,
 ) -> Result<String, String> {
-    let emit = window.clone();
-    let result = tauri::async_runtime::spawn_blocking(move || -> Result<String, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || -> Result<String, String> {
+        let conn = get_connection(&pool)?;
         // 1) insert into Contacts
-        let cid = create_contact(&name, &email, phone.as_deref(), handle.as_deref(), notes.as_deref(), profession.as_deref())
-            .map_err(|e| e.to_string())?;
-        // 2) if not default list, map it
-        add_contact_to_list(&list_id, &cid).map_err(|e| e.to_string())?;
-        Ok(cid)
-    }).await.map_err(|e| e.to_string())?;
-    let cid = result?;
-    emit.emit("create_contact_completed", &cid).ok();
-    Ok(cid)
+        let result = create_contact(&conn, &name, &email, phone.as_deref(), handle.as_deref(), notes.as_deref(), profession.as_deref())
+            // 2) if not default list, map it
+            .and_then(|cid| add_contact_to_list(&conn, &list_id, &cid).map(|_| cid));
+        let payload = match &result {
+            Ok(_) => format!("Contact '{}' created.", name),
+            Err(e) => format!("Failed to create contact '{}': {}", name, e),
+        };
+        finish_command(&conn, &window, "create_contact", None, NO_FILES, &result, &payload);
+        result.map_err(|e| e.to_string())
+    }).await.map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
 pub async fn delete_contact_command(
     window: Window,
+    pool: State<'_, DbPool>,
     contact_id: String
 ) -> Result<(), String> {
-    let emit = window.clone();
-    let contact_id_clone = contact_id.clone();
-    let _result = tauri::async_runtime::spawn_blocking(move || {
-        delete_contact(&contact_id_clone).map_err(|e| e.to_string())
-    }).await.map_err(|e| e.to_string())?;
-    emit.emit("delete_contact_completed", &contact_id).ok();
-    Ok(())
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_contact(&conn, &contact_id);
+        let payload = match &result {
+            Ok(_) => format!("Contact '{}' moved to the trash.", contact_id),
+            Err(e) => format!("Failed to delete contact '{}': {}", contact_id, e),
+        };
+        finish_command(&conn, &window, "delete_contact", None, NO_FILES, &result, &payload);
+        result.map_err(|e| e.to_string())
+    }).await.map_err(|e| e.to_string())?
 }
 
 // Update contact command
 #[tauri::command]
 pub async fn update_contact_command(
     window: Window,
+    pool: State<'_, DbPool>,
     contact: Contact
 ) -> Result<(), String> {
-    let contact_id = contact.id.clone();
-    let emit = window.clone();
-    let _result = tauri::async_runtime::spawn_blocking(move || {
-        update_contact(&contact).map_err(|e| e.to_string())
-    }).await.map_err(|e| e.to_string())?;
-    emit.emit("update_contact_completed", &contact_id).ok();
-    Ok(())
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = update_contact(&conn, &contact);
+        let payload = match &result {
+            Ok(_) => format!("Contact '{}' updated.", contact.id),
+            Err(e) => format!("Failed to update contact '{}': {}", contact.id, e),
+        };
+        finish_command(&conn, &window, "update_contact", None, NO_FILES, &result, &payload);
+        result.map_err(|e| e.to_string())
+    }).await.map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
 pub async fn update_contact_list_command(
     window: Window,
+    pool: State<'_, DbPool>,
     id: String,
     name: String
 ) -> Result<(), String> {
-    let emit = window.clone();
-    let id_emit = id.clone();
-    let _result = tauri::async_runtime::spawn_blocking(move || {
-        update_contact_list(&id, &name).map_err(|e| e.to_string())
-    }).await.map_err(|e| e.to_string())?;
-    emit.emit("update_contact_list_completed", &id_emit).ok();
-    Ok(())
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = update_contact_list(&conn, &id, &name);
+        let payload = match &result {
+            Ok(_) => format!("Contact list '{}' renamed to '{}'.", id, name),
+            Err(e) => format!("Failed to rename contact list '{}': {}", id, e),
+        };
+        finish_command(&conn, &window, "update_contact_list", None, NO_FILES, &result, &payload);
+        result.map_err(|e| e.to_string())
+    }).await.map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
 pub async fn delete_contact_list_command(
     window: Window,
+    pool: State<'_, DbPool>,
     id: String
 ) -> Result<(), String> {
-    let emit = window.clone();
-    let id_emit = id.clone();
-    let _result = tauri::async_runtime::spawn_blocking(move || {
-        delete_contact_list(&id).map_err(|e| e.to_string())
-    }).await.map_err(|e| e.to_string())?;
-    emit.emit("delete_contact_list_completed", &id_emit).ok();
-    Ok(())
-}
\ No newline at end of file
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_contact_list(&conn, &id);
+        let payload = match &result {
+            Ok(_) => format!("Contact list '{}' moved to the trash.", id),
+            Err(e) => format!("Failed to delete contact list '{}': {}", id, e),
+        };
+        finish_command(&conn, &window, "delete_contact_list", None, NO_FILES, &result, &payload);
+        result.map_err(|e| e.to_string())
+    }).await.map_err(|e| e.to_string())?
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::{add_repository, sample_file, scratch_dir, test_connection};
+    use crate::commands::structures::FileFilters;
+    use crate::commands::versions::get_file_versions;
+
+    fn add_files(conn: &Connection, repo_id: &str, names: &[&str]) -> Vec<FileMetadata> {
+        let dir = scratch_dir();
+        names
+            .iter()
+            .map(|name| {
+                let file = sample_file(&dir, name, name.as_bytes());
+                create_file(conn, repo_id, &file).unwrap();
+                get_file(conn, repo_id, &file.id).unwrap()
+            })
+            .collect()
+    }
+
+    /// Adds a file described by `describe` on top of a sample file named `name`.
+    fn add_described(conn: &Connection, repo_id: &str, name: &str, describe: impl FnOnce(&mut FileMetadata)) -> FileMetadata {
+        let mut file = sample_file(&scratch_dir(), name, name.as_bytes());
+        describe(&mut file);
+        create_file(conn, repo_id, &file).unwrap();
+        file
+    }
+
+    fn listed(conn: &Connection, query: &FileQuery) -> Vec<String> {
+        query_files(conn, query).unwrap().files.into_iter().map(|f| f.name).collect()
+    }
+
+    fn sorted_by(repo_id: &str, sort_by: FileSortKey, sort_direction: SortDirection) -> FileQuery {
+        FileQuery {
+            repo_id: repo_id.to_string(),
+            offset: 0,
+            limit: None,
+            sort_by,
+            sort_direction,
+            filters: FileFilters::default(),
+        }
+    }
+
+    #[test]
+    fn bulk_updates_keep_history_and_are_all_or_nothing() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let files = add_files(&conn, "repo", &["a.wav", "b.wav"]);
+        let retagged: Vec<FileMetadata> = files
+            .iter()
+            .map(|f| FileMetadata { tags: Some("drums".to_string()), ..f.clone() })
+            .collect();
+
+        let mut with_unknown = retagged.clone();
+        with_unknown.push(FileMetadata { id: "missing".to_string(), ..files[0].clone() });
+        assert!(update_files(&conn, "repo", &with_unknown).is_err());
+        assert_eq!(get_file(&conn, "repo", &files[0].id).unwrap().tags, None);
+
+        assert_eq!(update_files(&conn, "repo", &retagged).unwrap(), 2);
+        for file in &files {
+            assert_eq!(get_file(&conn, "repo", &file.id).unwrap().tags.as_deref(), Some("drums"));
+            let versions = get_file_versions(&conn, &file.id).unwrap();
+            assert!(versions.iter().any(|v| v.metadata.tags.is_none()));
+        }
+    }
+
+    #[test]
+    fn bulk_updates_that_change_nothing_add_no_versions() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let files = add_files(&conn, "repo", &["a.wav", "b.wav"]);
+
+        assert_eq!(update_files(&conn, "repo", &files).unwrap(), 2);
+        for file in &files {
+            assert_eq!(get_file_versions(&conn, &file.id).unwrap().len(), 1);
+        }
+    }
+
+    #[test]
+    fn moves_into_a_trashed_repository_are_refused() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        add_repository(&conn, "trashed");
+        let files = add_files(&conn, "repo", &["a.wav"]);
+        delete_repository(&conn, "trashed").unwrap();
+
+        assert!(move_files_to_repository(&conn, "repo", "trashed", &[files[0].id.clone()]).is_err());
+        assert!(get_file(&conn, "repo", &files[0].id).is_ok());
+    }
+
+    #[test]
+    fn bulk_deletes_and_moves_are_all_or_nothing() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        add_repository(&conn, "other");
+        let files = add_files(&conn, "repo", &["a.wav", "b.wav"]);
+        let ids: Vec<String> = files.iter().map(|f| f.id.clone()).collect();
+
+        let taken = FileMetadata { id: "taken".to_string(), ..files[1].clone() };
+        create_file(&conn, "other", &taken).unwrap();
+        assert!(move_files_to_repository(&conn, "repo", "other", &ids).is_err());
+        assert_eq!(get_files_in_repository(&conn, "repo").unwrap().len(), 2);
+        assert_eq!(move_files_to_repository(&conn, "repo", "other", &ids[..1]).unwrap(), 1);
+        assert_eq!(get_files_in_repository(&conn, "other").unwrap().len(), 2);
+
+        assert!(delete_files(&conn, "repo", &ids).is_err());
+        assert_eq!(get_files_in_repository(&conn, "repo").unwrap().len(), 1);
+        assert_eq!(delete_files(&conn, "repo", &ids[1..]).unwrap(), 1);
+        assert!(get_files_in_repository(&conn, "repo").unwrap().is_empty());
+    }
+
+    #[test]
+    fn pages_cover_every_file_once() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        add_files(&conn, "repo", &["a.wav", "b.wav", "c.wav", "d.wav", "e.wav"]);
+
+        let mut query = FileQuery { limit: Some(2), ..sorted_by("repo", FileSortKey::Name, SortDirection::Asc) };
+        let mut names = Vec::new();
+        let mut offsets = Vec::new();
+        loop {
+            let page = query_files(&conn, &query).unwrap();
+            assert_eq!(page.total, 5);
+            assert_eq!(page.limit, 2);
+            names.extend(page.files.into_iter().map(|f| f.name));
+            offsets.push(page.next_offset);
+            match page.next_offset {
+                Some(next) => query.offset = next,
+                None => break,
+            }
+        }
+        assert_eq!(names, vec!["a.wav", "b.wav", "c.wav", "d.wav", "e.wav"]);
+        assert_eq!(offsets, vec![Some(2), Some(4), None]);
+
+        query.offset = 5;
+        let past_the_end = query_files(&conn, &query).unwrap();
+        assert!(past_the_end.files.is_empty());
+        assert_eq!(past_the_end.next_offset, None);
+
+        query.offset = 0;
+        query.limit = Some(0);
+        assert_eq!(query_files(&conn, &query).unwrap().files.len(), 1);
+        query.limit = Some(MAX_PAGE_SIZE + 1);
+        assert_eq!(query_files(&conn, &query).unwrap().limit, MAX_PAGE_SIZE);
+        query.limit = None;
+        assert_eq!(query_files(&conn, &query).unwrap().limit, DEFAULT_PAGE_SIZE);
+    }
+
+    #[test]
+    fn every_sort_key_orders_its_values_with_missing_ones_first_ascending() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let text = |value: &str| Some(value.to_string());
+        add_described(&conn, "repo", "a.wav", |f| {
+            f.meta_size_on_disk = text("100");
+            f.meta_bit_rate = text("320");
+            f.meta_sample_rate = None;
+            f.date_modified = "2024-01-02T00:00:00.000Z".to_string();
+        });
+        add_described(&conn, "repo", "B.wav", |f| {
+            f.meta_size_on_disk = text("9");
+            f.meta_bit_rate = None;
+            f.meta_sample_rate = text("48000");
+            f.date_modified = "2024-01-03T00:00:00.000Z".to_string();
+        });
+        add_described(&conn, "repo", "c.wav", |f| {
+            f.meta_size_on_disk = text("1000");
+            f.meta_bit_rate = text("128");
+            f.meta_sample_rate = text("44100");
+            f.date_modified = "2024-01-01T00:00:00.000Z".to_string();
+        });
+
+        let cases = [
+            (FileSortKey::Name, ["a.wav", "B.wav", "c.wav"]),
+            (FileSortKey::DateModified, ["c.wav", "a.wav", "B.wav"]),
+            (FileSortKey::Size, ["B.wav", "a.wav", "c.wav"]),
+            (FileSortKey::BitRate, ["B.wav", "c.wav", "a.wav"]),
+            (FileSortKey::SampleRate, ["a.wav", "c.wav", "B.wav"]),
+        ];
+        for (key, ascending) in cases {
+            assert_eq!(listed(&conn, &sorted_by("repo", key, SortDirection::Asc)), ascending, "{:?}", key);
+            let mut descending = ascending.to_vec();
+            descending.reverse();
+            assert_eq!(listed(&conn, &sorted_by("repo", key, SortDirection::Desc)), descending, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn filters_combine_and_skip_trashed_and_other_files() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        add_repository(&conn, "other");
+        let fingerprint = Some("fingerprint".to_string());
+        add_described(&conn, "repo", "x.wav", |f| {
+            f.meta_genre = Some("Rock".to_string());
+            f.audio_fingerprint = fingerprint.clone();
+        });
+        add_described(&conn, "repo", "y.wav", |f| f.meta_genre = Some("rock".to_string()));
+        add_described(&conn, "repo", "z.mp3", |f| {
+            f.meta_genre = Some("Rock".to_string());
+            f.audio_fingerprint = fingerprint.clone();
+            f.accessible = false;
+        });
+        let trashed = add_described(&conn, "repo", "trashed.wav", |f| f.meta_genre = Some("Rock".to_string()));
+        delete_file(&conn, "repo", &trashed.id).unwrap();
+        add_described(&conn, "other", "elsewhere.wav", |f| f.meta_genre = Some("Rock".to_string()));
+
+        let filtered = |filters: FileFilters| {
+            listed(&conn, &FileQuery { filters, ..sorted_by("repo", FileSortKey::Name, SortDirection::Asc) })
+        };
+        assert_eq!(
+            filtered(FileFilters { genre: Some("ROCK".to_string()), ..Default::default() }),
+            vec!["x.wav", "y.wav", "z.mp3"]
+        );
+        assert_eq!(
+            filtered(FileFilters {
+                encoding: Some("WAV".to_string()),
+                genre: Some("rock".to_string()),
+                has_fingerprint: Some(true),
+                ..Default::default()
+            }),
+            vec!["x.wav"]
+        );
+        assert_eq!(filtered(FileFilters { has_fingerprint: Some(false), ..Default::default() }), vec!["y.wav"]);
+        assert_eq!(filtered(FileFilters { accessible: Some(false), ..Default::default() }), vec!["z.mp3"]);
+        let page = query_files(
+            &conn,
+            &FileQuery {
+                filters: FileFilters { encoding: Some("wav".to_string()), ..Default::default() },
+                ..sorted_by("repo", FileSortKey::Name, SortDirection::Asc)
+            },
+        )
+        .unwrap();
+        assert_eq!(page.total, 2);
+    }
+
+    #[test]
+    fn unknown_sort_fields_are_rejected() {
+        let query: FileQuery =
+            serde_json::from_value(serde_json::json!({ "repo_id": "repo", "sort_by": "sample_rate", "sort_direction": "desc" })).unwrap();
+        assert_eq!(query.sort_by, FileSortKey::SampleRate);
+        assert_eq!(query.sort_direction, SortDirection::Desc);
+        for bad in [
+            serde_json::json!({ "repo_id": "repo", "sort_by": "f.name; DROP TABLE Files" }),
+            serde_json::json!({ "repo_id": "repo", "sort_by": "meta_genre" }),
+            serde_json::json!({ "repo_id": "repo", "sort_direction": "sideways" }),
+        ] {
+            assert!(serde_json::from_value::<FileQuery>(bad).is_err());
+        }
+    }
+}
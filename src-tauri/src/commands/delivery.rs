@@ -0,0 +1,750 @@
+// src/commands/delivery.rs
+//! Delivery presets: how bundled files are prepared for the people receiving them.
+//!
+//! A preset names a format, bitrate, sample rate and bit depth to transcode to, a template
+//! for the file names inside the archive, and whether each file's stems and instrumentals
+//! go along with it. Contacts and contact lists can each carry a preset; a contact's own
+//! preset wins over that of the list they are reached through. Transcoding runs through
+//! the ffmpeg sidecar into a scratch directory while a bundle is generated, so the
+//! originals are never touched.
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::audio_ops::{codec_args, run_ffmpeg};
+use crate::commands::bundles::{ArchiveEntry, ArchiveProgress, CANCELLED};
+use crate::commands::db::{file_from_row, get_connection, DbPool, FILE_COLUMNS};
+use crate::commands::structures::{
+    BundleGenerationRecipient, BundleRecipientKind, DeliveryPreset, FileMetadata, FileRelationKind,
+};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, Result, Row};
+use tauri::{AppHandle, State, Window};
+use uuid::Uuid;
+
+/// Formats a preset can transcode to.
+pub const DELIVERY_FORMATS: [&str; 6] = ["mp3", "wav", "flac", "ogg", "aac", "m4a"];
+
+const PRESET_COLUMNS: &str = "id, name, format, bitrate_kbps, sample_rate, bit_depth,
+    naming_template, include_stems, date_created, date_modified";
+
+fn preset_from_row(row: &Row) -> Result<DeliveryPreset> {
+    Ok(DeliveryPreset {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        format: row.get(2)?,
+        bitrate_kbps: row.get(3)?,
+        sample_rate: row.get(4)?,
+        bit_depth: row.get(5)?,
+        naming_template: row.get(6)?,
+        include_stems: row.get(7)?,
+        date_created: row.get(8)?,
+        date_modified: row.get(9)?,
+    })
+}
+
+pub fn get_delivery_presets(conn: &Connection) -> Result<Vec<DeliveryPreset>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM DeliveryPresets ORDER BY name",
+        PRESET_COLUMNS
+    ))?;
+    let presets = stmt
+        .query_map([], preset_from_row)?
+        .collect::<Result<Vec<DeliveryPreset>>>()?;
+    Ok(presets)
+}
+
+pub fn get_delivery_preset(conn: &Connection, preset_id: &str) -> Result<DeliveryPreset> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM DeliveryPresets WHERE id = ?1",
+            PRESET_COLUMNS
+        ),
+        params![preset_id],
+        preset_from_row,
+    )
+}
+
+fn validate_preset(
+    preset: &DeliveryPreset,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    if preset.name.trim().is_empty() {
+        return Err("A delivery preset needs a name.".into());
+    }
+    if let Some(format) = &preset.format {
+        if !DELIVERY_FORMATS.contains(&format.to_lowercase().as_str()) {
+            return Err(format!("Files can't be delivered as '{}'.", format).into());
+        }
+    }
+    if let Some(bit_depth) = preset.bit_depth {
+        if ![16, 24, 32].contains(&bit_depth) {
+            return Err(format!("A bit depth of {} isn't supported.", bit_depth).into());
+        }
+    }
+    if preset.bitrate_kbps.is_some_and(|bitrate| bitrate <= 0)
+        || preset.sample_rate.is_some_and(|rate| rate <= 0)
+    {
+        return Err("Bitrate and sample rate must be positive.".into());
+    }
+    Ok(())
+}
+
+/// Saves a new preset; the id and dates given are ignored.
+pub fn create_delivery_preset(
+    conn: &Connection,
+    preset: &DeliveryPreset,
+) -> std::result::Result<DeliveryPreset, Box<dyn Error + Send + Sync>> {
+    validate_preset(preset)?;
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    conn.execute(
+        "INSERT INTO DeliveryPresets (
+            id, name, format, bitrate_kbps, sample_rate, bit_depth, naming_template,
+            include_stems, date_created, date_modified
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?9)",
+        params![
+            id,
+            preset.name,
+            preset.format.as_ref().map(|f| f.to_lowercase()),
+            preset.bitrate_kbps,
+            preset.sample_rate,
+            preset.bit_depth,
+            preset.naming_template,
+            preset.include_stems,
+            now
+        ],
+    )?;
+    Ok(get_delivery_preset(conn, &id)?)
+}
+
+pub fn update_delivery_preset(
+    conn: &Connection,
+    preset: &DeliveryPreset,
+) -> std::result::Result<DeliveryPreset, Box<dyn Error + Send + Sync>> {
+    validate_preset(preset)?;
+    let updated = conn.execute(
+        "UPDATE DeliveryPresets SET
+            name = ?1, format = ?2, bitrate_kbps = ?3, sample_rate = ?4, bit_depth = ?5,
+            naming_template = ?6, include_stems = ?7, date_modified = ?8
+         WHERE id = ?9",
+        params![
+            preset.name,
+            preset.format.as_ref().map(|f| f.to_lowercase()),
+            preset.bitrate_kbps,
+            preset.sample_rate,
+            preset.bit_depth,
+            preset.naming_template,
+            preset.include_stems,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            preset.id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(format!("Delivery preset {} doesn't exist.", preset.id).into());
+    }
+    Ok(get_delivery_preset(conn, &preset.id)?)
+}
+
+/// Deletes a preset. Contacts and lists that used it go back to the original files.
+pub fn delete_delivery_preset(conn: &Connection, preset_id: &str) -> Result<()> {
+    conn.execute(
+        "DELETE FROM DeliveryPresets WHERE id = ?1",
+        params![preset_id],
+    )?;
+    Ok(())
+}
+
+/// Gives a contact or contact list a preset, or takes it away when `preset_id` is None.
+pub fn set_delivery_preset(
+    conn: &Connection,
+    kind: BundleRecipientKind,
+    id: &str,
+    preset_id: Option<&str>,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(preset_id) = preset_id {
+        get_delivery_preset(conn, preset_id)?;
+    }
+    let table = match kind {
+        BundleRecipientKind::Contact => "Contacts",
+        BundleRecipientKind::ContactList => "ContactLists",
+    };
+    let updated = conn.execute(
+        &format!(
+            "UPDATE {} SET delivery_preset_id = ?1 WHERE id = ?2 AND deleted_at IS NULL",
+            table
+        ),
+        params![preset_id, id],
+    )?;
+    if updated == 0 {
+        return Err(format!("Recipient {} doesn't exist.", id).into());
+    }
+    Ok(())
+}
+
+/// Picks the preset to deliver with: the one asked for, or else the one every recipient
+/// shares. Recipients that want different presets can't be sent the same archive.
+pub fn delivery_preset_for(
+    conn: &Connection,
+    preset_id: Option<&str>,
+    recipients: &[BundleGenerationRecipient],
+) -> std::result::Result<Option<DeliveryPreset>, Box<dyn Error + Send + Sync>> {
+    if let Some(preset_id) = preset_id {
+        return Ok(Some(get_delivery_preset(conn, preset_id)?));
+    }
+    let wanted: HashSet<Option<&str>> = recipients
+        .iter()
+        .map(|recipient| recipient.delivery_preset_id.as_deref())
+        .collect();
+    if wanted.len() > 1 {
+        let message = "The recipients have different delivery presets; generate the bundle \
+            for each of them, or choose one preset for all.";
+        return Err(message.into());
+    }
+    match wanted.into_iter().next().flatten() {
+        Some(preset_id) => Ok(Some(get_delivery_preset(conn, preset_id)?)),
+        None => Ok(None),
+    }
+}
+
+/// Adds each file's stems and instrumentals right after it, leaving out files that are
+/// already in the list.
+pub fn with_stems(conn: &Connection, files: Vec<FileMetadata>) -> Result<Vec<FileMetadata>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM Files f
+         JOIN FileRelations r ON r.source_file_id = f.id
+         WHERE r.target_file_id = ?1 AND r.relation IN (?2, ?3) AND f.deleted_at IS NULL
+         ORDER BY r.relation, f.name",
+        FILE_COLUMNS
+    ))?;
+    let mut seen: HashSet<String> = files.iter().map(|file| file.id.clone()).collect();
+    let mut expanded = Vec::new();
+    for file in files {
+        let stems = stmt
+            .query_map(
+                params![
+                    file.id,
+                    FileRelationKind::StemOf.as_str(),
+                    FileRelationKind::InstrumentalOf.as_str()
+                ],
+                file_from_row,
+            )?
+            .collect::<Result<Vec<FileMetadata>>>()?;
+        expanded.push(file);
+        for stem in stems {
+            if seen.insert(stem.id.clone()) {
+                expanded.push(stem);
+            }
+        }
+    }
+    Ok(expanded)
+}
+
+/// Whether a preset changes the audio at all, rather than only naming the files.
+pub fn transcodes(preset: &DeliveryPreset) -> bool {
+    preset.format.is_some()
+        || preset.bitrate_kbps.is_some()
+        || preset.sample_rate.is_some()
+        || preset.bit_depth.is_some()
+}
+
+/// The ffmpeg arguments that encode a file to `format` as the preset asks.
+pub fn preset_ffmpeg_args(preset: &DeliveryPreset, format: &str) -> Vec<String> {
+    let format = format.to_lowercase();
+    let mut args = match (format.as_str(), preset.bit_depth) {
+        ("wav", Some(bit_depth)) => vec!["-acodec".to_string(), format!("pcm_s{}le", bit_depth)],
+        _ => codec_args(&format),
+    };
+    if let (Some(bit_depth), "flac") = (preset.bit_depth, format.as_str()) {
+        let sample_format = if bit_depth > 16 { "s32" } else { "s16" };
+        args.extend([
+            "-sample_fmt".to_string(),
+            sample_format.to_string(),
+            "-bits_per_raw_sample".to_string(),
+            bit_depth.min(24).to_string(),
+        ]);
+    }
+    if let Some(bitrate) = preset.bitrate_kbps {
+        // Lossless formats have no bitrate to choose.
+        if !matches!(format.as_str(), "wav" | "flac") {
+            args.extend(["-b:a".to_string(), format!("{}k", bitrate)]);
+        }
+    }
+    if let Some(sample_rate) = preset.sample_rate {
+        args.extend(["-ar".to_string(), sample_rate.to_string()]);
+    }
+    args
+}
+
+/// Replaces characters that aren't allowed in file names on some systems.
+pub fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Fills in a naming template for one file, without its extension.
+pub fn render_file_name(
+    template: &str,
+    index: usize,
+    file: &FileMetadata,
+    bundle_name: &str,
+) -> String {
+    let stem = Path::new(&file.path)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("unknown_file")
+        .to_string();
+    let value = |field: &Option<String>| field.clone().filter(|v| !v.is_empty());
+    let name = template
+        .replace("{index}", &format!("{:03}", index))
+        .replace("{name}", &stem)
+        .replace("{title}", &value(&file.meta_title).unwrap_or(stem.clone()))
+        .replace(
+            "{artist}",
+            &value(&file.meta_album_artist).unwrap_or_default(),
+        )
+        .replace("{bpm}", &value(&file.meta_bpm).unwrap_or_default())
+        .replace("{key}", &value(&file.meta_key).unwrap_or_default())
+        .replace("{bundle}", bundle_name);
+    let name = sanitize_file_name(&name);
+    if name.is_empty() {
+        stem
+    } else {
+        name
+    }
+}
+
+/// Files made ready for an archive: what to write, and each file's metadata as
+/// delivered, in the same order.
+pub struct Delivery {
+    pub entries: Vec<ArchiveEntry>,
+    pub files: Vec<FileMetadata>,
+}
+
+/// Transcodes and names files for an archive as the preset asks, writing transcoded
+/// copies into `work_dir`. Without a `work_dir` the files are named and described as they
+/// will be delivered but left as they are, for a caller that encodes them itself
+/// (watermarking does, so each file is encoded only once). Without a preset the files go
+/// in as they are, under their usual numbered names. Fails, naming the file, if one can't
+/// be transcoded; a bundle is never sent with a file quietly missing.
+pub fn prepare_delivery(
+    app: &AppHandle,
+    files: &[FileMetadata],
+    preset: Option<&DeliveryPreset>,
+    bundle_name: &str,
+    work_dir: Option<&Path>,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&ArchiveProgress),
+) -> std::result::Result<Delivery, Box<dyn Error + Send + Sync>> {
+    let mut delivery = Delivery {
+        entries: Vec::new(),
+        files: Vec::new(),
+    };
+    let mut taken_names = HashSet::new();
+
+    for (i, file) in files.iter().enumerate() {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(CANCELLED.into());
+        }
+        on_progress(&ArchiveProgress {
+            file_index: i + 1,
+            file_count: files.len(),
+            ..Default::default()
+        });
+
+        let source = Path::new(&file.path);
+        let source_extension = source
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let mut delivered = file.clone();
+        let (path, extension) = match preset.filter(|preset| transcodes(preset)) {
+            Some(preset) if source.is_file() => {
+                let format = preset.format.clone().unwrap_or(source_extension);
+                let path = match work_dir {
+                    Some(work_dir) => {
+                        let output = work_dir.join(format!("{:03}.{}", i, format));
+                        let args = preset_ffmpeg_args(preset, &format);
+                        tauri::async_runtime::block_on(run_ffmpeg(app, source, &args, &output))
+                            .map_err(|e| format!("Failed to transcode '{}': {}", file.path, e))?;
+                        output.to_string_lossy().to_string()
+                    }
+                    None => file.path.clone(),
+                };
+                delivered.encoding = format.clone();
+                if let Some(sample_rate) = preset.sample_rate {
+                    delivered.meta_sample_rate = Some(sample_rate.to_string());
+                }
+                if let Some(bitrate) = preset.bitrate_kbps {
+                    delivered.meta_bit_rate = Some(bitrate.to_string());
+                }
+                (path, format)
+            }
+            _ => (file.path.clone(), source_extension),
+        };
+
+        let base_name = match preset.and_then(|preset| preset.naming_template.as_deref()) {
+            Some(template) => render_file_name(template, i, file, bundle_name),
+            None => {
+                let stem = source
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("unknown_file");
+                format!("{:03}_{}", i, stem)
+            }
+        };
+        let with_extension = |name: &str| match extension.as_str() {
+            "" => name.to_string(),
+            extension => format!("{}.{}", name, extension),
+        };
+        let mut archive_name = with_extension(&base_name);
+        let mut copy = 2;
+        while !taken_names.insert(archive_name.to_lowercase()) {
+            archive_name = with_extension(&format!("{} ({})", base_name, copy));
+            copy += 1;
+        }
+
+        delivery.entries.push(ArchiveEntry { path, archive_name });
+        delivery.files.push(delivered);
+    }
+    Ok(delivery)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_delivery_presets_command(
+    pool: State<'_, DbPool>,
+) -> Result<Vec<DeliveryPreset>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_delivery_presets(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn create_delivery_preset_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    preset: DeliveryPreset,
+) -> Result<DeliveryPreset, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = create_delivery_preset(&conn, &preset);
+
+        let payload = match &result {
+            Ok(preset) => format!("Delivery preset '{}' created.", preset.name),
+            Err(e) => format!("Failed to create delivery preset '{}': {}", preset.name, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "create_delivery_preset",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn update_delivery_preset_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    preset: DeliveryPreset,
+) -> Result<DeliveryPreset, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = update_delivery_preset(&conn, &preset);
+
+        let payload = match &result {
+            Ok(preset) => format!("Delivery preset '{}' updated.", preset.name),
+            Err(e) => format!("Failed to update delivery preset '{}': {}", preset.name, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "update_delivery_preset",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_delivery_preset_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    preset_id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_delivery_preset(&conn, &preset_id);
+
+        let payload = match &result {
+            Ok(_) => format!("Delivery preset '{}' deleted.", preset_id),
+            Err(e) => format!("Failed to delete delivery preset '{}': {}", preset_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_delivery_preset",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn set_delivery_preset_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    kind: BundleRecipientKind,
+    id: String,
+    preset_id: Option<String>,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = set_delivery_preset(&conn, kind, &id, preset_id.as_deref());
+
+        let payload = match (&result, &preset_id) {
+            (Ok(_), Some(preset_id)) => {
+                format!("Delivery preset '{}' set for '{}'.", preset_id, id)
+            }
+            (Ok(_), None) => format!("Delivery preset cleared for '{}'.", id),
+            (Err(e), _) => format!("Failed to set the delivery preset for '{}': {}", id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "set_delivery_preset",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::create_file;
+    use crate::commands::relations::link_files;
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+
+    fn preset(name: &str) -> DeliveryPreset {
+        DeliveryPreset {
+            id: String::new(),
+            name: name.to_string(),
+            format: None,
+            bitrate_kbps: None,
+            sample_rate: None,
+            bit_depth: None,
+            naming_template: None,
+            include_stems: false,
+            date_created: String::new(),
+            date_modified: String::new(),
+        }
+    }
+
+    fn recipient(contact_id: &str, preset_id: Option<&str>) -> BundleGenerationRecipient {
+        BundleGenerationRecipient {
+            contact_id: contact_id.to_string(),
+            name: contact_id.to_string(),
+            email: format!("{}@example.com", contact_id),
+            delivery_preset_id: preset_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn ffmpeg_args_follow_the_preset() {
+        let mp3 = DeliveryPreset {
+            bitrate_kbps: Some(320),
+            sample_rate: Some(44100),
+            ..preset("A&R")
+        };
+        assert_eq!(
+            preset_ffmpeg_args(&mp3, "MP3"),
+            ["-acodec", "libmp3lame", "-b:a", "320k", "-ar", "44100"]
+        );
+
+        let wav = DeliveryPreset {
+            bit_depth: Some(24),
+            bitrate_kbps: Some(320),
+            ..preset("Mix")
+        };
+        assert_eq!(preset_ffmpeg_args(&wav, "wav"), ["-acodec", "pcm_s24le"]);
+
+        let flac = DeliveryPreset {
+            bit_depth: Some(24),
+            ..preset("Archive")
+        };
+        assert_eq!(
+            preset_ffmpeg_args(&flac, "flac"),
+            [
+                "-acodec",
+                "flac",
+                "-sample_fmt",
+                "s32",
+                "-bits_per_raw_sample",
+                "24"
+            ]
+        );
+    }
+
+    #[test]
+    fn only_audio_settings_make_a_preset_transcode() {
+        let renaming = DeliveryPreset {
+            naming_template: Some("{title}".to_string()),
+            include_stems: true,
+            ..preset("Rename")
+        };
+        assert!(!transcodes(&renaming));
+        assert!(transcodes(&DeliveryPreset {
+            bit_depth: Some(16),
+            ..preset("CD")
+        }));
+    }
+
+    #[test]
+    fn file_names_fill_in_the_template() {
+        let dir = scratch_dir();
+        let mut file = sample_file(&dir, "mix_v3.wav", b"mix");
+        file.meta_album_artist = Some("Nova".to_string());
+        file.meta_bpm = Some("120".to_string());
+        file.meta_key = Some("Am".to_string());
+
+        assert_eq!(
+            render_file_name("{index} {artist} - {title} ({bpm} {key})", 4, &file, "EP"),
+            "004 Nova - mix_v3 (120 Am)"
+        );
+        file.meta_title = Some("Night Drive".to_string());
+        assert_eq!(
+            render_file_name("{bundle}/{title}: {name}", 0, &file, "EP"),
+            "EP_Night Drive_ mix_v3"
+        );
+        assert_eq!(render_file_name("{key}", 0, &file, "EP"), "Am");
+        file.meta_key = None;
+        assert_eq!(render_file_name("  {key} ", 0, &file, "EP"), "mix_v3");
+    }
+
+    #[test]
+    fn presets_are_validated() {
+        assert!(validate_preset(&preset(" ")).is_err());
+        assert!(validate_preset(&DeliveryPreset {
+            format: Some("wma".to_string()),
+            ..preset("Old")
+        })
+        .is_err());
+        assert!(validate_preset(&DeliveryPreset {
+            bit_depth: Some(20),
+            ..preset("Odd")
+        })
+        .is_err());
+        assert!(validate_preset(&DeliveryPreset {
+            sample_rate: Some(0),
+            ..preset("Silent")
+        })
+        .is_err());
+        assert!(validate_preset(&DeliveryPreset {
+            format: Some("FLAC".to_string()),
+            bit_depth: Some(24),
+            ..preset("Archive")
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn recipients_share_one_preset_or_none() {
+        let conn = test_connection();
+        let mp3 = create_delivery_preset(&conn, &preset("A&R")).unwrap();
+        let wav = create_delivery_preset(&conn, &preset("Mix")).unwrap();
+
+        let shared = [recipient("a", Some(&mp3.id)), recipient("b", Some(&mp3.id))];
+        let chosen = delivery_preset_for(&conn, None, &shared).unwrap();
+        assert_eq!(chosen.map(|p| p.id), Some(mp3.id.clone()));
+
+        let mixed = [recipient("a", Some(&mp3.id)), recipient("b", None)];
+        assert!(delivery_preset_for(&conn, None, &mixed).is_err());
+        let chosen = delivery_preset_for(&conn, Some(&wav.id), &mixed).unwrap();
+        assert_eq!(chosen.map(|p| p.id), Some(wav.id));
+
+        let plain = [recipient("a", None)];
+        assert!(delivery_preset_for(&conn, None, &plain).unwrap().is_none());
+    }
+
+    #[test]
+    fn stems_follow_their_mix_once() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let dir = scratch_dir();
+        let mix = sample_file(&dir, "mix.wav", b"mix");
+        let vocals = sample_file(&dir, "vocals.wav", b"vocals");
+        let other = sample_file(&dir, "other.wav", b"other");
+        for file in [&mix, &vocals, &other] {
+            create_file(&conn, "repo", file).unwrap();
+        }
+        link_files(&conn, &vocals.id, &mix.id, FileRelationKind::StemOf).unwrap();
+
+        let ids = |files: Vec<FileMetadata>| -> Vec<String> {
+            files.into_iter().map(|file| file.id).collect()
+        };
+        let expanded = with_stems(&conn, vec![mix.clone(), other.clone()]).unwrap();
+        assert_eq!(
+            ids(expanded),
+            [mix.id.clone(), vocals.id.clone(), other.id.clone()]
+        );
+        let expanded = with_stems(&conn, vec![vocals.clone(), mix.clone()]).unwrap();
+        assert_eq!(ids(expanded), [vocals.id, mix.id]);
+    }
+}
@@ -2,128 +2,88 @@ use lofty::config::WriteOptions;
 use lofty::prelude::*;
 use lofty::probe::Probe;
 use lofty::tag::*;
-use serde_json::json;
 use std::error::Error;
 use std::fs;
 use std::path::Path;
+use std::time::SystemTime;
 
-use crate::commands::structures::FileMetadata;
-use base64::{engine::general_purpose, Engine as _};
-use std::io::Cursor;
+use crate::commands::activity::{record_activity, NO_FILES};
+use crate::commands::db::{get_connection, DbPool};
+use crate::commands::journal::{journal_audio_change, snapshot_audio};
+use crate::commands::structures::{ActivityActor, FileMetadata};
+use chrono::{DateTime, SecondsFormat, Utc};
 use tauri::Emitter;
-use tauri::Window;
-use zip::write::SimpleFileOptions;
-use zip::CompressionMethod;
+use tauri::{State, Window};
 
-#[tauri::command]
-pub async fn bundle_files_command(
-    window: Window,
-    file_paths: Vec<String>,
-) -> Result<String, String> {
-    tauri::async_runtime::spawn_blocking(move || {
-        let total_files = file_paths.len();
-        if total_files == 0 {
-            return Err("No files provided to bundle.".into());
-        }
+/// File extensions Repo Studio treats as audio when scanning or watching folders.
+pub const AUDIO_EXTENSIONS: [&str; 5] = ["mp3", "wav", "flac", "ogg", "aac"];
 
-        let mut buffer = Cursor::new(Vec::<u8>::new());
-
-        {
-            // Scope for ZipWriter
-            let mut zip = zip::ZipWriter::new(&mut buffer);
-
-            for (i, path_str) in file_paths.iter().enumerate() {
-                // Emit progress update
-                let progress_percentage = ((i + 1) as f64 / total_files as f64) * 100.0;
-                window
-                    .emit(
-                        "bundle_progress",
-                        json!({
-                            "progress": progress_percentage,
-                            "file_index": i + 1,
-                            "total": total_files,
-                        }),
-                    )
-                    .ok();
-
-                let path_obj = Path::new(path_str);
-                if !path_obj.exists() {
-                    eprintln!("Error: File does not exist, skipping: {}", path_str);
-                    continue;
-                }
-                if !path_obj.is_file() {
-                    eprintln!("Warning: Skipping non-file path: {}", path_str);
-                    continue;
-                }
-
-                let extension = path_obj
-                    .extension()
-                    .and_then(|os_str| os_str.to_str())
-                    .map(|s| s.to_lowercase());
-                let compression_method = match extension.as_deref() {
-                    Some("wav") => CompressionMethod::Deflated,
-                    Some("mp3") | Some("flac") | Some("ogg") | Some("aac") => {
-                        CompressionMethod::Stored
-                    }
-                    _ => CompressionMethod::Stored,
-                };
+/// Returns true if the path has one of the supported audio extensions.
+pub fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        .unwrap_or(false)
+}
 
-                println!(
-                    "Processing file: '{}', Using Compression: {:?}",
-                    path_str, compression_method
-                );
+/// Milliseconds between 1601-01-01 (the Windows file time epoch) and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET_MS: i64 = 11_644_473_600_000;
 
-                let options = SimpleFileOptions::default()
-                    .compression_method(compression_method)
-                    .unix_permissions(0o755);
-
-                let mut file = match std::fs::File::open(path_obj) {
-                    Ok(f) => f,
-                    Err(e) => {
-                        eprintln!(
-                            "Error: Failed to open file '{}': {}. Skipping.",
-                            path_str, e
-                        );
-                        continue;
-                    }
-                };
-
-                let base_name = path_obj
-                    .file_name()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("unknown_file");
-                let name_in_archive = format!("{:03}_{}", i, base_name);
-
-                if let Err(e) = zip.start_file(&name_in_archive, options) {
-                    eprintln!(
-                        "Error: Failed to start file '{}' in zip: {}. Skipping.",
-                        name_in_archive, e
-                    );
-                    continue;
-                }
-
-                if let Err(e) = std::io::copy(&mut file, &mut zip) {
-                    eprintln!(
-                        "Error: Failed to copy data for file '{}' to zip: {}. Skipping.",
-                        name_in_archive, e
-                    );
-                    continue;
-                }
-            }
+/// Formats a filesystem time as an RFC 3339 UTC timestamp with millisecond precision,
+/// the format of `FileMetadata.date_created` and `date_modified`.
+pub fn format_system_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Millis, true)
+}
 
-            zip.finish()
-                .map_err(|e| format!("Failed to finalize zip archive: {}", e))?;
-        }
+/// Parses a stored timestamp into milliseconds since the Unix epoch.
+///
+/// Besides RFC 3339 this reads the `Debug` output of `SystemTime` that older versions
+/// stored: `SystemTime { tv_sec: .., tv_nsec: .. }` on Unix and
+/// `SystemTime { intervals: .. }` (100 ns ticks since 1601) on Windows.
+pub fn parse_timestamp_millis(value: &str) -> Option<i64> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.timestamp_millis());
+    }
+    let field = |name: &str| -> Option<i64> {
+        let start = value.find(&format!("{}:", name))? + name.len() + 1;
+        let digits: String = value[start..]
+            .trim_start()
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '-')
+            .collect();
+        digits.parse().ok()
+    };
+    if let Some(intervals) = field("intervals") {
+        return Some(intervals / 10_000 - WINDOWS_EPOCH_OFFSET_MS);
+    }
+    let secs = field("tv_sec")?;
+    let nanos = field("tv_nsec").unwrap_or(0);
+    Some(secs * 1000 + nanos / 1_000_000)
+}
 
-        // Emit completion event
-        window.emit("bundle_completed", "Bundling completed").ok();
+/// Rewrites a stored timestamp in RFC 3339, leaving it as is if it can't be read.
+pub fn normalize_timestamp(value: &str) -> String {
+    parse_timestamp_millis(value)
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_else(|| value.to_string())
+}
 
-        // Encode the raw zip data as a base64 string before returning.
-        let zip_bytes = buffer.into_inner();
-        Ok(general_purpose::STANDARD.encode(&zip_bytes))
-    })
-    .await
-    .map_err(|join_error| format!("Async task failed: {}", join_error))?
+/// True if two stored timestamps name the same instant, whatever format they are in.
+pub fn timestamps_match(a: &str, b: &str) -> bool {
+    match (parse_timestamp_millis(a), parse_timestamp_millis(b)) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Reads a file's modification time from disk, formatted the same way as
+/// `FileMetadata.date_modified`.
+pub fn read_date_modified(path: &str) -> std::io::Result<String> {
+    Ok(fs::metadata(path)?
+        .modified()
+        .map(format_system_time)
+        .unwrap_or_default())
 }
 
 pub fn get_audio_metadata_from_file(
@@ -150,6 +110,8 @@ pub fn get_audio_metadata_from_file(
         let meta_track_number =
             tag.and_then(|t| t.get_string(&ItemKey::TrackNumber).map(|s| s.to_string()));
         let meta_genre = tag.and_then(|t| t.genre().map(|s| s.to_string()));
+        let meta_bpm = tag.and_then(|t| t.get_string(&ItemKey::Bpm).map(|s| s.to_string()));
+        let meta_key = tag.and_then(|t| t.get_string(&ItemKey::InitialKey).map(|s| s.to_string()));
 
         let encoding = path_obj
             .extension()
@@ -166,11 +128,11 @@ pub fn get_audio_metadata_from_file(
         let fs_metadata = fs::metadata(path)?;
         let date_created = fs_metadata
             .created()
-            .map(|t| format!("{:?}", t))
+            .map(format_system_time)
             .unwrap_or_default();
         let date_modified = fs_metadata
             .modified()
-            .map(|t| format!("{:?}", t))
+            .map(format_system_time)
             .unwrap_or_default();
 
         let properties = tagged_file.properties();
@@ -196,10 +158,13 @@ pub fn get_audio_metadata_from_file(
             meta_album,
             meta_track_number,
             meta_genre,
+            meta_bpm,
+            meta_key,
             meta_bit_rate,
             meta_channels,
             meta_sample_rate,
             meta_size_on_disk,
+            notes: None,
         })
     })();
 
@@ -211,11 +176,11 @@ pub fn get_audio_metadata_from_file(
             if let Ok(fs_metadata) = fs::metadata(path) {
                 let date_created = fs_metadata
                     .created()
-                    .map(|t| format!("{:?}", t))
+                    .map(format_system_time)
                     .unwrap_or_default();
                 let date_modified = fs_metadata
                     .modified()
-                    .map(|t| format!("{:?}", t))
+                    .map(format_system_time)
                     .unwrap_or_default();
                 let encoding = path_obj
                     .extension()
@@ -250,10 +215,13 @@ pub fn get_audio_metadata_from_file(
                     meta_album: None,
                     meta_track_number: None,
                     meta_genre: None,
+                    meta_bpm: None,
+                    meta_key: None,
                     meta_bit_rate: None,
                     meta_channels: None,
                     meta_sample_rate: None,
                     meta_size_on_disk: Some(fs_metadata.len().to_string()),
+                    notes: None,
                 })
             } else {
                 // If even fs::metadata fails, propagate the original error.
@@ -333,6 +301,15 @@ pub fn write_audio_metadata_to_file(file_metadata: &FileMetadata) -> Result<(),
     if let Some(ref genre) = file_metadata.meta_genre {
         tag.set_genre(genre.clone());
     }
+    if let Some(ref bpm) = file_metadata.meta_bpm {
+        tag.insert(TagItem::new(ItemKey::Bpm, ItemValue::Text(bpm.clone())));
+    }
+    if let Some(ref key) = file_metadata.meta_key {
+        tag.insert(TagItem::new(
+            ItemKey::InitialKey,
+            ItemValue::Text(key.clone()),
+        ));
+    }
 
     tag.save_to_path(&file_metadata.path, WriteOptions::default())?;
     Ok(())
@@ -371,17 +348,34 @@ pub async fn get_audio_metadata_from_file_command(
 #[tauri::command]
 pub async fn clear_audio_metadata_from_file_command(
     window: Window,
+    pool: State<'_, DbPool>,
     file_path: String,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     let result: Result<(), String> = tauri::async_runtime::spawn_blocking(move || {
+        let before = snapshot_audio(&file_path);
         let result = clear_audio_metadata_from_file(&file_path);
 
         let payload = match &result {
             Ok(_) => format!("Cleared metadata for file '{}'", file_path),
             Err(e) => format!("Failed to clear metadata for '{}': {}", file_path, e),
         };
+        if let Ok(conn) = get_connection(&pool) {
+            if result.is_ok() {
+                journal_audio_change(&conn, "clear_audio_metadata", &payload, before);
+            }
+            record_activity(
+                &conn,
+                ActivityActor::User,
+                "clear_audio_metadata",
+                None,
+                NO_FILES,
+                &result,
+                &payload,
+            );
+        }
 
         emit_window
             .emit("clear_audio_metadata_completed", payload)
@@ -398,11 +392,14 @@ pub async fn clear_audio_metadata_from_file_command(
 #[tauri::command]
 pub async fn write_audio_metadata_to_file_command(
     window: Window,
+    pool: State<'_, DbPool>,
     file_metadata: FileMetadata,
 ) -> Result<(), String> {
     let emit_window = window.clone();
+    let pool = pool.inner().clone();
 
     let result: Result<(), String> = tauri::async_runtime::spawn_blocking(move || {
+        let before = snapshot_audio(&file_metadata.path);
         let result = write_audio_metadata_to_file(&file_metadata);
 
         let payload = match &result {
@@ -412,6 +409,20 @@ pub async fn write_audio_metadata_to_file_command(
                 file_metadata.path, e
             ),
         };
+        if let Ok(conn) = get_connection(&pool) {
+            if result.is_ok() {
+                journal_audio_change(&conn, "write_audio_metadata", &payload, before);
+            }
+            record_activity(
+                &conn,
+                ActivityActor::User,
+                "write_audio_metadata",
+                None,
+                &[&file_metadata.id],
+                &result,
+                &payload,
+            );
+        }
 
         emit_window
             .emit("write_audio_metadata_completed", payload)
@@ -425,3 +436,69 @@ pub async fn write_audio_metadata_to_file_command(
     .map_err(|e| e.to_string())?;
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn rfc_3339_timestamps_are_read_in_any_offset() {
+        assert_eq!(parse_timestamp_millis("1970-01-01T00:00:01.250Z"), Some(1250));
+        assert_eq!(parse_timestamp_millis("1970-01-01T01:00:01+01:00"), Some(1000));
+        assert_eq!(
+            normalize_timestamp("2024-01-01T01:00:00+01:00"),
+            "2024-01-01T00:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn legacy_system_time_timestamps_are_read() {
+        assert_eq!(
+            parse_timestamp_millis("SystemTime { tv_sec: 2, tv_nsec: 5000000 }"),
+            Some(2005)
+        );
+        assert_eq!(parse_timestamp_millis("SystemTime { tv_sec: 7 }"), Some(7000));
+        assert_eq!(
+            parse_timestamp_millis("SystemTime { intervals: 116444736010000000 }"),
+            Some(1000)
+        );
+        assert_eq!(
+            normalize_timestamp("SystemTime { tv_sec: 1704067200, tv_nsec: 0 }"),
+            "2024-01-01T00:00:00.000Z"
+        );
+    }
+
+    #[test]
+    fn timestamps_are_truncated_to_milliseconds() {
+        assert_eq!(
+            parse_timestamp_millis("SystemTime { tv_sec: 1, tv_nsec: 999999999 }"),
+            Some(1999)
+        );
+        assert_eq!(
+            normalize_timestamp("1970-01-01T00:00:01.123999Z"),
+            "1970-01-01T00:00:01.123Z"
+        );
+        assert_eq!(
+            format_system_time(UNIX_EPOCH + Duration::from_micros(1_001_900)),
+            "1970-01-01T00:00:01.001Z"
+        );
+        assert!(timestamps_match(
+            "1970-01-01T00:00:01.001Z",
+            "SystemTime { tv_sec: 1, tv_nsec: 1900000 }"
+        ));
+        assert!(!timestamps_match(
+            "1970-01-01T00:00:01.001Z",
+            "1970-01-01T00:00:01.002Z"
+        ));
+    }
+
+    #[test]
+    fn invalid_timestamps_are_left_as_they_are() {
+        assert_eq!(parse_timestamp_millis("yesterday"), None);
+        assert_eq!(parse_timestamp_millis("SystemTime { tv_nsec: 5 }"), None);
+        assert_eq!(normalize_timestamp("yesterday"), "yesterday");
+        assert!(timestamps_match("yesterday", "yesterday"));
+        assert!(!timestamps_match("yesterday", "1970-01-01T00:00:00.000Z"));
+    }
+}
@@ -0,0 +1,345 @@
+// src/commands/hierarchy.rs
+//! Nesting repositories under one another, e.g. artist → project → session.
+//!
+//! Each repository points at its parent through `parent_id`; top-level repositories have
+//! none. Files still belong to exactly one repository, but can be listed for a whole
+//! subtree, and the tree reports file totals for every repository and its subtree. While
+//! a parent is in the trash its children are shown at the top level, and purging it moves
+//! them there for good.
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{
+    get_connection, get_repositories, get_repository, repository_file_from_row, DbPool,
+    FILE_COLUMNS,
+};
+use crate::commands::structures::{Repository, RepositoryFile, RepositoryNode, RepositoryStats};
+use rusqlite::{params, Connection, Result};
+use tauri::{State, Window};
+
+/// Binds `subtree(id)` to a live repository (`?1`) and every live repository nested under
+/// it. `UNION` rather than `UNION ALL` keeps a damaged, cyclic hierarchy from looping.
+const SUBTREE_CTE: &str = "WITH RECURSIVE subtree(id) AS (
+        SELECT id FROM Repositories WHERE id = ?1 AND deleted_at IS NULL
+        UNION
+        SELECT r.id FROM Repositories r
+        JOIN subtree s ON r.parent_id = s.id
+        WHERE r.deleted_at IS NULL
+    )";
+
+/// Whether `ancestor_id` is `repo_id` or one of its ancestors. Repositories in the trash
+/// count too, since restoring them puts them back in the chain.
+fn is_ancestor_or_self(conn: &Connection, ancestor_id: &str, repo_id: &str) -> Result<bool> {
+    conn.query_row(
+        "WITH RECURSIVE ancestors(id) AS (
+            SELECT ?1
+            UNION
+            SELECT r.parent_id FROM Repositories r
+            JOIN ancestors a ON r.id = a.id
+            WHERE r.parent_id IS NOT NULL
+        )
+        SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ?2)",
+        params![repo_id, ancestor_id],
+        |row| row.get(0),
+    )
+}
+
+/// Nests a repository under `parent_id`, or moves it to the top level when that is None.
+/// Fails if the move would put a repository inside itself.
+pub fn move_repository(
+    conn: &Connection,
+    repo_id: &str,
+    parent_id: Option<&str>,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    get_repository(conn, repo_id)?;
+    if let Some(parent_id) = parent_id {
+        get_repository(conn, parent_id)?;
+        if is_ancestor_or_self(conn, repo_id, parent_id)? {
+            return Err(
+                "A repository can't be moved into itself or one of its own children.".into(),
+            );
+        }
+    }
+    conn.execute(
+        "UPDATE Repositories SET parent_id = ?1 WHERE id = ?2",
+        params![parent_id, repo_id],
+    )?;
+    Ok(())
+}
+
+/// Returns the files of a repository and of every repository nested under it.
+pub fn get_subtree_files(conn: &Connection, repo_id: &str) -> Result<Vec<RepositoryFile>> {
+    get_repository(conn, repo_id)?;
+    let mut stmt = conn.prepare(&format!(
+        "{}
+        SELECT {}, f.repo_id FROM Files f
+        JOIN subtree s ON s.id = f.repo_id
+        WHERE f.deleted_at IS NULL
+        ORDER BY f.repo_id, f.name",
+        SUBTREE_CTE, FILE_COLUMNS
+    ))?;
+    let files = stmt
+        .query_map(params![repo_id], repository_file_from_row)?
+        .collect::<Result<Vec<RepositoryFile>>>()?;
+    Ok(files)
+}
+
+/// File totals for every repository that has live files, by repository id.
+fn repository_stats(conn: &Connection) -> Result<HashMap<String, RepositoryStats>> {
+    let mut stmt = conn.prepare(
+        "SELECT repo_id,
+                COUNT(*),
+                SUM(CASE WHEN accessible = 0 THEN 1 ELSE 0 END),
+                SUM(CAST(COALESCE(meta_size_on_disk, '0') AS INTEGER))
+         FROM Files
+         WHERE deleted_at IS NULL
+         GROUP BY repo_id",
+    )?;
+    let stats = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                RepositoryStats {
+                    repository_count: 1,
+                    file_count: row.get(1)?,
+                    missing_file_count: row.get(2)?,
+                    size_on_disk: row.get(3)?,
+                },
+            ))
+        })?
+        .collect::<Result<HashMap<String, RepositoryStats>>>()?;
+    Ok(stats)
+}
+
+fn build_node(
+    repository: Repository,
+    children_of: &mut HashMap<String, Vec<Repository>>,
+    stats: &HashMap<String, RepositoryStats>,
+) -> RepositoryNode {
+    let own_stats = stats
+        .get(&repository.id)
+        .cloned()
+        .unwrap_or(RepositoryStats {
+            repository_count: 1,
+            ..Default::default()
+        });
+    let children: Vec<RepositoryNode> = children_of
+        .remove(&repository.id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|child| build_node(child, children_of, stats))
+        .collect();
+
+    let mut subtree_stats = own_stats.clone();
+    for child in &children {
+        subtree_stats.repository_count += child.subtree_stats.repository_count;
+        subtree_stats.file_count += child.subtree_stats.file_count;
+        subtree_stats.missing_file_count += child.subtree_stats.missing_file_count;
+        subtree_stats.size_on_disk += child.subtree_stats.size_on_disk;
+    }
+    RepositoryNode {
+        repository,
+        stats: own_stats,
+        subtree_stats,
+        children,
+    }
+}
+
+/// Returns the repository hierarchy with file totals, children sorted by name. With a
+/// `root_id` only that repository's subtree is returned.
+pub fn get_repository_tree(
+    conn: &Connection,
+    root_id: Option<&str>,
+) -> Result<Vec<RepositoryNode>> {
+    let mut repositories = get_repositories(conn)?;
+    repositories.sort_by_key(|repo| repo.name.to_lowercase());
+    let live: HashSet<String> = repositories.iter().map(|repo| repo.id.clone()).collect();
+    let stats = repository_stats(conn)?;
+
+    let mut roots = Vec::new();
+    let mut children_of: HashMap<String, Vec<Repository>> = HashMap::new();
+    for repository in repositories {
+        let nested = match (&repository.parent_id, root_id) {
+            (_, Some(root_id)) if repository.id == root_id => false,
+            (Some(parent_id), _) => live.contains(parent_id),
+            (None, _) => false,
+        };
+        if nested {
+            children_of
+                .entry(repository.parent_id.clone().unwrap_or_default())
+                .or_default()
+                .push(repository);
+        } else if root_id.is_none() || root_id == Some(repository.id.as_str()) {
+            roots.push(repository);
+        }
+    }
+
+    if root_id.is_some() && roots.is_empty() {
+        return Err(rusqlite::Error::QueryReturnedNoRows);
+    }
+    Ok(roots
+        .into_iter()
+        .map(|root| build_node(root, &mut children_of, &stats))
+        .collect())
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn move_repository_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    parent_id: Option<String>,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = move_repository(&conn, &repo_id, parent_id.as_deref());
+
+        let payload = match (&result, &parent_id) {
+            (Ok(_), Some(parent_id)) => format!(
+                "Repository '{}' moved under repository '{}'.",
+                repo_id, parent_id
+            ),
+            (Ok(_), None) => format!("Repository '{}' moved to the top level.", repo_id),
+            (Err(e), _) => format!("Failed to move repository '{}': {}", repo_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "move_repository",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_repository_tree_command(
+    pool: State<'_, DbPool>,
+    root_id: Option<String>,
+) -> Result<Vec<RepositoryNode>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_repository_tree(&conn, root_id.as_deref()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_subtree_files_command(
+    pool: State<'_, DbPool>,
+    repo_id: String,
+) -> Result<Vec<RepositoryFile>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_subtree_files(&conn, &repo_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{create_file, create_repository_with_id, delete_repository};
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+
+    /// artist → project → session, plus an unrelated top-level repository.
+    fn nested_repositories() -> Connection {
+        let conn = test_connection();
+        add_repository(&conn, "artist");
+        create_repository_with_id(&conn, "project", "project", "", Some("artist")).unwrap();
+        create_repository_with_id(&conn, "session", "session", "", Some("project")).unwrap();
+        add_repository(&conn, "other");
+        conn
+    }
+
+    fn parent_of(conn: &Connection, repo_id: &str) -> Option<String> {
+        get_repository(conn, repo_id).unwrap().parent_id
+    }
+
+    #[test]
+    fn a_repository_cant_be_moved_into_itself_or_below_itself() {
+        let conn = nested_repositories();
+
+        assert!(move_repository(&conn, "artist", Some("artist")).is_err());
+        assert!(move_repository(&conn, "artist", Some("project")).is_err());
+        assert!(move_repository(&conn, "artist", Some("session")).is_err());
+        assert_eq!(parent_of(&conn, "artist"), None);
+    }
+
+    #[test]
+    fn a_trashed_child_still_counts_as_below() {
+        let conn = nested_repositories();
+        delete_repository(&conn, "project").unwrap();
+
+        assert!(move_repository(&conn, "artist", Some("session")).is_err());
+        assert_eq!(parent_of(&conn, "artist"), None);
+    }
+
+    #[test]
+    fn repositories_move_between_parents_and_to_the_top_level() {
+        let conn = nested_repositories();
+
+        move_repository(&conn, "session", Some("other")).unwrap();
+        assert_eq!(parent_of(&conn, "session").as_deref(), Some("other"));
+        move_repository(&conn, "project", None).unwrap();
+        assert_eq!(parent_of(&conn, "project"), None);
+        // Moving a child above its former parent is fine.
+        move_repository(&conn, "artist", Some("project")).unwrap();
+        assert_eq!(parent_of(&conn, "artist").as_deref(), Some("project"));
+        assert!(move_repository(&conn, "session", Some("missing")).is_err());
+    }
+
+    #[test]
+    fn subtrees_include_every_nested_repository_and_file() {
+        let conn = nested_repositories();
+        let dir = scratch_dir();
+        create_file(&conn, "artist", &sample_file(&dir, "a.wav", b"aa")).unwrap();
+        create_file(&conn, "session", &sample_file(&dir, "s.wav", b"ssss")).unwrap();
+        create_file(&conn, "other", &sample_file(&dir, "o.wav", b"o")).unwrap();
+
+        let files = get_subtree_files(&conn, "artist").unwrap();
+        let repo_ids: Vec<&str> = files.iter().map(|f| f.repo_id.as_str()).collect();
+        assert_eq!(repo_ids, vec!["artist", "session"]);
+
+        let tree = get_repository_tree(&conn, Some("artist")).unwrap();
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].subtree_stats.repository_count, 3);
+        assert_eq!(tree[0].subtree_stats.file_count, 2);
+        assert_eq!(tree[0].subtree_stats.size_on_disk, 6);
+        assert_eq!(tree[0].stats.file_count, 1);
+    }
+
+    #[test]
+    fn children_of_a_trashed_parent_show_at_the_top_level() {
+        let conn = nested_repositories();
+        delete_repository(&conn, "artist").unwrap();
+
+        let tree = get_repository_tree(&conn, None).unwrap();
+        let roots: Vec<&str> = tree
+            .iter()
+            .map(|node| node.repository.id.as_str())
+            .collect();
+        assert_eq!(roots, vec!["other", "project"]);
+        assert_eq!(tree[1].children.len(), 1);
+    }
+}
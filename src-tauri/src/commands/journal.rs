@@ -0,0 +1,777 @@
+// src/commands/journal.rs
+//! Undo and redo for operations that change files or file records.
+//!
+//! Each journaled operation stores two lists of steps: the ones that put things back as
+//! they were before it ran, and the ones that do it again. Audio is captured whole in the
+//! object store (the same one `versions` uses), so a snapshot includes the embedded tags;
+//! rows are stored as `FileMetadata`. The journal lives in the database, so it survives a
+//! restart, and keeps the last `JOURNAL_LIMIT` operations. Like an editor, recording a new
+//! operation throws away anything that was undone.
+//!
+//! Before touching the disk, every audio step checks that the file still holds what the
+//! operation left there, so changes made since (in another program, say) are never
+//! overwritten; the undo fails instead. The rows and the entry's state change in one
+//! transaction, and if that fails the audio written before it is put back.
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{
+    create_file, delete_file, get_connection, get_file, move_file_rows, purge_file, update_file,
+    DbPool,
+};
+use crate::commands::file_ops::read_date_modified;
+use crate::commands::relations::{delete_file_relation, insert_file_relation};
+use crate::commands::structures::{
+    FileMetadata, FileRelationKind, JournalEntry, JournalState, TrashItemKind,
+};
+use crate::commands::trash::untrash_item;
+use crate::commands::versions::{
+    hash_file, put_back_audio, remove_unreferenced_objects, store_object, write_object_to,
+};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use tauri::{State, Window};
+
+/// How many operations the journal keeps.
+const JOURNAL_LIMIT: i64 = 50;
+
+/// One action taken when undoing or redoing an operation.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum JournalStep {
+    /// Puts a stored object at `path`, or removes the file when `content_hash` is None.
+    /// `replaces` is what the path must hold beforehand (None: no file).
+    WriteAudio {
+        path: String,
+        content_hash: Option<String>,
+        replaces: Option<String>,
+    },
+    /// Writes a file row back, creating it if it no longer exists.
+    PutFileRow {
+        repo_id: String,
+        file: Box<FileMetadata>,
+    },
+    /// Deletes a file row for good.
+    RemoveFileRow {
+        repo_id: String,
+        file_id: String,
+    },
+    TrashFile {
+        repo_id: String,
+        file_id: String,
+    },
+    UntrashFile {
+        file_id: String,
+    },
+    LinkFiles {
+        source_file_id: String,
+        target_file_id: String,
+        relation: FileRelationKind,
+    },
+    UnlinkFiles {
+        source_file_id: String,
+        target_file_id: String,
+        relation: FileRelationKind,
+    },
+    /// Moves file rows, with their history, from one repository to another.
+    MoveFiles {
+        from_repo_id: String,
+        to_repo_id: String,
+        file_ids: Vec<String>,
+    },
+}
+
+/// The audio at a path at some moment: its stored content hash, or None if there was no
+/// file.
+#[derive(Debug, Clone)]
+pub struct AudioSnapshot {
+    pub path: String,
+    pub content_hash: Option<String>,
+}
+
+/// Copies the audio at `path` into the object store. Returns None, after printing why,
+/// if the file exists but can't be stored; the operation then just isn't journaled.
+pub fn snapshot_audio(path: &str) -> Option<AudioSnapshot> {
+    if !Path::new(path).exists() {
+        return Some(AudioSnapshot {
+            path: path.to_string(),
+            content_hash: None,
+        });
+    }
+    match store_object(Path::new(path)) {
+        Ok((content_hash, _)) => Some(AudioSnapshot {
+            path: path.to_string(),
+            content_hash: Some(content_hash),
+        }),
+        Err(e) => {
+            println!("Failed to snapshot '{}' for the undo journal: {}", path, e);
+            None
+        }
+    }
+}
+
+/// A step that puts `snapshot` back in place of `current`.
+pub fn restore_audio_step(snapshot: &AudioSnapshot, current: &AudioSnapshot) -> JournalStep {
+    JournalStep::WriteAudio {
+        path: snapshot.path.clone(),
+        content_hash: snapshot.content_hash.clone(),
+        replaces: current.content_hash.clone(),
+    }
+}
+
+/// Journals a change to one audio file, given its state before the change. Nothing is
+/// journaled if either state can't be captured or the file didn't actually change.
+pub fn journal_audio_change(
+    conn: &Connection,
+    operation: &str,
+    description: &str,
+    before: Option<AudioSnapshot>,
+) {
+    let Some(before) = before else { return };
+    let Some(after) = snapshot_audio(&before.path) else {
+        return;
+    };
+    if before.content_hash == after.content_hash {
+        return;
+    }
+    journal_operation(
+        conn,
+        operation,
+        description,
+        vec![restore_audio_step(&before, &after)],
+        vec![restore_audio_step(&after, &before)],
+    );
+}
+
+/// Journals an update of file rows, given the rows as they were before it.
+pub fn journal_file_rows(
+    conn: &Connection,
+    operation: &str,
+    description: &str,
+    repo_id: &str,
+    before: Vec<FileMetadata>,
+) {
+    let mut undo = Vec::new();
+    let mut redo = Vec::new();
+    for previous in before {
+        match get_file(conn, repo_id, &previous.id) {
+            Ok(current) => redo.push(JournalStep::PutFileRow {
+                repo_id: repo_id.to_string(),
+                file: Box::new(current),
+            }),
+            Err(e) => {
+                println!(
+                    "Failed to read '{}' for the undo journal: {}",
+                    previous.id, e
+                );
+                return;
+            }
+        }
+        undo.push(JournalStep::PutFileRow {
+            repo_id: repo_id.to_string(),
+            file: Box::new(previous),
+        });
+    }
+    journal_operation(conn, operation, description, undo, redo);
+}
+
+/// Journals moving file rows to the trash.
+pub fn journal_trashed_files(
+    conn: &Connection,
+    operation: &str,
+    description: &str,
+    repo_id: &str,
+    file_ids: &[String],
+) {
+    let undo = file_ids
+        .iter()
+        .map(|file_id| JournalStep::UntrashFile {
+            file_id: file_id.clone(),
+        })
+        .collect();
+    let redo = file_ids
+        .iter()
+        .map(|file_id| JournalStep::TrashFile {
+            repo_id: repo_id.to_string(),
+            file_id: file_id.clone(),
+        })
+        .collect();
+    journal_operation(conn, operation, description, undo, redo);
+}
+
+/// Journals a move of file rows between repositories.
+pub fn journal_moved_files(
+    conn: &Connection,
+    operation: &str,
+    description: &str,
+    from_repo_id: &str,
+    to_repo_id: &str,
+    file_ids: &[String],
+) {
+    let step = |from: &str, to: &str| JournalStep::MoveFiles {
+        from_repo_id: from.to_string(),
+        to_repo_id: to.to_string(),
+        file_ids: file_ids.to_vec(),
+    };
+    journal_operation(
+        conn,
+        operation,
+        description,
+        vec![step(to_repo_id, from_repo_id)],
+        vec![step(from_repo_id, to_repo_id)],
+    );
+}
+
+/// Records an operation in the journal. Failing to do so is reported but never fails the
+/// operation itself.
+pub fn journal_operation(
+    conn: &Connection,
+    operation: &str,
+    description: &str,
+    undo: Vec<JournalStep>,
+    redo: Vec<JournalStep>,
+) {
+    if undo.is_empty() && redo.is_empty() {
+        return;
+    }
+    if let Err(e) = record_journal(conn, operation, description, &undo, &redo) {
+        println!(
+            "Failed to record '{}' in the undo journal: {}",
+            operation, e
+        );
+    }
+}
+
+/// Content hashes the steps need kept in the object store.
+fn referenced_objects(steps: &[JournalStep]) -> Vec<&str> {
+    steps
+        .iter()
+        .filter_map(|step| match step {
+            JournalStep::WriteAudio {
+                content_hash: Some(content_hash),
+                ..
+            } => Some(content_hash.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn record_journal(
+    conn: &Connection,
+    operation: &str,
+    description: &str,
+    undo: &[JournalStep],
+    redo: &[JournalStep],
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let tx = conn.unchecked_transaction()?;
+    // A new operation replaces whatever was undone.
+    let mut dropped = journal_objects(&tx, "WHERE j.state = 'undone'")?;
+    tx.execute("DELETE FROM Journal WHERE state = 'undone'", [])?;
+
+    tx.execute(
+        "INSERT INTO Journal (timestamp, operation, description, undo_steps, redo_steps)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            operation,
+            description,
+            serde_json::to_string(undo)?,
+            serde_json::to_string(redo)?
+        ],
+    )?;
+    let journal_id = tx.last_insert_rowid();
+    {
+        let mut link = tx.prepare_cached(
+            "INSERT OR IGNORE INTO JournalObjects (journal_id, content_hash) VALUES (?1, ?2)",
+        )?;
+        for content_hash in referenced_objects(undo)
+            .into_iter()
+            .chain(referenced_objects(redo))
+        {
+            link.execute(params![journal_id, content_hash])?;
+        }
+    }
+
+    // Everything older than the last JOURNAL_LIMIT entries goes.
+    let oldest_dropped = format!(
+        "(SELECT id FROM Journal ORDER BY id DESC LIMIT 1 OFFSET {})",
+        JOURNAL_LIMIT
+    );
+    dropped.extend(journal_objects(
+        &tx,
+        &format!("WHERE j.id <= {}", oldest_dropped),
+    )?);
+    tx.execute(
+        &format!("DELETE FROM Journal WHERE id <= {}", oldest_dropped),
+        [],
+    )?;
+    tx.commit()?;
+
+    remove_unreferenced_objects(conn, &dropped);
+    Ok(())
+}
+
+/// Content hashes referenced by the journal entries matching `where_clause`.
+fn journal_objects(conn: &Connection, where_clause: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT o.content_hash FROM JournalObjects o
+         JOIN Journal j ON j.id = o.journal_id
+         {}",
+        where_clause
+    ))?;
+    let hashes = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(hashes)
+}
+
+/// The content hash of the file at `path`, or None if there is no file.
+fn current_content_hash(
+    path: &str,
+) -> std::result::Result<Option<String>, Box<dyn Error + Send + Sync>> {
+    if Path::new(path).exists() {
+        Ok(Some(hash_file(Path::new(path))?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Carries out the steps of an entry and moves it to `state`. Every audio file is checked
+/// before any is written; the audio there is kept so it can be put back if a write or the
+/// row changes fail. The rows and the entry's state change in one transaction.
+fn apply_steps(
+    conn: &Connection,
+    steps: &[JournalStep],
+    entry_id: i64,
+    state: &str,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let mut previous_audio: Vec<(PathBuf, Option<String>)> = Vec::new();
+    for step in steps {
+        if let JournalStep::WriteAudio { path, replaces, .. } = step {
+            if current_content_hash(path)? != *replaces {
+                return Err(format!(
+                    "'{}' has changed since this operation; it can't be reverted.",
+                    path
+                )
+                .into());
+            }
+            let snapshot = snapshot_audio(path)
+                .ok_or_else(|| format!("Failed to keep a copy of '{}'.", path))?;
+            previous_audio.push((PathBuf::from(path), snapshot.content_hash));
+        }
+    }
+
+    for step in steps {
+        if let JournalStep::WriteAudio {
+            path, content_hash, ..
+        } = step
+        {
+            let written = match content_hash {
+                Some(content_hash) => write_object_to(content_hash, Path::new(path)),
+                None if Path::new(path).exists() => fs::remove_file(path).map_err(|e| e.into()),
+                None => Ok(()),
+            };
+            if let Err(e) = written {
+                put_back_audio(&previous_audio);
+                return Err(e);
+            }
+        }
+    }
+
+    let result = (|| -> std::result::Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let tx = conn.unchecked_transaction()?;
+        let mut dropped = Vec::new();
+        for step in steps {
+            apply_row_step(&tx, step, &mut dropped)?;
+        }
+        tx.execute(
+            "UPDATE Journal SET state = ?1 WHERE id = ?2",
+            params![state, entry_id],
+        )?;
+        tx.commit()?;
+        Ok(dropped)
+    })();
+    match result {
+        Ok(dropped) => {
+            remove_unreferenced_objects(conn, &dropped);
+            Ok(())
+        }
+        Err(e) => {
+            put_back_audio(&previous_audio);
+            Err(e)
+        }
+    }
+}
+
+/// Carries out one step's change to the rows. Audio steps have already been written.
+/// Objects that rows removed here referred to are added to `dropped`.
+fn apply_row_step(
+    conn: &Connection,
+    step: &JournalStep,
+    dropped: &mut Vec<String>,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    match step {
+        JournalStep::WriteAudio { .. } => {}
+        JournalStep::PutFileRow { repo_id, file } => {
+            // Dates and availability follow the file on disk as it is now.
+            let exists = Path::new(&file.path).exists();
+            let file = FileMetadata {
+                accessible: exists,
+                date_modified: if exists {
+                    read_date_modified(&file.path).unwrap_or_else(|_| file.date_modified.clone())
+                } else {
+                    file.date_modified.clone()
+                },
+                ..(**file).clone()
+            };
+            let row_exists: bool = conn.query_row(
+                "SELECT EXISTS(SELECT 1 FROM Files WHERE id = ?1 AND repo_id = ?2)",
+                params![file.id, repo_id],
+                |row| row.get(0),
+            )?;
+            if row_exists {
+                update_file(conn, repo_id, &file)?;
+            } else {
+                create_file(conn, repo_id, &file)?;
+            }
+        }
+        JournalStep::RemoveFileRow { repo_id, file_id } => {
+            dropped.extend(purge_file(conn, repo_id, file_id)?);
+        }
+        JournalStep::TrashFile { repo_id, file_id } => {
+            delete_file(conn, repo_id, file_id)?;
+        }
+        JournalStep::UntrashFile { file_id } => {
+            untrash_item(conn, TrashItemKind::File, file_id)?;
+        }
+        JournalStep::LinkFiles {
+            source_file_id,
+            target_file_id,
+            relation,
+        } => {
+            insert_file_relation(conn, source_file_id, target_file_id, *relation)?;
+        }
+        JournalStep::UnlinkFiles {
+            source_file_id,
+            target_file_id,
+            relation,
+        } => {
+            let relation_id: Option<String> = conn
+                .query_row(
+                    "SELECT id FROM FileRelations
+                     WHERE source_file_id = ?1 AND target_file_id = ?2 AND relation = ?3",
+                    params![source_file_id, target_file_id, relation.as_str()],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if let Some(relation_id) = relation_id {
+                delete_file_relation(conn, &relation_id)?;
+            }
+        }
+        JournalStep::MoveFiles {
+            from_repo_id,
+            to_repo_id,
+            file_ids,
+        } => {
+            move_file_rows(conn, from_repo_id, to_repo_id, file_ids)?;
+        }
+    }
+    Ok(())
+}
+
+/// Undoes the most recent operation that is still in effect, returning it.
+pub fn undo(conn: &Connection) -> std::result::Result<JournalEntry, Box<dyn Error + Send + Sync>> {
+    let (entry, steps) = journal_entry(
+        conn,
+        "SELECT id, timestamp, operation, description, state, undo_steps FROM Journal
+         WHERE state = 'done' ORDER BY id DESC LIMIT 1",
+    )?
+    .ok_or("There is nothing to undo.")?;
+    apply_steps(conn, &steps, entry.id, "undone")?;
+    Ok(JournalEntry {
+        state: JournalState::Undone,
+        ..entry
+    })
+}
+
+/// Redoes the earliest undone operation, returning it.
+pub fn redo(conn: &Connection) -> std::result::Result<JournalEntry, Box<dyn Error + Send + Sync>> {
+    let (entry, steps) = journal_entry(
+        conn,
+        "SELECT id, timestamp, operation, description, state, redo_steps FROM Journal
+         WHERE state = 'undone' ORDER BY id ASC LIMIT 1",
+    )?
+    .ok_or("There is nothing to redo.")?;
+    apply_steps(conn, &steps, entry.id, "done")?;
+    Ok(JournalEntry {
+        state: JournalState::Done,
+        ..entry
+    })
+}
+
+/// An entry together with the steps that undo or redo it.
+type EntrySteps = (JournalEntry, Vec<JournalStep>);
+
+/// Reads one entry and the steps in its sixth column.
+fn journal_entry(
+    conn: &Connection,
+    sql: &str,
+) -> std::result::Result<Option<EntrySteps>, Box<dyn Error + Send + Sync>> {
+    let row = conn
+        .query_row(sql, [], |row| {
+            Ok((entry_from_row(row)?, row.get::<_, String>(5)?))
+        })
+        .optional()?;
+    match row {
+        Some((entry, steps)) => Ok(Some((entry, serde_json::from_str(&steps)?))),
+        None => Ok(None),
+    }
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> Result<JournalEntry> {
+    let state: String = row.get(4)?;
+    Ok(JournalEntry {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        operation: row.get(2)?,
+        description: row.get(3)?,
+        state: if state == "undone" {
+            JournalState::Undone
+        } else {
+            JournalState::Done
+        },
+    })
+}
+
+/// Returns the journal, newest first.
+pub fn get_journal(conn: &Connection) -> Result<Vec<JournalEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, timestamp, operation, description, state FROM Journal ORDER BY id DESC",
+    )?;
+    let entries = stmt
+        .query_map([], entry_from_row)?
+        .collect::<Result<Vec<JournalEntry>>>()?;
+    Ok(entries)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn undo_command(window: Window, pool: State<'_, DbPool>) -> Result<JournalEntry, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = undo(&conn);
+
+        let payload = match &result {
+            Ok(entry) => format!("Undid '{}'.", entry.description),
+            Err(e) => format!("Failed to undo: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "undo",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn redo_command(window: Window, pool: State<'_, DbPool>) -> Result<JournalEntry, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = redo(&conn);
+
+        let payload = match &result {
+            Ok(entry) => format!("Redid '{}'.", entry.description),
+            Err(e) => format!("Failed to redo: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "redo",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_journal_command(pool: State<'_, DbPool>) -> Result<Vec<JournalEntry>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_journal(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+
+    fn journal_state(conn: &Connection) -> String {
+        conn.query_row(
+            "SELECT state FROM Journal ORDER BY id DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn is_trashed(conn: &Connection, file_id: &str) -> bool {
+        conn.query_row(
+            "SELECT deleted_at IS NOT NULL FROM Files WHERE id = ?1",
+            params![file_id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn undo_and_redo_put_the_audio_back() {
+        let conn = test_connection();
+        let dir = scratch_dir();
+        let file = sample_file(&dir, "take.wav", b"first take");
+
+        let before = snapshot_audio(&file.path);
+        fs::write(&file.path, b"second take").unwrap();
+        journal_audio_change(&conn, "edit", "Edited the take", before);
+
+        let undone = undo(&conn).unwrap();
+        assert_eq!(undone.state, JournalState::Undone);
+        assert_eq!(fs::read(&file.path).unwrap(), b"first take");
+        assert_eq!(journal_state(&conn), "undone");
+
+        redo(&conn).unwrap();
+        assert_eq!(fs::read(&file.path).unwrap(), b"second take");
+        assert_eq!(journal_state(&conn), "done");
+        assert!(redo(&conn).is_err());
+    }
+
+    #[test]
+    fn undo_refuses_audio_changed_since() {
+        let conn = test_connection();
+        let dir = scratch_dir();
+        let file = sample_file(&dir, "take.wav", b"first take");
+
+        let before = snapshot_audio(&file.path);
+        fs::write(&file.path, b"second take").unwrap();
+        journal_audio_change(&conn, "edit", "Edited the take", before);
+        fs::write(&file.path, b"edited elsewhere").unwrap();
+
+        assert!(undo(&conn).is_err());
+        assert_eq!(fs::read(&file.path).unwrap(), b"edited elsewhere");
+        assert_eq!(journal_state(&conn), "done");
+    }
+
+    #[test]
+    fn undo_and_redo_trashing() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let dir = scratch_dir();
+        let file = sample_file(&dir, "take.wav", b"take");
+        create_file(&conn, "repo", &file).unwrap();
+
+        delete_file(&conn, "repo", &file.id).unwrap();
+        journal_trashed_files(
+            &conn,
+            "delete_files",
+            "Trashed a take",
+            "repo",
+            std::slice::from_ref(&file.id),
+        );
+
+        undo(&conn).unwrap();
+        assert!(!is_trashed(&conn, &file.id));
+        redo(&conn).unwrap();
+        assert!(is_trashed(&conn, &file.id));
+    }
+
+    #[test]
+    fn undo_and_redo_a_move_between_repositories() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        add_repository(&conn, "other");
+        let dir = scratch_dir();
+        let file = sample_file(&dir, "take.wav", b"take");
+        create_file(&conn, "repo", &file).unwrap();
+        let file_ids = std::slice::from_ref(&file.id);
+
+        move_file_rows(&conn, "repo", "other", file_ids).unwrap();
+        journal_moved_files(
+            &conn,
+            "move_files_to_repository",
+            "Moved a take",
+            "repo",
+            "other",
+            file_ids,
+        );
+
+        undo(&conn).unwrap();
+        assert!(get_file(&conn, "repo", &file.id).is_ok());
+        redo(&conn).unwrap();
+        assert!(get_file(&conn, "other", &file.id).is_ok());
+    }
+
+    #[test]
+    fn a_failed_step_leaves_rows_audio_and_journal_unchanged() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let dir = scratch_dir();
+        let file = sample_file(&dir, "take.wav", b"first take");
+        create_file(&conn, "repo", &file).unwrap();
+
+        let before = snapshot_audio(&file.path).unwrap();
+        fs::write(&file.path, b"second take").unwrap();
+        let after = snapshot_audio(&file.path).unwrap();
+        let annotated = FileMetadata {
+            notes: Some("undone".to_string()),
+            ..file.clone()
+        };
+        // The file isn't in the trash, so the last step fails after the others ran.
+        let undo_steps = vec![
+            restore_audio_step(&before, &after),
+            JournalStep::PutFileRow {
+                repo_id: "repo".to_string(),
+                file: Box::new(annotated),
+            },
+            JournalStep::UntrashFile {
+                file_id: file.id.clone(),
+            },
+        ];
+        journal_operation(&conn, "edit", "Edited the take", undo_steps, Vec::new());
+
+        assert!(undo(&conn).is_err());
+        assert_eq!(fs::read(&file.path).unwrap(), b"second take");
+        assert_eq!(get_file(&conn, "repo", &file.id).unwrap().notes, None);
+        assert_eq!(journal_state(&conn), "done");
+    }
+}
@@ -0,0 +1,620 @@
+// src/commands/maintenance.rs
+//! Backups, restores and integrity checks of the library database.
+//!
+//! Backups are taken with SQLite's online backup API, so they are consistent even while
+//! other connections keep writing. Each backup is a standalone `db.sqlite` copy named
+//! `repostudio-backup-<UTC timestamp>.sqlite`; listing a folder finds them by that name.
+//! They go to the folder in BackupSettings, or `RepoStudio_AppData/Backups` if none is set.
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{get_app_data_dir, get_connection, DbPool};
+use crate::commands::migrations::{get_schema_version, latest_version, run_migrations};
+use crate::commands::structures::{BackupInfo, BackupSettings, IntegrityReport, TrackedFolder};
+use chrono::{NaiveDateTime, SecondsFormat, Utc};
+use rusqlite::{backup::Progress, params, Connection, OpenFlags, Result, MAIN_DB};
+use tauri::{State, Window};
+
+const BACKUP_PREFIX: &str = "repostudio-backup-";
+const BACKUP_EXTENSION: &str = "sqlite";
+const BACKUP_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+// ---------------------------------------------------------------------------
+// Settings
+// ---------------------------------------------------------------------------
+
+pub fn get_backup_settings(conn: &Connection) -> Result<BackupSettings> {
+    conn.query_row(
+        "SELECT folder, interval_hours, keep_count FROM BackupSettings WHERE id = 1",
+        [],
+        |row| {
+            Ok(BackupSettings {
+                folder: row.get(0)?,
+                interval_hours: row.get(1)?,
+                keep_count: row.get(2)?,
+            })
+        },
+    )
+}
+
+pub fn update_backup_settings(conn: &Connection, settings: &BackupSettings) -> Result<()> {
+    conn.execute(
+        "UPDATE BackupSettings SET folder = ?1, interval_hours = ?2, keep_count = ?3 WHERE id = 1",
+        params![
+            settings
+                .folder
+                .as_deref()
+                .map(str::trim)
+                .filter(|f| !f.is_empty()),
+            settings.interval_hours,
+            settings.keep_count
+        ],
+    )?;
+    Ok(())
+}
+
+/// The folder backups go to when none is given.
+pub fn get_backup_folder(conn: &Connection) -> Result<PathBuf> {
+    Ok(get_backup_settings(conn)?
+        .folder
+        .map(PathBuf::from)
+        .unwrap_or_else(|| get_app_data_dir().join("Backups")))
+}
+
+// ---------------------------------------------------------------------------
+// Backups
+// ---------------------------------------------------------------------------
+
+fn backup_info(path: &Path) -> Option<BackupInfo> {
+    let file_name = path.file_name()?.to_str()?.to_string();
+    let timestamp = file_name
+        .strip_prefix(BACKUP_PREFIX)?
+        .strip_suffix(&format!(".{}", BACKUP_EXTENSION))?;
+    let created_at = NaiveDateTime::parse_from_str(timestamp, BACKUP_TIMESTAMP_FORMAT)
+        .ok()?
+        .and_utc()
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+    let metadata = fs::metadata(path).ok()?;
+
+    // A backup that can't be opened is still listed, so it can be found and removed.
+    let schema_version = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|conn| get_schema_version(&conn))
+        .ok();
+
+    Some(BackupInfo {
+        path: path.to_string_lossy().to_string(),
+        file_name,
+        created_at,
+        size_on_disk: metadata.len(),
+        schema_version,
+    })
+}
+
+/// Lists the backups in a folder, newest first.
+pub fn list_backups(
+    folder: &Path,
+) -> std::result::Result<Vec<BackupInfo>, Box<dyn Error + Send + Sync>> {
+    if !folder.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups: Vec<BackupInfo> = fs::read_dir(folder)?
+        .flatten()
+        .filter_map(|entry| backup_info(&entry.path()))
+        .collect();
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(backups)
+}
+
+/// Writes a consistent copy of the live database into `folder`. The copy is written
+/// under a temporary name and renamed once complete, so a half-written file never looks
+/// like a backup.
+pub fn create_backup(
+    conn: &Connection,
+    folder: &Path,
+) -> std::result::Result<BackupInfo, Box<dyn Error + Send + Sync>> {
+    fs::create_dir_all(folder)?;
+    let timestamp = Utc::now().format(BACKUP_TIMESTAMP_FORMAT).to_string();
+    let file_name = format!("{}{}.{}", BACKUP_PREFIX, timestamp, BACKUP_EXTENSION);
+    let final_path = folder.join(&file_name);
+    let temp_path = folder.join(format!("{}.partial", file_name));
+
+    if let Err(e) = conn.backup(MAIN_DB, &temp_path, None) {
+        fs::remove_file(&temp_path).ok();
+        return Err(e.into());
+    }
+    fs::rename(&temp_path, &final_path)?;
+    println!("Backed up the library to {:?}", final_path);
+
+    backup_info(&final_path).ok_or_else(|| "The backup was written but can't be read back.".into())
+}
+
+/// Deletes the oldest backups in `folder` beyond the newest `keep`. Zero keeps them all.
+pub fn prune_backups(
+    folder: &Path,
+    keep: u32,
+) -> std::result::Result<usize, Box<dyn Error + Send + Sync>> {
+    if keep == 0 {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for backup in list_backups(folder)?.into_iter().skip(keep as usize) {
+        fs::remove_file(&backup.path)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Runs `PRAGMA integrity_check` on a backup file without opening it for writing.
+fn verify_backup_file(path: &Path) -> std::result::Result<i32, Box<dyn Error + Send + Sync>> {
+    let backup = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let problems = run_integrity_check(&backup)?;
+    if !problems.is_empty() {
+        return Err(format!("The backup is damaged: {}", problems.join("; ")).into());
+    }
+    let version = get_schema_version(&backup)?;
+    if version > latest_version() {
+        return Err(format!(
+            "The backup was made by a newer version of Repo Studio (schema version {}).",
+            version
+        )
+        .into());
+    }
+    Ok(version)
+}
+
+/// Replaces the live database with a backup, then brings the restored schema up to date.
+/// The current library is backed up first, so the restore itself can be undone.
+pub fn restore_backup(
+    conn: &mut Connection,
+    backup_path: &Path,
+) -> std::result::Result<BackupInfo, Box<dyn Error + Send + Sync>> {
+    verify_backup_file(backup_path)?;
+
+    let safety_backup = create_backup(conn, &get_backup_folder(conn)?)?;
+    conn.restore(MAIN_DB, backup_path, None::<fn(Progress)>)?;
+    run_migrations(conn)?;
+
+    println!(
+        "Restored the library from {:?} (previous state saved to {})",
+        backup_path, safety_backup.path
+    );
+    Ok(safety_backup)
+}
+
+// ---------------------------------------------------------------------------
+// Integrity
+// ---------------------------------------------------------------------------
+
+/// Returns the problems `PRAGMA integrity_check` reports; empty means the file is sound.
+fn run_integrity_check(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let messages = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(messages.into_iter().filter(|m| m != "ok").collect())
+}
+
+/// Rows that break a foreign key: (table, rowid, referenced table).
+fn foreign_key_violations(conn: &Connection) -> Result<Vec<(String, i64, String)>> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let violations = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<(String, i64, String)>>>()?;
+    Ok(violations)
+}
+
+fn orphaned_tracked_folders(conn: &Connection) -> Result<Vec<TrackedFolder>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, repo_id, folder_path FROM TrackedFolders
+         WHERE repo_id NOT IN (SELECT id FROM Repositories)",
+    )?;
+    let folders = stmt
+        .query_map([], |row| {
+            Ok(TrackedFolder {
+                id: row.get(0)?,
+                repo_id: row.get(1)?,
+                folder_path: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<TrackedFolder>>>()?;
+    Ok(folders)
+}
+
+/// Per-repository file tables (named after the repository id) left behind from before
+/// the Files table. Their contents were copied when the repository still existed.
+fn orphan_repository_tables(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master
+         WHERE type = 'table' AND name GLOB '????????-????-????-????-????????????'
+         ORDER BY name",
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(tables)
+}
+
+fn count(conn: &Connection, sql: &str) -> Result<i64> {
+    conn.query_row(sql, [], |row| row.get(0))
+}
+
+const ORPHANED_SEARCH_ENTRIES: &str =
+    "FROM FilesSearch WHERE file_id NOT IN (SELECT id FROM Files)";
+const ORPHANED_COMMITS: &str = "FROM Commits WHERE repo_id NOT IN (SELECT id FROM Repositories)";
+
+fn build_integrity_report(conn: &Connection, repaired: bool) -> Result<IntegrityReport> {
+    let integrity_errors = run_integrity_check(conn)?;
+    let foreign_key_violations: Vec<String> = foreign_key_violations(conn)?
+        .into_iter()
+        .map(|(table, rowid, parent)| {
+            format!("{} row {} points at a missing {} row", table, rowid, parent)
+        })
+        .collect();
+    let orphaned_tracked_folders = orphaned_tracked_folders(conn)?;
+    let orphan_tables = orphan_repository_tables(conn)?;
+    let orphaned_search_entries = count(
+        conn,
+        &format!("SELECT COUNT(*) {}", ORPHANED_SEARCH_ENTRIES),
+    )?;
+    let orphaned_commits = count(conn, &format!("SELECT COUNT(*) {}", ORPHANED_COMMITS))?;
+
+    let ok = integrity_errors.is_empty()
+        && foreign_key_violations.is_empty()
+        && orphaned_tracked_folders.is_empty()
+        && orphan_tables.is_empty()
+        && orphaned_search_entries == 0
+        && orphaned_commits == 0;
+
+    Ok(IntegrityReport {
+        ok,
+        integrity_errors,
+        foreign_key_violations,
+        orphaned_tracked_folders,
+        orphan_tables,
+        orphaned_search_entries,
+        orphaned_commits,
+        repaired,
+        backup: None,
+    })
+}
+
+/// Removes everything the referential checks flag, in one transaction, and rebuilds the
+/// indexes if SQLite reported damage. Pages that are actually corrupt can't be repaired
+/// here; restore a backup instead.
+fn repair(conn: &Connection) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    for (table, rowid, _) in foreign_key_violations(&tx)? {
+        tx.execute(
+            &format!(
+                "DELETE FROM \"{}\" WHERE rowid = ?1",
+                table.replace('"', "\"\"")
+            ),
+            params![rowid],
+        )?;
+    }
+    tx.execute(
+        "DELETE FROM TrackedFolders WHERE repo_id NOT IN (SELECT id FROM Repositories)",
+        [],
+    )?;
+    for table in orphan_repository_tables(&tx)? {
+        tx.execute_batch(&format!("DROP TABLE \"{}\"", table.replace('"', "\"\"")))?;
+    }
+    tx.execute(&format!("DELETE {}", ORPHANED_SEARCH_ENTRIES), [])?;
+    tx.execute(&format!("DELETE {}", ORPHANED_COMMITS), [])?;
+    tx.commit()?;
+
+    if !run_integrity_check(conn)?.is_empty() {
+        conn.execute_batch("REINDEX")?;
+    }
+    Ok(())
+}
+
+/// Checks the database file and the references between tables. With `auto_repair`,
+/// fixes what it can and reports the state afterwards. The library is backed up before
+/// anything is repaired, so rows the repair deletes can still be recovered.
+pub fn check_integrity(
+    conn: &Connection,
+    auto_repair: bool,
+) -> std::result::Result<IntegrityReport, Box<dyn Error + Send + Sync>> {
+    let report = build_integrity_report(conn, false)?;
+    if report.ok || !auto_repair {
+        return Ok(report);
+    }
+    let safety_backup = create_backup(conn, &get_backup_folder(conn)?)?;
+    repair(conn)?;
+    Ok(IntegrityReport {
+        backup: Some(safety_backup),
+        ..build_integrity_report(conn, true)?
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_backup_settings_command(
+    pool: State<'_, DbPool>,
+) -> Result<BackupSettings, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_backup_settings(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn update_backup_settings_command(
+    pool: State<'_, DbPool>,
+    settings: BackupSettings,
+) -> Result<(), String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        update_backup_settings(&conn, &settings).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Lists the backups in `folder`, or in the configured backup folder.
+#[tauri::command]
+pub async fn list_backups_command(
+    pool: State<'_, DbPool>,
+    folder: Option<String>,
+) -> Result<Vec<BackupInfo>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let folder = match folder {
+            Some(folder) => PathBuf::from(folder),
+            None => {
+                let conn = get_connection(&pool)?;
+                get_backup_folder(&conn).map_err(|e| e.to_string())?
+            }
+        };
+        list_backups(&folder).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Backs up the library now, to `folder` or the configured backup folder.
+#[tauri::command]
+pub async fn create_backup_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    folder: Option<String>,
+) -> Result<BackupInfo, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = match folder {
+            Some(folder) => Ok(PathBuf::from(folder)),
+            None => get_backup_folder(&conn),
+        }
+        .map_err(|e| e.into())
+        .and_then(|folder| create_backup(&conn, &folder));
+
+        let payload = match &result {
+            Ok(backup) => format!("Library backed up to '{}'.", backup.path),
+            Err(e) => format!("Failed to back up the library: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "create_backup",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Restores the library from a backup. Returns the backup taken of the replaced state.
+#[tauri::command]
+pub async fn restore_backup_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    backup_path: String,
+) -> Result<BackupInfo, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let mut conn = get_connection(&pool)?;
+        let result = restore_backup(&mut conn, Path::new(&backup_path));
+
+        let payload = match &result {
+            Ok(previous) => format!(
+                "Library restored from '{}'. The previous state was saved to '{}'.",
+                backup_path, previous.path
+            ),
+            Err(e) => format!(
+                "Failed to restore the library from '{}': {}",
+                backup_path, e
+            ),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "restore_backup",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn check_integrity_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    auto_repair: bool,
+) -> Result<IntegrityReport, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = check_integrity(&conn, auto_repair);
+
+        let payload = match &result {
+            Ok(IntegrityReport {
+                ok: true,
+                backup: Some(backup),
+                ..
+            }) => format!(
+                "Library problems found and repaired. The previous state was saved to '{}'.",
+                backup.path
+            ),
+            Ok(report) if report.ok => "Library integrity check passed.".to_string(),
+            Ok(_) => "Library integrity check found problems.".to_string(),
+            Err(e) => format!("Failed to check library integrity: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "check_integrity",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{create_tracked_folder, get_repository};
+    use crate::commands::test_support::{add_repository, scratch_dir, test_connection};
+    use std::thread;
+    use std::time::Duration;
+
+    /// A test library whose backups go to a folder of its own.
+    fn library() -> (Connection, PathBuf) {
+        let conn = test_connection();
+        let folder = scratch_dir();
+        update_backup_settings(
+            &conn,
+            &BackupSettings {
+                folder: Some(folder.to_string_lossy().to_string()),
+                interval_hours: 0,
+                keep_count: 0,
+            },
+        )
+        .unwrap();
+        (conn, folder)
+    }
+
+    fn backups_in(folder: &Path) -> Vec<BackupInfo> {
+        list_backups(folder).unwrap()
+    }
+
+    #[test]
+    fn backups_are_listed_newest_first_and_pruned_oldest_first() {
+        let (conn, folder) = library();
+        let mut created = Vec::new();
+        for _ in 0..3 {
+            created.push(create_backup(&conn, &folder).unwrap());
+            thread::sleep(Duration::from_millis(5));
+        }
+        fs::write(folder.join("notes.txt"), b"not a backup").unwrap();
+
+        let listed = backups_in(&folder);
+        assert_eq!(listed.len(), 3);
+        assert_eq!(listed[0].path, created[2].path);
+        assert_eq!(listed[0].schema_version, Some(latest_version()));
+
+        assert_eq!(prune_backups(&folder, 0).unwrap(), 0);
+        assert_eq!(prune_backups(&folder, 2).unwrap(), 1);
+        let kept: Vec<String> = backups_in(&folder).into_iter().map(|b| b.path).collect();
+        assert_eq!(kept, vec![created[2].path.clone(), created[1].path.clone()]);
+        assert!(backups_in(&scratch_dir().join("missing")).is_empty());
+    }
+
+    #[test]
+    fn restoring_a_backup_brings_its_library_back_and_saves_the_current_one() {
+        let (mut conn, folder) = library();
+        add_repository(&conn, "before");
+        let backup = create_backup(&conn, &folder).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        add_repository(&conn, "after");
+
+        let safety_backup = restore_backup(&mut conn, Path::new(&backup.path)).unwrap();
+        assert!(get_repository(&conn, "before").is_ok());
+        assert!(get_repository(&conn, "after").is_err());
+        assert_eq!(backups_in(&folder).len(), 2);
+
+        restore_backup(&mut conn, Path::new(&safety_backup.path)).unwrap();
+        assert!(get_repository(&conn, "after").is_ok());
+    }
+
+    #[test]
+    fn damaged_backups_are_refused() {
+        let (mut conn, folder) = library();
+        let damaged = folder.join("damaged.sqlite");
+        fs::write(&damaged, b"not a database").unwrap();
+
+        assert!(restore_backup(&mut conn, &damaged).is_err());
+        assert!(backups_in(&folder).is_empty());
+    }
+
+    #[test]
+    fn repair_removes_orphans_after_taking_a_backup() {
+        let (conn, folder) = library();
+        add_repository(&conn, "repo");
+        create_tracked_folder(&conn, "repo", "/music/repo").unwrap();
+        create_tracked_folder(&conn, "gone", "/music/gone").unwrap();
+        conn.execute_batch(
+            "CREATE TABLE \"0b1c2d3e-4f50-6172-8394-a5b6c7d8e9f0\" (id TEXT PRIMARY KEY)",
+        )
+        .unwrap();
+
+        let report = check_integrity(&conn, false).unwrap();
+        assert!(!report.ok);
+        assert_eq!(report.orphaned_tracked_folders.len(), 1);
+        assert_eq!(report.orphaned_tracked_folders[0].repo_id, "gone");
+        assert_eq!(report.orphan_tables.len(), 1);
+        assert!(report.backup.is_none());
+        assert!(backups_in(&folder).is_empty());
+
+        let repaired = check_integrity(&conn, true).unwrap();
+        assert!(repaired.ok);
+        assert!(repaired.repaired);
+        let backup = repaired.backup.unwrap();
+        assert_eq!(backups_in(&folder)[0].path, backup.path);
+
+        let kept: i64 = conn
+            .query_row("SELECT COUNT(*) FROM TrackedFolders", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(kept, 1);
+        assert!(orphan_repository_tables(&conn).unwrap().is_empty());
+
+        // The backup still holds what the repair removed.
+        let saved =
+            Connection::open_with_flags(&backup.path, OpenFlags::SQLITE_OPEN_READ_ONLY).unwrap();
+        assert_eq!(orphaned_tracked_folders(&saved).unwrap().len(), 1);
+        assert_eq!(orphan_repository_tables(&saved).unwrap().len(), 1);
+    }
+}
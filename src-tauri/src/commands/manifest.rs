@@ -0,0 +1,442 @@
+// src/commands/manifest.rs
+//! Manifests written into bundle archives.
+//!
+//! When asked for, the bundler adds `manifest.json`, `manifest.txt` and `manifest.csv`
+//! next to the numbered files. They list each file with what the library knows about it
+//! (title, artist, BPM and key, format, sample rate, notes), its duration and bit depth,
+//! and its SHA-256 checksum. The JSON manifest is the one a received archive is verified
+//! against; the text and CSV copies are there for people.
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Seek, Write};
+use std::path::Path;
+
+use crate::commands::db::{file_from_row, FILE_COLUMNS};
+use crate::commands::structures::{
+    BundleManifest, BundleVerification, FileMetadata, ManifestCheck, ManifestCheckStatus,
+    ManifestEntry,
+};
+use lofty::prelude::*;
+use lofty::probe::Probe;
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub const MANIFEST_JSON: &str = "manifest.json";
+pub const MANIFEST_TEXT: &str = "manifest.txt";
+pub const MANIFEST_CSV: &str = "manifest.csv";
+
+/// What goes into a manifest besides the files themselves: a title, and the library's
+/// metadata for each path being bundled, where there is any.
+pub struct ManifestSource {
+    pub title: Option<String>,
+    pub files: Vec<Option<FileMetadata>>,
+}
+
+/// Returns the library's metadata for a path, from any repository that tracks it.
+pub fn library_metadata_for_path(conn: &Connection, path: &str) -> Result<Option<FileMetadata>> {
+    conn.prepare_cached(&format!(
+        "SELECT {} FROM Files f WHERE f.path = ?1 AND f.deleted_at IS NULL LIMIT 1",
+        FILE_COLUMNS
+    ))?
+    .query_row(params![path], file_from_row)
+    .optional()
+}
+
+/// Reads how long an audio file plays and its bit depth, where it can be decoded. They
+/// come from the file as delivered, so a preset's bit depth shows as written.
+fn read_audio_properties(path: &Path) -> (Option<f64>, Option<u8>) {
+    let Some(tagged_file) = Probe::open(path).ok().and_then(|probe| probe.read().ok()) else {
+        return (None, None);
+    };
+    let properties = tagged_file.properties();
+    let duration = properties.duration();
+    (
+        (!duration.is_zero()).then_some(duration.as_secs_f64()),
+        properties.bit_depth(),
+    )
+}
+
+/// Builds the manifest entry for a file that went into the archive as `archive_name`.
+pub fn manifest_entry(
+    archive_name: &str,
+    path: &Path,
+    metadata: Option<&FileMetadata>,
+    size: u64,
+    sha256: String,
+) -> ManifestEntry {
+    let (duration_seconds, bit_depth) = read_audio_properties(path);
+    let extension_format = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_uppercase());
+    ManifestEntry {
+        archive_name: archive_name.to_string(),
+        file_id: metadata.map(|m| m.id.clone()),
+        title: metadata
+            .and_then(|m| m.meta_title.clone())
+            .filter(|title| !title.is_empty()),
+        artist: metadata
+            .and_then(|m| m.meta_album_artist.clone())
+            .filter(|artist| !artist.is_empty()),
+        bpm: metadata
+            .and_then(|m| m.meta_bpm.clone())
+            .filter(|bpm| !bpm.is_empty()),
+        key: metadata
+            .and_then(|m| m.meta_key.clone())
+            .filter(|key| !key.is_empty()),
+        duration_seconds,
+        format: metadata
+            .map(|m| m.encoding.to_uppercase())
+            .filter(|format| !format.is_empty())
+            .or(extension_format),
+        sample_rate: metadata
+            .and_then(|m| m.meta_sample_rate.clone())
+            .filter(|rate| !rate.is_empty()),
+        bit_depth,
+        size,
+        sha256,
+        notes: metadata
+            .and_then(|m| m.notes.clone())
+            .filter(|notes| !notes.is_empty()),
+    }
+}
+
+/// Formats a duration as `m:ss`.
+fn format_duration(seconds: f64) -> String {
+    let total = seconds.round() as u64;
+    format!("{}:{:02}", total / 60, total % 60)
+}
+
+fn manifest_text(manifest: &BundleManifest) -> String {
+    let mut text = String::new();
+    if let Some(title) = &manifest.title {
+        text.push_str(&format!("Bundle: {}\n", title));
+    }
+    text.push_str(&format!("Generated: {}\n", manifest.generated_at));
+    text.push_str(&format!("Files: {}\n", manifest.files.len()));
+
+    for entry in &manifest.files {
+        text.push_str(&format!("\n{}\n", entry.archive_name));
+        let lines = [
+            ("Title", entry.title.clone()),
+            ("Artist", entry.artist.clone()),
+            ("BPM", entry.bpm.clone()),
+            ("Key", entry.key.clone()),
+            ("Duration", entry.duration_seconds.map(format_duration)),
+            ("Format", entry.format.clone()),
+            (
+                "Sample rate",
+                entry.sample_rate.as_ref().map(|r| format!("{} Hz", r)),
+            ),
+            ("Bit depth", entry.bit_depth.map(|b| format!("{}-bit", b))),
+            ("Size", Some(format!("{} bytes", entry.size))),
+            ("SHA-256", Some(entry.sha256.clone())),
+            ("Notes", entry.notes.clone()),
+        ];
+        for (label, value) in lines {
+            if let Some(value) = value {
+                text.push_str(&format!("  {}: {}\n", label, value));
+            }
+        }
+    }
+    text
+}
+
+/// Quotes a CSV field when it holds a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn manifest_csv(manifest: &BundleManifest) -> String {
+    let mut csv = String::from(
+        "archive_name,title,artist,bpm,key,duration_seconds,format,sample_rate,bit_depth,size,\
+         sha256,notes\n",
+    );
+    for entry in &manifest.files {
+        let fields = [
+            entry.archive_name.clone(),
+            entry.title.clone().unwrap_or_default(),
+            entry.artist.clone().unwrap_or_default(),
+            entry.bpm.clone().unwrap_or_default(),
+            entry.key.clone().unwrap_or_default(),
+            entry
+                .duration_seconds
+                .map(|d| format!("{:.3}", d))
+                .unwrap_or_default(),
+            entry.format.clone().unwrap_or_default(),
+            entry.sample_rate.clone().unwrap_or_default(),
+            entry.bit_depth.map(|b| b.to_string()).unwrap_or_default(),
+            entry.size.to_string(),
+            entry.sha256.clone(),
+            entry.notes.clone().unwrap_or_default(),
+        ];
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Adds the JSON, text and CSV manifests to an archive being written.
+pub fn write_manifest<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    manifest: &BundleManifest,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .unix_permissions(0o644);
+    let files = [
+        (MANIFEST_JSON, serde_json::to_string_pretty(manifest)?),
+        (MANIFEST_TEXT, manifest_text(manifest)),
+        (MANIFEST_CSV, manifest_csv(manifest)),
+    ];
+    for (name, contents) in files {
+        zip.start_file(name, options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Checks a received archive against its `manifest.json`: every listed file must be
+/// present with a matching checksum, and nothing else may have been added.
+pub fn verify_bundle(
+    archive_path: &Path,
+) -> std::result::Result<BundleVerification, Box<dyn Error + Send + Sync>> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+    let manifest: BundleManifest = match archive.by_name(MANIFEST_JSON) {
+        Ok(entry) => serde_json::from_reader(entry)?,
+        Err(zip::result::ZipError::FileNotFound) => {
+            return Err(format!("The archive has no {} to verify against.", MANIFEST_JSON).into())
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut checks = Vec::new();
+    for entry in &manifest.files {
+        let actual_sha256 = match archive.by_name(&entry.archive_name) {
+            Ok(mut file) => {
+                let mut hasher = Sha256::new();
+                std::io::copy(&mut file, &mut hasher)?;
+                Some(format!("{:x}", hasher.finalize()))
+            }
+            Err(zip::result::ZipError::FileNotFound) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let status = match &actual_sha256 {
+            None => ManifestCheckStatus::Missing,
+            Some(actual) if *actual == entry.sha256 => ManifestCheckStatus::Ok,
+            Some(_) => ManifestCheckStatus::Mismatch,
+        };
+        checks.push(ManifestCheck {
+            archive_name: entry.archive_name.clone(),
+            status,
+            expected_sha256: Some(entry.sha256.clone()),
+            actual_sha256,
+        });
+    }
+
+    let listed: HashSet<&str> = manifest
+        .files
+        .iter()
+        .map(|entry| entry.archive_name.as_str())
+        .chain([MANIFEST_JSON, MANIFEST_TEXT, MANIFEST_CSV])
+        .collect();
+    let unlisted: Vec<String> = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/') && !listed.contains(name))
+        .map(|name| name.to_string())
+        .collect();
+    for archive_name in unlisted {
+        checks.push(ManifestCheck {
+            archive_name,
+            status: ManifestCheckStatus::Unlisted,
+            expected_sha256: None,
+            actual_sha256: None,
+        });
+    }
+
+    Ok(BundleVerification {
+        valid: checks
+            .iter()
+            .all(|check| check.status == ManifestCheckStatus::Ok),
+        title: manifest.title,
+        generated_at: manifest.generated_at,
+        checks,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn verify_bundle_command(archive_path: String) -> Result<BundleVerification, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        verify_bundle(Path::new(&archive_path)).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::bundles::{archive_entries, write_bundle_archive};
+    use crate::commands::test_support::scratch_dir;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicBool;
+
+    fn entry(archive_name: &str, contents: &[u8]) -> ManifestEntry {
+        ManifestEntry {
+            archive_name: archive_name.to_string(),
+            file_id: None,
+            title: None,
+            artist: None,
+            bpm: None,
+            key: None,
+            duration_seconds: None,
+            format: None,
+            sample_rate: None,
+            bit_depth: None,
+            size: contents.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(contents)),
+            notes: None,
+        }
+    }
+
+    /// Writes an archive holding `files` and a manifest listing `listed`.
+    fn archive_with(files: &[(&str, &[u8])], listed: Vec<ManifestEntry>) -> PathBuf {
+        let path = scratch_dir().join("bundle.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        let manifest = BundleManifest {
+            title: Some("Demos".to_string()),
+            generated_at: "2024-05-01T12:00:00.000Z".to_string(),
+            files: listed,
+        };
+        write_manifest(&mut zip, &manifest).unwrap();
+        zip.finish().unwrap();
+        path
+    }
+
+    fn statuses(verification: &BundleVerification) -> Vec<(&str, ManifestCheckStatus)> {
+        verification
+            .checks
+            .iter()
+            .map(|check| (check.archive_name.as_str(), check.status))
+            .collect()
+    }
+
+    #[test]
+    fn a_bundled_archive_verifies() {
+        let dir = scratch_dir();
+        let take = dir.join("take.wav");
+        std::fs::write(&take, b"take audio").unwrap();
+        let paths = vec![take.to_string_lossy().to_string()];
+        let source = ManifestSource {
+            title: Some("Demos".to_string()),
+            files: vec![None],
+        };
+        let archive_path = dir.join("bundle.zip");
+        write_bundle_archive(
+            File::create(&archive_path).unwrap(),
+            &archive_entries(&paths),
+            Some(&source),
+            &AtomicBool::new(false),
+            |_| {},
+        )
+        .unwrap();
+
+        let verification = verify_bundle(&archive_path).unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.title.as_deref(), Some("Demos"));
+        assert_eq!(
+            statuses(&verification),
+            vec![("000_take.wav", ManifestCheckStatus::Ok)]
+        );
+    }
+
+    #[test]
+    fn changed_missing_and_added_files_are_reported() {
+        let archive_path = archive_with(
+            &[
+                ("000_intact.wav", b"intact"),
+                ("001_changed.wav", b"changed"),
+                ("extra.wav", b"extra"),
+            ],
+            vec![
+                entry("000_intact.wav", b"intact"),
+                entry("001_changed.wav", b"original"),
+                entry("002_missing.wav", b"missing"),
+            ],
+        );
+
+        let verification = verify_bundle(&archive_path).unwrap();
+        assert!(!verification.valid);
+        assert_eq!(
+            statuses(&verification),
+            vec![
+                ("000_intact.wav", ManifestCheckStatus::Ok),
+                ("001_changed.wav", ManifestCheckStatus::Mismatch),
+                ("002_missing.wav", ManifestCheckStatus::Missing),
+                ("extra.wav", ManifestCheckStatus::Unlisted),
+            ]
+        );
+        assert_eq!(verification.checks[2].actual_sha256, None);
+    }
+
+    #[test]
+    fn an_archive_without_a_manifest_cant_be_verified() {
+        let path = scratch_dir().join("plain.zip");
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        zip.start_file("take.wav", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"take").unwrap();
+        zip.finish().unwrap();
+
+        assert!(verify_bundle(&path).is_err());
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn text_and_csv_manifests_list_every_file() {
+        let mut listed = entry("000_take.wav", b"take");
+        listed.title = Some("Take, one".to_string());
+        listed.duration_seconds = Some(125.4);
+        listed.bit_depth = Some(24);
+        let manifest = BundleManifest {
+            title: Some("Demos".to_string()),
+            generated_at: "2024-05-01T12:00:00.000Z".to_string(),
+            files: vec![listed],
+        };
+
+        let text = manifest_text(&manifest);
+        assert!(text.starts_with("Bundle: Demos\n"));
+        assert!(text.contains("  Title: Take, one\n"));
+        assert!(text.contains("  Duration: 2:05\n"));
+        assert!(text.contains("  Bit depth: 24-bit\n"));
+        assert!(!text.contains("Artist"));
+
+        let csv = manifest_csv(&manifest);
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[1].starts_with("000_take.wav,\"Take, one\",,,,125.400,,,24,"));
+    }
+}
@@ -0,0 +1,1234 @@
+// src/commands/migrations.rs
+//! Versioned schema migrations for the SQLite database.
+//!
+//! The schema version is stored in `PRAGMA user_version`. At startup every migration newer
+//! than that version is applied in order, inside a single transaction together with the
+//! version bump, so a failure leaves the database exactly as it was.
+//!
+//! To change the schema, append a new `Migration` to `MIGRATIONS`; never edit or reorder
+//! migrations that have already shipped.
+use std::collections::HashSet;
+use std::error::Error;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result, Transaction};
+use uuid::Uuid;
+
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> Result<()>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create core tables",
+        up: create_core_tables,
+    },
+    Migration {
+        version: 2,
+        description: "Add BPM and key columns to repository file tables",
+        up: add_bpm_and_key_columns,
+    },
+    Migration {
+        version: 3,
+        description: "Move repository file tables into a single Files table",
+        up: create_files_table,
+    },
+    Migration {
+        version: 4,
+        description: "Add a full-text search index over files",
+        up: create_files_search_index,
+    },
+    Migration {
+        version: 5,
+        description: "Add indexes for sorted and filtered file listings",
+        up: create_file_listing_indexes,
+    },
+    Migration {
+        version: 6,
+        description: "Move comma-separated file tags into Tags and FileTags tables",
+        up: create_tag_tables,
+    },
+    Migration {
+        version: 7,
+        description: "Add smart collections",
+        up: create_smart_collection_tables,
+    },
+    Migration {
+        version: 8,
+        description: "Store file dates as RFC 3339 with millisecond sort keys",
+        up: normalize_file_timestamps,
+    },
+    Migration {
+        version: 9,
+        description: "Add typed relations between files",
+        up: create_file_relations_table,
+    },
+    Migration {
+        version: 10,
+        description: "Add backup settings",
+        up: create_backup_settings_table,
+    },
+    Migration {
+        version: 11,
+        description: "Add the activity log",
+        up: create_activity_log_tables,
+    },
+    Migration {
+        version: 12,
+        description: "Add soft delete and trash retention",
+        up: add_soft_delete_columns,
+    },
+    Migration {
+        version: 13,
+        description: "Add the undo journal",
+        up: create_journal_tables,
+    },
+    Migration {
+        version: 14,
+        description: "Add shared assets and per-repository notes",
+        up: create_assets_table,
+    },
+    Migration {
+        version: 15,
+        description: "Nest repositories under a parent",
+        up: add_repository_parent,
+    },
+    Migration {
+        version: 16,
+        description: "Persist bundles with their files, recipients and generations",
+        up: create_bundle_tables,
+    },
+    Migration {
+        version: 17,
+        description: "Add delivery presets for contacts and contact lists",
+        up: create_delivery_presets_table,
+    },
+    Migration {
+        version: 18,
+        description: "Record the watermarks embedded in delivered files",
+        up: create_watermarks_table,
+    },
+];
+
+/// Returns the schema version the current build expects.
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Reads the schema version recorded in the database.
+pub fn get_schema_version(conn: &Connection) -> Result<i32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Applies every pending migration. Returns the resulting schema version.
+pub fn run_migrations(
+    conn: &mut Connection,
+) -> std::result::Result<i32, Box<dyn Error + Send + Sync>> {
+    let current = get_schema_version(conn)?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(format!(
+            "The database schema (version {}) is newer than this version of Repo Studio supports (version {}).",
+            current, latest
+        )
+        .into());
+    }
+    if current == latest {
+        return Ok(current);
+    }
+
+    let tx = conn.transaction()?;
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        println!(
+            "Applying database migration {}: {}",
+            migration.version, migration.description
+        );
+        (migration.up)(&tx).map_err(|e| {
+            format!(
+                "Database migration {} ({}) failed: {}. No changes were made.",
+                migration.version, migration.description, e
+            )
+        })?;
+    }
+    // PRAGMA statements can't take bound parameters; the version is a trusted integer.
+    tx.execute_batch(&format!("PRAGMA user_version = {}", latest))?;
+    tx.commit()?;
+
+    println!(
+        "Database schema migrated from version {} to {}",
+        current, latest
+    );
+    Ok(latest)
+}
+
+/// Returns the ids of all repositories whose file table exists.
+fn repository_tables(tx: &Transaction) -> Result<Vec<String>> {
+    let mut stmt = tx.prepare(
+        "SELECT r.id FROM Repositories r
+         JOIN sqlite_master m ON m.type = 'table' AND m.name = r.id",
+    )?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(ids)
+}
+
+/// Returns true if `table` already has a column named `column`.
+fn column_exists(tx: &Transaction, table: &str, column: &str) -> Result<bool> {
+    tx.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)",
+        params![table, column],
+        |row| row.get(0),
+    )
+}
+
+// ---------------------------------------------------------------------------
+// Migrations
+// ---------------------------------------------------------------------------
+
+/// Version 1: the tables that used to be created on every connection. Uses
+/// `IF NOT EXISTS` so databases created before migrations existed are adopted as-is.
+fn create_core_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Repositories (
+            id          TEXT PRIMARY KEY,
+            name        TEXT NOT NULL,
+            description TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS AppSettings (
+            general_auto_fingerprint INTEGER NOT NULL,
+            general_theme TEXT NOT NULL,
+            audio_autoplay INTEGER NOT NULL,
+            setup_selected_repository TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS TrackedFolders (
+            id          TEXT PRIMARY KEY,
+            repo_id     TEXT NOT NULL,
+            folder_path TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS Bundles (
+            id          TEXT PRIMARY KEY,
+            name        TEXT,
+            description TEXT,
+            date_created TEXT NOT NULL,
+            included_files TEXT NOT NULL,
+            recipients TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS FileVersions (
+            id            TEXT PRIMARY KEY,
+            file_id       TEXT NOT NULL,
+            repo_id       TEXT NOT NULL,
+            content_hash  TEXT NOT NULL,
+            size_on_disk  INTEGER NOT NULL,
+            date_modified TEXT NOT NULL,
+            date_recorded TEXT NOT NULL,
+            metadata      TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_file_versions_file_id ON FileVersions (file_id);
+
+        CREATE TABLE IF NOT EXISTS Commits (
+            id          TEXT PRIMARY KEY,
+            repo_id     TEXT NOT NULL,
+            parent_id   TEXT,
+            message     TEXT NOT NULL,
+            author      TEXT NOT NULL,
+            timestamp   TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS CommitFiles (
+            commit_id    TEXT NOT NULL,
+            file_id      TEXT NOT NULL,
+            path         TEXT NOT NULL,
+            content_hash TEXT,
+            metadata     TEXT NOT NULL,
+            PRIMARY KEY (commit_id, file_id),
+            FOREIGN KEY (commit_id) REFERENCES Commits(id) ON DELETE CASCADE
+        );
+
+        CREATE TABLE IF NOT EXISTS Contacts (
+            id          TEXT PRIMARY KEY,
+            name        TEXT NOT NULL,
+            email       TEXT NOT NULL,
+            phone       TEXT,
+            handle      TEXT,
+            notes       TEXT,
+            profession  TEXT
+        );
+
+        CREATE TABLE IF NOT EXISTS ContactLists (
+            id   TEXT PRIMARY KEY,
+            name TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS ContactListContacts (
+            list_id    TEXT NOT NULL,
+            contact_id TEXT NOT NULL,
+            PRIMARY KEY (list_id, contact_id),
+            FOREIGN KEY (list_id)    REFERENCES ContactLists(id) ON DELETE CASCADE,
+            FOREIGN KEY (contact_id) REFERENCES Contacts(id)     ON DELETE CASCADE
+        );",
+    )?;
+
+    // Ensure the default “All Contacts” list exists
+    tx.execute(
+        "INSERT OR IGNORE INTO ContactLists (id, name) VALUES (?1, ?2)",
+        params!["all", "All Contacts"],
+    )?;
+
+    let app_settings_exists: bool =
+        tx.query_row("SELECT EXISTS(SELECT 1 FROM AppSettings)", [], |row| {
+            row.get(0)
+        })?;
+    if !app_settings_exists {
+        tx.execute(
+            "INSERT INTO AppSettings (general_auto_fingerprint, general_theme, audio_autoplay, setup_selected_repository)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![0, "theme-light", 0, "default"],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Version 2: BPM and musical key, added to every existing repository file table.
+fn add_bpm_and_key_columns(tx: &Transaction) -> Result<()> {
+    for table in repository_tables(tx)? {
+        for column in ["meta_bpm", "meta_key"] {
+            if !column_exists(tx, &table, column)? {
+                tx.execute(
+                    &format!(
+                        "ALTER TABLE \"{}\" ADD COLUMN {} TEXT",
+                        table.replace('"', "\"\""),
+                        column
+                    ),
+                    [],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Columns shared by the legacy per-repository tables and `Files`, in table order.
+const LEGACY_FILE_COLUMNS: &str = "id, name, encoding, path, related_files, tags,
+    date_created, date_modified, audio_fingerprint, accessible,
+    meta_title, meta_comment, meta_album_artist, meta_album, meta_track_number,
+    meta_genre, meta_bit_rate, meta_channels, meta_sample_rate, meta_size_on_disk,
+    meta_bpm, meta_key";
+
+/// Version 3: one `Files` table keyed by `repo_id` replaces the table each repository
+/// used to get. Existing rows are copied over and the old tables dropped.
+fn create_files_table(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Files (
+            id                TEXT PRIMARY KEY,
+            repo_id           TEXT NOT NULL,
+            name              TEXT NOT NULL,
+            encoding          TEXT NOT NULL,
+            path              TEXT NOT NULL,
+            related_files     TEXT,
+            tags              TEXT,
+            date_created      TEXT NOT NULL,
+            date_modified     TEXT NOT NULL,
+            audio_fingerprint TEXT,
+            accessible        BOOLEAN,
+            meta_title        TEXT,
+            meta_comment      TEXT,
+            meta_album_artist TEXT,
+            meta_album        TEXT,
+            meta_track_number TEXT,
+            meta_genre        TEXT,
+            meta_bit_rate     TEXT,
+            meta_channels     TEXT,
+            meta_sample_rate  TEXT,
+            meta_size_on_disk TEXT,
+            meta_bpm          TEXT,
+            meta_key          TEXT,
+            FOREIGN KEY (repo_id) REFERENCES Repositories(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_files_repo_id ON Files (repo_id);
+        CREATE INDEX IF NOT EXISTS idx_files_path ON Files (path);",
+    )?;
+
+    for table in repository_tables(tx)? {
+        let quoted = table.replace('"', "\"\"");
+        // A file id can only belong to one repository; if an id somehow appears in two
+        // legacy tables the first copy wins.
+        tx.execute(
+            &format!(
+                "INSERT OR IGNORE INTO Files (repo_id, {cols}) SELECT ?1, {cols} FROM \"{table}\"",
+                cols = LEGACY_FILE_COLUMNS,
+                table = quoted
+            ),
+            params![table],
+        )?;
+        tx.execute(&format!("DROP TABLE \"{}\"", quoted), [])?;
+    }
+    Ok(())
+}
+
+/// Version 4: the FTS5 index behind `search_files`, filled from the existing files.
+/// `file_id` is stored but not tokenized; every other column is searchable.
+fn create_files_search_index(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS FilesSearch USING fts5(
+            file_id UNINDEXED,
+            name,
+            tags,
+            meta_title,
+            meta_album,
+            meta_album_artist,
+            meta_genre,
+            meta_comment,
+            tokenize = 'unicode61 remove_diacritics 2',
+            prefix = '2 3'
+        );
+
+        DELETE FROM FilesSearch;
+        INSERT INTO FilesSearch (
+            file_id, name, tags, meta_title, meta_album, meta_album_artist, meta_genre, meta_comment
+        )
+        SELECT id, name, tags, meta_title, meta_album, meta_album_artist, meta_genre, meta_comment
+        FROM Files;",
+    )
+}
+
+/// Version 5: indexes backing `query_files`. The numeric columns are stored as text, so
+/// their indexes are on the same `CAST` expressions the listing sorts by.
+fn create_file_listing_indexes(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS idx_files_repo_name
+            ON Files (repo_id, name COLLATE NOCASE);
+        CREATE INDEX IF NOT EXISTS idx_files_repo_date_modified
+            ON Files (repo_id, date_modified);
+        CREATE INDEX IF NOT EXISTS idx_files_repo_size
+            ON Files (repo_id, CAST(meta_size_on_disk AS INTEGER));
+        CREATE INDEX IF NOT EXISTS idx_files_repo_bit_rate
+            ON Files (repo_id, CAST(meta_bit_rate AS INTEGER));
+        CREATE INDEX IF NOT EXISTS idx_files_repo_sample_rate
+            ON Files (repo_id, CAST(meta_sample_rate AS INTEGER));
+        CREATE INDEX IF NOT EXISTS idx_files_repo_encoding
+            ON Files (repo_id, encoding COLLATE NOCASE);
+        CREATE INDEX IF NOT EXISTS idx_files_repo_genre
+            ON Files (repo_id, meta_genre COLLATE NOCASE);",
+    )
+}
+
+/// Version 6: tags become rows of their own, linked to files through FileTags. Every
+/// file's free-form tag list is parsed, its tags created, and the list rewritten in the
+/// normalized form `update_file` keeps it in from now on.
+fn create_tag_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Tags (
+            id        TEXT PRIMARY KEY,
+            name      TEXT NOT NULL,
+            path      TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            color     TEXT,
+            parent_id TEXT,
+            FOREIGN KEY (parent_id) REFERENCES Tags(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_tags_parent_id ON Tags (parent_id);
+        CREATE INDEX IF NOT EXISTS idx_tags_name ON Tags (name COLLATE NOCASE);
+
+        CREATE TABLE IF NOT EXISTS FileTags (
+            file_id TEXT NOT NULL,
+            tag_id  TEXT NOT NULL,
+            PRIMARY KEY (file_id, tag_id),
+            FOREIGN KEY (file_id) REFERENCES Files(id) ON DELETE CASCADE,
+            FOREIGN KEY (tag_id)  REFERENCES Tags(id)  ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_file_tags_tag_id ON FileTags (tag_id);",
+    )?;
+
+    let tagged: Vec<(String, String)> = {
+        let mut stmt =
+            tx.prepare("SELECT id, tags FROM Files WHERE tags IS NOT NULL AND tags != ''")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, String)>>>()?;
+        rows
+    };
+    for (file_id, tags) in tagged {
+        let mut paths = Vec::new();
+        for path in parse_legacy_tag_list(&tags) {
+            let (tag_id, path) = ensure_migrated_tag(tx, &path)?;
+            tx.execute(
+                "INSERT OR IGNORE INTO FileTags (file_id, tag_id) VALUES (?1, ?2)",
+                params![file_id, tag_id],
+            )?;
+            paths.push(path);
+        }
+        let normalized = if paths.is_empty() {
+            None
+        } else {
+            Some(paths.join(", "))
+        };
+        tx.execute(
+            "UPDATE Files SET tags = ?1 WHERE id = ?2",
+            params![normalized, file_id],
+        )?;
+        tx.execute(
+            "UPDATE FilesSearch SET tags = ?1 WHERE file_id = ?2",
+            params![normalized, file_id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Splits a version 6 tag list into paths: every segment trimmed, empty ones dropped, and
+/// duplicates (ignoring case) removed. Kept here as it was when the migration shipped,
+/// rather than shared with `tags.rs`, so the migration can't change after the fact.
+fn parse_legacy_tag_list(tags: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.split(',')
+        .filter_map(|raw| {
+            let segments: Vec<&str> = raw
+                .split('/')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .collect();
+            (!segments.is_empty()).then(|| segments.join("/"))
+        })
+        .filter(|path| seen.insert(path.to_lowercase()))
+        .collect()
+}
+
+/// Returns the id and stored path of the version 6 tag at `path`, creating it and any
+/// missing ancestors. Queries only the columns the Tags table had in version 6.
+fn ensure_migrated_tag(tx: &Transaction, path: &str) -> Result<(String, String)> {
+    let mut parent: Option<(String, String)> = None;
+    for segment in path.split('/') {
+        let full_path = match &parent {
+            Some((_, parent_path)) => format!("{}/{}", parent_path, segment),
+            None => segment.to_string(),
+        };
+        let existing = tx
+            .query_row(
+                "SELECT id, path FROM Tags WHERE path = ?1",
+                params![full_path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let tag = match existing {
+            Some(tag) => tag,
+            None => {
+                let id = Uuid::new_v4().to_string();
+                tx.execute(
+                    "INSERT INTO Tags (id, name, path, color, parent_id) VALUES (?1, ?2, ?3, NULL, ?4)",
+                    params![id, segment, full_path, parent.as_ref().map(|(id, _)| id)],
+                )?;
+                (id, full_path)
+            }
+        };
+        parent = Some(tag);
+    }
+    // Paths are never empty once parsed, so the loop ran at least once.
+    parent.ok_or(rusqlite::Error::InvalidQuery)
+}
+
+fn create_smart_collection_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS SmartCollections (
+            id           TEXT PRIMARY KEY,
+            name         TEXT NOT NULL,
+            repo_id      TEXT,
+            query        TEXT NOT NULL,
+            date_created TEXT NOT NULL,
+            FOREIGN KEY (repo_id) REFERENCES Repositories(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_smart_collections_repo_id ON SmartCollections (repo_id);
+
+        CREATE TABLE IF NOT EXISTS SmartCollectionFiles (
+            collection_id TEXT NOT NULL,
+            file_id       TEXT NOT NULL,
+            PRIMARY KEY (collection_id, file_id),
+            FOREIGN KEY (collection_id) REFERENCES SmartCollections(id) ON DELETE CASCADE,
+            FOREIGN KEY (file_id)       REFERENCES Files(id)            ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_smart_collection_files_file_id
+            ON SmartCollectionFiles (file_id);",
+    )
+}
+
+/// Milliseconds between 1601-01-01 (the Windows file time epoch) and the Unix epoch.
+const WINDOWS_EPOCH_OFFSET_MS: i64 = 11_644_473_600_000;
+
+/// Parses a date as stored before version 8 into milliseconds since the Unix epoch: RFC
+/// 3339, or the `Debug` output of `SystemTime` on Unix (`tv_sec`/`tv_nsec`) or Windows
+/// (`intervals` of 100 ns since 1601). A frozen copy of what `file_ops` read when
+/// version 8 shipped.
+fn parse_legacy_timestamp_millis(value: &str) -> Option<i64> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(value) {
+        return Some(date.timestamp_millis());
+    }
+    let field = |name: &str| -> Option<i64> {
+        let start = value.find(&format!("{}:", name))? + name.len() + 1;
+        let digits: String = value[start..]
+            .trim_start()
+            .chars()
+            .take_while(|c| c.is_ascii_digit() || *c == '-')
+            .collect();
+        digits.parse().ok()
+    };
+    if let Some(intervals) = field("intervals") {
+        return Some(intervals / 10_000 - WINDOWS_EPOCH_OFFSET_MS);
+    }
+    let secs = field("tv_sec")?;
+    let nanos = field("tv_nsec").unwrap_or(0);
+    Some(secs * 1000 + nanos / 1_000_000)
+}
+
+/// Rewrites a legacy date in RFC 3339, leaving it as is if it can't be read.
+fn normalize_legacy_timestamp(value: &str) -> String {
+    parse_legacy_timestamp_millis(value)
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+        .map(|date| date.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_else(|| value.to_string())
+}
+
+/// Rewrites the dates inside a stored `FileMetadata` JSON snapshot in RFC 3339, so old
+/// versions and commits still compare equal to the rows they were taken from.
+fn normalize_snapshot_timestamps(metadata: &str) -> String {
+    let mut value: serde_json::Value = match serde_json::from_str(metadata) {
+        Ok(value) => value,
+        Err(_) => return metadata.to_string(),
+    };
+    if let Some(map) = value.as_object_mut() {
+        for key in ["date_created", "date_modified"] {
+            if let Some(serde_json::Value::String(date)) = map.get(key) {
+                let normalized = normalize_legacy_timestamp(date);
+                map.insert(key.to_string(), serde_json::Value::String(normalized));
+            }
+        }
+    }
+    value.to_string()
+}
+
+fn normalize_file_timestamps(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE Files ADD COLUMN date_created_ms INTEGER;
+        ALTER TABLE Files ADD COLUMN date_modified_ms INTEGER;
+        DROP INDEX IF EXISTS idx_files_repo_date_modified;
+        CREATE INDEX IF NOT EXISTS idx_files_repo_date_modified_ms
+            ON Files (repo_id, date_modified_ms);",
+    )?;
+
+    let files: Vec<(String, String, String)> = {
+        let mut stmt = tx.prepare("SELECT id, date_created, date_modified FROM Files")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<(String, String, String)>>>()?;
+        rows
+    };
+    for (id, date_created, date_modified) in files {
+        tx.execute(
+            "UPDATE Files SET date_created = ?1, date_modified = ?2,
+                date_created_ms = ?3, date_modified_ms = ?4
+             WHERE id = ?5",
+            params![
+                normalize_legacy_timestamp(&date_created),
+                normalize_legacy_timestamp(&date_modified),
+                parse_legacy_timestamp_millis(&date_created),
+                parse_legacy_timestamp_millis(&date_modified),
+                id
+            ],
+        )?;
+    }
+
+    let versions: Vec<(String, String, String)> = {
+        let mut stmt = tx.prepare("SELECT id, date_modified, metadata FROM FileVersions")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<(String, String, String)>>>()?;
+        rows
+    };
+    for (id, date_modified, metadata) in versions {
+        tx.execute(
+            "UPDATE FileVersions SET date_modified = ?1, metadata = ?2 WHERE id = ?3",
+            params![
+                normalize_legacy_timestamp(&date_modified),
+                normalize_snapshot_timestamps(&metadata),
+                id
+            ],
+        )?;
+    }
+
+    let commit_files: Vec<(String, String, String)> = {
+        let mut stmt = tx.prepare("SELECT commit_id, file_id, metadata FROM CommitFiles")?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<Result<Vec<(String, String, String)>>>()?;
+        rows
+    };
+    for (commit_id, file_id, metadata) in commit_files {
+        tx.execute(
+            "UPDATE CommitFiles SET metadata = ?1 WHERE commit_id = ?2 AND file_id = ?3",
+            params![normalize_snapshot_timestamps(&metadata), commit_id, file_id],
+        )?;
+    }
+    Ok(())
+}
+
+fn create_file_relations_table(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS FileRelations (
+            id             TEXT PRIMARY KEY,
+            source_file_id TEXT NOT NULL,
+            target_file_id TEXT NOT NULL,
+            relation       TEXT NOT NULL CHECK (relation IN (
+                'stem_of', 'instrumental_of', 'remix_of', 'master_of', 'preview_of', 'converted_from'
+            )),
+            date_created   TEXT NOT NULL,
+            UNIQUE (source_file_id, target_file_id, relation),
+            FOREIGN KEY (source_file_id) REFERENCES Files(id) ON DELETE CASCADE,
+            FOREIGN KEY (target_file_id) REFERENCES Files(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_file_relations_target_file_id
+            ON FileRelations (target_file_id);
+
+        -- Nothing wrote related_files before; it now caches the relations above.
+        UPDATE Files SET related_files = NULL;",
+    )
+}
+
+fn create_backup_settings_table(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS BackupSettings (
+            id             INTEGER PRIMARY KEY CHECK (id = 1),
+            folder         TEXT,
+            interval_hours INTEGER NOT NULL DEFAULT 24,
+            keep_count     INTEGER NOT NULL DEFAULT 10
+        );
+        INSERT OR IGNORE INTO BackupSettings (id) VALUES (1);",
+    )
+}
+
+fn create_activity_log_tables(tx: &Transaction) -> Result<()> {
+    // No foreign keys: entries outlive the repositories and files they mention.
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS ActivityLog (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp    TEXT NOT NULL,
+            timestamp_ms INTEGER NOT NULL,
+            operation    TEXT NOT NULL,
+            actor        TEXT NOT NULL,
+            repo_id      TEXT,
+            outcome      TEXT NOT NULL,
+            error        TEXT,
+            details      TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_activity_log_timestamp_ms ON ActivityLog (timestamp_ms);
+        CREATE INDEX IF NOT EXISTS idx_activity_log_repo_id
+            ON ActivityLog (repo_id, timestamp_ms);
+
+        CREATE TABLE IF NOT EXISTS ActivityLogFiles (
+            activity_id INTEGER NOT NULL,
+            file_id     TEXT NOT NULL,
+            PRIMARY KEY (activity_id, file_id),
+            FOREIGN KEY (activity_id) REFERENCES ActivityLog(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_activity_log_files_file_id ON ActivityLogFiles (file_id);",
+    )
+}
+
+fn add_soft_delete_columns(tx: &Transaction) -> Result<()> {
+    // `deleted_at` is the RFC 3339 time a row was moved to the trash, NULL while it's live.
+    tx.execute_batch(
+        "ALTER TABLE Repositories ADD COLUMN deleted_at TEXT;
+        ALTER TABLE Files ADD COLUMN deleted_at TEXT;
+        ALTER TABLE Contacts ADD COLUMN deleted_at TEXT;
+        ALTER TABLE ContactLists ADD COLUMN deleted_at TEXT;
+        CREATE INDEX IF NOT EXISTS idx_files_deleted_at
+            ON Files (deleted_at) WHERE deleted_at IS NOT NULL;
+
+        ALTER TABLE AppSettings ADD COLUMN trash_retention_days INTEGER NOT NULL DEFAULT 30;",
+    )
+}
+
+fn create_journal_tables(tx: &Transaction) -> Result<()> {
+    // JournalObjects lists the stored audio an entry's steps refer to, so objects can be
+    // removed from the store once no entry, version or commit needs them.
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Journal (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp   TEXT NOT NULL,
+            operation   TEXT NOT NULL,
+            description TEXT NOT NULL,
+            undo_steps  TEXT NOT NULL,
+            redo_steps  TEXT NOT NULL,
+            state       TEXT NOT NULL DEFAULT 'done' CHECK (state IN ('done', 'undone'))
+        );
+
+        CREATE TABLE IF NOT EXISTS JournalObjects (
+            journal_id   INTEGER NOT NULL,
+            content_hash TEXT NOT NULL,
+            PRIMARY KEY (journal_id, content_hash),
+            FOREIGN KEY (journal_id) REFERENCES Journal(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_journal_objects_content_hash
+            ON JournalObjects (content_hash);",
+    )
+}
+
+fn create_assets_table(tx: &Transaction) -> Result<()> {
+    // An asset is one file on disk. Every repository row for its path points at it, and it
+    // holds the state they share; tags, notes and relations stay on the rows.
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Assets (
+            id                TEXT PRIMARY KEY,
+            path              TEXT NOT NULL UNIQUE,
+            content_hash      TEXT,
+            name              TEXT NOT NULL,
+            encoding          TEXT NOT NULL,
+            date_created      TEXT NOT NULL,
+            date_modified     TEXT NOT NULL,
+            audio_fingerprint TEXT,
+            accessible        BOOLEAN,
+            meta_title        TEXT,
+            meta_comment      TEXT,
+            meta_album_artist TEXT,
+            meta_album        TEXT,
+            meta_track_number TEXT,
+            meta_genre        TEXT,
+            meta_bit_rate     TEXT,
+            meta_channels     TEXT,
+            meta_sample_rate  TEXT,
+            meta_size_on_disk TEXT,
+            meta_bpm          TEXT,
+            meta_key          TEXT,
+            date_created_ms   INTEGER,
+            date_modified_ms  INTEGER
+        );
+        CREATE INDEX IF NOT EXISTS idx_assets_content_hash ON Assets (content_hash);
+
+        ALTER TABLE Files ADD COLUMN asset_id TEXT REFERENCES Assets(id);
+        ALTER TABLE Files ADD COLUMN notes TEXT;
+        CREATE INDEX IF NOT EXISTS idx_files_asset_id ON Files (asset_id);",
+    )?;
+
+    // One asset per path, taken from its most recently modified row. Content hashes are
+    // filled in as the files are next written.
+    let files: Vec<(String, String)> = {
+        let mut stmt = tx.prepare(
+            "SELECT f.id, f.path FROM Files f
+             WHERE f.id = (
+                 SELECT g.id FROM Files g WHERE g.path = f.path
+                 ORDER BY g.date_modified_ms DESC, g.id LIMIT 1
+             )",
+        )?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, String)>>>()?;
+        rows
+    };
+    for (file_id, path) in files {
+        let asset_id = Uuid::new_v4().to_string();
+        tx.execute(
+            "INSERT INTO Assets (
+                id, path, name, encoding, date_created, date_modified, audio_fingerprint,
+                accessible, meta_title, meta_comment, meta_album_artist, meta_album,
+                meta_track_number, meta_genre, meta_bit_rate, meta_channels, meta_sample_rate,
+                meta_size_on_disk, meta_bpm, meta_key, date_created_ms, date_modified_ms
+            )
+            SELECT ?1, path, name, encoding, date_created, date_modified, audio_fingerprint,
+                accessible, meta_title, meta_comment, meta_album_artist, meta_album,
+                meta_track_number, meta_genre, meta_bit_rate, meta_channels, meta_sample_rate,
+                meta_size_on_disk, meta_bpm, meta_key, date_created_ms, date_modified_ms
+            FROM Files WHERE id = ?2",
+            params![asset_id, file_id],
+        )?;
+        tx.execute(
+            "UPDATE Files SET asset_id = ?1 WHERE path = ?2",
+            params![asset_id, path],
+        )?;
+    }
+    Ok(())
+}
+
+fn add_repository_parent(tx: &Transaction) -> Result<()> {
+    // Purging a repository moves its children up to the top level.
+    tx.execute_batch(
+        "ALTER TABLE Repositories ADD COLUMN parent_id TEXT
+            REFERENCES Repositories(id) ON DELETE SET NULL;
+        CREATE INDEX IF NOT EXISTS idx_repositories_parent_id ON Repositories (parent_id);",
+    )
+}
+
+/// Splits one of the legacy Bundles list columns, which nothing ever wrote, so its exact
+/// format is unknown; commas or a JSON array are both accepted.
+fn legacy_bundle_list(value: &str) -> Vec<String> {
+    serde_json::from_str::<Vec<String>>(value).unwrap_or_else(|_| {
+        value
+            .split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+/// A legacy Bundles row: id, name, description, date_created, included_files, recipients.
+type LegacyBundle = (
+    String,
+    Option<String>,
+    Option<String>,
+    String,
+    String,
+    String,
+);
+
+fn create_bundle_tables(tx: &Transaction) -> Result<()> {
+    // The original Bundles table kept raw paths and recipients in text columns. Bundles
+    // now refer to files, contacts and contact lists by id, so the table is rebuilt and
+    // any legacy rows are carried over as far as their entries can be matched.
+    tx.execute_batch(
+        "ALTER TABLE Bundles RENAME TO LegacyBundles;
+
+        CREATE TABLE Bundles (
+            id            TEXT PRIMARY KEY,
+            name          TEXT NOT NULL,
+            description   TEXT,
+            date_created  TEXT NOT NULL,
+            date_modified TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS BundleFiles (
+            bundle_id TEXT NOT NULL,
+            file_id   TEXT NOT NULL,
+            position  INTEGER NOT NULL,
+            PRIMARY KEY (bundle_id, file_id),
+            FOREIGN KEY (bundle_id) REFERENCES Bundles(id) ON DELETE CASCADE,
+            FOREIGN KEY (file_id)   REFERENCES Files(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_bundle_files_file_id ON BundleFiles (file_id);
+
+        CREATE TABLE IF NOT EXISTS BundleRecipients (
+            bundle_id       TEXT NOT NULL,
+            contact_id      TEXT,
+            contact_list_id TEXT,
+            CHECK ((contact_id IS NULL) != (contact_list_id IS NULL)),
+            FOREIGN KEY (bundle_id)       REFERENCES Bundles(id) ON DELETE CASCADE,
+            FOREIGN KEY (contact_id)      REFERENCES Contacts(id) ON DELETE CASCADE,
+            FOREIGN KEY (contact_list_id) REFERENCES ContactLists(id) ON DELETE CASCADE
+        );
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_bundle_recipients_contact
+            ON BundleRecipients (bundle_id, contact_id) WHERE contact_id IS NOT NULL;
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_bundle_recipients_contact_list
+            ON BundleRecipients (bundle_id, contact_list_id) WHERE contact_list_id IS NOT NULL;
+
+        -- Generations keep the files and recipients they were built with as JSON, so the
+        -- history stays readable after those are changed or deleted.
+        CREATE TABLE IF NOT EXISTS BundleGenerations (
+            id           TEXT PRIMARY KEY,
+            bundle_id    TEXT NOT NULL,
+            generated_at TEXT NOT NULL,
+            output_path  TEXT NOT NULL,
+            size_on_disk INTEGER NOT NULL,
+            file_ids     TEXT NOT NULL,
+            recipients   TEXT NOT NULL,
+            FOREIGN KEY (bundle_id) REFERENCES Bundles(id) ON DELETE CASCADE
+        );
+        CREATE INDEX IF NOT EXISTS idx_bundle_generations_bundle_id
+            ON BundleGenerations (bundle_id, generated_at);",
+    )?;
+
+    let legacy: Vec<LegacyBundle> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, name, description, date_created, included_files, recipients
+             FROM LegacyBundles",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        rows
+    };
+    for (id, name, description, date_created, included_files, recipients) in legacy {
+        let date_created = normalize_legacy_timestamp(&date_created);
+        tx.execute(
+            "INSERT INTO Bundles (id, name, description, date_created, date_modified)
+             VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![id, name.unwrap_or_default(), description, date_created],
+        )?;
+        for (position, entry) in legacy_bundle_list(&included_files).iter().enumerate() {
+            tx.execute(
+                "INSERT OR IGNORE INTO BundleFiles (bundle_id, file_id, position)
+                 SELECT ?1, id, ?2 FROM Files WHERE id = ?3 OR path = ?3 LIMIT 1",
+                params![id, position as i64, entry],
+            )?;
+        }
+        for entry in legacy_bundle_list(&recipients) {
+            tx.execute(
+                "INSERT OR IGNORE INTO BundleRecipients (bundle_id, contact_id)
+                 SELECT ?1, id FROM Contacts WHERE id = ?2 OR email = ?2 LIMIT 1",
+                params![id, entry],
+            )?;
+        }
+    }
+    tx.execute_batch("DROP TABLE LegacyBundles;")
+}
+
+fn create_delivery_presets_table(tx: &Transaction) -> Result<()> {
+    // A preset left without a value keeps that property of the original file.
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS DeliveryPresets (
+            id              TEXT PRIMARY KEY,
+            name            TEXT NOT NULL,
+            format          TEXT,
+            bitrate_kbps    INTEGER,
+            sample_rate     INTEGER,
+            bit_depth       INTEGER,
+            naming_template TEXT,
+            include_stems   INTEGER NOT NULL DEFAULT 0,
+            date_created    TEXT NOT NULL,
+            date_modified   TEXT NOT NULL
+        );
+        ALTER TABLE Contacts ADD COLUMN delivery_preset_id TEXT
+            REFERENCES DeliveryPresets(id) ON DELETE SET NULL;
+        ALTER TABLE ContactLists ADD COLUMN delivery_preset_id TEXT
+            REFERENCES DeliveryPresets(id) ON DELETE SET NULL;
+
+        -- The preset a generation was delivered with, as JSON, like its files and recipients.
+        ALTER TABLE BundleGenerations ADD COLUMN delivery_preset TEXT;",
+    )
+}
+
+fn create_watermarks_table(tx: &Transaction) -> Result<()> {
+    // Rows outlive the bundle and contact they name, so a leak can still be traced after
+    // either is deleted; the names are kept as they were when the file was sent.
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS Watermarks (
+            mark          INTEGER PRIMARY KEY,
+            generation_id TEXT NOT NULL,
+            bundle_id     TEXT NOT NULL,
+            bundle_name   TEXT NOT NULL,
+            contact_id    TEXT REFERENCES Contacts(id) ON DELETE SET NULL,
+            contact_name  TEXT NOT NULL,
+            contact_email TEXT NOT NULL,
+            file_id       TEXT,
+            archive_name  TEXT NOT NULL,
+            date_created  TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_watermarks_contact_id ON Watermarks (contact_id);
+        CREATE INDEX IF NOT EXISTS idx_watermarks_generation_id ON Watermarks (generation_id);",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A database as the last release before migrations left it: a file table per
+    /// repository, without the BPM and key columns, holding tagged rows with the old
+    /// `SystemTime` dates.
+    fn legacy_database() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+            CREATE TABLE Repositories (id TEXT PRIMARY KEY, name TEXT NOT NULL, description TEXT);
+            INSERT INTO Repositories (id, name, description) VALUES ('repo', 'Beats', NULL);
+            CREATE TABLE repo (
+                id TEXT PRIMARY KEY, name TEXT NOT NULL, encoding TEXT NOT NULL,
+                path TEXT NOT NULL, related_files TEXT, tags TEXT,
+                date_created TEXT NOT NULL, date_modified TEXT NOT NULL,
+                audio_fingerprint TEXT, accessible BOOLEAN, meta_title TEXT,
+                meta_comment TEXT, meta_album_artist TEXT, meta_album TEXT,
+                meta_track_number TEXT, meta_genre TEXT, meta_bit_rate TEXT,
+                meta_channels TEXT, meta_sample_rate TEXT, meta_size_on_disk TEXT
+            );
+            INSERT INTO repo (id, name, encoding, path, tags, date_created, date_modified, accessible)
+            VALUES
+                ('kick', 'kick.wav', 'wav', '/beats/kick.wav', ' Drums / Kick/, drums/kick ,loud',
+                 'SystemTime { tv_sec: 1700000000, tv_nsec: 250000000 }',
+                 'SystemTime { tv_sec: 1700000001, tv_nsec: 0 }', 1),
+                ('snare', 'snare.wav', 'wav', '/beats/snare.wav', 'DRUMS/snare',
+                 'SystemTime { intervals: 133444736000000000 }',
+                 '2023-11-14T22:13:20Z', 1),
+                ('pad', 'pad.wav', 'wav', '/beats/pad.wav', '', 'unreadable', 'unreadable', 0);
+            CREATE TABLE Bundles (
+                id TEXT PRIMARY KEY, name TEXT, description TEXT, date_created TEXT NOT NULL,
+                included_files TEXT NOT NULL, recipients TEXT NOT NULL
+            );
+            INSERT INTO Bundles VALUES
+                ('bundle', 'Pack', NULL, 'SystemTime { tv_sec: 1700000000, tv_nsec: 0 }',
+                 '/beats/kick.wav, snare', '[]');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn versions_are_contiguous_and_increasing() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, i as i32 + 1, "{}", migration.description);
+        }
+        assert_eq!(latest_version(), MIGRATIONS.len() as i32);
+    }
+
+    #[test]
+    fn a_new_database_migrates_to_the_latest_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+        assert_eq!(get_schema_version(&conn).unwrap(), latest_version());
+        // Running again is a no-op.
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn a_legacy_database_upgrades_through_every_version() {
+        let mut conn = legacy_database();
+        assert_eq!(get_schema_version(&conn).unwrap(), 0);
+        assert_eq!(run_migrations(&mut conn).unwrap(), latest_version());
+
+        let legacy_table: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE name = 'repo')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!legacy_table);
+
+        let repo_ids: Vec<String> = conn
+            .prepare("SELECT DISTINCT repo_id FROM Files")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(repo_ids, vec!["repo".to_string()]);
+
+        let files: Vec<(String, Option<String>, String, Option<i64>)> = conn
+            .prepare("SELECT id, tags, date_created, date_created_ms FROM Files ORDER BY id")
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            files,
+            vec![
+                (
+                    "kick".to_string(),
+                    Some("Drums/Kick, loud".to_string()),
+                    "2023-11-14T22:13:20.250Z".to_string(),
+                    Some(1_700_000_000_250),
+                ),
+                (
+                    "pad".to_string(),
+                    Some(String::new()),
+                    "unreadable".to_string(),
+                    None
+                ),
+                (
+                    "snare".to_string(),
+                    Some("Drums/snare".to_string()),
+                    "2023-11-14T22:13:20.000Z".to_string(),
+                    Some(1_700_000_000_000),
+                ),
+            ]
+        );
+
+        let tags: Vec<(String, Option<String>)> = conn
+            .prepare(
+                "SELECT t.path, p.path FROM Tags t LEFT JOIN Tags p ON p.id = t.parent_id
+                 ORDER BY t.path",
+            )
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            tags,
+            vec![
+                ("Drums".to_string(), None),
+                ("Drums/Kick".to_string(), Some("Drums".to_string())),
+                ("Drums/snare".to_string(), Some("Drums".to_string())),
+                ("loud".to_string(), None),
+            ]
+        );
+        let links: i64 = conn
+            .query_row("SELECT COUNT(*) FROM FileTags", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(links, 3);
+        let searchable: String = conn
+            .query_row(
+                "SELECT tags FROM FilesSearch WHERE file_id = 'kick'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(searchable, "Drums/Kick, loud");
+
+        let bundle: (String, i64) = conn
+            .query_row(
+                "SELECT b.date_created, (SELECT COUNT(*) FROM BundleFiles WHERE bundle_id = b.id)
+                 FROM Bundles b WHERE b.id = 'bundle'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(bundle, ("2023-11-14T22:13:20.000Z".to_string(), 2));
+
+        let violations: i64 = conn
+            .query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(violations, 0);
+    }
+
+    #[test]
+    fn a_failed_migration_changes_nothing() {
+        let mut conn = legacy_database();
+        // A file table missing the legacy columns can't be copied into Files, so
+        // version 3 fails after versions 1 and 2 have run.
+        conn.execute_batch("DROP TABLE repo; CREATE TABLE repo (id TEXT PRIMARY KEY);")
+            .unwrap();
+        assert!(run_migrations(&mut conn).is_err());
+        assert_eq!(get_schema_version(&conn).unwrap(), 0);
+        let tables: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 3);
+    }
+
+    #[test]
+    fn legacy_timestamps_are_read_in_every_stored_format() {
+        assert_eq!(
+            parse_legacy_timestamp_millis("SystemTime { tv_sec: 2, tv_nsec: 5000000 }"),
+            Some(2005)
+        );
+        assert_eq!(
+            parse_legacy_timestamp_millis("SystemTime { intervals: 116444736000000000 }"),
+            Some(0)
+        );
+        assert_eq!(
+            normalize_legacy_timestamp("2024-01-01T01:00:00+01:00"),
+            "2024-01-01T00:00:00.000Z"
+        );
+        assert_eq!(normalize_legacy_timestamp("garbage"), "garbage");
+    }
+}
@@ -1,13 +1,48 @@
 // src/commands/mod.rs
 pub mod actions;
+pub mod activity;
+pub mod assets;
 pub mod audio_ops;
+pub mod bundles;
+pub mod collections;
+pub mod commits;
 pub mod db;
+pub mod delivery;
 pub mod file_ops;
+pub mod hierarchy;
+pub mod journal;
+pub mod maintenance;
+pub mod manifest;
+pub mod migrations;
+pub mod relations;
+pub mod search;
 pub mod structures;
+pub mod tags;
+#[cfg(test)]
+pub mod test_support;
+pub mod trash;
+pub mod versions;
+pub mod watermark;
 
 // Optionally, re-export specific command functions for easier access:
 // pub use structures::*;
 pub use actions::*;
+pub use activity::*;
+pub use assets::*;
 pub use audio_ops::*;
+pub use bundles::*;
+pub use collections::*;
+pub use commits::*;
 pub use db::*;
+pub use delivery::*;
 pub use file_ops::*;
+pub use hierarchy::*;
+pub use journal::*;
+pub use maintenance::*;
+pub use manifest::*;
+pub use relations::*;
+pub use search::*;
+pub use tags::*;
+pub use trash::*;
+pub use versions::*;
+pub use watermark::*;
@@ -0,0 +1,486 @@
+// src/commands/relations.rs
+//! Typed relationships between files.
+//!
+//! A relation reads "source is <kind> target": a stem is `stem_of` its mix, an MP3 made
+//! from a WAV is `converted_from` it. Relations live in the FileRelations table and are
+//! removed with either file.
+//!
+//! Files in the trash keep their relations, and are still listed with them, flagged as
+//! trashed, so a converted file can lead back to an original that was trashed for it.
+//!
+//! `FileMetadata.related_files` is kept as a cache of the ids of every directly related
+//! file, in either direction, so listings can show that a file has relatives without a
+//! second query. Only this module writes it; `update_file` preserves whatever is cached.
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{get_connection, repository_file_from_row, DbPool, FILE_COLUMNS};
+use crate::commands::structures::{FileGraph, FileRelation, FileRelationKind};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use tauri::{State, Window};
+use uuid::Uuid;
+
+/// The most files a graph query returns, so a heavily linked library can't stall the UI.
+const MAX_GRAPH_FILES: usize = 500;
+
+const RELATION_COLUMNS: &str = "id, source_file_id, target_file_id, relation, date_created,
+    EXISTS(
+        SELECT 1 FROM Files f
+        WHERE f.id IN (source_file_id, target_file_id) AND f.deleted_at IS NOT NULL
+    )";
+
+impl FileRelationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileRelationKind::StemOf => "stem_of",
+            FileRelationKind::InstrumentalOf => "instrumental_of",
+            FileRelationKind::RemixOf => "remix_of",
+            FileRelationKind::MasterOf => "master_of",
+            FileRelationKind::PreviewOf => "preview_of",
+            FileRelationKind::ConvertedFrom => "converted_from",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<FileRelationKind> {
+        match value {
+            "stem_of" => Some(FileRelationKind::StemOf),
+            "instrumental_of" => Some(FileRelationKind::InstrumentalOf),
+            "remix_of" => Some(FileRelationKind::RemixOf),
+            "master_of" => Some(FileRelationKind::MasterOf),
+            "preview_of" => Some(FileRelationKind::PreviewOf),
+            "converted_from" => Some(FileRelationKind::ConvertedFrom),
+            _ => None,
+        }
+    }
+}
+
+fn relation_from_row(row: &Row) -> Result<FileRelation> {
+    let relation: String = row.get(3)?;
+    Ok(FileRelation {
+        id: row.get(0)?,
+        source_file_id: row.get(1)?,
+        target_file_id: row.get(2)?,
+        relation: FileRelationKind::parse(&relation).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                3,
+                rusqlite::types::Type::Text,
+                format!("unknown file relation '{}'", relation).into(),
+            )
+        })?,
+        date_created: row.get(4)?,
+        trashed: row.get(5)?,
+    })
+}
+
+/// Returns every relation the file takes part in, as source or target, including those
+/// to files in the trash.
+pub fn get_file_relations(conn: &Connection, file_id: &str) -> Result<Vec<FileRelation>> {
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM FileRelations
+         WHERE source_file_id = ?1 OR target_file_id = ?1
+         ORDER BY date_created",
+        RELATION_COLUMNS
+    ))?;
+    let relations = stmt
+        .query_map(params![file_id], relation_from_row)?
+        .collect::<Result<Vec<FileRelation>>>()?;
+    Ok(relations)
+}
+
+/// Ids of the files directly related to a file, in either direction.
+pub fn related_file_ids(conn: &Connection, file_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT DISTINCT CASE WHEN source_file_id = ?1 THEN target_file_id ELSE source_file_id END AS other
+         FROM FileRelations
+         WHERE source_file_id = ?1 OR target_file_id = ?1
+         ORDER BY other",
+    )?;
+    let ids = stmt
+        .query_map(params![file_id], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(ids)
+}
+
+/// Builds the `related_files` cache value for a file.
+pub fn related_file_list(conn: &Connection, file_id: &str) -> Result<Option<String>> {
+    let ids = related_file_ids(conn, file_id)?;
+    Ok(if ids.is_empty() {
+        None
+    } else {
+        Some(ids.join(", "))
+    })
+}
+
+/// Rewrites the `related_files` cache on a file row.
+pub fn refresh_related_files(conn: &Connection, file_id: &str) -> Result<()> {
+    conn.prepare_cached("UPDATE Files SET related_files = ?1 WHERE id = ?2")?
+        .execute(params![related_file_list(conn, file_id)?, file_id])?;
+    Ok(())
+}
+
+/// Records that `source_file_id` is `relation` `target_file_id`. Linking the same pair
+/// the same way twice returns the existing relation.
+pub fn link_files(
+    conn: &Connection,
+    source_file_id: &str,
+    target_file_id: &str,
+    relation: FileRelationKind,
+) -> std::result::Result<FileRelation, Box<dyn Error + Send + Sync>> {
+    let tx = conn.unchecked_transaction()?;
+    let linked = insert_file_relation(&tx, source_file_id, target_file_id, relation)?;
+    tx.commit()?;
+    Ok(linked)
+}
+
+/// `link_files` without a transaction of its own, for callers that link files as part of
+/// a larger change.
+pub fn insert_file_relation(
+    conn: &Connection,
+    source_file_id: &str,
+    target_file_id: &str,
+    relation: FileRelationKind,
+) -> std::result::Result<FileRelation, Box<dyn Error + Send + Sync>> {
+    if source_file_id == target_file_id {
+        return Err("A file can't be related to itself.".into());
+    }
+    let existing_files: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM Files WHERE id IN (?1, ?2)",
+        params![source_file_id, target_file_id],
+        |row| row.get(0),
+    )?;
+    if existing_files != 2 {
+        return Err("Both files must exist to relate them.".into());
+    }
+
+    conn.execute(
+        "INSERT OR IGNORE INTO FileRelations (id, source_file_id, target_file_id, relation, date_created)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            Uuid::new_v4().to_string(),
+            source_file_id,
+            target_file_id,
+            relation.as_str(),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+        ],
+    )?;
+    refresh_related_files(conn, source_file_id)?;
+    refresh_related_files(conn, target_file_id)?;
+    let linked = conn.query_row(
+        &format!(
+            "SELECT {} FROM FileRelations
+             WHERE source_file_id = ?1 AND target_file_id = ?2 AND relation = ?3",
+            RELATION_COLUMNS
+        ),
+        params![source_file_id, target_file_id, relation.as_str()],
+        relation_from_row,
+    )?;
+    Ok(linked)
+}
+
+/// Removes a relation. Returns false if it didn't exist.
+pub fn unlink_files(conn: &Connection, relation_id: &str) -> Result<bool> {
+    let tx = conn.unchecked_transaction()?;
+    let removed = delete_file_relation(&tx, relation_id)?;
+    tx.commit()?;
+    Ok(removed)
+}
+
+/// `unlink_files` without a transaction of its own.
+pub fn delete_file_relation(conn: &Connection, relation_id: &str) -> Result<bool> {
+    let relation = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM FileRelations WHERE id = ?1",
+                RELATION_COLUMNS
+            ),
+            params![relation_id],
+            relation_from_row,
+        )
+        .optional()?;
+    let relation = match relation {
+        Some(relation) => relation,
+        None => return Ok(false),
+    };
+    conn.execute(
+        "DELETE FROM FileRelations WHERE id = ?1",
+        params![relation_id],
+    )?;
+    refresh_related_files(conn, &relation.source_file_id)?;
+    refresh_related_files(conn, &relation.target_file_id)?;
+    Ok(true)
+}
+
+/// Returns every file reachable from `file_id` through relations in either direction,
+/// and the relations between them, walking outwards breadth-first. Files in the trash are
+/// included and listed in `trashed_file_ids`.
+pub fn get_file_graph(conn: &Connection, file_id: &str) -> Result<FileGraph> {
+    let mut visited: HashSet<String> = HashSet::new();
+    let mut queue: VecDeque<String> = VecDeque::new();
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    let mut seen_edges: HashSet<String> = HashSet::new();
+    let mut trashed_file_ids = Vec::new();
+
+    visited.insert(file_id.to_string());
+    queue.push_back(file_id.to_string());
+
+    while let Some(current) = queue.pop_front() {
+        let node = conn
+            .query_row(
+                &format!(
+                    "SELECT {}, f.repo_id, f.deleted_at IS NOT NULL FROM Files f WHERE f.id = ?1",
+                    FILE_COLUMNS
+                ),
+                params![current],
+                |row| Ok((repository_file_from_row(row)?, row.get::<_, bool>(24)?)),
+            )
+            .optional()?;
+        match node {
+            Some((node, trashed)) => {
+                if trashed {
+                    trashed_file_ids.push(current.clone());
+                }
+                nodes.push(node);
+            }
+            None => continue,
+        }
+
+        for relation in get_file_relations(conn, &current)? {
+            let other = if relation.source_file_id == current {
+                relation.target_file_id.clone()
+            } else {
+                relation.source_file_id.clone()
+            };
+            if visited.len() < MAX_GRAPH_FILES && visited.insert(other.clone()) {
+                queue.push_back(other);
+            }
+            let both_included = visited.contains(&relation.source_file_id)
+                && visited.contains(&relation.target_file_id);
+            if both_included && seen_edges.insert(relation.id.clone()) {
+                edges.push(relation);
+            }
+        }
+    }
+
+    Ok(FileGraph {
+        root_file_id: file_id.to_string(),
+        nodes,
+        edges,
+        trashed_file_ids,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_file_relations_command(
+    pool: State<'_, DbPool>,
+    file_id: String,
+) -> Result<Vec<FileRelation>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_file_relations(&conn, &file_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_file_graph_command(
+    pool: State<'_, DbPool>,
+    file_id: String,
+) -> Result<FileGraph, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_file_graph(&conn, &file_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn link_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    source_file_id: String,
+    target_file_id: String,
+    relation: FileRelationKind,
+) -> Result<FileRelation, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = link_files(&conn, &source_file_id, &target_file_id, relation);
+
+        let payload = match &result {
+            Ok(relation) => format!(
+                "File {} linked as {} {}.",
+                relation.source_file_id,
+                relation.relation.as_str(),
+                relation.target_file_id
+            ),
+            Err(e) => format!("Failed to link files: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "link_files",
+            None,
+            &[&source_file_id, &target_file_id],
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn unlink_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    relation_id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = unlink_files(&conn, &relation_id);
+
+        let payload = match &result {
+            Ok(true) => "File relation removed.".to_string(),
+            Ok(false) => format!("File relation {} not found.", relation_id),
+            Err(e) => format!("Failed to remove file relation: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "unlink_files",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map(|_| ()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{create_file, delete_file, get_file};
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+
+    fn add_files(conn: &Connection, count: usize) -> Vec<String> {
+        add_repository(conn, "repo");
+        let dir = scratch_dir();
+        (0..count)
+            .map(|i| {
+                let file = sample_file(&dir, &format!("{}.wav", i), i.to_string().as_bytes());
+                create_file(conn, "repo", &file).unwrap();
+                file.id
+            })
+            .collect()
+    }
+
+    fn cached(conn: &Connection, file_id: &str) -> Option<String> {
+        get_file(conn, "repo", file_id).unwrap().related_files
+    }
+
+    #[test]
+    fn linking_and_unlinking_keep_the_cache_in_step() {
+        let conn = test_connection();
+        let ids = add_files(&conn, 2);
+
+        let relation = link_files(&conn, &ids[0], &ids[1], FileRelationKind::StemOf).unwrap();
+        assert_eq!(get_file_relations(&conn, &ids[1]).unwrap().len(), 1);
+        assert_eq!(cached(&conn, &ids[0]), Some(ids[1].clone()));
+        assert_eq!(cached(&conn, &ids[1]), Some(ids[0].clone()));
+
+        assert!(unlink_files(&conn, &relation.id).unwrap());
+        assert!(!unlink_files(&conn, &relation.id).unwrap());
+        assert!(get_file_relations(&conn, &ids[0]).unwrap().is_empty());
+        assert_eq!(cached(&conn, &ids[0]), None);
+        assert_eq!(cached(&conn, &ids[1]), None);
+    }
+
+    #[test]
+    fn files_cant_be_linked_to_themselves_or_to_missing_files() {
+        let conn = test_connection();
+        let ids = add_files(&conn, 1);
+
+        assert!(link_files(&conn, &ids[0], &ids[0], FileRelationKind::RemixOf).is_err());
+        assert!(link_files(&conn, &ids[0], "missing", FileRelationKind::RemixOf).is_err());
+        assert_eq!(cached(&conn, &ids[0]), None);
+    }
+
+    #[test]
+    fn linking_twice_returns_the_existing_relation() {
+        let conn = test_connection();
+        let ids = add_files(&conn, 2);
+
+        let first = link_files(&conn, &ids[0], &ids[1], FileRelationKind::MasterOf).unwrap();
+        let second = link_files(&conn, &ids[0], &ids[1], FileRelationKind::MasterOf).unwrap();
+        assert_eq!(first.id, second.id);
+        assert_eq!(get_file_relations(&conn, &ids[0]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn trashed_relatives_are_listed_as_trashed() {
+        let conn = test_connection();
+        let ids = add_files(&conn, 2);
+        link_files(&conn, &ids[1], &ids[0], FileRelationKind::ConvertedFrom).unwrap();
+        delete_file(&conn, "repo", &ids[0]).unwrap();
+
+        let relations = get_file_relations(&conn, &ids[1]).unwrap();
+        assert_eq!(relations.len(), 1);
+        assert!(relations[0].trashed);
+
+        let graph = get_file_graph(&conn, &ids[1]).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.trashed_file_ids, vec![ids[0].clone()]);
+    }
+
+    #[test]
+    fn the_graph_walks_every_relation_in_either_direction() {
+        let conn = test_connection();
+        let ids = add_files(&conn, 4);
+        link_files(&conn, &ids[1], &ids[0], FileRelationKind::StemOf).unwrap();
+        link_files(&conn, &ids[2], &ids[0], FileRelationKind::StemOf).unwrap();
+        link_files(&conn, &ids[3], &ids[2], FileRelationKind::PreviewOf).unwrap();
+
+        let graph = get_file_graph(&conn, &ids[3]).unwrap();
+        assert_eq!(graph.root_file_id, ids[3]);
+        assert_eq!(graph.nodes.len(), 4);
+        assert_eq!(graph.edges.len(), 3);
+        assert!(graph.trashed_file_ids.is_empty());
+    }
+
+    #[test]
+    fn the_graph_stops_at_its_cap() {
+        let conn = test_connection();
+        let ids = add_files(&conn, MAX_GRAPH_FILES + 5);
+        for other in &ids[1..] {
+            insert_file_relation(&conn, other, &ids[0], FileRelationKind::StemOf).unwrap();
+        }
+
+        let graph = get_file_graph(&conn, &ids[0]).unwrap();
+        assert_eq!(graph.nodes.len(), MAX_GRAPH_FILES);
+        assert_eq!(graph.edges.len(), MAX_GRAPH_FILES - 1);
+    }
+}
@@ -0,0 +1,215 @@
+// src/commands/search.rs
+//! Full-text search over file names, tags and embedded metadata.
+//!
+//! The FTS5 table `FilesSearch` mirrors the searchable columns of `Files`. It is kept in
+//! sync explicitly by `create_file`, `update_file` and `purge_file` in `db.rs`, and
+//! queried here with BM25 ranking and highlighted snippets. Trashed files stay indexed
+//! but are left out of results.
+use crate::commands::db::{file_from_row, get_connection, DbPool, FILE_COLUMNS};
+use crate::commands::structures::{FileMetadata, SearchResult};
+use rusqlite::{params, params_from_iter, types::Value, Connection, Result};
+use tauri::{Emitter, State, Window};
+
+/// The most results a single search returns.
+const SEARCH_RESULT_LIMIT: i64 = 200;
+
+/// Column weights for `bm25()`, in `FilesSearch` column order (`file_id` is unindexed).
+/// A hit in the name counts for more than one in the comment.
+const BM25_WEIGHTS: &str = "0.0, 10.0, 6.0, 6.0, 3.0, 4.0, 3.0, 1.0";
+
+/// Adds a file to the search index, replacing any entry it already had.
+pub fn index_file(conn: &Connection, file: &FileMetadata) -> Result<()> {
+    unindex_file(conn, &file.id)?;
+    conn.prepare_cached(
+        "INSERT INTO FilesSearch (
+            file_id, name, tags, meta_title, meta_album, meta_album_artist, meta_genre, meta_comment
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?
+    .execute(params![
+        file.id,
+        file.name,
+        file.tags,
+        file.meta_title,
+        file.meta_album,
+        file.meta_album_artist,
+        file.meta_genre,
+        file.meta_comment,
+    ])?;
+    Ok(())
+}
+
+/// Removes a file from the search index.
+pub fn unindex_file(conn: &Connection, file_id: &str) -> Result<()> {
+    conn.prepare_cached("DELETE FROM FilesSearch WHERE file_id = ?1")?
+        .execute(params![file_id])?;
+    Ok(())
+}
+
+/// Turns free text typed by the user into an FTS5 query. Every word becomes a quoted
+/// prefix term, so punctuation can't be misread as query syntax and partial words match
+/// ("trap dar" finds "Dark Trap Beat"). All words must match.
+fn build_match_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Searches every file (or only those in `repo_ids`), best matches first.
+pub fn search_files(
+    conn: &Connection,
+    query: &str,
+    repo_ids: Option<&[String]>,
+) -> Result<Vec<SearchResult>> {
+    let match_query = match build_match_query(query) {
+        Some(match_query) => match_query,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut values: Vec<Value> = vec![Value::Text(match_query)];
+    let mut repo_filter = String::new();
+    if let Some(repo_ids) = repo_ids {
+        if repo_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let placeholders: Vec<String> = repo_ids
+            .iter()
+            .map(|id| {
+                values.push(Value::Text(id.clone()));
+                format!("?{}", values.len())
+            })
+            .collect();
+        repo_filter = format!("AND f.repo_id IN ({})", placeholders.join(", "));
+    }
+    values.push(Value::Integer(SEARCH_RESULT_LIMIT));
+
+    let sql = format!(
+        "SELECT {columns}, f.repo_id,
+                bm25(FilesSearch, {weights}) AS score,
+                snippet(FilesSearch, -1, '<mark>', '</mark>', '…', 12)
+         FROM FilesSearch
+         JOIN Files f ON f.id = FilesSearch.file_id
+         WHERE FilesSearch MATCH ?1 AND f.deleted_at IS NULL {repo_filter}
+         ORDER BY score
+         LIMIT ?{limit}",
+        columns = FILE_COLUMNS,
+        weights = BM25_WEIGHTS,
+        repo_filter = repo_filter,
+        limit = values.len()
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let results = stmt
+        .query_map(params_from_iter(values), |row| {
+            Ok(SearchResult {
+                file: file_from_row(row)?,
+                repo_id: row.get(23)?,
+                // bm25() scores better matches lower; flip it so higher means better.
+                rank: -row.get::<_, f64>(24)?,
+                snippet: row.get(25)?,
+            })
+        })?
+        .collect::<Result<Vec<SearchResult>>>()?;
+    Ok(results)
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn search_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    query: String,
+    repo_ids: Option<Vec<String>>,
+) -> Result<Vec<SearchResult>, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+    let query_for_db = query.clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        search_files(&conn, &query_for_db, repo_ids.as_deref()).map_err(|e| e.to_string())
+    })
+    .await;
+
+    if let Ok(Ok(results)) = &result {
+        emit_window
+            .emit(
+                "search_files_completed",
+                format!("Found {} files matching '{}'.", results.len(), query),
+            )
+            .unwrap_or_else(|e| println!("Failed to emit search_files_completed event: {}", e));
+    }
+
+    result.map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::create_file;
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+
+    #[test]
+    fn words_become_quoted_prefix_terms() {
+        assert_eq!(
+            build_match_query("trap  dar").as_deref(),
+            Some("\"trap\"* \"dar\"*")
+        );
+    }
+
+    #[test]
+    fn quotes_and_query_syntax_are_taken_literally() {
+        assert_eq!(
+            build_match_query("\"kick\" OR NEAR(").as_deref(),
+            Some("\"kick\"* \"OR\"* \"NEAR(\"*")
+        );
+        assert_eq!(build_match_query("  \"\" "), None);
+        assert_eq!(build_match_query(""), None);
+    }
+
+    #[test]
+    fn search_matches_prefixes_and_skips_trashed_files() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let dir = scratch_dir();
+        let mut beat = sample_file(&dir, "Dark Trap Beat.wav", b"beat");
+        beat.tags = Some("drums/808".to_string());
+        let mut pad = sample_file(&dir, "Pad.wav", b"pad");
+        pad.meta_comment = Some("dark and airy".to_string());
+        create_file(&conn, "repo", &beat).unwrap();
+        create_file(&conn, "repo", &pad).unwrap();
+
+        let names = |query: &str, repos: Option<&[String]>| -> Vec<String> {
+            search_files(&conn, query, repos)
+                .unwrap()
+                .into_iter()
+                .map(|r| r.file.name)
+                .collect()
+        };
+        // The name outweighs the comment.
+        assert_eq!(names("dar", None), vec!["Dark Trap Beat.wav", "Pad.wav"]);
+        assert_eq!(names("trap dar", None), vec!["Dark Trap Beat.wav"]);
+        assert_eq!(names("808", None), vec!["Dark Trap Beat.wav"]);
+        assert!(names("dark", Some(&[])).is_empty());
+        assert!(names("dark", Some(&["other".to_string()])).is_empty());
+
+        conn.execute(
+            "UPDATE Files SET deleted_at = '2024-01-01T00:00:00.000Z' WHERE id = ?1",
+            params![beat.id],
+        )
+        .unwrap();
+        assert_eq!(names("dar", None), vec!["Pad.wav"]);
+    }
+}
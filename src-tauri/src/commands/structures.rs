@@ -9,6 +9,9 @@ pub struct Contact {
     pub profession: Option<String>,
     pub notes: Option<String>,
     pub handle: Option<String>,
+    // Set through `set_delivery_preset`; `update_contact` leaves it alone
+    #[serde(default)]
+    pub delivery_preset_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -16,6 +19,8 @@ pub struct ContactList {
     pub id: String,
     pub name: String,
     pub contacts: Vec<Contact>,
+    #[serde(default)]
+    pub delivery_preset_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,6 +29,10 @@ pub struct AppSettings {
     pub general_theme: String,
     pub audio_autoplay: bool,
     pub setup_selected_repository: String,
+    // Days trashed items are kept before being deleted for good; 0 keeps them forever.
+    // Left out of an update, the stored value is kept
+    #[serde(default)]
+    pub trash_retention_days: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -53,18 +62,570 @@ pub struct FileMetadata {
     pub meta_album: Option<String>,
     pub meta_track_number: Option<String>,
     pub meta_genre: Option<String>,
+    #[serde(default)]
+    pub meta_bpm: Option<String>,
+    #[serde(default)]
+    pub meta_key: Option<String>,
 
     // Audio metadata fields
     pub meta_bit_rate: Option<String>,
     pub meta_channels: Option<String>,
     pub meta_sample_rate: Option<String>,
     pub meta_size_on_disk: Option<String>,
+
+    // Notes on the file, kept per repository like its tags
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+// A snapshot of a tracked file's audio, stored in the content-addressed object store
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileVersion {
+    pub id: String,
+    pub file_id: String,
+    pub repo_id: String,
+    pub content_hash: String,
+    pub size_on_disk: i64,
+    pub date_modified: String,
+    pub date_recorded: String,
+    pub metadata: FileMetadata,
+}
+
+// The outcome of restoring a repository to a point in time
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepositoryRestore {
+    pub restored: usize,
+    // Files with no history at that point, left as they are
+    pub added_since: Vec<FileMetadata>,
+}
+
+// A named, authored snapshot of every file in a repository
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Commit {
+    pub id: String,
+    pub repo_id: String,
+    pub parent_id: Option<String>,
+    pub message: String,
+    pub author: String,
+    pub timestamp: String,
+    pub file_count: i64,
+}
+
+// The state of one file as captured by a commit
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommitFile {
+    pub commit_id: String,
+    pub file_id: String,
+    pub path: String,
+    pub content_hash: Option<String>,
+    pub metadata: FileMetadata,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommitDetails {
+    pub commit: Commit,
+    pub files: Vec<CommitFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+// A single difference between a commit and another commit (or the current state)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommitFileChange {
+    pub file_id: String,
+    pub name: String,
+    pub path: String,
+    pub change: ChangeKind,
+}
+
+// How a file on disk compares to its row in the repository table
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Unchanged,
+    Modified,
+    Missing,
+    Untracked,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileStatusEntry {
+    pub file_id: Option<String>,
+    pub name: String,
+    pub path: String,
+    pub status: FileStatus,
+}
+
+// The result of comparing a repository's files against its tracked folders
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepositoryStatus {
+    pub repo_id: String,
+    pub tracked_folders: Vec<String>,
+    pub files: Vec<FileStatusEntry>,
+    pub unchanged: usize,
+    pub modified: usize,
+    pub missing: usize,
+    pub untracked: usize,
+}
+
+// A tag, e.g. `drums/kick` is the tag `kick` whose parent is `drums`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub color: Option<String>,
+    pub parent_id: Option<String>,
+    pub file_count: i64,
+}
+
+// Columns a file listing can be sorted by
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSortKey {
+    #[default]
+    Name,
+    DateModified,
+    Size,
+    BitRate,
+    SampleRate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    #[default]
+    Asc,
+    Desc,
+}
+
+// Column filters for a file listing; unset filters match everything
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct FileFilters {
+    pub encoding: Option<String>,
+    pub accessible: Option<bool>,
+    pub has_fingerprint: Option<bool>,
+    pub genre: Option<String>,
+}
+
+// One page of a repository's files: which rows, in what order, matching what
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileQuery {
+    pub repo_id: String,
+    #[serde(default)]
+    pub offset: u32,
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub sort_by: FileSortKey,
+    #[serde(default)]
+    pub sort_direction: SortDirection,
+    #[serde(default)]
+    pub filters: FileFilters,
+}
+
+// A page of files plus what the UI needs to page through the rest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FilePage {
+    pub files: Vec<FileMetadata>,
+    pub total: i64,
+    pub offset: u32,
+    pub limit: u32,
+    pub next_offset: Option<u32>,
+}
+
+// A file matched by a full-text search, with the matching text highlighted
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchResult {
+    pub repo_id: String,
+    pub file: FileMetadata,
+    pub rank: f64,
+    pub snippet: String,
+}
+
+// A saved query over file metadata; `repo_id` is None for library-wide collections
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SmartCollection {
+    pub id: String,
+    pub name: String,
+    pub repo_id: Option<String>,
+    pub query: String,
+    pub date_created: String,
+    pub file_count: i64,
+}
+
+// A file together with the repository it belongs to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepositoryFile {
+    pub repo_id: String,
+    pub file: FileMetadata,
+}
+
+// Who a bundle is for: a single contact or every contact in a list
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BundleRecipientKind {
+    Contact,
+    ContactList,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct BundleRecipient {
+    pub kind: BundleRecipientKind,
+    pub id: String,
+}
+
+// A saved selection of files to send, and who to send it to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Bundle {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub date_created: String,
+    pub date_modified: String,
+    pub file_ids: Vec<String>,
+    pub recipients: Vec<BundleRecipient>,
+}
+
+// A contact a bundle was generated for, as they were at the time
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleGenerationRecipient {
+    pub contact_id: String,
+    pub name: String,
+    pub email: String,
+    // The contact's own preset, or else that of the list they were reached through
+    #[serde(default)]
+    pub delivery_preset_id: Option<String>,
+}
+
+// One time a bundle's archive was built
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleGeneration {
+    pub id: String,
+    pub bundle_id: String,
+    pub generated_at: String,
+    pub output_path: String,
+    pub size_on_disk: i64,
+    pub file_ids: Vec<String>,
+    pub recipients: Vec<BundleGenerationRecipient>,
+    #[serde(default)]
+    pub delivery_preset: Option<DeliveryPreset>,
+}
+
+// How files are delivered to a recipient. Properties left empty are kept from the
+// original files; the naming template takes {index}, {name}, {title}, {artist}, {bpm},
+// {key} and {bundle}
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DeliveryPreset {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub format: Option<String>,
+    pub bitrate_kbps: Option<i64>,
+    pub sample_rate: Option<i64>,
+    pub bit_depth: Option<i64>,
+    pub naming_template: Option<String>,
+    pub include_stems: bool,
+    #[serde(default)]
+    pub date_created: String,
+    #[serde(default)]
+    pub date_modified: String,
+}
+
+// Choices made when a bundle is generated. Recipients default to the bundle's own, and
+// the delivery preset to the one they all share
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GenerateBundleOptions {
+    #[serde(default)]
+    pub recipients: Option<Vec<BundleRecipient>>,
+    #[serde(default)]
+    pub delivery_preset_id: Option<String>,
+    #[serde(default)]
+    pub include_manifest: bool,
+    // Marks every file with an identifier for its recipient; each recipient gets an
+    // archive of their own
+    #[serde(default)]
+    pub watermark: bool,
+}
+
+// A bundle archive exported to disk
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleArchive {
+    pub output_path: String,
+    pub size_on_disk: i64,
+    pub included_paths: Vec<String>,
+}
+
+// One file as listed in a bundle manifest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestEntry {
+    pub archive_name: String,
+    pub file_id: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub bpm: Option<String>,
+    pub key: Option<String>,
+    pub duration_seconds: Option<f64>,
+    pub format: Option<String>,
+    pub sample_rate: Option<String>,
+    #[serde(default)]
+    pub bit_depth: Option<u8>,
+    pub size: u64,
+    pub sha256: String,
+    pub notes: Option<String>,
+}
+
+// The manifest written into a bundle archive
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleManifest {
+    pub title: Option<String>,
+    pub generated_at: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+// How an archive entry compares with its manifest
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestCheckStatus {
+    Ok,
+    Mismatch,
+    Missing,
+    Unlisted,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestCheck {
+    pub archive_name: String,
+    pub status: ManifestCheckStatus,
+    pub expected_sha256: Option<String>,
+    pub actual_sha256: Option<String>,
+}
+
+// The result of checking a received bundle against its manifest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BundleVerification {
+    pub valid: bool,
+    pub title: Option<String>,
+    pub generated_at: String,
+    pub checks: Vec<ManifestCheck>,
+}
+
+// The identifier embedded in one delivered file, and who it was sent to
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Watermark {
+    pub mark: u32,
+    pub generation_id: String,
+    pub bundle_id: String,
+    pub bundle_name: String,
+    pub contact_id: Option<String>,
+    pub contact_name: String,
+    pub contact_email: String,
+    pub file_id: Option<String>,
+    pub archive_name: String,
+    pub date_created: String,
+}
+
+// What was found in a suspect file. `confidence` is the average strength of the decoded
+// bits in standard deviations; `watermark` is set when the mark was sent by this library
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LeakTrace {
+    pub mark: Option<u32>,
+    pub confidence: f64,
+    pub watermark: Option<Watermark>,
+}
+
+// A file on disk as the library knows it, with every repository row that refers to it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Asset {
+    pub id: String,
+    pub path: String,
+    pub content_hash: Option<String>,
+    pub files: Vec<RepositoryFile>,
+}
+
+// How one file relates to another: the source is `<kind>` the target
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileRelationKind {
+    StemOf,
+    InstrumentalOf,
+    RemixOf,
+    MasterOf,
+    PreviewOf,
+    ConvertedFrom,
+}
+
+// A typed link between two files
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileRelation {
+    pub id: String,
+    pub source_file_id: String,
+    pub target_file_id: String,
+    pub relation: FileRelationKind,
+    pub date_created: String,
+    // Whether either file is in the trash
+    pub trashed: bool,
+}
+
+// Every file connected to a root file through relations, and the relations themselves
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileGraph {
+    pub root_file_id: String,
+    pub nodes: Vec<RepositoryFile>,
+    pub edges: Vec<FileRelation>,
+    pub trashed_file_ids: Vec<String>,
+}
+
+// Where and how often the library database is backed up; an interval of 0 turns off
+// scheduled backups and a keep count of 0 keeps every backup
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupSettings {
+    pub folder: Option<String>,
+    pub interval_hours: u32,
+    pub keep_count: u32,
+}
+
+// A backup copy of the library database
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupInfo {
+    pub path: String,
+    pub file_name: String,
+    pub created_at: String,
+    pub size_on_disk: u64,
+    pub schema_version: Option<i32>,
+}
+
+// What an integrity check found (after repairing, if it repaired anything)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    pub integrity_errors: Vec<String>,
+    pub foreign_key_violations: Vec<String>,
+    pub orphaned_tracked_folders: Vec<TrackedFolder>,
+    pub orphan_tables: Vec<String>,
+    pub orphaned_search_entries: i64,
+    pub orphaned_commits: i64,
+    pub repaired: bool,
+    // The backup taken of the library before repairing it
+    pub backup: Option<BackupInfo>,
+}
+
+// Who started a logged operation
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityActor {
+    User,
+    Watcher,
+    System,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivityOutcome {
+    Success,
+    Failure,
+}
+
+// One entry of the activity log
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActivityEntry {
+    pub id: i64,
+    pub timestamp: String,
+    pub operation: String,
+    pub actor: ActivityActor,
+    pub repo_id: Option<String>,
+    pub file_ids: Vec<String>,
+    pub outcome: ActivityOutcome,
+    pub error: Option<String>,
+    pub details: Option<String>,
+}
+
+// Which activity log entries to return; unset filters match everything.
+// `since` and `until` are RFC 3339 timestamps
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ActivityFilter {
+    pub repo_id: Option<String>,
+    pub file_id: Option<String>,
+    pub operation: Option<String>,
+    pub actor: Option<ActivityActor>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+
+// Kinds of records that are moved to the trash instead of being deleted
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashItemKind {
+    Repository,
+    File,
+    Contact,
+    ContactList,
+}
+
+// A record in the trash; `purge_at` is when it will be deleted for good, if ever
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrashItem {
+    pub kind: TrashItemKind,
+    pub id: String,
+    pub name: String,
+    pub repo_id: Option<String>,
+    pub deleted_at: String,
+    pub purge_at: Option<String>,
+}
+
+// Whether a journaled operation is in effect or has been undone
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalState {
+    Done,
+    Undone,
+}
+
+// An operation in the undo journal
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JournalEntry {
+    pub id: i64,
+    pub timestamp: String,
+    pub operation: String,
+    pub description: String,
+    pub state: JournalState,
 }
 
 // Data model for a repository table
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Repository {
     pub id: String,
     pub name: String,
     pub description: String,
+    // The repository this one is nested under, None at the top level
+    #[serde(default)]
+    pub parent_id: Option<String>,
+}
+
+// File totals for a repository, or for a repository and everything nested under it
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RepositoryStats {
+    pub repository_count: i64,
+    pub file_count: i64,
+    pub missing_file_count: i64,
+    pub size_on_disk: i64,
+}
+
+// A repository in the hierarchy, with its children and the totals for it and its subtree
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RepositoryNode {
+    pub repository: Repository,
+    pub stats: RepositoryStats,
+    pub subtree_stats: RepositoryStats,
+    pub children: Vec<RepositoryNode>,
 }
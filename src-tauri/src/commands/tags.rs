@@ -0,0 +1,879 @@
+// src/commands/tags.rs
+//! Tags as first-class entities.
+//!
+//! Each tag has a name, an optional color and an optional parent, so `drums/kick` is the
+//! tag `kick` under `drums`. Its full `path` is stored alongside it for lookups and
+//! autocomplete. Files are linked to tags through the FileTags table.
+//!
+//! `FileMetadata.tags` is still a comma-separated list of tag paths. It is kept as a
+//! cache of the links so search and the existing editors keep working: `create_file` and
+//! `update_file` resolve the list into links, and every command here rewrites the list
+//! of each file it touches.
+use std::collections::HashSet;
+use std::error::Error;
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{file_from_row, get_connection, update_file, DbPool, FILE_COLUMNS};
+use crate::commands::structures::{FileMetadata, Tag};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+use tauri::{State, Window};
+use uuid::Uuid;
+
+/// How many suggestions autocomplete returns when no limit is given.
+const DEFAULT_AUTOCOMPLETE_LIMIT: u32 = 10;
+
+const TAG_COLUMNS: &str = "t.id, t.name, t.path, t.color, t.parent_id,
+    (SELECT COUNT(*) FROM FileTags ft JOIN Files f ON f.id = ft.file_id
+     WHERE ft.tag_id = t.id AND f.deleted_at IS NULL) AS file_count";
+
+fn tag_from_row(row: &Row) -> Result<Tag> {
+    Ok(Tag {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        path: row.get(2)?,
+        color: row.get(3)?,
+        parent_id: row.get(4)?,
+        file_count: row.get(5)?,
+    })
+}
+
+/// Cleans up a tag path typed by the user: trims every segment and drops empty ones,
+/// so " Drums / Kick/" becomes "Drums/Kick".
+pub fn normalize_tag_path(raw: &str) -> Option<String> {
+    let segments: Vec<&str> = raw
+        .split('/')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .collect();
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments.join("/"))
+    }
+}
+
+/// Splits a comma-separated tag list into normalized paths, dropping duplicates.
+pub fn parse_tag_list(tags: Option<&str>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.unwrap_or("")
+        .split(',')
+        .filter_map(normalize_tag_path)
+        .filter(|path| seen.insert(path.to_lowercase()))
+        .collect()
+}
+
+/// Joins tags back into the comma-separated list stored on the file row.
+pub fn join_tag_paths(tags: &[Tag]) -> Option<String> {
+    if tags.is_empty() {
+        None
+    } else {
+        Some(
+            tags.iter()
+                .map(|t| t.path.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+}
+
+pub fn get_tag(conn: &Connection, tag_id: &str) -> Result<Tag> {
+    conn.query_row(
+        &format!("SELECT {} FROM Tags t WHERE t.id = ?1", TAG_COLUMNS),
+        params![tag_id],
+        tag_from_row,
+    )
+}
+
+/// Looks a tag up by its full path, ignoring case.
+pub fn find_tag_by_path(conn: &Connection, path: &str) -> Result<Option<Tag>> {
+    conn.prepare_cached(&format!(
+        "SELECT {} FROM Tags t WHERE t.path = ?1",
+        TAG_COLUMNS
+    ))?
+    .query_row(params![path], tag_from_row)
+    .optional()
+}
+
+/// Returns the tag with the given path, creating it and any missing ancestors.
+/// An existing tag keeps its own capitalization.
+pub fn ensure_tag(conn: &Connection, path: &str) -> Result<Tag> {
+    let mut parent: Option<Tag> = None;
+    for segment in path.split('/') {
+        let full_path = match &parent {
+            Some(p) => format!("{}/{}", p.path, segment),
+            None => segment.to_string(),
+        };
+        let tag = match find_tag_by_path(conn, &full_path)? {
+            Some(tag) => tag,
+            None => {
+                let tag = Tag {
+                    id: Uuid::new_v4().to_string(),
+                    name: segment.to_string(),
+                    path: full_path,
+                    color: None,
+                    parent_id: parent.as_ref().map(|p| p.id.clone()),
+                    file_count: 0,
+                };
+                conn.execute(
+                    "INSERT INTO Tags (id, name, path, color, parent_id) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![tag.id, tag.name, tag.path, tag.color, tag.parent_id],
+                )?;
+                tag
+            }
+        };
+        parent = Some(tag);
+    }
+    // `path` is never empty once normalized, so the loop ran at least once.
+    parent.ok_or(rusqlite::Error::InvalidQuery)
+}
+
+/// Resolves a comma-separated tag list into tags, creating any that don't exist yet.
+pub fn resolve_tags(conn: &Connection, tags: Option<&str>) -> Result<Vec<Tag>> {
+    parse_tag_list(tags)
+        .iter()
+        .map(|path| ensure_tag(conn, path))
+        .collect()
+}
+
+/// Replaces a file's tag links with the given tags.
+pub fn link_file_tags(conn: &Connection, file_id: &str, tags: &[Tag]) -> Result<()> {
+    conn.prepare_cached("DELETE FROM FileTags WHERE file_id = ?1")?
+        .execute(params![file_id])?;
+    let mut stmt =
+        conn.prepare_cached("INSERT OR IGNORE INTO FileTags (file_id, tag_id) VALUES (?1, ?2)")?;
+    for tag in tags {
+        stmt.execute(params![file_id, tag.id])?;
+    }
+    Ok(())
+}
+
+/// Returns every tag, ordered by path, with how many files carry each.
+pub fn get_tags(conn: &Connection) -> Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM Tags t ORDER BY t.path COLLATE NOCASE",
+        TAG_COLUMNS
+    ))?;
+    let tags = stmt
+        .query_map([], tag_from_row)?
+        .collect::<Result<Vec<Tag>>>()?;
+    Ok(tags)
+}
+
+/// Returns the tags linked to a file, in the order they appear in its tag list.
+pub fn get_file_tags(conn: &Connection, file_id: &str) -> Result<Vec<Tag>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM Tags t
+         JOIN FileTags ft ON ft.tag_id = t.id
+         WHERE ft.file_id = ?1
+         ORDER BY ft.rowid",
+        TAG_COLUMNS
+    ))?;
+    let tags = stmt
+        .query_map(params![file_id], tag_from_row)?
+        .collect::<Result<Vec<Tag>>>()?;
+    Ok(tags)
+}
+
+/// Suggests tags whose path or name starts with `prefix`, most used first.
+pub fn autocomplete_tags(conn: &Connection, prefix: &str, limit: Option<u32>) -> Result<Vec<Tag>> {
+    let pattern = format!(
+        "{}%",
+        prefix
+            .trim()
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    );
+    let limit = limit.unwrap_or(DEFAULT_AUTOCOMPLETE_LIMIT);
+    let mut stmt = conn.prepare_cached(&format!(
+        "SELECT {} FROM Tags t
+         WHERE t.path LIKE ?1 ESCAPE '\\' OR t.name LIKE ?1 ESCAPE '\\'
+         ORDER BY file_count DESC, t.path COLLATE NOCASE
+         LIMIT ?2",
+        TAG_COLUMNS
+    ))?;
+    let tags = stmt
+        .query_map(params![pattern, limit], tag_from_row)?
+        .collect::<Result<Vec<Tag>>>()?;
+    Ok(tags)
+}
+
+/// Returns the ids of a tag and all of its descendants.
+fn get_subtree_ids(conn: &Connection, tag_id: &str) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE subtree(id) AS (
+            SELECT ?1
+            UNION ALL
+            SELECT t.id FROM Tags t JOIN subtree s ON t.parent_id = s.id
+         )
+         SELECT id FROM subtree",
+    )?;
+    let ids = stmt
+        .query_map(params![tag_id], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    Ok(ids)
+}
+
+/// Returns the ids of files linked to any of the given tags.
+fn get_tagged_file_ids(conn: &Connection, tag_ids: &[String]) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare_cached("SELECT file_id FROM FileTags WHERE tag_id = ?1")?;
+    let mut file_ids = Vec::new();
+    let mut seen = HashSet::new();
+    for tag_id in tag_ids {
+        for file_id in stmt.query_map(params![tag_id], |row| row.get::<_, String>(0))? {
+            let file_id = file_id?;
+            if seen.insert(file_id.clone()) {
+                file_ids.push(file_id);
+            }
+        }
+    }
+    Ok(file_ids)
+}
+
+fn get_file_by_id(conn: &Connection, file_id: &str) -> Result<(String, FileMetadata)> {
+    conn.query_row(
+        &format!(
+            "SELECT {}, f.repo_id FROM Files f WHERE f.id = ?1",
+            FILE_COLUMNS
+        ),
+        params![file_id],
+        |row| Ok((row.get(23)?, file_from_row(row)?)),
+    )
+}
+
+/// Rewrites a file's tag list from its tag links, going through `update_file` so the
+/// change is versioned and re-indexed like any other edit.
+fn refresh_file_tag_list(conn: &Connection, file_id: &str) -> Result<()> {
+    let (repo_id, mut file) = get_file_by_id(conn, file_id)?;
+    let tags = join_tag_paths(&get_file_tags(conn, file_id)?);
+    if file.tags != tags {
+        file.tags = tags;
+        update_file(conn, &repo_id, &file)?;
+    }
+    Ok(())
+}
+
+/// Applies `edit` to the tag list of each file and saves the ones that changed.
+fn edit_file_tag_lists<F>(conn: &Connection, file_ids: &[String], edit: F) -> Result<usize>
+where
+    F: Fn(&mut Vec<String>),
+{
+    let mut changed = 0;
+    for file_id in file_ids {
+        let (repo_id, mut file) = get_file_by_id(conn, file_id)?;
+        let before = parse_tag_list(file.tags.as_deref());
+        let mut after = before.clone();
+        edit(&mut after);
+        if after != before {
+            file.tags = if after.is_empty() {
+                None
+            } else {
+                Some(after.join(", "))
+            };
+            update_file(conn, &repo_id, &file)?;
+            changed += 1;
+        }
+    }
+    Ok(changed)
+}
+
+/// Adds a tag (created if needed) to every given file. Returns how many files changed.
+pub fn add_tag_to_files(
+    conn: &Connection,
+    file_ids: &[String],
+    tag_path: &str,
+) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let path = normalize_tag_path(tag_path).ok_or("A tag name can't be empty")?;
+    let tx = conn.unchecked_transaction()?;
+    let tag = ensure_tag(&tx, &path)?;
+    let changed = edit_file_tag_lists(&tx, file_ids, |tags| {
+        if !tags.iter().any(|t| t.eq_ignore_ascii_case(&tag.path)) {
+            tags.push(tag.path.clone());
+        }
+    })?;
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Removes a tag from every given file. Returns how many files changed.
+pub fn remove_tag_from_files(
+    conn: &Connection,
+    file_ids: &[String],
+    tag_id: &str,
+) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let tag = get_tag(&tx, tag_id)?;
+    let changed = edit_file_tag_lists(&tx, file_ids, |tags| {
+        tags.retain(|t| !t.eq_ignore_ascii_case(&tag.path));
+    })?;
+    tx.commit()?;
+    Ok(changed)
+}
+
+/// Moves a tag (and everything below it) to a new path, e.g. renaming `kick` to `kicks`
+/// or moving `kick` under `drums`. Fails if a tag already has that path; use
+/// `merge_tags` to combine two tags.
+pub fn rename_tag(
+    conn: &Connection,
+    tag_id: &str,
+    new_path: &str,
+) -> Result<Tag, Box<dyn Error + Send + Sync>> {
+    let new_path = normalize_tag_path(new_path).ok_or("A tag name can't be empty")?;
+    let tx = conn.unchecked_transaction()?;
+    let tag = get_tag(&tx, tag_id)?;
+
+    if let Some(existing) = find_tag_by_path(&tx, &new_path)? {
+        if existing.id != tag.id {
+            return Err(format!(
+                "A tag named '{}' already exists. Merge the tags instead.",
+                existing.path
+            )
+            .into());
+        }
+    }
+    // Checked on the path, since the new parent may not exist yet and would be created
+    // inside the subtree.
+    if new_path
+        .to_lowercase()
+        .starts_with(&format!("{}/", tag.path.to_lowercase()))
+    {
+        return Err(format!("Can't move '{}' below itself.", tag.path).into());
+    }
+    let subtree = get_subtree_ids(&tx, tag_id)?;
+    let (parent_path, name) = match new_path.rsplit_once('/') {
+        Some((parent, name)) => (Some(parent), name),
+        None => (None, new_path.as_str()),
+    };
+    let parent_id = match parent_path {
+        Some(parent_path) => {
+            let parent = ensure_tag(&tx, parent_path)?;
+            if subtree.contains(&parent.id) {
+                return Err(format!("Can't move '{}' below itself.", tag.path).into());
+            }
+            Some(parent.id)
+        }
+        None => None,
+    };
+
+    move_tag(&tx, &tag, name, parent_id.as_deref(), &new_path)?;
+    for file_id in get_tagged_file_ids(&tx, &subtree)? {
+        refresh_file_tag_list(&tx, &file_id)?;
+    }
+    let renamed = get_tag(&tx, tag_id)?;
+    tx.commit()?;
+    Ok(renamed)
+}
+
+/// Updates a tag's name, parent and path, and the paths of all its descendants.
+fn move_tag(
+    conn: &Connection,
+    tag: &Tag,
+    name: &str,
+    parent_id: Option<&str>,
+    new_path: &str,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE Tags SET path = ?1 || substr(path, length(?2) + 1)
+         WHERE substr(path, 1, length(?2) + 1) = ?2 || '/'",
+        params![new_path, tag.path],
+    )?;
+    conn.execute(
+        "UPDATE Tags SET name = ?1, parent_id = ?2, path = ?3 WHERE id = ?4",
+        params![name, parent_id, new_path, tag.id],
+    )?;
+    Ok(())
+}
+
+/// Folds `source` into `target`: files tagged with `source` get `target`, and children of
+/// `source` move under `target` (merging with same-named children already there).
+fn merge_tag_into(conn: &Connection, source: &Tag, target: &Tag) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO FileTags (file_id, tag_id)
+         SELECT file_id, ?2 FROM FileTags WHERE tag_id = ?1",
+        params![source.id, target.id],
+    )?;
+
+    let children: Vec<Tag> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM Tags t WHERE t.parent_id = ?1",
+            TAG_COLUMNS
+        ))?;
+        let children = stmt
+            .query_map(params![source.id], tag_from_row)?
+            .collect::<Result<Vec<Tag>>>()?;
+        children
+    };
+    for child in children {
+        let new_path = format!("{}/{}", target.path, child.name);
+        match find_tag_by_path(conn, &new_path)? {
+            Some(existing) => merge_tag_into(conn, &child, &existing)?,
+            None => move_tag(conn, &child, &child.name, Some(&target.id), &new_path)?,
+        }
+    }
+
+    conn.execute("DELETE FROM Tags WHERE id = ?1", params![source.id])?;
+    Ok(())
+}
+
+/// Merges several tags into one. Returns the target tag with its new file count.
+pub fn merge_tags(
+    conn: &Connection,
+    source_ids: &[String],
+    target_id: &str,
+) -> Result<Tag, Box<dyn Error + Send + Sync>> {
+    let tx = conn.unchecked_transaction()?;
+    let target = get_tag(&tx, target_id)?;
+    let target_ancestry = target.path.to_lowercase();
+
+    let mut affected = Vec::new();
+    for source_id in source_ids.iter().filter(|id| *id != target_id) {
+        let source = get_tag(&tx, source_id)?;
+        if target_ancestry.starts_with(&format!("{}/", source.path.to_lowercase())) {
+            return Err(format!(
+                "Can't merge '{}' into '{}', which is below it.",
+                source.path, target.path
+            )
+            .into());
+        }
+        affected.extend(get_tagged_file_ids(&tx, &get_subtree_ids(&tx, source_id)?)?);
+        merge_tag_into(&tx, &source, &target)?;
+    }
+    for file_id in affected {
+        refresh_file_tag_list(&tx, &file_id)?;
+    }
+    let merged = get_tag(&tx, target_id)?;
+    tx.commit()?;
+    Ok(merged)
+}
+
+/// Deletes a tag and everything below it, removing them from all files.
+pub fn delete_tag(conn: &Connection, tag_id: &str) -> Result<usize> {
+    let tx = conn.unchecked_transaction()?;
+    let affected = get_tagged_file_ids(&tx, &get_subtree_ids(&tx, tag_id)?)?;
+    tx.execute("DELETE FROM Tags WHERE id = ?1", params![tag_id])?;
+    for file_id in &affected {
+        refresh_file_tag_list(&tx, file_id)?;
+    }
+    tx.commit()?;
+    Ok(affected.len())
+}
+
+pub fn set_tag_color(conn: &Connection, tag_id: &str, color: Option<&str>) -> Result<()> {
+    conn.execute(
+        "UPDATE Tags SET color = ?1 WHERE id = ?2",
+        params![color, tag_id],
+    )?;
+    Ok(())
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_tags_command(pool: State<'_, DbPool>) -> Result<Vec<Tag>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_tags(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_file_tags_command(
+    pool: State<'_, DbPool>,
+    file_id: String,
+) -> Result<Vec<Tag>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_file_tags(&conn, &file_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn autocomplete_tags_command(
+    pool: State<'_, DbPool>,
+    prefix: String,
+    limit: Option<u32>,
+) -> Result<Vec<Tag>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        autocomplete_tags(&conn, &prefix, limit).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn add_tag_to_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    file_ids: Vec<String>,
+    tag_path: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = add_tag_to_files(&conn, &file_ids, &tag_path);
+
+        let payload = match &result {
+            Ok(count) => format!("Tag '{}' added to {} files.", tag_path, count),
+            Err(e) => format!("Failed to add tag '{}': {}", tag_path, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "add_tag_to_files",
+            None,
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map(|_| ()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn remove_tag_from_files_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    file_ids: Vec<String>,
+    tag_id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = remove_tag_from_files(&conn, &file_ids, &tag_id);
+
+        let payload = match &result {
+            Ok(count) => format!("Tag '{}' removed from {} files.", tag_id, count),
+            Err(e) => format!("Failed to remove tag '{}': {}", tag_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "remove_tag_from_files",
+            None,
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map(|_| ()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn rename_tag_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    tag_id: String,
+    new_path: String,
+) -> Result<Tag, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = rename_tag(&conn, &tag_id, &new_path);
+
+        let payload = match &result {
+            Ok(tag) => format!("Tag renamed to '{}'.", tag.path),
+            Err(e) => format!("Failed to rename tag to '{}': {}", new_path, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "rename_tag",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn merge_tags_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    source_ids: Vec<String>,
+    target_id: String,
+) -> Result<Tag, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = merge_tags(&conn, &source_ids, &target_id);
+
+        let payload = match &result {
+            Ok(tag) => format!("Merged {} tags into '{}'.", source_ids.len(), tag.path),
+            Err(e) => format!("Failed to merge tags: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "merge_tags",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_tag_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    tag_id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_tag(&conn, &tag_id);
+
+        let payload = match &result {
+            Ok(count) => format!("Tag '{}' deleted from {} files.", tag_id, count),
+            Err(e) => format!("Failed to delete tag '{}': {}", tag_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_tag",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map(|_| ()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn set_tag_color_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    tag_id: String,
+    color: Option<String>,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = set_tag_color(&conn, &tag_id, color.as_deref());
+
+        let payload = match &result {
+            Ok(_) => format!("Tag '{}' color updated.", tag_id),
+            Err(e) => format!("Failed to update color of tag '{}': {}", tag_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "set_tag_color",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::{create_file, get_file};
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+
+    fn tagged_file(conn: &Connection, tags: &str) -> String {
+        let mut file = sample_file(&scratch_dir(), "a.wav", b"audio");
+        file.tags = Some(tags.to_string());
+        create_file(conn, "repo", &file).unwrap();
+        file.id
+    }
+
+    fn tag_list(conn: &Connection, file_id: &str) -> Option<String> {
+        get_file(conn, "repo", file_id).unwrap().tags
+    }
+
+    fn paths(conn: &Connection) -> Vec<String> {
+        get_tags(conn)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect()
+    }
+
+    #[test]
+    fn paths_are_trimmed_and_empty_segments_dropped() {
+        assert_eq!(
+            normalize_tag_path(" Drums / Kick/").as_deref(),
+            Some("Drums/Kick")
+        );
+        assert_eq!(normalize_tag_path(" / "), None);
+        assert_eq!(
+            parse_tag_list(Some("drums/kick, Drums/Kick ,, loud")),
+            vec!["drums/kick", "loud"]
+        );
+        assert!(parse_tag_list(None).is_empty());
+    }
+
+    #[test]
+    fn tags_are_created_with_their_ancestors() {
+        let conn = test_connection();
+        let kick = ensure_tag(&conn, "Drums/Kick").unwrap();
+        let drums = find_tag_by_path(&conn, "drums").unwrap().unwrap();
+        assert_eq!(kick.parent_id.as_deref(), Some(drums.id.as_str()));
+        assert_eq!(drums.parent_id, None);
+        // Lookups ignore case and keep the existing capitalization.
+        assert_eq!(ensure_tag(&conn, "DRUMS/kick").unwrap().id, kick.id);
+        assert_eq!(paths(&conn), vec!["Drums", "Drums/Kick"]);
+    }
+
+    #[test]
+    fn file_tag_lists_are_normalized_and_counted() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file_id = tagged_file(&conn, " drums / kick, loud, Loud");
+        assert_eq!(
+            tag_list(&conn, &file_id).as_deref(),
+            Some("drums/kick, loud")
+        );
+        let tags = get_file_tags(&conn, &file_id).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert!(tags.iter().all(|t| t.file_count == 1));
+        // Parents aren't linked to the file themselves.
+        assert_eq!(
+            find_tag_by_path(&conn, "drums")
+                .unwrap()
+                .unwrap()
+                .file_count,
+            0
+        );
+    }
+
+    #[test]
+    fn renaming_moves_the_subtree_and_rewrites_file_lists() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file_id = tagged_file(&conn, "kick/808, loud");
+        let kick = find_tag_by_path(&conn, "kick").unwrap().unwrap();
+
+        let renamed = rename_tag(&conn, &kick.id, "drums/kicks").unwrap();
+        assert_eq!(renamed.path, "drums/kicks");
+        assert_eq!(
+            paths(&conn),
+            vec!["drums", "drums/kicks", "drums/kicks/808", "loud"]
+        );
+        assert_eq!(
+            tag_list(&conn, &file_id).as_deref(),
+            Some("drums/kicks/808, loud")
+        );
+    }
+
+    #[test]
+    fn a_tag_cant_move_below_itself_or_onto_another() {
+        let conn = test_connection();
+        let drums = ensure_tag(&conn, "drums").unwrap();
+        ensure_tag(&conn, "loud").unwrap();
+        assert!(rename_tag(&conn, &drums.id, "drums/kick/drums").is_err());
+        assert!(rename_tag(&conn, &drums.id, "Loud").is_err());
+        // The failed move left nothing behind.
+        assert_eq!(paths(&conn), vec!["drums", "loud"]);
+    }
+
+    #[test]
+    fn merging_folds_children_and_files_into_the_target() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let first = tagged_file(&conn, "kicks/808");
+        let second = tagged_file(&conn, "kick/808, kick/acoustic");
+        let kicks = find_tag_by_path(&conn, "kicks").unwrap().unwrap();
+        let kick = find_tag_by_path(&conn, "kick").unwrap().unwrap();
+
+        assert!(merge_tags(
+            &conn,
+            std::slice::from_ref(&kick.id),
+            &find_tag_by_path(&conn, "kick/808").unwrap().unwrap().id
+        )
+        .is_err());
+        merge_tags(&conn, std::slice::from_ref(&kicks.id), &kick.id).unwrap();
+        assert_eq!(paths(&conn), vec!["kick", "kick/808", "kick/acoustic"]);
+        assert_eq!(tag_list(&conn, &first).as_deref(), Some("kick/808"));
+        assert_eq!(
+            tag_list(&conn, &second).as_deref(),
+            Some("kick/808, kick/acoustic")
+        );
+        assert_eq!(
+            find_tag_by_path(&conn, "kick/808")
+                .unwrap()
+                .unwrap()
+                .file_count,
+            2
+        );
+    }
+
+    #[test]
+    fn deleting_a_tag_removes_its_subtree_from_files() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file_id = tagged_file(&conn, "drums/kick, loud");
+        let drums = find_tag_by_path(&conn, "drums").unwrap().unwrap();
+        assert_eq!(delete_tag(&conn, &drums.id).unwrap(), 1);
+        assert_eq!(paths(&conn), vec!["loud"]);
+        assert_eq!(tag_list(&conn, &file_id).as_deref(), Some("loud"));
+    }
+
+    #[test]
+    fn autocomplete_escapes_wildcards_and_prefers_used_tags() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        ensure_tag(&conn, "dark").unwrap();
+        tagged_file(&conn, "drums");
+        ensure_tag(&conn, "d_x").unwrap();
+        let suggested: Vec<String> = autocomplete_tags(&conn, "d", None)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.path)
+            .collect();
+        assert_eq!(suggested, vec!["drums", "d_x", "dark"]);
+        assert_eq!(autocomplete_tags(&conn, "d_", None).unwrap().len(), 1);
+        assert_eq!(autocomplete_tags(&conn, "d", Some(1)).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,113 @@
+// src/commands/test_support.rs
+//! Fixtures shared by the unit tests: a migrated in-memory database, scratch folders
+//! and file rows pointing at real files.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+use crate::commands::db::{create_repository_with_id, APP_DB_PATH};
+use crate::commands::file_ops::read_date_modified;
+use crate::commands::migrations::run_migrations;
+use crate::commands::structures::FileMetadata;
+use rusqlite::Connection;
+use uuid::Uuid;
+
+static APP_DATA: Once = Once::new();
+
+/// Points the app data directory, and with it the object store, at a scratch folder
+/// shared by every test in the run.
+pub fn init_app_data_dir() {
+    APP_DATA.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("repostudio_test_{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).expect("create app data dir");
+        APP_DB_PATH
+            .set(dir.join("db.sqlite").to_string_lossy().to_string())
+            .expect("app data dir set twice");
+    });
+}
+
+/// Opens an in-memory database migrated to the latest schema, set up like a pooled
+/// connection.
+pub fn test_connection() -> Connection {
+    init_app_data_dir();
+    let mut conn = Connection::open_in_memory().expect("open database");
+    conn.execute_batch("PRAGMA foreign_keys = ON;")
+        .expect("enable foreign keys");
+    run_migrations(&mut conn).expect("migrate database");
+    conn
+}
+
+/// Creates an empty folder of its own for a test.
+pub fn scratch_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("repostudio_scratch_{}", Uuid::new_v4()));
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    dir
+}
+
+/// Adds a top-level repository.
+pub fn add_repository(conn: &Connection, id: &str) {
+    create_repository_with_id(conn, id, id, "", None).expect("create repository");
+}
+
+/// Writes `bytes` to `dir/name` and returns a row describing the file as it is on disk.
+pub fn sample_file(dir: &Path, name: &str, bytes: &[u8]) -> FileMetadata {
+    let path = dir.join(name);
+    fs::write(&path, bytes).expect("write sample file");
+    let path = path.to_string_lossy().to_string();
+    let date_modified = read_date_modified(&path).expect("read modification time");
+    FileMetadata {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        encoding: Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_string(),
+        path,
+        related_files: None,
+        tags: None,
+        date_created: date_modified.clone(),
+        date_modified,
+        audio_fingerprint: None,
+        accessible: true,
+        meta_title: None,
+        meta_comment: None,
+        meta_album_artist: None,
+        meta_album: None,
+        meta_track_number: None,
+        meta_genre: None,
+        meta_bpm: None,
+        meta_key: None,
+        meta_bit_rate: None,
+        meta_channels: None,
+        meta_sample_rate: None,
+        meta_size_on_disk: Some(bytes.len().to_string()),
+        notes: None,
+    }
+}
+
+/// Returns a short mono 16-bit WAV whose samples follow `seed`, so different seeds give
+/// different audio that tags can still be written to.
+pub fn wav_bytes(seed: u8) -> Vec<u8> {
+    let samples: Vec<i16> = (0..800)
+        .map(|i| ((i * (seed as i32 + 1)) % 2000 - 1000) as i16)
+        .collect();
+    let data_length = (samples.len() * 2) as u32;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_length).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&8000u32.to_le_bytes());
+    bytes.extend_from_slice(&16000u32.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_length.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    bytes
+}
@@ -0,0 +1,583 @@
+// src/commands/trash.rs
+//! The trash: deleted repositories, files, contacts and contact lists.
+//!
+//! Deleting one of these only sets its `deleted_at`; every read leaves such rows out, but
+//! their tags, relations, version history and list memberships stay in place, so a
+//! restore brings them back as they were. A repository's files are trashed with it and
+//! only appear in the trash through the repository. Items older than the retention in
+//! `AppSettings` are purged for good by the background purger; a trashed file still
+//! claims its path, so the same file can't be added again until it is restored or purged.
+use std::error::Error;
+
+use crate::background::folder_watcher::start_repository_watchers;
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::collections::refresh_file_collections;
+use crate::commands::db::{get_connection, purge_file, purge_repository, DbPool};
+use crate::commands::structures::{TrashItem, TrashItemKind};
+use crate::commands::versions::remove_unreferenced_objects;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use tauri::{Manager, State, Window};
+
+/// Every item in the trash with the time it was deleted. Files trashed with their
+/// repository are left out; they come back with it.
+const TRASH_QUERY: &str = "
+    SELECT 'repository' AS kind, id, name, id AS repo_id, deleted_at FROM Repositories
+    WHERE deleted_at IS NOT NULL
+    UNION ALL
+    SELECT 'file', f.id, f.name, f.repo_id, f.deleted_at FROM Files f
+    JOIN Repositories r ON r.id = f.repo_id
+    WHERE f.deleted_at IS NOT NULL AND r.deleted_at IS NULL
+    UNION ALL
+    SELECT 'contact', id, name, NULL, deleted_at FROM Contacts
+    WHERE deleted_at IS NOT NULL
+    UNION ALL
+    SELECT 'contact_list', id, name, NULL, deleted_at FROM ContactLists
+    WHERE deleted_at IS NOT NULL";
+
+impl TrashItemKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrashItemKind::Repository => "repository",
+            TrashItemKind::File => "file",
+            TrashItemKind::Contact => "contact",
+            TrashItemKind::ContactList => "contact_list",
+        }
+    }
+
+    fn parse(value: &str) -> Option<TrashItemKind> {
+        match value {
+            "repository" => Some(TrashItemKind::Repository),
+            "file" => Some(TrashItemKind::File),
+            "contact" => Some(TrashItemKind::Contact),
+            "contact_list" => Some(TrashItemKind::ContactList),
+            _ => None,
+        }
+    }
+
+    fn table(&self) -> &'static str {
+        match self {
+            TrashItemKind::Repository => "Repositories",
+            TrashItemKind::File => "Files",
+            TrashItemKind::Contact => "Contacts",
+            TrashItemKind::ContactList => "ContactLists",
+        }
+    }
+}
+
+fn get_trash_retention_days(conn: &Connection) -> Result<u32> {
+    conn.query_row(
+        "SELECT trash_retention_days FROM AppSettings LIMIT 1",
+        [],
+        |row| row.get(0),
+    )
+}
+
+/// When an item deleted at `deleted_at` will be purged, or None if nothing is purged.
+fn purge_time(deleted_at: &str, retention_days: u32) -> Option<String> {
+    if retention_days == 0 {
+        return None;
+    }
+    DateTime::parse_from_rfc3339(deleted_at).ok().map(|date| {
+        (date.with_timezone(&Utc) + Duration::days(retention_days as i64))
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+    })
+}
+
+/// Returns everything in the trash, most recently deleted first.
+pub fn get_trash(conn: &Connection) -> Result<Vec<TrashItem>> {
+    let retention_days = get_trash_retention_days(conn)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT * FROM ({}) ORDER BY deleted_at DESC",
+        TRASH_QUERY
+    ))?;
+    let items = stmt
+        .query_map([], |row| {
+            let kind: String = row.get(0)?;
+            let deleted_at: String = row.get(4)?;
+            Ok(TrashItem {
+                kind: TrashItemKind::parse(&kind).ok_or_else(|| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        0,
+                        rusqlite::types::Type::Text,
+                        format!("unknown trash item kind '{}'", kind).into(),
+                    )
+                })?,
+                id: row.get(1)?,
+                name: row.get(2)?,
+                repo_id: row.get(3)?,
+                purge_at: purge_time(&deleted_at, retention_days),
+                deleted_at,
+            })
+        })?
+        .collect::<Result<Vec<TrashItem>>>()?;
+    Ok(items)
+}
+
+/// The time an item was trashed, or None if it isn't in the trash.
+fn trashed_at(conn: &Connection, kind: TrashItemKind, id: &str) -> Result<Option<String>> {
+    conn.query_row(
+        &format!(
+            "SELECT deleted_at FROM {} WHERE id = ?1 AND deleted_at IS NOT NULL",
+            kind.table()
+        ),
+        params![id],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Takes an item back out of the trash. A file can only be restored while its repository
+/// isn't in the trash.
+pub fn restore_trash_item(
+    conn: &Connection,
+    kind: TrashItemKind,
+    id: &str,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let tx = conn.unchecked_transaction()?;
+    untrash_item(&tx, kind, id)?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// `restore_trash_item` without a transaction of its own, for callers that restore an
+/// item as part of a larger change.
+pub fn untrash_item(
+    conn: &Connection,
+    kind: TrashItemKind,
+    id: &str,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let deleted_at = match trashed_at(conn, kind, id)? {
+        Some(deleted_at) => deleted_at,
+        None => return Err(format!("{} {} isn't in the trash.", kind.as_str(), id).into()),
+    };
+
+    match kind {
+        TrashItemKind::Repository => {
+            conn.execute(
+                "UPDATE Repositories SET deleted_at = NULL WHERE id = ?1",
+                params![id],
+            )?;
+            // Files trashed on their own before the repository stay in the trash.
+            let file_ids = {
+                let mut stmt =
+                    conn.prepare("SELECT id FROM Files WHERE repo_id = ?1 AND deleted_at = ?2")?;
+                let ids = stmt
+                    .query_map(params![id, deleted_at], |row| row.get(0))?
+                    .collect::<Result<Vec<String>>>()?;
+                ids
+            };
+            conn.execute(
+                "UPDATE Files SET deleted_at = NULL WHERE repo_id = ?1 AND deleted_at = ?2",
+                params![id, deleted_at],
+            )?;
+            for file_id in &file_ids {
+                refresh_file_collections(conn, id, file_id)?;
+            }
+        }
+        TrashItemKind::File => {
+            let (repo_id, repo_trashed): (String, bool) = conn.query_row(
+                "SELECT r.id, r.deleted_at IS NOT NULL FROM Files f
+                 JOIN Repositories r ON r.id = f.repo_id
+                 WHERE f.id = ?1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            if repo_trashed {
+                return Err(format!(
+                    "The repository of file {} is in the trash; restore it first.",
+                    id
+                )
+                .into());
+            }
+            conn.execute(
+                "UPDATE Files SET deleted_at = NULL WHERE id = ?1",
+                params![id],
+            )?;
+            refresh_file_collections(conn, &repo_id, id)?;
+        }
+        TrashItemKind::Contact | TrashItemKind::ContactList => {
+            conn.execute(
+                &format!(
+                    "UPDATE {} SET deleted_at = NULL WHERE id = ?1",
+                    kind.table()
+                ),
+                params![id],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes an item for good, without checking that it is in the trash. Returns the
+/// objects its history referred to, to clear once the transaction commits.
+fn purge_item(conn: &Connection, kind: TrashItemKind, id: &str) -> Result<Vec<String>> {
+    match kind {
+        TrashItemKind::Repository => purge_repository(conn, id),
+        TrashItemKind::File => {
+            let repo_id: String = conn.query_row(
+                "SELECT repo_id FROM Files WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )?;
+            purge_file(conn, &repo_id, id)
+        }
+        TrashItemKind::Contact | TrashItemKind::ContactList => {
+            conn.execute(
+                &format!("DELETE FROM {} WHERE id = ?1", kind.table()),
+                params![id],
+            )?;
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// Deletes a trashed item for good. Returns false if it wasn't in the trash.
+pub fn delete_trash_item(conn: &Connection, kind: TrashItemKind, id: &str) -> Result<bool> {
+    if trashed_at(conn, kind, id)?.is_none() {
+        return Ok(false);
+    }
+    let tx = conn.unchecked_transaction()?;
+    let dropped = purge_item(&tx, kind, id)?;
+    tx.commit()?;
+    remove_unreferenced_objects(conn, &dropped);
+    Ok(true)
+}
+
+/// Deletes for good everything trashed before `cutoff` (an RFC 3339 time), or everything
+/// in the trash if there is no cutoff. Returns how many items were deleted.
+fn purge_trash(conn: &Connection, cutoff: Option<&str>) -> Result<usize> {
+    let items: Vec<(String, String)> = {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT * FROM ({}) WHERE ?1 IS NULL OR deleted_at < ?1",
+            TRASH_QUERY
+        ))?;
+        let items = stmt
+            .query_map(params![cutoff], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<(String, String)>>>()?;
+        items
+    };
+
+    let tx = conn.unchecked_transaction()?;
+    let mut purged = 0;
+    let mut dropped = Vec::new();
+    for (kind, id) in &items {
+        if let Some(kind) = TrashItemKind::parse(kind) {
+            dropped.extend(purge_item(&tx, kind, id)?);
+            purged += 1;
+        }
+    }
+    tx.commit()?;
+    remove_unreferenced_objects(conn, &dropped);
+    Ok(purged)
+}
+
+/// Deletes everything in the trash for good.
+pub fn empty_trash(conn: &Connection) -> Result<usize> {
+    purge_trash(conn, None)
+}
+
+/// Deletes for good whatever has been in the trash longer than the configured retention.
+pub fn purge_expired_trash(conn: &Connection) -> Result<usize> {
+    let retention_days = get_trash_retention_days(conn)?;
+    if retention_days == 0 {
+        return Ok(0);
+    }
+    let cutoff = (Utc::now() - Duration::days(retention_days as i64))
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+    purge_trash(conn, Some(&cutoff))
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_trash_command(pool: State<'_, DbPool>) -> Result<Vec<TrashItem>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_trash(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn restore_trash_item_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    kind: TrashItemKind,
+    id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = restore_trash_item(&conn, kind, &id);
+        if result.is_ok() && kind == TrashItemKind::Repository {
+            if let Some(webview) = emit_window.get_webview_window("main") {
+                if let Err(e) = start_repository_watchers(&webview, &pool, &conn, &id) {
+                    println!("Failed to watch the folders of repository '{}': {}", id, e);
+                }
+            }
+        }
+
+        let payload = match &result {
+            Ok(_) => format!("Restored {} {} from the trash.", kind.as_str(), id),
+            Err(e) => format!("Failed to restore {} {}: {}", kind.as_str(), id, e),
+        };
+        let file_ids = if kind == TrashItemKind::File {
+            vec![id.clone()]
+        } else {
+            Vec::new()
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "restore_trash_item",
+            None,
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_trash_item_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    kind: TrashItemKind,
+    id: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = delete_trash_item(&conn, kind, &id);
+
+        let payload = match &result {
+            Ok(true) => format!("Deleted {} {} for good.", kind.as_str(), id),
+            Ok(false) => format!("{} {} isn't in the trash.", kind.as_str(), id),
+            Err(e) => format!("Failed to delete {} {}: {}", kind.as_str(), id, e),
+        };
+        let file_ids = if kind == TrashItemKind::File {
+            vec![id.clone()]
+        } else {
+            Vec::new()
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "delete_trash_item",
+            None,
+            &file_ids,
+            &result,
+            &payload,
+        );
+
+        result.map(|_| ()).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn empty_trash_command(window: Window, pool: State<'_, DbPool>) -> Result<usize, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = empty_trash(&conn);
+
+        let payload = match &result {
+            Ok(count) => format!("Deleted {} items from the trash for good.", count),
+            Err(e) => format!("Failed to empty the trash: {}", e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "empty_trash",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::commits::commit_repository;
+    use crate::commands::db::{
+        create_contact, create_contact_list, create_file, create_tracked_folder, delete_contact,
+        delete_contact_list, delete_file, delete_repository, get_file, get_repository,
+    };
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection,
+    };
+    use crate::commands::versions::{get_file_versions, object_path};
+    use uuid::Uuid;
+
+    /// Adds a repository holding one file with its own unique audio.
+    fn repository_with_file(conn: &Connection, repo_id: &str) -> String {
+        add_repository(conn, repo_id);
+        let bytes = format!("take {}", Uuid::new_v4());
+        let file = sample_file(&scratch_dir(), "take.wav", bytes.as_bytes());
+        create_file(conn, repo_id, &file).unwrap();
+        file.id
+    }
+
+    fn count(conn: &Connection, sql: &str, id: &str) -> i64 {
+        conn.query_row(sql, params![id], |row| row.get(0)).unwrap()
+    }
+
+    fn backdate(conn: &Connection, table: &str, id: &str, days: i64) {
+        let deleted_at =
+            (Utc::now() - Duration::days(days)).to_rfc3339_opts(SecondsFormat::Millis, true);
+        conn.execute(
+            &format!("UPDATE {} SET deleted_at = ?1 WHERE id = ?2", table),
+            params![deleted_at, id],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn restoring_a_repository_brings_back_the_files_trashed_with_it() {
+        let conn = test_connection();
+        let file_id = repository_with_file(&conn, "repo");
+        let kept_id = {
+            let file = sample_file(&scratch_dir(), "kept.wav", b"kept");
+            create_file(&conn, "repo", &file).unwrap();
+            file.id
+        };
+        delete_file(&conn, "repo", &kept_id).unwrap();
+        backdate(&conn, "Files", &kept_id, 1);
+        delete_repository(&conn, "repo").unwrap();
+        assert_eq!(get_trash(&conn).unwrap().len(), 1);
+        assert!(restore_trash_item(&conn, TrashItemKind::File, &file_id).is_err());
+
+        restore_trash_item(&conn, TrashItemKind::Repository, "repo").unwrap();
+        assert!(get_repository(&conn, "repo").is_ok());
+        assert!(get_file(&conn, "repo", &file_id).is_ok());
+        assert!(get_file(&conn, "repo", &kept_id).is_err());
+
+        restore_trash_item(&conn, TrashItemKind::File, &kept_id).unwrap();
+        assert!(get_file(&conn, "repo", &kept_id).is_ok());
+        assert!(restore_trash_item(&conn, TrashItemKind::File, &kept_id).is_err());
+    }
+
+    #[test]
+    fn purging_a_repository_takes_its_history_and_objects() {
+        let conn = test_connection();
+        let file_id = repository_with_file(&conn, "repo");
+        commit_repository(&conn, "repo", "First take", "me").unwrap();
+        create_tracked_folder(&conn, "repo", "/music/repo").unwrap();
+        let content_hash = get_file_versions(&conn, &file_id).unwrap()[0]
+            .content_hash
+            .clone();
+        assert!(object_path(&content_hash).exists());
+
+        delete_repository(&conn, "repo").unwrap();
+        assert!(delete_trash_item(&conn, TrashItemKind::Repository, "repo").unwrap());
+
+        for sql in [
+            "SELECT COUNT(*) FROM Files WHERE repo_id = ?1",
+            "SELECT COUNT(*) FROM FileVersions WHERE repo_id = ?1",
+            "SELECT COUNT(*) FROM Commits WHERE repo_id = ?1",
+            "SELECT COUNT(*) FROM TrackedFolders WHERE repo_id = ?1",
+        ] {
+            assert_eq!(count(&conn, sql, "repo"), 0, "{}", sql);
+        }
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM CommitFiles WHERE file_id = ?1",
+                &file_id
+            ),
+            0
+        );
+        assert!(!object_path(&content_hash).exists());
+    }
+
+    #[test]
+    fn purging_a_file_takes_its_history_and_objects() {
+        let conn = test_connection();
+        let file_id = repository_with_file(&conn, "repo");
+        let content_hash = get_file_versions(&conn, &file_id).unwrap()[0]
+            .content_hash
+            .clone();
+
+        delete_file(&conn, "repo", &file_id).unwrap();
+        assert!(delete_trash_item(&conn, TrashItemKind::File, &file_id).unwrap());
+        assert!(!delete_trash_item(&conn, TrashItemKind::File, &file_id).unwrap());
+
+        assert!(get_file_versions(&conn, &file_id).unwrap().is_empty());
+        assert!(!object_path(&content_hash).exists());
+    }
+
+    #[test]
+    fn purging_contacts_and_lists_deletes_their_rows() {
+        let conn = test_connection();
+        let contact_id =
+            create_contact(&conn, "Ada", "ada@example.com", None, None, None, None).unwrap();
+        let list_id = create_contact_list(&conn, "Labels").unwrap();
+        delete_contact(&conn, &contact_id).unwrap();
+        delete_contact_list(&conn, &list_id).unwrap();
+
+        assert!(delete_trash_item(&conn, TrashItemKind::Contact, &contact_id).unwrap());
+        assert!(delete_trash_item(&conn, TrashItemKind::ContactList, &list_id).unwrap());
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM Contacts WHERE id = ?1",
+                &contact_id
+            ),
+            0
+        );
+        assert_eq!(
+            count(
+                &conn,
+                "SELECT COUNT(*) FROM ContactLists WHERE id = ?1",
+                &list_id
+            ),
+            0
+        );
+    }
+
+    #[test]
+    fn only_items_past_the_retention_are_purged() {
+        let conn = test_connection();
+        let old_id =
+            create_contact(&conn, "Old", "old@example.com", None, None, None, None).unwrap();
+        let recent_id =
+            create_contact(&conn, "New", "new@example.com", None, None, None, None).unwrap();
+        backdate(&conn, "Contacts", &old_id, 31);
+        backdate(&conn, "Contacts", &recent_id, 29);
+
+        assert_eq!(purge_expired_trash(&conn).unwrap(), 1);
+        let trash = get_trash(&conn).unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].id, recent_id);
+        assert!(trash[0].purge_at.is_some());
+
+        conn.execute("UPDATE AppSettings SET trash_retention_days = 0", [])
+            .unwrap();
+        backdate(&conn, "Contacts", &recent_id, 400);
+        assert_eq!(purge_expired_trash(&conn).unwrap(), 0);
+        assert_eq!(empty_trash(&conn).unwrap(), 1);
+    }
+}
@@ -0,0 +1,1016 @@
+// src/commands/versions.rs
+//! Content-addressed version history for tracked audio files.
+//!
+//! Whenever a tracked file's audio changes, its bytes are copied into an object store
+//! under `RepoStudio_AppData/objects`, named after their SHA-256 hash (with a git-style
+//! two character fan-out directory), and a row describing the snapshot is added to the
+//! FileVersions table. Identical audio is only ever stored once.
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::commands::activity::{finish_command, NO_FILES};
+use crate::commands::db::{
+    get_app_data_dir, get_connection, get_file, get_files_in_repository, update_file, DbPool,
+};
+use crate::commands::file_ops::{
+    read_date_modified, timestamps_match, write_audio_metadata_to_file,
+};
+use crate::commands::structures::{FileMetadata, FileVersion, RepositoryRestore};
+use base64::{engine::general_purpose, Engine as _};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tauri::{Emitter, State, Window};
+use uuid::Uuid;
+
+/// Returns the directory holding all stored objects.
+pub fn get_objects_dir() -> PathBuf {
+    get_app_data_dir().join("objects")
+}
+
+/// Returns the on-disk location of the object with the given content hash.
+pub fn object_path(content_hash: &str) -> PathBuf {
+    let (prefix, rest) = content_hash.split_at(2.min(content_hash.len()));
+    get_objects_dir().join(prefix).join(rest)
+}
+
+/// Computes the SHA-256 hash of a file without loading it into memory.
+pub fn hash_file(path: &Path) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Copies a file into the object store and returns its content hash and size.
+/// The bytes are hashed while they are copied into a temporary file, which is then
+/// renamed into place (or discarded if the object already exists).
+pub fn store_object(path: &Path) -> Result<(String, u64), Box<dyn Error + Send + Sync>> {
+    let objects_dir = get_objects_dir();
+    fs::create_dir_all(&objects_dir)?;
+
+    let temp_path = objects_dir.join(format!("tmp-{}", Uuid::new_v4()));
+    let mut source = File::open(path)?;
+    let mut temp = File::create(&temp_path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    let mut size: u64 = 0;
+
+    let copy_result = (|| -> Result<(), Box<dyn Error + Send + Sync>> {
+        loop {
+            let read = source.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            temp.write_all(&buffer[..read])?;
+            size += read as u64;
+        }
+        temp.sync_all()?;
+        Ok(())
+    })();
+    drop(temp);
+
+    if let Err(e) = copy_result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+
+    let content_hash = format!("{:x}", hasher.finalize());
+    let destination = object_path(&content_hash);
+    if destination.exists() {
+        fs::remove_file(&temp_path)?;
+    } else {
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&temp_path, &destination)?;
+    }
+
+    Ok((content_hash, size))
+}
+
+fn version_from_row(row: &Row) -> rusqlite::Result<FileVersion> {
+    let metadata_json: String = row.get(7)?;
+    let metadata: FileMetadata = serde_json::from_str(&metadata_json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(7, rusqlite::types::Type::Text, Box::new(e))
+    })?;
+    Ok(FileVersion {
+        id: row.get(0)?,
+        file_id: row.get(1)?,
+        repo_id: row.get(2)?,
+        content_hash: row.get(3)?,
+        size_on_disk: row.get(4)?,
+        date_modified: row.get(5)?,
+        date_recorded: row.get(6)?,
+        metadata,
+    })
+}
+
+/// Returns every recorded version of a file, newest first.
+pub fn get_file_versions(conn: &Connection, file_id: &str) -> rusqlite::Result<Vec<FileVersion>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, file_id, repo_id, content_hash, size_on_disk, date_modified, date_recorded, metadata
+         FROM FileVersions WHERE file_id = ?1
+         ORDER BY date_recorded DESC",
+    )?;
+    let versions = stmt
+        .query_map(params![file_id], version_from_row)?
+        .collect::<rusqlite::Result<Vec<FileVersion>>>()?;
+    Ok(versions)
+}
+
+/// Returns a single version by id.
+pub fn get_file_version(conn: &Connection, version_id: &str) -> rusqlite::Result<FileVersion> {
+    conn.query_row(
+        "SELECT id, file_id, repo_id, content_hash, size_on_disk, date_modified, date_recorded, metadata
+         FROM FileVersions WHERE id = ?1",
+        params![version_id],
+        version_from_row,
+    )
+}
+
+/// Returns the most recent version of a file, if one has been recorded.
+pub fn get_latest_file_version(
+    conn: &Connection,
+    file_id: &str,
+) -> rusqlite::Result<Option<FileVersion>> {
+    conn.prepare_cached(
+        "SELECT id, file_id, repo_id, content_hash, size_on_disk, date_modified, date_recorded, metadata
+         FROM FileVersions WHERE file_id = ?1
+         ORDER BY date_recorded DESC LIMIT 1",
+    )?
+    .query_row(params![file_id], version_from_row)
+    .optional()
+}
+
+/// Compares two states of a file row, ignoring the transient `accessible` flag.
+pub fn file_state_differs(a: &FileMetadata, b: &FileMetadata) -> bool {
+    let strip = |file: &FileMetadata| {
+        let mut value = serde_json::to_value(file).unwrap_or_default();
+        if let Some(map) = value.as_object_mut() {
+            map.remove("accessible");
+        }
+        value
+    };
+    strip(a) != strip(b)
+}
+
+/// Records a version of a tracked file: its row as given plus a copy of its audio.
+/// Returns the new version, or `None` if neither the audio nor the row changed since the
+/// latest recorded version. The audio is only re-read when its modification time differs
+/// from the latest version. If the audio on disk has moved on since the given state was
+/// read, the row is still recorded, paired with the latest recorded audio (or, for a file
+/// with no history, the audio as it is now); the version's `date_modified` always
+/// describes the audio it holds.
+pub fn record_file_version(
+    conn: &Connection,
+    repo_id: &str,
+    file: &FileMetadata,
+) -> Result<Option<FileVersion>, Box<dyn Error + Send + Sync>> {
+    let latest = get_latest_file_version(conn, &file.id)?;
+
+    let (content_hash, size, date_modified) = match &latest {
+        Some(latest) if timestamps_match(&latest.date_modified, &file.date_modified) => (
+            latest.content_hash.clone(),
+            latest.size_on_disk as u64,
+            file.date_modified.clone(),
+        ),
+        _ => match (read_date_modified(&file.path), &latest) {
+            (Ok(on_disk), _) if timestamps_match(&on_disk, &file.date_modified) => {
+                let (content_hash, size) = store_object(Path::new(&file.path))?;
+                (content_hash, size, file.date_modified.clone())
+            }
+            (_, Some(latest)) => {
+                println!(
+                    "The audio of '{}' has changed since this state; keeping it with the last recorded audio",
+                    file.name
+                );
+                (
+                    latest.content_hash.clone(),
+                    latest.size_on_disk as u64,
+                    latest.date_modified.clone(),
+                )
+            }
+            (Ok(on_disk), None) => {
+                println!(
+                    "The audio of '{}' has changed since this state; keeping it with the current audio",
+                    file.name
+                );
+                let (content_hash, size) = store_object(Path::new(&file.path))?;
+                (content_hash, size, on_disk)
+            }
+            (Err(e), None) => return Err(e.into()),
+        },
+    };
+
+    if let Some(latest) = &latest {
+        if latest.content_hash == content_hash && !file_state_differs(&latest.metadata, file) {
+            return Ok(None);
+        }
+    }
+
+    let version = FileVersion {
+        id: Uuid::new_v4().to_string(),
+        file_id: file.id.clone(),
+        repo_id: repo_id.to_string(),
+        content_hash,
+        size_on_disk: size as i64,
+        date_modified,
+        date_recorded: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        metadata: file.clone(),
+    };
+
+    conn.prepare_cached(
+        "INSERT INTO FileVersions (
+            id, file_id, repo_id, content_hash, size_on_disk, date_modified, date_recorded, metadata
+         ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+    )?
+    .execute(params![
+        version.id,
+        version.file_id,
+        version.repo_id,
+        version.content_hash,
+        version.size_on_disk,
+        version.date_modified,
+        version.date_recorded,
+        serde_json::to_string(&version.metadata)?,
+    ])?;
+
+    println!(
+        "Recorded version {} of '{}' ({})",
+        version.id, file.name, version.content_hash
+    );
+    Ok(Some(version))
+}
+
+/// Deletes objects that no journal entry, file version or commit refers to anymore.
+pub fn remove_unreferenced_objects(conn: &Connection, content_hashes: &[String]) {
+    for content_hash in content_hashes {
+        let referenced = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM JournalObjects WHERE content_hash = ?1)
+                 OR EXISTS(SELECT 1 FROM FileVersions WHERE content_hash = ?1)
+                 OR EXISTS(SELECT 1 FROM CommitFiles WHERE content_hash = ?1)",
+            params![content_hash],
+            |row| row.get::<_, bool>(0),
+        );
+        match referenced {
+            Ok(false) => {
+                if let Err(e) = fs::remove_file(object_path(content_hash)) {
+                    println!("Failed to remove object {}: {}", content_hash, e);
+                }
+            }
+            Ok(true) => {}
+            Err(e) => println!(
+                "Failed to check references to object {}: {}",
+                content_hash, e
+            ),
+        }
+    }
+}
+
+/// Returns the repository and id of every live, accessible file with no recorded version.
+pub fn get_unversioned_files(conn: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare(
+        "SELECT f.repo_id, f.id FROM Files f
+         WHERE f.deleted_at IS NULL AND f.accessible
+           AND NOT EXISTS (SELECT 1 FROM FileVersions v WHERE v.file_id = f.id)",
+    )?;
+    let files = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+    Ok(files)
+}
+
+/// Records a first version of every file that has none yet. Each file is copied in its
+/// own statement, outside any transaction; one that can't be read is skipped and tried
+/// again next time. Returns how many files were snapshotted.
+pub fn record_baseline_versions(conn: &Connection) -> Result<usize, Box<dyn Error + Send + Sync>> {
+    let mut recorded = 0;
+    for (repo_id, file_id) in get_unversioned_files(conn)? {
+        let file = match get_file(conn, &repo_id, &file_id) {
+            Ok(file) => file,
+            // Removed since the list was read.
+            Err(rusqlite::Error::QueryReturnedNoRows) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        match record_file_version(conn, &repo_id, &file) {
+            Ok(_) => recorded += 1,
+            Err(e) => println!(
+                "Failed to take a baseline snapshot of '{}': {}",
+                file.name, e
+            ),
+        }
+    }
+    Ok(recorded)
+}
+
+/// Reads the audio bytes of a past version from the object store.
+pub fn read_file_version(
+    conn: &Connection,
+    version_id: &str,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let version = get_file_version(conn, version_id)?;
+    Ok(fs::read(object_path(&version.content_hash))?)
+}
+
+/// Copies the audio of a past version out of the object store to the given path.
+pub fn export_file_version(
+    conn: &Connection,
+    version_id: &str,
+    destination_path: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let version = get_file_version(conn, version_id)?;
+    fs::copy(object_path(&version.content_hash), destination_path)?;
+    Ok(())
+}
+
+/// Returns the latest version of a file recorded at or before the given timestamp.
+pub fn get_file_version_at(
+    conn: &Connection,
+    file_id: &str,
+    timestamp: &str,
+) -> rusqlite::Result<Option<FileVersion>> {
+    conn.query_row(
+        "SELECT id, file_id, repo_id, content_hash, size_on_disk, date_modified, date_recorded, metadata
+         FROM FileVersions WHERE file_id = ?1 AND date_recorded <= ?2
+         ORDER BY date_recorded DESC LIMIT 1",
+        params![file_id, timestamp],
+        version_from_row,
+    )
+    .optional()
+}
+
+/// Returns true if the audio on disk differs from every recorded version of the file,
+/// i.e. restoring over it would lose changes that exist nowhere else.
+pub fn has_unrecorded_changes(
+    conn: &Connection,
+    file: &FileMetadata,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    let path = Path::new(&file.path);
+    if !path.exists() {
+        return Ok(false);
+    }
+    if let Some(latest) = get_latest_file_version(conn, &file.id)? {
+        if timestamps_match(&latest.date_modified, &read_date_modified(&file.path)?) {
+            return Ok(false);
+        }
+    }
+    let content_hash = hash_file(path)?;
+    let recorded: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM FileVersions WHERE file_id = ?1 AND content_hash = ?2)",
+        params![file.id, content_hash],
+        |row| row.get(0),
+    )?;
+    Ok(!recorded)
+}
+
+/// Overwrites `destination` with a stored object, going through a temporary file in the
+/// same directory so a failure never leaves a half-written file behind.
+pub fn write_object_to(
+    content_hash: &str,
+    destination: &Path,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let parent = destination
+        .parent()
+        .ok_or("Destination has no parent directory")?;
+    fs::create_dir_all(parent)?;
+    let temp_path = parent.join(format!(".repostudio-restore-{}", Uuid::new_v4()));
+    if let Err(e) = fs::copy(object_path(content_hash), &temp_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.into());
+    }
+    if destination.exists() {
+        fs::remove_file(destination)?;
+    }
+    fs::rename(&temp_path, destination)?;
+    Ok(())
+}
+
+/// Puts a file back to a recorded version without any safety checks. The row and its new
+/// version are written in one transaction; if writing the audio or that transaction
+/// fails, the audio on disk is put back.
+fn apply_file_version(
+    conn: &Connection,
+    repo_id: &str,
+    current: &FileMetadata,
+    version: &FileVersion,
+) -> Result<FileMetadata, Box<dyn Error + Send + Sync>> {
+    // Keep the state we're about to replace, so the restore itself can be undone, and
+    // hold on to the audio at both paths the restore may write to.
+    if current.accessible {
+        record_file_version(conn, repo_id, current)?;
+    }
+    let mut previous_audio = Vec::new();
+    for path in [&current.path, &version.metadata.path] {
+        let path = Path::new(path);
+        if previous_audio.iter().any(|(saved, _)| saved == path) {
+            continue;
+        }
+        let content_hash = if path.exists() {
+            Some(store_object(path)?.0)
+        } else {
+            None
+        };
+        previous_audio.push((path.to_path_buf(), content_hash));
+    }
+
+    let restored = match write_file_version(current, version) {
+        Ok(restored) => restored,
+        Err(e) => {
+            put_back_audio(&previous_audio);
+            return Err(e);
+        }
+    };
+    let write_row = || -> Result<(), Box<dyn Error + Send + Sync>> {
+        let tx = conn.unchecked_transaction()?;
+        update_file(&tx, repo_id, &restored)?;
+        record_file_version(&tx, repo_id, &restored)?;
+        tx.commit()?;
+        Ok(())
+    };
+    if let Err(e) = write_row() {
+        put_back_audio(&previous_audio);
+        return Err(e);
+    }
+
+    Ok(restored)
+}
+
+/// Restores a single file's row, audio and embedded tags to a recorded version.
+/// Refuses to overwrite audio that was never recorded unless `force` is set.
+pub fn restore_file_version(
+    conn: &Connection,
+    repo_id: &str,
+    version_id: &str,
+    force: bool,
+) -> Result<FileMetadata, Box<dyn Error + Send + Sync>> {
+    let version = get_file_version(conn, version_id)?;
+    let current = get_file(conn, repo_id, &version.file_id)?;
+
+    if !force && has_unrecorded_changes(conn, &current)? {
+        return Err(format!(
+            "'{}' has changes on disk that aren't in its history. Restore with force to overwrite them.",
+            current.name
+        )
+        .into());
+    }
+
+    let restored = apply_file_version(conn, repo_id, &current, &version)?;
+    println!("Restored '{}' to version {}", restored.name, version.id);
+    Ok(restored)
+}
+
+/// Restores every file in a repository to its latest version recorded at or before
+/// `timestamp` (RFC 3339). Files with no history before that point are left alone and
+/// reported as `added_since`. Every file is checked before any is written: nothing is
+/// touched if a version's audio is missing from the object store, or if a file has
+/// unrecorded changes unless `force` is set. The rows change in one transaction; if that
+/// fails, the audio already written is put back.
+pub fn restore_repository_to_time(
+    conn: &Connection,
+    window: &Window,
+    repo_id: &str,
+    timestamp: &str,
+    force: bool,
+) -> Result<RepositoryRestore, Box<dyn Error + Send + Sync>> {
+    let point_in_time = DateTime::parse_from_rfc3339(timestamp)?
+        .with_timezone(&Utc)
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
+
+    let mut plan = Vec::new();
+    let mut added_since = Vec::new();
+    for file in get_files_in_repository(conn, repo_id)? {
+        match get_file_version_at(conn, &file.id, &point_in_time)? {
+            Some(version) => {
+                let unchanged = timestamps_match(&file.date_modified, &version.date_modified)
+                    && !file_state_differs(&file, &version.metadata);
+                if !unchanged {
+                    plan.push((file, version));
+                }
+            }
+            None => added_since.push(file),
+        }
+    }
+
+    let mut missing = Vec::new();
+    let mut dirty = Vec::new();
+    for (file, version) in &plan {
+        if !object_path(&version.content_hash).exists() {
+            missing.push(file.name.clone());
+        } else if !force && has_unrecorded_changes(conn, file)? {
+            dirty.push(file.name.clone());
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!(
+            "The recorded audio of these files is missing from the object store: {}",
+            missing.join(", ")
+        )
+        .into());
+    }
+    if !dirty.is_empty() {
+        return Err(format!(
+            "These files have changes on disk that aren't in their history: {}. Restore with force to overwrite them.",
+            dirty.join(", ")
+        )
+        .into());
+    }
+
+    // Keep the states we're about to replace, so the restore itself can be undone, and
+    // hold on to the audio on disk to put back if the restore fails part way.
+    let mut previous_audio = Vec::new();
+    for (file, _) in &plan {
+        if file.accessible {
+            record_file_version(conn, repo_id, file)?;
+        }
+        let path = Path::new(&file.path);
+        let content_hash = if path.exists() {
+            Some(store_object(path)?.0)
+        } else {
+            None
+        };
+        previous_audio.push((path.to_path_buf(), content_hash));
+    }
+
+    let total = plan.len();
+    let mut restored = Vec::new();
+    for (i, (file, version)) in plan.iter().enumerate() {
+        window
+            .emit(
+                "restore_repository_progress",
+                json!({
+                    "progress": ((i + 1) as f64 / total as f64) * 100.0,
+                    "file_index": i + 1,
+                    "total": total,
+                    "file_name": file.name,
+                }),
+            )
+            .ok();
+
+        match write_file_version(file, version) {
+            Ok(state) => restored.push(state),
+            Err(e) => {
+                put_back_audio(&previous_audio[..=i]);
+                return Err(e);
+            }
+        }
+    }
+
+    let write_rows = || -> rusqlite::Result<()> {
+        let tx = conn.unchecked_transaction()?;
+        for state in &restored {
+            update_file(&tx, repo_id, state)?;
+        }
+        tx.commit()
+    };
+    if let Err(e) = write_rows() {
+        put_back_audio(&previous_audio);
+        return Err(e.into());
+    }
+
+    for state in &restored {
+        if let Err(e) = record_file_version(conn, repo_id, state) {
+            println!("Failed to record restored state of '{}': {}", state.name, e);
+        }
+    }
+
+    println!(
+        "Restored {} files in repository '{}' to {} ({} added since)",
+        restored.len(),
+        repo_id,
+        point_in_time,
+        added_since.len()
+    );
+    Ok(RepositoryRestore {
+        restored: restored.len(),
+        added_since,
+    })
+}
+
+/// Writes a version's audio and embedded tags over a file, returning the row that now
+/// describes it. The database is left alone.
+fn write_file_version(
+    current: &FileMetadata,
+    version: &FileVersion,
+) -> Result<FileMetadata, Box<dyn Error + Send + Sync>> {
+    let mut restored = version.metadata.clone();
+    restored.id = current.id.clone();
+    restored.accessible = true;
+
+    let destination = Path::new(&restored.path);
+    let on_disk_hash = if destination.exists() {
+        Some(hash_file(destination)?)
+    } else {
+        None
+    };
+    if on_disk_hash.as_deref() != Some(version.content_hash.as_str()) {
+        write_object_to(&version.content_hash, destination)?;
+    }
+
+    write_audio_metadata_to_file(&restored).map_err(|e| e.to_string())?;
+
+    // Writing the tags touches the file, so pick up the new timestamp to keep the next
+    // refresh from treating the restore as an external change.
+    restored.date_modified = read_date_modified(&restored.path)?;
+    Ok(restored)
+}
+
+/// Puts audio saved before a restore back in place, removing files that didn't exist.
+pub fn put_back_audio(previous_audio: &[(PathBuf, Option<String>)]) {
+    for (path, content_hash) in previous_audio {
+        let result = match content_hash {
+            Some(content_hash) => write_object_to(content_hash, path),
+            None if path.exists() => fs::remove_file(path).map_err(|e| e.into()),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            println!("Failed to put back '{}': {}", path.display(), e);
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn get_file_versions_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    file_id: String,
+) -> Result<Vec<FileVersion>, String> {
+    let emit_window = window.clone();
+    let file_id_for_db = file_id.clone();
+    let pool = pool.inner().clone();
+
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_file_versions(&conn, &file_id_for_db).map_err(|e| e.to_string())
+    })
+    .await;
+
+    if let Ok(Ok(versions)) = &result {
+        emit_window
+            .emit(
+                "get_file_versions_completed",
+                format!("Loaded {} versions for file '{}'.", versions.len(), file_id),
+            )
+            .unwrap_or_else(|e| {
+                println!("Failed to emit get_file_versions_completed event: {}", e)
+            });
+    }
+
+    result.map_err(|e| e.to_string())?
+}
+
+/// Returns the audio of a past version as a base64 string.
+#[tauri::command]
+pub async fn read_file_version_command(
+    pool: State<'_, DbPool>,
+    version_id: String,
+) -> Result<String, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        read_file_version(&conn, &version_id)
+            .map(|bytes| general_purpose::STANDARD.encode(bytes))
+            .map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn export_file_version_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    version_id: String,
+    destination_path: String,
+) -> Result<(), String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = export_file_version(&conn, &version_id, &destination_path);
+
+        let payload = match &result {
+            Ok(_) => format!(
+                "Version '{}' exported to '{}'.",
+                version_id, destination_path
+            ),
+            Err(e) => format!("Failed to export version '{}': {}", version_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "export_file_version",
+            None,
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn restore_file_version_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    version_id: String,
+    force: bool,
+) -> Result<FileMetadata, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = restore_file_version(&conn, &repo_id, &version_id, force);
+
+        let payload = match &result {
+            Ok(file) => format!("File '{}' restored to version '{}'.", file.name, version_id),
+            Err(e) => format!("Failed to restore version '{}': {}", version_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "restore_file_version",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn restore_repository_command(
+    window: Window,
+    pool: State<'_, DbPool>,
+    repo_id: String,
+    timestamp: String,
+    force: bool,
+) -> Result<RepositoryRestore, String> {
+    let emit_window = window.clone();
+    let pool = pool.inner().clone();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        let result = restore_repository_to_time(&conn, &emit_window, &repo_id, &timestamp, force);
+
+        let event_payload = match &result {
+            Ok(restore) if restore.added_since.is_empty() => format!(
+                "Restored {} files in repository {} to {}.",
+                restore.restored, repo_id, timestamp
+            ),
+            Ok(restore) => format!(
+                "Restored {} files in repository {} to {}. Left {} files added since then: {}.",
+                restore.restored,
+                repo_id,
+                timestamp,
+                restore.added_since.len(),
+                restore
+                    .added_since
+                    .iter()
+                    .map(|f| f.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Err(e) => format!("Failed to restore repository {}: {}", repo_id, e),
+        };
+        finish_command(
+            &conn,
+            &emit_window,
+            "restore_repository",
+            Some(&repo_id),
+            NO_FILES,
+            &result,
+            &event_payload,
+        );
+
+        result.map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::db::create_file;
+    use crate::commands::test_support::{
+        add_repository, sample_file, scratch_dir, test_connection, wav_bytes,
+    };
+
+    #[test]
+    fn object_paths_fan_out_on_the_first_two_characters() {
+        let path = object_path("abcdef");
+        assert_eq!(path.file_name().unwrap(), "cdef");
+        assert_eq!(path.parent().unwrap().file_name().unwrap(), "ab");
+    }
+
+    #[test]
+    fn accessibility_alone_is_not_a_change() {
+        let file = sample_file(&scratch_dir(), "a.wav", b"audio");
+        let mut other = file.clone();
+        other.accessible = false;
+        assert!(!file_state_differs(&file, &other));
+        other.meta_title = Some("Title".to_string());
+        assert!(file_state_differs(&file, &other));
+    }
+
+    #[test]
+    fn new_files_get_a_first_version() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = sample_file(&scratch_dir(), "a.wav", b"first take");
+        create_file(&conn, "repo", &file).unwrap();
+
+        let versions = get_file_versions(&conn, &file.id).unwrap();
+        assert_eq!(versions.len(), 1);
+        assert_eq!(
+            fs::read(object_path(&versions[0].content_hash)).unwrap(),
+            b"first take"
+        );
+        assert!(get_unversioned_files(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn baseline_snapshots_files_with_no_history() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = sample_file(&scratch_dir(), "a.wav", b"old library");
+        create_file(&conn, "repo", &file).unwrap();
+        conn.execute("DELETE FROM FileVersions", []).unwrap();
+
+        assert_eq!(
+            get_unversioned_files(&conn).unwrap(),
+            vec![("repo".to_string(), file.id.clone())]
+        );
+        assert_eq!(record_baseline_versions(&conn).unwrap(), 1);
+        assert_eq!(record_baseline_versions(&conn).unwrap(), 0);
+        assert_eq!(get_file(&conn, "repo", &file.id).unwrap().name, "a.wav");
+    }
+
+    /// Rewrites a file with a modification time an hour later than before.
+    fn rewrite_later(path: &str, bytes: &[u8]) {
+        let modified = fs::metadata(path).unwrap().modified().unwrap();
+        fs::write(path, bytes).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified + std::time::Duration::from_secs(3600))
+            .unwrap();
+    }
+
+    #[test]
+    fn a_row_whose_audio_moved_on_keeps_the_last_recorded_audio() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = sample_file(&scratch_dir(), "a.wav", b"first take");
+        create_file(&conn, "repo", &file).unwrap();
+        let first = get_latest_file_version(&conn, &file.id).unwrap().unwrap();
+        rewrite_later(&file.path, b"second take");
+
+        let renamed = FileMetadata {
+            meta_title: Some("Renamed".to_string()),
+            ..get_file(&conn, "repo", &file.id).unwrap()
+        };
+        let version = record_file_version(&conn, "repo", &renamed)
+            .unwrap()
+            .unwrap();
+        assert_eq!(version.content_hash, first.content_hash);
+        assert_eq!(version.date_modified, first.date_modified);
+        assert_eq!(version.metadata.meta_title.as_deref(), Some("Renamed"));
+    }
+
+    #[test]
+    fn a_row_with_no_history_keeps_the_current_audio() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = sample_file(&scratch_dir(), "a.wav", b"first take");
+        create_file(&conn, "repo", &file).unwrap();
+        conn.execute("DELETE FROM FileVersions", []).unwrap();
+        rewrite_later(&file.path, b"second take");
+
+        let version = record_file_version(&conn, "repo", &file).unwrap().unwrap();
+        assert_eq!(
+            fs::read(object_path(&version.content_hash)).unwrap(),
+            b"second take"
+        );
+        assert_eq!(
+            version.date_modified,
+            read_date_modified(&file.path).unwrap()
+        );
+    }
+
+    #[test]
+    fn updates_keep_the_previous_state() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = sample_file(&scratch_dir(), "a.wav", b"first take");
+        create_file(&conn, "repo", &file).unwrap();
+        rewrite_later(&file.path, b"second take");
+
+        let previous = get_file(&conn, "repo", &file.id).unwrap();
+        let updated = FileMetadata {
+            notes: Some("Louder".to_string()),
+            date_modified: read_date_modified(&file.path).unwrap(),
+            ..previous.clone()
+        };
+        update_file(&conn, "repo", &updated).unwrap();
+        record_file_version(&conn, "repo", &updated).unwrap();
+
+        let versions = get_file_versions(&conn, &file.id).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert!(versions
+            .iter()
+            .any(|v| v.metadata.notes.is_none() && !file_state_differs(&v.metadata, &previous)));
+        assert!(versions
+            .iter()
+            .any(|v| fs::read(object_path(&v.content_hash)).unwrap() == b"second take"));
+    }
+
+    /// Adds a WAV file, then rewrites it so it has two versions: the first and the
+    /// current audio. Returns the row and the first version.
+    fn file_with_two_takes(conn: &Connection) -> (FileMetadata, FileVersion) {
+        add_repository(conn, "repo");
+        let file = sample_file(&scratch_dir(), "take.wav", &wav_bytes(1));
+        create_file(conn, "repo", &file).unwrap();
+        let first = get_latest_file_version(conn, &file.id).unwrap().unwrap();
+        rewrite_later(&file.path, &wav_bytes(2));
+        let current = FileMetadata {
+            date_modified: read_date_modified(&file.path).unwrap(),
+            ..file
+        };
+        update_file(conn, "repo", &current).unwrap();
+        record_file_version(conn, "repo", &current).unwrap();
+        (current, first)
+    }
+
+    #[test]
+    fn restoring_a_version_brings_back_its_audio() {
+        let conn = test_connection();
+        let (file, first) = file_with_two_takes(&conn);
+
+        let restored = restore_file_version(&conn, "repo", &first.id, false).unwrap();
+        assert_eq!(
+            hash_file(Path::new(&file.path)).unwrap(),
+            hash_file(&object_path(&first.content_hash)).unwrap()
+        );
+        let row = get_file(&conn, "repo", &file.id).unwrap();
+        assert_eq!(row.date_modified, restored.date_modified);
+    }
+
+    #[test]
+    fn a_restore_whose_row_fails_puts_the_audio_back() {
+        let conn = test_connection();
+        let (file, first) = file_with_two_takes(&conn);
+        let versions = get_file_versions(&conn, &file.id).unwrap().len();
+        conn.execute_batch(
+            "CREATE TEMP TRIGGER refuse_updates BEFORE UPDATE ON Files
+             BEGIN SELECT RAISE(ABORT, 'refused'); END;",
+        )
+        .unwrap();
+
+        assert!(restore_file_version(&conn, "repo", &first.id, false).is_err());
+        assert_eq!(fs::read(&file.path).unwrap(), wav_bytes(2));
+        let row = get_file(&conn, "repo", &file.id).unwrap();
+        assert!(!file_state_differs(&row, &file));
+        assert_eq!(get_file_versions(&conn, &file.id).unwrap().len(), versions);
+    }
+
+    #[test]
+    fn a_restore_whose_tags_fail_puts_the_audio_back() {
+        let conn = test_connection();
+        add_repository(&conn, "repo");
+        let file = sample_file(&scratch_dir(), "take.wav", b"not really audio");
+        create_file(&conn, "repo", &file).unwrap();
+        let first = get_latest_file_version(&conn, &file.id).unwrap().unwrap();
+        rewrite_later(&file.path, b"still not audio");
+
+        assert!(restore_file_version(&conn, "repo", &first.id, true).is_err());
+        assert_eq!(fs::read(&file.path).unwrap(), b"still not audio");
+    }
+}
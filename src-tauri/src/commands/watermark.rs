@@ -0,0 +1,787 @@
+// src/commands/watermark.rs
+//! Inaudible, per-recipient watermarks for delivered audio.
+//!
+//! When a bundle is generated with watermarking on, every recipient gets an archive of
+//! their own. Each file is decoded from the original, marked with a random 32-bit
+//! identifier and encoded once into its delivered format. The identifier is recorded in
+//! `Watermarks` against the contact the file went to, so a copy that turns up where it
+//! shouldn't can be traced back with `trace_leak_command`.
+//!
+//! The mark is spread-spectrum. The identifier and a 16-bit check make up 48 bits, each
+//! carried by a quarter second of pseudo-random noise that follows the loudness of the
+//! music about 36 dB below it, and the whole frame repeats for the length of the track.
+//! Detection folds every repetition together and correlates it against the same noise,
+//! which is what lets the mark survive lossy encoding. A copy resampled since it was
+//! delivered is resampled back to the common rates until the mark lines up again.
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::commands::audio_ops::{codec_args, run_ffmpeg};
+use crate::commands::bundles::{ArchiveEntry, ArchiveProgress, CANCELLED};
+use crate::commands::db::{get_connection, DbPool};
+use crate::commands::delivery::{preset_ffmpeg_args, transcodes, Delivery};
+use crate::commands::structures::{
+    BundleGeneration, BundleGenerationRecipient, DeliveryPreset, LeakTrace, Watermark,
+};
+use rusqlite::{params, Connection, OptionalExtension, Result};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphError;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::default::{get_codecs, get_probe};
+use tauri::{AppHandle, State};
+use uuid::Uuid;
+
+/// Seeds the noise every payload bit is spread over. Changing it orphans existing marks.
+const WATERMARK_KEY: u64 = 0x5245_504f_5354_5544;
+const MARK_BITS: usize = 32;
+const CHECK_BITS: usize = 16;
+const PAYLOAD_BITS: usize = MARK_BITS + CHECK_BITS;
+/// How many payload bits are carried per second of audio.
+const BITS_PER_SECOND: u32 = 4;
+/// The mark's level relative to the loudness of the audio around it.
+const STRENGTH: f32 = 0.015;
+/// Passages quieter than this are left unmarked rather than given audible hiss.
+const SILENCE_RMS: f32 = 1e-4;
+/// How far a suspect file may have drifted from the delivered one, in samples, through
+/// encoder delay or a trimmed start.
+const SYNC_BEFORE: usize = 2048;
+const SYNC_AFTER: usize = 4096;
+/// How many payload bits are used to find where the mark starts.
+const SYNC_BITS: usize = 8;
+/// Only this much of a suspect file is read; it holds dozens of repetitions.
+const MAX_TRACE_SECONDS: u32 = 600;
+/// The average bit strength, in standard deviations, below which a decoded mark is noise.
+const MIN_CONFIDENCE: f64 = 3.0;
+/// Rates a file may have been marked at before a copy of it was resampled.
+const ORIGINAL_RATES: [u32; 6] = [44_100, 48_000, 88_200, 96_000, 32_000, 22_050];
+
+/// The 16 bits stored after a mark, to tell a real mark from noise.
+fn check_bits(mark: u32) -> u16 {
+    (mark.wrapping_mul(0x9E37_79B1) >> 16) as u16
+}
+
+fn payload(mark: u32) -> [bool; PAYLOAD_BITS] {
+    let check = check_bits(mark);
+    let mut bits = [false; PAYLOAD_BITS];
+    for (i, bit) in bits.iter_mut().enumerate() {
+        *bit = if i < MARK_BITS {
+            (mark >> (MARK_BITS - 1 - i)) & 1 == 1
+        } else {
+            (check >> (PAYLOAD_BITS - 1 - i)) & 1 == 1
+        };
+    }
+    bits
+}
+
+/// The ±1 noise sample for position `position` of payload bit `bit`.
+fn chip(bit: usize, position: u64) -> f32 {
+    let mut z = WATERMARK_KEY ^ ((bit as u64) << 40) ^ position;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    if z & 1 == 0 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+/// How many samples carry one payload bit at a sample rate.
+fn bit_length(sample_rate: u32) -> u64 {
+    (sample_rate / BITS_PER_SECOND).max(1) as u64
+}
+
+/// Adds the mark to a run of interleaved samples that starts at frame `first_frame`.
+fn mark_samples(
+    samples: &mut [f32],
+    channels: usize,
+    first_frame: u64,
+    bit_length: u64,
+    bits: &[bool; PAYLOAD_BITS],
+) {
+    if samples.is_empty() {
+        return;
+    }
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    if rms < SILENCE_RMS {
+        return;
+    }
+    let amplitude = STRENGTH * rms;
+    for (i, frame) in samples.chunks_mut(channels.max(1)).enumerate() {
+        let n = first_frame + i as u64;
+        let bit = ((n / bit_length) % PAYLOAD_BITS as u64) as usize;
+        let sign = if bits[bit] { 1.0 } else { -1.0 };
+        let value = amplitude * sign * chip(bit, n % bit_length);
+        for sample in frame {
+            *sample = (*sample + value).clamp(-1.0, 1.0);
+        }
+    }
+}
+
+/// Reads the mark back from mono audio, returning it when its check bits agree along with
+/// how strongly the bits were found. The noise is laid out in samples of the rate the
+/// file was marked at, which a later resample changes, so the audio is read at its own
+/// rate first and then resampled to each of `ORIGINAL_RATES` until the mark turns up.
+fn read_mark(mono: &[f32], sample_rate: u32) -> (Option<u32>, f64) {
+    let mut best = read_mark_at(mono, sample_rate);
+    for &original_rate in ORIGINAL_RATES.iter() {
+        if best.0.is_some() {
+            break;
+        }
+        if original_rate == sample_rate {
+            continue;
+        }
+        let read = read_mark_at(&resample(mono, sample_rate, original_rate), original_rate);
+        if read.0.is_some() || read.1 > best.1 {
+            best = read;
+        }
+    }
+    best
+}
+
+/// Resamples mono audio by linear interpolation. Good enough to line the noise back up
+/// for detection; nothing resampled here is ever played.
+fn resample(mono: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if mono.is_empty() || from_rate == to_rate {
+        return mono.to_vec();
+    }
+    let step = from_rate as f64 / to_rate as f64;
+    let length = ((mono.len() - 1) as f64 / step) as usize + 1;
+    (0..length)
+        .map(|n| {
+            let position = n as f64 * step;
+            let i = position as usize;
+            let fraction = (position - i as f64) as f32;
+            let next = mono.get(i + 1).copied().unwrap_or(mono[i]);
+            mono[i] + (next - mono[i]) * fraction
+        })
+        .collect()
+}
+
+/// Reads the mark from mono audio taken to be at the rate it was marked at.
+fn read_mark_at(mono: &[f32], sample_rate: u32) -> (Option<u32>, f64) {
+    let bit_length = bit_length(sample_rate) as usize;
+    let frame_length = bit_length * PAYLOAD_BITS;
+
+    // Differencing whitens the music, which is mostly low frequencies, and leaves the
+    // noise; the reference noise is differenced the same way.
+    let reference: Vec<Vec<f32>> = (0..PAYLOAD_BITS)
+        .map(|bit| {
+            (0..bit_length as u64)
+                .map(|p| chip(bit, p) - if p > 0 { chip(bit, p - 1) } else { 0.0 })
+                .collect()
+        })
+        .collect();
+
+    // The noise is the same in every repetition, so adding the repetitions together lets
+    // a single pass over one frame correlate against all of them.
+    let mut folded = vec![0.0f32; 2 * frame_length];
+    for (n, pair) in mono.windows(2).enumerate() {
+        // Each position is kept twice over, so a frame starting late still reads whole.
+        let difference = pair[1] - pair[0];
+        let j = (n + 1) % frame_length;
+        folded[j] += difference;
+        folded[j + frame_length] += difference;
+    }
+
+    let score = |offset: usize, bit: usize| -> f64 {
+        let start = offset + bit * bit_length;
+        let (mut correlation, mut energy) = (0.0f64, 0.0f64);
+        for (value, reference) in folded[start..start + bit_length]
+            .iter()
+            .zip(&reference[bit])
+        {
+            let product = (value * reference) as f64;
+            correlation += product;
+            energy += product * product;
+        }
+        if energy > 0.0 {
+            correlation / energy.sqrt()
+        } else {
+            0.0
+        }
+    };
+
+    let offsets = (0..=SYNC_AFTER.min(frame_length - 1))
+        .chain(frame_length.saturating_sub(SYNC_BEFORE)..frame_length);
+    let mut best = (0usize, f64::MIN);
+    for offset in offsets {
+        let strength: f64 = (0..SYNC_BITS).map(|bit| score(offset, bit).abs()).sum();
+        if strength > best.1 {
+            best = (offset, strength);
+        }
+    }
+
+    let scores: Vec<f64> = (0..PAYLOAD_BITS).map(|bit| score(best.0, bit)).collect();
+    let confidence = scores.iter().map(|z| z.abs()).sum::<f64>() / PAYLOAD_BITS as f64;
+    let value = |bits: &[f64]| {
+        bits.iter()
+            .fold(0u32, |value, z| (value << 1) | u32::from(*z > 0.0))
+    };
+    let mark = value(&scores[..MARK_BITS]);
+    let check = value(&scores[MARK_BITS..]) as u16;
+    let found = check == check_bits(mark) && confidence >= MIN_CONFIDENCE;
+    (found.then_some(mark), confidence)
+}
+
+/// One decoded packet: interleaved samples and how many channels they hold.
+type DecodedSamples<'a> = (&'a mut [f32], usize);
+
+/// Decodes an audio file packet by packet into interleaved `f32` samples.
+struct AudioReader {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    sample_rate: u32,
+    bits_per_sample: Option<u32>,
+    buffer: Option<SampleBuffer<f32>>,
+}
+
+impl AudioReader {
+    fn open(path: &Path) -> std::result::Result<Self, Box<dyn Error + Send + Sync>> {
+        let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut hint = symphonia::core::probe::Hint::new();
+        if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+        // Gapless playback trims encoder delay, so an MP3 lines up with what was encoded.
+        let format_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+        let probed = get_probe().format(&hint, mss, &format_opts, &MetadataOptions::default())?;
+        let format = probed.format;
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or("No supported audio track found")?;
+        let decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+        Ok(AudioReader {
+            track_id: track.id,
+            sample_rate: track
+                .codec_params
+                .sample_rate
+                .ok_or("Missing sample rate")?,
+            bits_per_sample: track.codec_params.bits_per_sample,
+            format,
+            decoder,
+            buffer: None,
+        })
+    }
+
+    /// Decodes the next packet, returning its samples and channel count, or `None` at the
+    /// end of the stream.
+    fn next_samples(
+        &mut self,
+    ) -> std::result::Result<Option<DecodedSamples<'_>>, Box<dyn Error + Send + Sync>> {
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                // A damaged packet is skipped, as a player would.
+                Err(SymphError::DecodeError(_)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let spec = *decoded.spec();
+            let channels = spec.channels.count();
+            let needed = decoded.capacity() * channels;
+            if self.buffer.as_ref().is_none_or(|b| b.capacity() < needed) {
+                self.buffer = Some(SampleBuffer::new(decoded.capacity() as u64, spec));
+            }
+            let buffer = self.buffer.as_mut().ok_or("No sample buffer")?;
+            buffer.copy_interleaved_ref(decoded);
+            return Ok(Some((buffer.samples_mut(), channels)));
+        }
+    }
+}
+
+/// Writes integer PCM samples to a WAV file, filling in the sizes once it is finished.
+struct WavWriter {
+    out: BufWriter<File>,
+    bits_per_sample: u16,
+    channels: u16,
+    sample_rate: u32,
+    data_length: u64,
+}
+
+impl WavWriter {
+    fn create(
+        path: &Path,
+        sample_rate: u32,
+        channels: u16,
+        bits_per_sample: u16,
+    ) -> std::io::Result<Self> {
+        let mut writer = WavWriter {
+            out: BufWriter::new(File::create(path)?),
+            bits_per_sample,
+            channels,
+            sample_rate,
+            data_length: 0,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let block_align = self.channels * (self.bits_per_sample / 8);
+        let data_length = self.data_length as u32;
+        self.out.write_all(b"RIFF")?;
+        self.out.write_all(&(36 + data_length).to_le_bytes())?;
+        self.out.write_all(b"WAVEfmt ")?;
+        self.out.write_all(&16u32.to_le_bytes())?;
+        self.out.write_all(&1u16.to_le_bytes())?;
+        self.out.write_all(&self.channels.to_le_bytes())?;
+        self.out.write_all(&self.sample_rate.to_le_bytes())?;
+        self.out
+            .write_all(&(self.sample_rate * block_align as u32).to_le_bytes())?;
+        self.out.write_all(&block_align.to_le_bytes())?;
+        self.out.write_all(&self.bits_per_sample.to_le_bytes())?;
+        self.out.write_all(b"data")?;
+        self.out.write_all(&data_length.to_le_bytes())
+    }
+
+    fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        let bytes = (self.bits_per_sample / 8) as usize;
+        let full_scale = ((1i64 << (self.bits_per_sample - 1)) - 1) as f64;
+        for sample in samples {
+            let value = (*sample as f64 * full_scale).round() as i32;
+            self.out.write_all(&value.to_le_bytes()[..bytes])?;
+        }
+        self.data_length += (samples.len() * bytes) as u64;
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        if 36 + self.data_length > u32::MAX as u64 {
+            return Err(std::io::Error::other("Audio is too long for a WAV file."));
+        }
+        self.out.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        let file = self.out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    }
+}
+
+/// Picks the bit depth of a marked WAV from what was asked for or what the source had,
+/// rounded up to a depth PCM WAV supports.
+fn wav_bit_depth(bits: Option<u32>) -> u16 {
+    match bits {
+        Some(bits) if bits <= 16 => 16,
+        Some(bits) if bits > 24 => 32,
+        _ => 24,
+    }
+}
+
+/// Decodes `source`, adds `mark` and writes the result to `output` as WAV.
+fn mark_to_wav(
+    source: &Path,
+    output: &Path,
+    mark: u32,
+    bit_depth: Option<u32>,
+    cancel: &AtomicBool,
+) -> std::result::Result<(), Box<dyn Error + Send + Sync>> {
+    let mut reader = AudioReader::open(source)?;
+    let bits = payload(mark);
+    let bit_length = bit_length(reader.sample_rate);
+    let bits_per_sample = wav_bit_depth(bit_depth.or(reader.bits_per_sample));
+    let sample_rate = reader.sample_rate;
+    let mut writer: Option<WavWriter> = None;
+    let mut frame = 0u64;
+
+    while let Some((samples, channels)) = reader.next_samples()? {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(CANCELLED.into());
+        }
+        mark_samples(samples, channels, frame, bit_length, &bits);
+        frame += (samples.len() / channels.max(1)) as u64;
+        if writer.is_none() {
+            writer = Some(WavWriter::create(
+                output,
+                sample_rate,
+                channels as u16,
+                bits_per_sample,
+            )?);
+        }
+        if let Some(writer) = writer.as_mut() {
+            writer.write_samples(samples)?;
+        }
+    }
+    writer.ok_or("The file has no audio to mark.")?.finish()?;
+    Ok(())
+}
+
+/// Picks `count` new marks that no delivered file carries yet.
+pub fn allocate_marks(conn: &Connection, count: usize) -> Result<Vec<u32>> {
+    let mut marks = Vec::with_capacity(count);
+    let mut taken = HashSet::new();
+    while marks.len() < count {
+        let mark = Uuid::new_v4().as_u128() as u32;
+        let used: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM Watermarks WHERE mark = ?1)",
+            params![mark],
+            |row| row.get(0),
+        )?;
+        if mark != 0 && !used && taken.insert(mark) {
+            marks.push(mark);
+        }
+    }
+    Ok(marks)
+}
+
+/// Marks every file of a delivery with its mark from `marks`, writing the marked copies
+/// into `work_dir` in the format each is delivered in. Each file is decoded from the
+/// original, marked and encoded once, straight to what the preset asks for; the delivery
+/// must have been prepared without encoding. A file that can't be marked fails the whole
+/// delivery, since sending it unmarked would defeat the point.
+pub fn watermark_delivery(
+    app: &AppHandle,
+    delivery: &Delivery,
+    marks: &[u32],
+    preset: Option<&DeliveryPreset>,
+    work_dir: &Path,
+    cancel: &AtomicBool,
+    mut on_progress: impl FnMut(&ArchiveProgress),
+) -> std::result::Result<Vec<ArchiveEntry>, Box<dyn Error + Send + Sync>> {
+    let preset = preset.filter(|preset| transcodes(preset));
+    let mut entries = Vec::with_capacity(delivery.entries.len());
+    for (i, ((entry, file), mark)) in delivery
+        .entries
+        .iter()
+        .zip(&delivery.files)
+        .zip(marks)
+        .enumerate()
+    {
+        if cancel.load(Ordering::Relaxed) {
+            return Err(CANCELLED.into());
+        }
+        on_progress(&ArchiveProgress {
+            file_index: i + 1,
+            file_count: delivery.entries.len(),
+            ..Default::default()
+        });
+
+        let extension = Path::new(&entry.archive_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let bit_depth = preset
+            .and_then(|preset| preset.bit_depth)
+            .map(|bits| bits as u32);
+        let marked_wav = work_dir.join(format!("{:03}_marked.wav", i));
+        mark_to_wav(Path::new(&file.path), &marked_wav, *mark, bit_depth, cancel)
+            .map_err(|e| format!("Couldn't watermark '{}': {}", entry.archive_name, e))?;
+
+        // A WAV at the original rate is ready as it is; anything else is encoded once,
+        // resampling on the way if the preset asks.
+        let resamples = preset.is_some_and(|preset| preset.sample_rate.is_some());
+        let path = if extension == "wav" && !resamples {
+            marked_wav
+        } else {
+            let output = work_dir.join(format!("{:03}_marked.{}", i, extension));
+            // The original file is a second input so its tags carry over.
+            let mut args = vec![
+                "-i".to_string(),
+                file.path.clone(),
+                "-map".to_string(),
+                "0:a".to_string(),
+                "-map_metadata".to_string(),
+                "1".to_string(),
+            ];
+            match preset {
+                Some(preset) => args.extend(preset_ffmpeg_args(preset, &extension)),
+                None => {
+                    args.extend(codec_args(&extension));
+                    let bitrate = file
+                        .meta_bit_rate
+                        .as_deref()
+                        .and_then(|rate| rate.parse::<u32>().ok());
+                    if let (Some(bitrate), false) =
+                        (bitrate, matches!(extension.as_str(), "flac" | "wav"))
+                    {
+                        args.extend(["-b:a".to_string(), format!("{}k", bitrate)]);
+                    }
+                }
+            }
+            tauri::async_runtime::block_on(run_ffmpeg(app, &marked_wav, &args, &output))
+                .map_err(|e| format!("Couldn't encode '{}': {}", entry.archive_name, e))?;
+            fs::remove_file(&marked_wav).ok();
+            output
+        };
+        entries.push(ArchiveEntry {
+            path: path.to_string_lossy().to_string(),
+            archive_name: entry.archive_name.clone(),
+        });
+    }
+    Ok(entries)
+}
+
+/// Records who each marked file of a generation was sent to. Run it in the transaction
+/// that records the generation; a mark that another generation took since it was
+/// allocated fails on the table's key, so no mark is ever recorded twice.
+pub fn record_watermarks(
+    conn: &Connection,
+    generation: &BundleGeneration,
+    bundle_name: &str,
+    recipient: &BundleGenerationRecipient,
+    marked: &[(u32, String, String)],
+) -> Result<()> {
+    for (mark, file_id, archive_name) in marked {
+        conn.execute(
+            "INSERT INTO Watermarks (
+                mark, generation_id, bundle_id, bundle_name, contact_id, contact_name,
+                contact_email, file_id, archive_name, date_created
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                mark,
+                generation.id,
+                generation.bundle_id,
+                bundle_name,
+                recipient.contact_id,
+                recipient.name,
+                recipient.email,
+                file_id,
+                archive_name,
+                generation.generated_at,
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+const WATERMARK_COLUMNS: &str = "mark, generation_id, bundle_id, bundle_name, contact_id, \
+     contact_name, contact_email, file_id, archive_name, date_created";
+
+fn watermark_from_row(row: &rusqlite::Row) -> Result<Watermark> {
+    Ok(Watermark {
+        mark: row.get(0)?,
+        generation_id: row.get(1)?,
+        bundle_id: row.get(2)?,
+        bundle_name: row.get(3)?,
+        contact_id: row.get(4)?,
+        contact_name: row.get(5)?,
+        contact_email: row.get(6)?,
+        file_id: row.get(7)?,
+        archive_name: row.get(8)?,
+        date_created: row.get(9)?,
+    })
+}
+
+/// Returns every marked file sent to a contact, newest first.
+pub fn get_contact_watermarks(conn: &Connection, contact_id: &str) -> Result<Vec<Watermark>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM Watermarks WHERE contact_id = ?1 ORDER BY date_created DESC",
+        WATERMARK_COLUMNS
+    ))?;
+    let watermarks = stmt
+        .query_map(params![contact_id], watermark_from_row)?
+        .collect::<Result<Vec<Watermark>>>()?;
+    Ok(watermarks)
+}
+
+/// Decodes a suspect file and looks up who the mark in it was sent to.
+pub fn trace_leak(
+    conn: &Connection,
+    path: &Path,
+) -> std::result::Result<LeakTrace, Box<dyn Error + Send + Sync>> {
+    let mut reader = AudioReader::open(path)?;
+    let limit = (reader.sample_rate * MAX_TRACE_SECONDS) as usize;
+    let mut mono = Vec::new();
+    while let Some((samples, channels)) = reader.next_samples()? {
+        let channels = channels.max(1);
+        mono.extend(
+            samples
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32),
+        );
+        if mono.len() >= limit {
+            mono.truncate(limit);
+            break;
+        }
+    }
+    let frame_seconds = PAYLOAD_BITS as u32 / BITS_PER_SECOND;
+    if mono.len() < (reader.sample_rate * frame_seconds) as usize {
+        return Err(format!(
+            "The file is too short to carry a watermark; at least {} seconds are needed.",
+            frame_seconds
+        )
+        .into());
+    }
+
+    let (mark, confidence) = read_mark(&mono, reader.sample_rate);
+    let watermark = match mark {
+        Some(mark) => conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM Watermarks WHERE mark = ?1",
+                    WATERMARK_COLUMNS
+                ),
+                params![mark],
+                watermark_from_row,
+            )
+            .optional()?,
+        None => None,
+    };
+    Ok(LeakTrace {
+        mark,
+        confidence,
+        watermark,
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Tauri command wrappers
+// ---------------------------------------------------------------------------
+
+#[tauri::command]
+pub async fn trace_leak_command(
+    pool: State<'_, DbPool>,
+    path: String,
+) -> Result<LeakTrace, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        trace_leak(&conn, Path::new(&path)).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_contact_watermarks_command(
+    pool: State<'_, DbPool>,
+    contact_id: String,
+) -> Result<Vec<Watermark>, String> {
+    let pool = pool.inner().clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let conn = get_connection(&pool)?;
+        get_contact_watermarks(&conn, &contact_id).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A few seconds of something music-like: chords with a slow swell, a kick on every
+    /// beat and a little hiss, from a fixed seed so every run is the same.
+    fn music(sample_rate: u32, seconds: u32) -> Vec<f32> {
+        let mut seed = 0x2545_F491_4F6C_DD1Du64;
+        let mut noise = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+        };
+        (0..sample_rate * seconds)
+            .map(|n| {
+                let t = n as f32 / sample_rate as f32;
+                let swell = 0.6 + 0.4 * (t * 0.5).sin();
+                let chord: f32 = [110.0f32, 164.8, 220.0, 277.2, 329.6]
+                    .iter()
+                    .map(|f| (2.0 * std::f32::consts::PI * f * t).sin())
+                    .sum::<f32>()
+                    / 5.0;
+                let beat = t % 0.5;
+                let kick = (-beat * 30.0).exp() * (2.0 * std::f32::consts::PI * 55.0 * beat).sin();
+                0.4 * swell * chord + 0.3 * kick + 0.02 * noise()
+            })
+            .collect()
+    }
+
+    /// Marks mono audio the way `mark_to_wav` does, one decoded packet at a time.
+    fn marked(mut mono: Vec<f32>, sample_rate: u32, mark: u32) -> Vec<f32> {
+        let bits = payload(mark);
+        for (i, packet) in mono.chunks_mut(1152).enumerate() {
+            mark_samples(packet, 1, (i * 1152) as u64, bit_length(sample_rate), &bits);
+        }
+        mono
+    }
+
+    /// What a lossy encoder does to the mark: everything above `cutoff` Hz is thrown away
+    /// and the rest is quantized to 16 bits.
+    fn lossy(mono: &[f32], sample_rate: u32, cutoff: f32) -> Vec<f32> {
+        const TAPS: isize = 32;
+        let fc = cutoff / sample_rate as f32;
+        let kernel: Vec<f32> = (-TAPS..=TAPS)
+            .map(|k| {
+                let x = k as f32;
+                let sinc = if k == 0 {
+                    2.0 * fc
+                } else {
+                    (2.0 * std::f32::consts::PI * fc * x).sin() / (std::f32::consts::PI * x)
+                };
+                let window = 0.54 + 0.46 * (std::f32::consts::PI * x / TAPS as f32).cos();
+                sinc * window
+            })
+            .collect();
+        (0..mono.len() as isize)
+            .map(|n| {
+                let filtered: f32 = kernel
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, k)| {
+                        let at = n + j as isize - TAPS;
+                        (at >= 0).then(|| mono.get(at as usize).map(|s| s * k))?
+                    })
+                    .sum();
+                (filtered * 32767.0).round() / 32767.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn payloads_carry_the_mark_and_its_check() {
+        let bits = payload(0x8000_0001);
+        assert!(bits[0] && bits[MARK_BITS - 1]);
+        assert!(bits[1..MARK_BITS - 1].iter().all(|bit| !bit));
+        let check = bits[MARK_BITS..]
+            .iter()
+            .fold(0u16, |value, bit| (value << 1) | u16::from(*bit));
+        assert_eq!(check, check_bits(0x8000_0001));
+    }
+
+    #[test]
+    fn a_mark_is_read_back_from_the_marked_audio() {
+        let mark = 0x1234_5678;
+        let (found, confidence) = read_mark(&marked(music(22_050, 15), 22_050, mark), 22_050);
+        assert_eq!(found, Some(mark));
+        assert!(confidence >= MIN_CONFIDENCE);
+    }
+
+    #[test]
+    fn unmarked_audio_has_no_mark() {
+        let (found, confidence) = read_mark_at(&music(22_050, 15), 22_050);
+        assert_eq!(found, None);
+        assert!(confidence < MIN_CONFIDENCE);
+    }
+
+    #[test]
+    fn a_mark_survives_lossy_encoding_and_resampling() {
+        let mark = 0xC0FF_EE42;
+        let encoded = lossy(&marked(music(48_000, 15), 48_000, mark), 48_000, 16_000.0);
+        let resampled = resample(&encoded, 48_000, 44_100);
+
+        let (found, _) = read_mark(&resampled, 44_100);
+        assert_eq!(found, Some(mark));
+    }
+}
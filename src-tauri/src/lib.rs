@@ -1,4 +1,6 @@
-use background::folder_watcher;
+use background::{
+    activity_pruner, backup_scheduler, folder_watcher, trash_purger, version_baseline,
+};
 // lib.rs
 use tauri::{
     // menu::{Menu, MenuItem},
@@ -6,7 +8,7 @@ use tauri::{
     Manager,
 };
 mod commands;
-use commands::{db, refresh_files_in_all_repositories};
+use commands::{db, migrations, refresh_files_in_all_repositories};
 mod background;
 
 pub fn run() {
@@ -31,13 +33,16 @@ pub fn run() {
             commands::get_repository_command,
             commands::update_repository_command,
             commands::get_files_in_repository_command,
+            commands::query_files_command,
             // Files
             commands::get_file_command,
             commands::create_file_command,
             commands::update_file_command,
             commands::delete_file_command,
+            commands::update_files_command,
+            commands::delete_files_command,
+            commands::move_files_to_repository_command,
             commands::remove_duplicate_files_command,
-            commands::bundle_files_command,
             // Settings
             commands::get_app_settings_command,
             commands::update_app_settings_command,
@@ -66,6 +71,121 @@ pub fn run() {
             // ---------------------------------- //
             commands::refresh_files_in_repository_command,
             commands::refresh_files_in_all_repositories_command,
+            commands::get_repository_status_command,
+            // ---------------------------------- //
+            //            versions.rs             //
+            // ---------------------------------- //
+            commands::get_file_versions_command,
+            commands::read_file_version_command,
+            commands::export_file_version_command,
+            commands::restore_file_version_command,
+            commands::restore_repository_command,
+            // ---------------------------------- //
+            //             commits.rs             //
+            // ---------------------------------- //
+            commands::commit_repository_command,
+            commands::get_commit_log_command,
+            commands::get_commit_command,
+            commands::diff_commit_command,
+            // ---------------------------------- //
+            //             search.rs              //
+            // ---------------------------------- //
+            commands::search_files_command,
+            // ---------------------------------- //
+            //              tags.rs               //
+            // ---------------------------------- //
+            commands::get_tags_command,
+            commands::get_file_tags_command,
+            commands::autocomplete_tags_command,
+            commands::add_tag_to_files_command,
+            commands::remove_tag_from_files_command,
+            commands::rename_tag_command,
+            commands::merge_tags_command,
+            commands::delete_tag_command,
+            commands::set_tag_color_command,
+            // ---------------------------------- //
+            //          collections.rs            //
+            // ---------------------------------- //
+            commands::get_smart_collections_command,
+            commands::get_smart_collection_files_command,
+            commands::preview_smart_collection_command,
+            commands::create_smart_collection_command,
+            commands::update_smart_collection_command,
+            commands::refresh_smart_collection_command,
+            commands::delete_smart_collection_command,
+            // ---------------------------------- //
+            //           relations.rs             //
+            // ---------------------------------- //
+            commands::get_file_relations_command,
+            commands::get_file_graph_command,
+            commands::link_files_command,
+            commands::unlink_files_command,
+            // ---------------------------------- //
+            //          maintenance.rs            //
+            // ---------------------------------- //
+            commands::get_backup_settings_command,
+            commands::update_backup_settings_command,
+            commands::list_backups_command,
+            commands::create_backup_command,
+            commands::restore_backup_command,
+            commands::check_integrity_command,
+            // ---------------------------------- //
+            //            activity.rs             //
+            // ---------------------------------- //
+            commands::get_activity_log_command,
+            // ---------------------------------- //
+            //              trash.rs              //
+            // ---------------------------------- //
+            commands::get_trash_command,
+            commands::restore_trash_item_command,
+            commands::delete_trash_item_command,
+            commands::empty_trash_command,
+            // ---------------------------------- //
+            //             journal.rs             //
+            // ---------------------------------- //
+            commands::undo_command,
+            commands::redo_command,
+            commands::get_journal_command,
+            // ---------------------------------- //
+            //             assets.rs              //
+            // ---------------------------------- //
+            commands::get_asset_command,
+            commands::add_file_to_repository_command,
+            // ---------------------------------- //
+            //            hierarchy.rs            //
+            // ---------------------------------- //
+            commands::move_repository_command,
+            commands::get_repository_tree_command,
+            commands::get_subtree_files_command,
+            // ---------------------------------- //
+            //             bundles.rs             //
+            // ---------------------------------- //
+            commands::bundle_files_command,
+            commands::export_bundle_files_command,
+            commands::cancel_bundle_command,
+            commands::get_bundles_command,
+            commands::create_bundle_command,
+            commands::update_bundle_command,
+            commands::delete_bundle_command,
+            commands::generate_bundle_command,
+            commands::get_bundle_generations_command,
+            // ---------------------------------- //
+            //            delivery.rs             //
+            // ---------------------------------- //
+            commands::get_delivery_presets_command,
+            commands::create_delivery_preset_command,
+            commands::update_delivery_preset_command,
+            commands::delete_delivery_preset_command,
+            commands::set_delivery_preset_command,
+            // ---------------------------------- //
+            //            manifest.rs             //
+            // ---------------------------------- //
+            commands::verify_bundle_command,
+            // ---------------------------------- //
+            //            watermark.rs            //
+            // ---------------------------------- //
+            commands::trace_leak_command,
+            commands::get_contact_watermarks_command,
             // ---------------------------------- //
             //         folder_watcher.rs          //
             // ---------------------------------- //
@@ -95,22 +215,38 @@ pub fn run() {
                 .set(db_path.to_string_lossy().to_string())
                 .expect("Database path already set");
 
-            match refresh_files_in_all_repositories() {
+            // Open the shared connection pool and hand it to Tauri as managed state
+            let pool = db::create_pool()?;
+            app.manage(pool.clone());
+
+            // Bring the database schema up to date before anything else touches it
+            let mut conn = pool.get()?;
+            if let Err(e) = migrations::run_migrations(&mut conn) {
+                println!("Failed to migrate database: {}", e);
+                return Err(e.to_string().into());
+            }
+
+            match refresh_files_in_all_repositories(&conn) {
                 Ok(_) => println!("Checked all file accessibility at startup!"),
                 Err(e) => println!("Failed to refresh files at startup: {:?}", e),
             };
 
             // Start folder watchers on launch
             let window = app.get_webview_window("main").unwrap(); // Or get the correct window
-            match db::get_tracked_folders() {
+            match db::get_tracked_folders(&conn) {
                 Ok(folders) => {
                     for folder in folders {
+                        // A trashed repository's folders are watched again once it is restored.
+                        if db::get_repository(&conn, &folder.repo_id).is_err() {
+                            continue;
+                        }
                         println!(
                             "Auto-watching folder: {} for repo {}",
                             folder.folder_path, folder.repo_id
                         );
-                        if let Err(e) = folder_watcher::watch_folder(
+                        if let Err(e) = folder_watcher::resume_watching(
                             window.clone(),
+                            pool.clone(),
                             folder.repo_id.clone(),
                             folder.folder_path.clone(),
                         ) {
@@ -120,6 +256,18 @@ pub fn run() {
                 }
                 Err(e) => println!("Failed to load tracked folders: {:?}", e),
             }
+
+            // Take scheduled backups of the library in the background
+            backup_scheduler::start_backup_scheduler(pool.clone());
+
+            // Delete trashed items once they are past the retention period
+            trash_purger::start_trash_purger(pool.clone());
+
+            // Keep the activity log within its retention period
+            activity_pruner::start_activity_pruner(pool.clone());
+
+            // Give files that have never been versioned their first snapshot
+            version_baseline::start_version_baseline(pool.clone());
             Ok(())
         })
         .run(tauri::generate_context!())
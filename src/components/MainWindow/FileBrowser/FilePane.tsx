@@ -252,18 +252,14 @@ const FilePane: React.FC = () => {
           comp = a.name.localeCompare(b.name);
           break;
         case 'dateCreated': {
-          const matchA = a.date_created.match(/intervals:\s*(\d+)/);
-          const matchB = b.date_created.match(/intervals:\s*(\d+)/);
-          const numA = matchA ? parseInt(matchA[1], 10) : 0;
-          const numB = matchB ? parseInt(matchB[1], 10) : 0;
+          const numA = Date.parse(a.date_created) || 0;
+          const numB = Date.parse(b.date_created) || 0;
           comp = numA - numB;
           break;
         }
         case 'dateModified': {
-          const matchA = a.date_modified.match(/intervals:\s*(\d+)/);
-          const matchB = b.date_modified.match(/intervals:\s*(\d+)/);
-          const numA = matchA ? parseInt(matchA[1], 10) : 0;
-          const numB = matchB ? parseInt(matchB[1], 10) : 0;
+          const numA = Date.parse(a.date_modified) || 0;
+          const numB = Date.parse(b.date_modified) || 0;
           comp = numA - numB;
           break;
         }
@@ -47,11 +47,8 @@ const PropertiesPane: React.FC = () => {
     (str: string): Date | null;
   }
   const parseSystemTime: IParseSystemTime = (str: string): Date | null => {
-    const match: RegExpMatchArray | null = str.match(/intervals:\s*(\d+)/);
-    if (!match) return null;
-    const fileTime: number = parseInt(match[1], 10);
-    const unixTime: number = fileTime / 10000 - 11644473600000;
-    return new Date(unixTime);
+    const date: Date = new Date(str);
+    return isNaN(date.getTime()) ? null : date;
   };
   
 
@@ -19,12 +19,14 @@ export interface Contact {
   email?: string;
   profession?: string;
   handle?: string;
+  delivery_preset_id?: string | null;
 }
 
 export interface ContactList {
   id: string;
   name: string;
   contacts: Contact[];
+  delivery_preset_id?: string | null;
 }
 
 export interface ContactStore {
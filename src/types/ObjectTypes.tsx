@@ -21,12 +21,16 @@ export interface FileMetadata {
     meta_album: string | null;
     meta_track_number: string | null;
     meta_genre: string | null;
+    meta_bpm: string | null;
+    meta_key: string | null;
 
 
     meta_bit_rate: string | null;
     meta_channels: string | null;
     meta_sample_rate: string | null;
     meta_size_on_disk: string | null;
+
+    notes?: string | null;
 }
 
 // Repository type definition
@@ -34,4 +38,320 @@ export interface Repository {
     id: string;
     name: string;
     description: string;
-}
\ No newline at end of file
+    parent_id?: string | null;
+}
+
+// Repository status type definitions (see get_repository_status_command)
+export type FileStatus = 'unchanged' | 'modified' | 'missing' | 'untracked';
+
+export interface FileStatusEntry {
+    file_id: string | null;
+    name: string;
+    path: string;
+    status: FileStatus;
+}
+
+export interface RepositoryStatus {
+    repo_id: string;
+    tracked_folders: string[];
+    files: FileStatusEntry[];
+    unchanged: number;
+    modified: number;
+    missing: number;
+    untracked: number;
+}
+
+export interface SearchResult {
+    repo_id: string;
+    file: FileMetadata;
+    rank: number;
+    snippet: string;
+}
+
+export type FileSortKey = 'name' | 'date_modified' | 'size' | 'bit_rate' | 'sample_rate';
+
+export type SortDirection = 'asc' | 'desc';
+
+export interface FileFilters {
+    encoding?: string;
+    accessible?: boolean;
+    has_fingerprint?: boolean;
+    genre?: string;
+}
+
+export interface FileQuery {
+    repo_id: string;
+    offset?: number;
+    limit?: number;
+    sort_by?: FileSortKey;
+    sort_direction?: SortDirection;
+    filters?: FileFilters;
+}
+
+export interface FilePage {
+    files: FileMetadata[];
+    total: number;
+    offset: number;
+    limit: number;
+    next_offset: number | null;
+}
+
+export interface Tag {
+    id: string;
+    name: string;
+    path: string;
+    color: string | null;
+    parent_id: string | null;
+    file_count: number;
+}
+
+export interface SmartCollection {
+    id: string;
+    name: string;
+    repo_id: string | null;
+    query: string;
+    date_created: string;
+    file_count: number;
+}
+
+export interface RepositoryFile {
+    repo_id: string;
+    file: FileMetadata;
+}
+
+export type FileRelationKind = 'stem_of' | 'instrumental_of' | 'remix_of' | 'master_of' | 'preview_of' | 'converted_from';
+
+export interface FileRelation {
+    id: string;
+    source_file_id: string;
+    target_file_id: string;
+    relation: FileRelationKind;
+    date_created: string;
+    trashed: boolean;
+}
+
+export interface FileGraph {
+    root_file_id: string;
+    nodes: RepositoryFile[];
+    edges: FileRelation[];
+    trashed_file_ids: string[];
+}
+
+export interface TrackedFolder {
+    id: string;
+    repo_id: string;
+    folder_path: string;
+}
+
+export interface BackupSettings {
+    folder: string | null;
+    interval_hours: number;
+    keep_count: number;
+}
+
+export interface BackupInfo {
+    path: string;
+    file_name: string;
+    created_at: string;
+    size_on_disk: number;
+    schema_version: number | null;
+}
+
+export interface IntegrityReport {
+    ok: boolean;
+    integrity_errors: string[];
+    foreign_key_violations: string[];
+    orphaned_tracked_folders: TrackedFolder[];
+    orphan_tables: string[];
+    orphaned_search_entries: number;
+    orphaned_commits: number;
+    repaired: boolean;
+    backup: BackupInfo | null;
+}
+
+export type ActivityActor = 'user' | 'watcher' | 'system';
+
+export type ActivityOutcome = 'success' | 'failure';
+
+export interface ActivityEntry {
+    id: number;
+    timestamp: string;
+    operation: string;
+    actor: ActivityActor;
+    repo_id: string | null;
+    file_ids: string[];
+    outcome: ActivityOutcome;
+    error: string | null;
+    details: string | null;
+}
+
+export interface ActivityFilter {
+    repo_id?: string | null;
+    file_id?: string | null;
+    operation?: string | null;
+    actor?: ActivityActor | null;
+    since?: string | null;
+    until?: string | null;
+    limit?: number | null;
+    offset?: number;
+}
+
+export type TrashItemKind = 'repository' | 'file' | 'contact' | 'contact_list';
+
+export interface TrashItem {
+    kind: TrashItemKind;
+    id: string;
+    name: string;
+    repo_id: string | null;
+    deleted_at: string;
+    purge_at: string | null;
+}
+
+export type JournalState = 'done' | 'undone';
+
+export interface JournalEntry {
+    id: number;
+    timestamp: string;
+    operation: string;
+    description: string;
+    state: JournalState;
+}
+
+export interface Asset {
+    id: string;
+    path: string;
+    content_hash: string | null;
+    files: RepositoryFile[];
+}
+
+export interface RepositoryStats {
+    repository_count: number;
+    file_count: number;
+    missing_file_count: number;
+    size_on_disk: number;
+}
+
+export interface RepositoryNode {
+    repository: Repository;
+    stats: RepositoryStats;
+    subtree_stats: RepositoryStats;
+    children: RepositoryNode[];
+}
+
+export type BundleRecipientKind = 'contact' | 'contact_list';
+
+export interface BundleRecipient {
+    kind: BundleRecipientKind;
+    id: string;
+}
+
+export interface Bundle {
+    id: string;
+    name: string;
+    description: string | null;
+    date_created: string;
+    date_modified: string;
+    file_ids: string[];
+    recipients: BundleRecipient[];
+}
+
+export interface BundleGenerationRecipient {
+    contact_id: string;
+    name: string;
+    email: string;
+    delivery_preset_id: string | null;
+}
+
+export interface BundleGeneration {
+    id: string;
+    bundle_id: string;
+    generated_at: string;
+    output_path: string;
+    size_on_disk: number;
+    file_ids: string[];
+    recipients: BundleGenerationRecipient[];
+    delivery_preset: DeliveryPreset | null;
+}
+
+export interface BundleArchive {
+    output_path: string;
+    size_on_disk: number;
+    included_paths: string[];
+}
+
+export interface ManifestEntry {
+    archive_name: string;
+    file_id: string | null;
+    title: string | null;
+    artist: string | null;
+    bpm: string | null;
+    key: string | null;
+    duration_seconds: number | null;
+    format: string | null;
+    sample_rate: string | null;
+    bit_depth: number | null;
+    size: number;
+    sha256: string;
+    notes: string | null;
+}
+
+export interface BundleManifest {
+    title: string | null;
+    generated_at: string;
+    files: ManifestEntry[];
+}
+
+export type ManifestCheckStatus = 'ok' | 'mismatch' | 'missing' | 'unlisted';
+
+export interface ManifestCheck {
+    archive_name: string;
+    status: ManifestCheckStatus;
+    expected_sha256: string | null;
+    actual_sha256: string | null;
+}
+
+export interface BundleVerification {
+    valid: boolean;
+    title: string | null;
+    generated_at: string;
+    checks: ManifestCheck[];
+}
+
+export interface DeliveryPreset {
+    id: string;
+    name: string;
+    format: string | null;
+    bitrate_kbps: number | null;
+    sample_rate: number | null;
+    bit_depth: number | null;
+    naming_template: string | null;
+    include_stems: boolean;
+    date_created: string;
+    date_modified: string;
+}
+
+export interface GenerateBundleOptions {
+    recipients?: BundleRecipient[] | null;
+    delivery_preset_id?: string | null;
+    include_manifest?: boolean;
+    watermark?: boolean;
+}
+
+export interface Watermark {
+    mark: number;
+    generation_id: string;
+    bundle_id: string;
+    bundle_name: string;
+    contact_id: string | null;
+    contact_name: string;
+    contact_email: string;
+    file_id: string | null;
+    archive_name: string;
+    date_created: string;
+}
+
+export interface LeakTrace {
+    mark: number | null;
+    confidence: number;
+    watermark: Watermark | null;
+}